@@ -0,0 +1,316 @@
+//! A minimal ssh-agent that signs with FIDO2 backed OpenSSH keys (`ecdsa-sk` and `ed25519-sk`).
+//!
+//! Usage: `agent <socket> <private key file>...`, then `SSH_AUTH_SOCK=<socket> ssh ...`
+//!
+//! Only unencrypted key files are supported. Resident keys can be written to files with
+//! `ssh-keygen -K`. Keys that require user verification are refused, as the agent can't ask
+//! for a PIN. Every signature is made by the first detected device.
+
+#[cfg(unix)]
+fn main() {
+    if let Err(err) = agent::run() {
+        eprintln!("agent: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("agent: ssh-agent sockets are only supported on unix");
+}
+
+#[cfg(unix)]
+mod agent {
+    use libfido2::*;
+    use std::{
+        env,
+        error::Error,
+        ffi::CString,
+        fs,
+        io::{Read, Write},
+        os::unix::net::{UnixListener, UnixStream},
+    };
+
+    type AgentResult<T> = Result<T, Box<dyn Error>>;
+
+    // Agent protocol messages
+    const SSH_AGENT_FAILURE: u8 = 5;
+    const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+    const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+    const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+    const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+    // Flags of security key backed keys
+    const SSH_SK_USER_PRESENCE_REQD: u8 = 0x01;
+    const SSH_SK_USER_VERIFICATION_REQD: u8 = 0x04;
+
+    const ECDSA_SK: &str = "sk-ecdsa-sha2-nistp256@openssh.com";
+    const ED25519_SK: &str = "sk-ssh-ed25519@openssh.com";
+
+    /// A loaded key: the public key blob offered to clients and what is needed to sign.
+    struct Key {
+        key_type: String,
+        public_blob: Vec<u8>,
+        application: CString,
+        flags: u8,
+        key_handle: Vec<u8>,
+        comment: String,
+    }
+
+    pub fn run() -> AgentResult<()> {
+        let args = env::args().skip(1).collect::<Vec<_>>();
+        if args.len() < 2 {
+            return Err("usage: agent <socket> <private key file>...".into());
+        }
+        let keys = args[1..]
+            .iter()
+            .map(|path| load_key(path).map_err(|err| format!("{}: {}", path, err)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let fido = Fido::new(false);
+        let listener = UnixListener::bind(&args[0])?;
+        eprintln!("Listening on {}", args[0]);
+        for stream in listener.incoming() {
+            if let Err(err) = serve(&fido, &keys, stream?) {
+                eprintln!("agent: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    fn serve(fido: &Fido, keys: &[Key], mut stream: UnixStream) -> AgentResult<()> {
+        loop {
+            let mut len = [0; 4];
+            if stream.read_exact(&mut len).is_err() {
+                // Client disconnected
+                return Ok(());
+            }
+            let mut message = vec![0; u32::from_be_bytes(len) as usize];
+            stream.read_exact(&mut message)?;
+
+            let response = match handle(fido, keys, &message) {
+                Ok(response) => response,
+                Err(err) => {
+                    eprintln!("agent: {}", err);
+                    vec![SSH_AGENT_FAILURE]
+                }
+            };
+            stream.write_all(&(response.len() as u32).to_be_bytes())?;
+            stream.write_all(&response)?;
+        }
+    }
+
+    fn handle(fido: &Fido, keys: &[Key], message: &[u8]) -> AgentResult<Vec<u8>> {
+        let mut message = Wire(message);
+        match message.byte()? {
+            SSH_AGENTC_REQUEST_IDENTITIES => {
+                let mut response = vec![SSH_AGENT_IDENTITIES_ANSWER];
+                put_u32(&mut response, keys.len() as u32);
+                for key in keys {
+                    put_string(&mut response, &key.public_blob);
+                    put_string(&mut response, key.comment.as_bytes());
+                }
+                Ok(response)
+            }
+            SSH_AGENTC_SIGN_REQUEST => {
+                let blob = message.string()?;
+                let data = message.string()?;
+                let key = keys
+                    .iter()
+                    .find(|key| key.public_blob == blob)
+                    .ok_or("Unknown key")?;
+                let mut response = vec![SSH_AGENT_SIGN_RESPONSE];
+                put_string(&mut response, &sign(fido, key, data)?);
+                Ok(response)
+            }
+            _ => Ok(vec![SSH_AGENT_FAILURE]),
+        }
+    }
+
+    fn sign(fido: &Fido, key: &Key, data: &[u8]) -> AgentResult<Vec<u8>> {
+        if key.flags & SSH_SK_USER_VERIFICATION_REQD != 0 {
+            return Err("Keys that require user verification are not supported".into());
+        }
+        let allowed = [&key.key_handle[..]];
+        let mut request = AssertionCreationData::with_defaults(
+            Some(&allowed),
+            // Replaced by the hash of `data` below
+            &[0; 32],
+            RpEntity::new(&key.application)?,
+        );
+        if key.flags & SSH_SK_USER_PRESENCE_REQD != 0 {
+            request.options = AssertionOptions::USER_PRESENCE;
+        }
+        let mut creator = fido.new_assertion_creator(request)?;
+        creator.set_client_data(data)?;
+
+        let devices = fido.try_detect_devices(1)?;
+        let info = devices.iter().next().ok_or("No device found")?;
+        let mut device = fido.new_device(info.path)?;
+        if key.flags & SSH_SK_USER_PRESENCE_REQD != 0 {
+            eprintln!("Touch the device to sign with {}", key.comment);
+        }
+        let assertion = device.request_assertion_verification(creator, None)?;
+        let statement = assertion.iter().next().ok_or("No signature returned")?;
+
+        // The authenticator data is a CBOR byte string: rpIdHash, flags, signCount
+        let auth_data = strip_cbor_bytes_header(statement.auth_data).ok_or("Invalid auth data")?;
+        if auth_data.len() < 37 {
+            return Err("Invalid auth data".into());
+        }
+        let mut signature_blob = Vec::new();
+        if key.key_type == ECDSA_SK {
+            let raw = statement
+                .signature_raw(CoseAlgorithm::ES256)
+                .ok_or("Invalid signature")?;
+            let (r, s) = raw.split_at(raw.len() / 2);
+            put_mpint(&mut signature_blob, r);
+            put_mpint(&mut signature_blob, s);
+        } else {
+            signature_blob.extend_from_slice(statement.signature);
+        }
+
+        let mut signature = Vec::new();
+        put_string(&mut signature, key.key_type.as_bytes());
+        put_string(&mut signature, &signature_blob);
+        signature.push(auth_data[32]);
+        signature.extend_from_slice(&auth_data[33..37]);
+        Ok(signature)
+    }
+
+    /// Loads an unencrypted `openssh-key-v1` private key file of a security key backed key.
+    fn load_key(path: &str) -> AgentResult<Key> {
+        let pem = fs::read_to_string(path)?;
+        let body = pem
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect::<String>();
+        let data = base64_decode(&body).ok_or("Invalid base64")?;
+        let data = data
+            .strip_prefix(&b"openssh-key-v1\0"[..])
+            .ok_or("Not an OpenSSH private key")?;
+
+        let mut file = Wire(data);
+        if file.string()? != b"none" {
+            return Err("Encrypted keys are not supported".into());
+        }
+        let _kdf_name = file.string()?;
+        let _kdf_options = file.string()?;
+        if file.u32()? != 1 {
+            return Err("Expected a single key".into());
+        }
+        let public_blob = file.string()?.to_vec();
+
+        let mut private = Wire(file.string()?);
+        if private.u32()? != private.u32()? {
+            return Err("Invalid private key".into());
+        }
+        let key_type = String::from_utf8(private.string()?.to_vec())?;
+        match key_type.as_str() {
+            ECDSA_SK => {
+                let _curve = private.string()?;
+                let _point = private.string()?;
+            }
+            ED25519_SK => {
+                let _public_key = private.string()?;
+            }
+            _ => return Err(format!("Unsupported key type {}", key_type).into()),
+        }
+        let application = CString::new(private.string()?)?;
+        let flags = private.byte()?;
+        let key_handle = private.string()?.to_vec();
+        let _reserved = private.string()?;
+        let comment = String::from_utf8_lossy(private.string()?).into_owned();
+
+        Ok(Key {
+            key_type,
+            public_blob,
+            application,
+            flags,
+            key_handle,
+            comment,
+        })
+    }
+
+    /// Reader of the SSH wire encoding.
+    struct Wire<'a>(&'a [u8]);
+
+    impl<'a> Wire<'a> {
+        fn take(&mut self, n: usize) -> AgentResult<&'a [u8]> {
+            if self.0.len() < n {
+                return Err("Message too short".into());
+            }
+            let (head, tail) = self.0.split_at(n);
+            self.0 = tail;
+            Ok(head)
+        }
+
+        fn byte(&mut self) -> AgentResult<u8> {
+            Ok(self.take(1)?[0])
+        }
+
+        fn u32(&mut self) -> AgentResult<u32> {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(self.take(4)?);
+            Ok(u32::from_be_bytes(bytes))
+        }
+
+        fn string(&mut self) -> AgentResult<&'a [u8]> {
+            let len = self.u32()? as usize;
+            self.take(len)
+        }
+    }
+
+    fn put_u32(out: &mut Vec<u8>, n: u32) {
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+
+    fn put_string(out: &mut Vec<u8>, data: &[u8]) {
+        put_u32(out, data.len() as u32);
+        out.extend_from_slice(data);
+    }
+
+    fn put_mpint(out: &mut Vec<u8>, unsigned: &[u8]) {
+        let start = unsigned.iter().position(|&b| b != 0).unwrap_or(unsigned.len());
+        let unsigned = &unsigned[start..];
+        if unsigned.first().is_some_and(|&b| b & 0x80 != 0) {
+            put_u32(out, unsigned.len() as u32 + 1);
+            out.push(0);
+            out.extend_from_slice(unsigned);
+        } else {
+            put_string(out, unsigned);
+        }
+    }
+
+    fn strip_cbor_bytes_header(data: &[u8]) -> Option<&[u8]> {
+        match data.first()? {
+            0x40..=0x57 => Some(&data[1..]),
+            0x58 => data.get(2..),
+            0x59 => data.get(3..),
+            _ => None,
+        }
+    }
+
+    fn base64_decode(s: &str) -> Option<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut buffer = 0u32;
+        let mut bits = 0;
+        for c in s.bytes().filter(|&c| c != b'=') {
+            let value = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return None,
+            };
+            buffer = buffer << 6 | u32::from(value);
+            bits += 6;
+            if bits >= 8 {
+                bits -= 8;
+                out.push((buffer >> bits) as u8);
+            }
+        }
+        Some(out)
+    }
+}
@@ -0,0 +1,51 @@
+//! A reference broker for sandboxed apps, see `BROKER_SOCKET_ENV`.
+//!
+//! Listens on the socket given as the first argument, and asks on the terminal before
+//! handing out each hidraw node.
+
+#[cfg(target_os = "linux")]
+fn main() {
+    if let Err(err) = broker::run() {
+        eprintln!("broker: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("broker: brokers hand out hidraw nodes, which only exist on Linux");
+}
+
+#[cfg(target_os = "linux")]
+mod broker {
+    use std::{
+        env, fs,
+        io::{self, BufRead, Write},
+        os::unix::net::UnixListener,
+    };
+
+    pub fn run() -> io::Result<()> {
+        let socket = env::args().nth(1).expect("Usage: broker <socket>");
+        let _ = fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket)?;
+        println!(
+            "Set {}={} in the sandbox",
+            libfido2::BROKER_SOCKET_ENV,
+            socket
+        );
+
+        for stream in listener.incoming() {
+            let result = libfido2::serve_broker_request(&mut stream?, |path| {
+                print!("Allow access to {}? [y/N] ", path.display());
+                let _ = io::stdout().flush();
+                let mut answer = String::new();
+                let _ = io::stdin().lock().read_line(&mut answer);
+                answer.trim().eq_ignore_ascii_case("y")
+            });
+            if let Err(e) = result {
+                eprintln!("Request failed: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
@@ -11,15 +11,18 @@ const USER_ID: [u8; 32] = [
     0xe7, 0xa4, 0x2b, 0x44, 0x89, 0x29, 0x39, 0xc5, 0x56, 0x64, 0x01, 0x27, 0x0d, 0xbb, 0xc4, 0x49,
 ];
 
-const USER_NAME: &'static str = "John Doe";
-const RELYING_PARTY_ID: &'static str = "localhost";
-const RELYING_PARTY_NAME: &'static str = "Oost West, Thuis Best";
+const USER_NAME: &str = "John Doe";
+const RELYING_PARTY_ID: &str = "localhost";
+const RELYING_PARTY_NAME: &str = "Oost West, Thuis Best";
 
 pub fn main() {
     // Prepare CStrings
     let relying_party_id = CString::new(RELYING_PARTY_ID).unwrap();
     let relying_party_name = CString::new(RELYING_PARTY_NAME).unwrap();
     let user_name = CString::new(USER_NAME).unwrap();
+    let relying_party = RpEntity::new(&relying_party_id)
+        .unwrap()
+        .with_name(&relying_party_name);
 
     // Initialize library
     let fido = Fido::new(false);
@@ -34,11 +37,10 @@ pub fn main() {
     println!("Mode: {:?}", device.mode());
     println!("CTAPHID info: {:#?}", device.ctap_hid_info());
     println!(
-        "CBOR info: {:#?}",
+        "Authenticator info: {:#?}",
         device
-            .request_cbor_data()
-            .expect("Unable to request CBOR info")
-            .as_ref()
+            .authenticator_info()
+            .expect("Unable to request authenticator info")
     );
 
     // Create a new, non resident credential
@@ -47,17 +49,14 @@ pub fn main() {
         .request_credential_creation(
             fido.new_credential_creator(CredentialCreationData::with_defaults(
                 &CLIENT_DATA_HASH,
-                &relying_party_id,
-                &relying_party_name,
-                &USER_ID,
-                &user_name,
+                relying_party,
+                UserEntity::new(&USER_ID, &user_name).unwrap(),
             ))
             .unwrap(),
             None,
         )
         .unwrap();
-    assert!(credential.verify().is_ok());
-    let credential = credential.as_ref();
+    assert!(credential.credential().verify().is_ok());
     println!("Created credential: {:?}", credential);
 
     // Create a new assertion matching the generated credential
@@ -65,9 +64,9 @@ pub fn main() {
     let assertion = device
         .request_assertion_verification(
             fido.new_assertion_creator(AssertionCreationData::with_defaults(
-                Some(&[credential.id]),
+                Some(&[credential.id().as_bytes()]),
                 &CLIENT_DATA_HASH,
-                &relying_party_id,
+                relying_party,
             ))
             .unwrap(),
             None,
@@ -1,5 +1,7 @@
 fn main() {
-    if cfg!(target_os = "windows") {
+    if cfg!(feature = "vendored") {
+        vendored::build();
+    } else if cfg!(target_os = "windows") {
         let lib_dir = std::env::var("FIDO2_LIB_DIR")
             .expect("Please set the FIDO2_LIB_DIR environment variable");
         println!("cargo:rustc-link-search=native={}", lib_dir);
@@ -12,3 +14,87 @@ fn main() {
         panic!("Unsupported platform");
     }
 }
+
+/// Builds a static libfido2 from source with CMake, so binaries don't depend on the libfido2
+/// version of the system they run on.
+mod vendored {
+    use std::{
+        env,
+        path::{Path, PathBuf},
+        process::Command,
+    };
+
+    pub fn build() {
+        println!("cargo:rerun-if-env-changed=FIDO2_SRC_DIR");
+        println!("cargo:rerun-if-env-changed=FIDO2_STATIC_DEPS");
+        let source = env::var_os("FIDO2_SRC_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("libfido2"));
+        if !source.join("CMakeLists.txt").exists() {
+            panic!(
+                "No libfido2 sources in {}, please set the FIDO2_SRC_DIR environment variable",
+                source.display()
+            );
+        }
+
+        let out = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+        let build = out.join("build");
+        let install = out.join("install");
+        run(Command::new("cmake")
+            .arg("-S")
+            .arg(&source)
+            .arg("-B")
+            .arg(&build)
+            .arg(format!("-DCMAKE_INSTALL_PREFIX={}", install.display()))
+            .arg("-DCMAKE_INSTALL_LIBDIR=lib")
+            .arg("-DCMAKE_BUILD_TYPE=Release")
+            .arg("-DCMAKE_POSITION_INDEPENDENT_CODE=ON")
+            .arg("-DBUILD_SHARED_LIBS=OFF")
+            .arg("-DBUILD_STATIC_LIBS=ON")
+            .arg("-DBUILD_EXAMPLES=OFF")
+            .arg("-DBUILD_MANPAGES=OFF")
+            .arg("-DBUILD_TOOLS=OFF")
+            .arg("-DBUILD_TESTS=OFF"));
+        run(Command::new("cmake")
+            .arg("--build")
+            .arg(&build)
+            .arg("--target")
+            .arg("install"));
+
+        println!("cargo:rustc-link-search=native={}", install.join("lib").display());
+        println!("cargo:rustc-link-lib=static=fido2");
+
+        // The dependencies of libfido2, linked statically if requested and available
+        let kind = if env::var_os("FIDO2_STATIC_DEPS").is_some() {
+            "static"
+        } else {
+            "dylib"
+        };
+        for lib in &["cbor", "crypto", "z"] {
+            println!("cargo:rustc-link-lib={}={}", kind, lib);
+        }
+        // The system libraries libfido2 talks to devices with, for the target instead of the host
+        match env::var("CARGO_CFG_TARGET_OS").as_deref() {
+            Ok("linux") => println!("cargo:rustc-link-lib=dylib=udev"),
+            Ok("macos") => {
+                println!("cargo:rustc-link-lib=framework=IOKit");
+                println!("cargo:rustc-link-lib=framework=CoreFoundation");
+            }
+            Ok("windows") => {
+                for lib in &["hid", "setupapi", "bcrypt", "ws2_32"] {
+                    println!("cargo:rustc-link-lib=dylib={}", lib);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn run(command: &mut Command) {
+        let status = command.status().unwrap_or_else(|err| {
+            panic!("Failed to run cmake, which is needed to build libfido2: {}", err)
+        });
+        if !status.success() {
+            panic!("Building libfido2 failed: {:?} exited with {}", command, status);
+        }
+    }
+}
@@ -144,7 +144,7 @@ fn bindgen_test_layout___sigset_t() {
         concat!("Alignment of ", stringify!(__sigset_t))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<__sigset_t>())).__val as *const _ as usize },
+        ::std::mem::offset_of!(__sigset_t, __val),
         0usize,
         concat!(
             "Offset of field: ",
@@ -239,7 +239,7 @@ fn bindgen_test_layout_fido_dev_io() {
         concat!("Alignment of ", stringify!(fido_dev_io))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<fido_dev_io>())).open as *const _ as usize },
+        ::std::mem::offset_of!(fido_dev_io, open),
         0usize,
         concat!(
             "Offset of field: ",
@@ -249,7 +249,7 @@ fn bindgen_test_layout_fido_dev_io() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<fido_dev_io>())).close as *const _ as usize },
+        ::std::mem::offset_of!(fido_dev_io, close),
         8usize,
         concat!(
             "Offset of field: ",
@@ -259,7 +259,7 @@ fn bindgen_test_layout_fido_dev_io() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<fido_dev_io>())).read as *const _ as usize },
+        ::std::mem::offset_of!(fido_dev_io, read),
         16usize,
         concat!(
             "Offset of field: ",
@@ -269,7 +269,7 @@ fn bindgen_test_layout_fido_dev_io() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<fido_dev_io>())).write as *const _ as usize },
+        ::std::mem::offset_of!(fido_dev_io, write),
         24usize,
         concat!(
             "Offset of field: ",
@@ -299,7 +299,7 @@ fn bindgen_test_layout_fido_dev_transport() {
         concat!("Alignment of ", stringify!(fido_dev_transport))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<fido_dev_transport>())).rx as *const _ as usize },
+        ::std::mem::offset_of!(fido_dev_transport, rx),
         0usize,
         concat!(
             "Offset of field: ",
@@ -309,7 +309,7 @@ fn bindgen_test_layout_fido_dev_transport() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<fido_dev_transport>())).tx as *const _ as usize },
+        ::std::mem::offset_of!(fido_dev_transport, tx),
         8usize,
         concat!(
             "Offset of field: ",
@@ -341,6 +341,24 @@ pub struct fido_cbor_info {
 pub type fido_cbor_info_t = fido_cbor_info;
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
+pub struct fido_credman_metadata {
+    _unused: [u8; 0],
+}
+pub type fido_credman_metadata_t = fido_credman_metadata;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct fido_credman_rk {
+    _unused: [u8; 0],
+}
+pub type fido_credman_rk_t = fido_credman_rk;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct fido_bio_info {
+    _unused: [u8; 0],
+}
+pub type fido_bio_info_t = fido_bio_info;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
 pub struct fido_cred {
     _unused: [u8; 0],
 }
@@ -913,12 +931,48 @@ extern "C" {
         arg3: *const ::std::os::raw::c_char,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn fido_dev_set_pin_minlen(
+        arg1: *mut fido_dev_t,
+        arg2: size_t,
+        arg3: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn fido_dev_set_pin_minlen_rpid(
+        arg1: *mut fido_dev_t,
+        arg2: *const *const ::std::os::raw::c_char,
+        arg3: size_t,
+        arg4: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn fido_dev_force_pin_change(
+        arg1: *mut fido_dev_t,
+        arg2: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn fido_dev_enable_entattest(
+        arg1: *mut fido_dev_t,
+        arg2: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn fido_dev_toggle_always_uv(
+        arg1: *mut fido_dev_t,
+        arg2: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn fido_dev_set_transport_functions(
         arg1: *mut fido_dev_t,
         arg2: *const fido_dev_transport_t,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn fido_dev_io_handle(arg1: *const fido_dev_t) -> *mut ::std::os::raw::c_void;
+}
 extern "C" {
     pub fn fido_dev_set_timeout(
         arg1: *mut fido_dev_t,
@@ -1057,6 +1111,9 @@ extern "C" {
 extern "C" {
     pub fn fido_cbor_info_fwversion(arg1: *const fido_cbor_info_t) -> u64;
 }
+extern "C" {
+    pub fn fido_cbor_info_minpinlen(arg1: *const fido_cbor_info_t) -> u64;
+}
 extern "C" {
     pub fn fido_dev_has_pin(arg1: *const fido_dev_t) -> bool;
 }
@@ -1198,3 +1255,60 @@ extern "C" {
         arg3: size_t,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn fido_credman_metadata_new() -> *mut fido_credman_metadata_t;
+}
+extern "C" {
+    pub fn fido_credman_metadata_free(arg1: *mut *mut fido_credman_metadata_t);
+}
+extern "C" {
+    pub fn fido_credman_get_dev_metadata(
+        arg1: *mut fido_dev_t,
+        arg2: *mut fido_credman_metadata_t,
+        arg3: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn fido_credman_rk_existing(arg1: *const fido_credman_metadata_t) -> u64;
+}
+extern "C" {
+    pub fn fido_credman_rk_remaining(arg1: *const fido_credman_metadata_t) -> u64;
+}
+extern "C" {
+    pub fn fido_credman_rk_new() -> *mut fido_credman_rk_t;
+}
+extern "C" {
+    pub fn fido_credman_rk_free(arg1: *mut *mut fido_credman_rk_t);
+}
+extern "C" {
+    pub fn fido_credman_get_dev_rk(
+        arg1: *mut fido_dev_t,
+        arg2: *const ::std::os::raw::c_char,
+        arg3: *mut fido_credman_rk_t,
+        arg4: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn fido_credman_rk_count(arg1: *const fido_credman_rk_t) -> size_t;
+}
+extern "C" {
+    pub fn fido_credman_rk(arg1: *const fido_credman_rk_t, arg2: size_t) -> *const fido_cred_t;
+}
+extern "C" {
+    pub fn fido_bio_info_new() -> *mut fido_bio_info_t;
+}
+extern "C" {
+    pub fn fido_bio_info_free(arg1: *mut *mut fido_bio_info_t);
+}
+extern "C" {
+    pub fn fido_bio_dev_get_info(
+        arg1: *mut fido_dev_t,
+        arg2: *mut fido_bio_info_t,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn fido_bio_info_type(arg1: *const fido_bio_info_t) -> u8;
+}
+extern "C" {
+    pub fn fido_bio_info_max_samples(arg1: *const fido_bio_info_t) -> u8;
+}
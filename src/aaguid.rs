@@ -0,0 +1,146 @@
+//! AAGUIDs, which identify the model of an authenticator, and the names of well-known models.
+
+use std::{convert::TryFrom, fmt};
+
+/// The 16 byte AAGUID of an authenticator model, e.g. from `CreatedCredential::aaguid` or the
+/// `aag_uid` of `AuthenticatorInfo`.
+///
+/// Formats as a UUID, e.g. `cb69481e-8ff7-4039-93ec-0a2729a154a8`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Aaguid(pub [u8; 16]);
+
+impl Aaguid {
+    /// Copies the AAGUID from `bytes`, or returns `None` if they aren't 16 bytes.
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        <[u8; 16]>::try_from(bytes).ok().map(Aaguid)
+    }
+
+    /// Returns whether the AAGUID is all zeros, which U2F devices and authenticators that hide
+    /// their model, e.g. with `none` attestation, report.
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 16]
+    }
+
+    /// Returns the name of the model, e.g. `"YubiKey 5 Series with NFC"`, if it is a well-known
+    /// one.
+    ///
+    /// # Remarks
+    /// - The names come from an embedded table of the community list of AAGUIDs at
+    ///   <https://github.com/passkeydeveloper/passkey-authenticator-aaguids>, as of this release
+    ///   and without network access. Use FIDO Metadata Service for models not listed, or to trust
+    ///   the name, as AAGUIDs are only authenticated by attestation.
+    #[cfg(feature = "aaguid-names")]
+    pub fn model_name(&self) -> Option<&'static str> {
+        let aaguid = u128::from_be_bytes(self.0);
+        MODELS
+            .iter()
+            .find(|(model, _)| *model == aaguid)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl From<[u8; 16]> for Aaguid {
+    fn from(bytes: [u8; 16]) -> Self {
+        Aaguid(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Aaguid {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Aaguid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if let 4 | 6 | 8 | 10 = i {
+                f.write_str("-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "aaguid-names")]
+const MODELS: &[(u128, &str)] = &[
+    // Yubico
+    (0xcb69481e_8ff7_4039_93ec_0a2729a154a8, "YubiKey 5 Series"),
+    (0xee882879_721c_4913_9775_3dfcce97072a, "YubiKey 5 Series"),
+    (
+        0xfa2b99dc_9e39_4257_8f92_4a30d23c4118,
+        "YubiKey 5 Series with NFC",
+    ),
+    (
+        0x2fc0579f_8113_47ea_b116_bb5a8db9202a,
+        "YubiKey 5 Series with NFC",
+    ),
+    (0xc5ef55ff_ad9a_4b9f_b580_adebafe026d0, "YubiKey 5Ci"),
+    (
+        0x73bb0cd4_e502_49b8_9c6f_b59445bf720b,
+        "YubiKey 5 FIPS Series",
+    ),
+    (
+        0xc1f9a0bc_1dd2_404a_b27f_8e29047a43fd,
+        "YubiKey 5 FIPS Series with NFC",
+    ),
+    (0x85203421_48f9_4355_9bc8_8a53846e5083, "YubiKey 5Ci FIPS"),
+    (0xd8522d9f_575b_4866_88a9_ba99fa02f35b, "YubiKey Bio Series"),
+    (
+        0xf8a011f3_8c0a_4d15_8006_17111f9edc7d,
+        "Security Key by Yubico",
+    ),
+    (
+        0xb92c3f9a_c014_4056_887f_140a2501163b,
+        "Security Key by Yubico",
+    ),
+    (
+        0x6d44ba9b_f6ec_2e49_b930_0c8fe920cb73,
+        "Security Key by Yubico with NFC",
+    ),
+    (
+        0x149a2021_8ef6_4133_96b8_81f8d5b7f1f5,
+        "Security Key by Yubico with NFC",
+    ),
+    (
+        0xa4e9fc6d_4cbe_4758_b8ba_37598bb5bbaa,
+        "Security Key NFC by Yubico",
+    ),
+    (
+        0x0bb43545_fd2c_4185_87dd_feb0b2916ace,
+        "Security Key NFC by Yubico - Enterprise Edition",
+    ),
+    // Google
+    (
+        0x42b4fb4a_2866_43b2_9bf7_6c6669c2e5d3,
+        "Google Titan Security Key v2",
+    ),
+    (
+        0xea9b8d66_4d01_1d21_3ce4_b6b48cb575d4,
+        "Google Password Manager",
+    ),
+    (0xadce0002_35bc_c60a_648b_0b25f1f05503, "Chrome on Mac"),
+    (0xb5397666_4885_aa6b_cebf_e52262a439a2, "Chromium Browser"),
+    // SoloKeys
+    (
+        0x8876631b_d4a0_427f_5773_0ec71c9e0279,
+        "Solo Secp256R1 FIDO2 CTAP2 Authenticator",
+    ),
+    // Platforms and password managers
+    (0x08987058_cadc_4b81_b6e1_30de50dcbe96, "Windows Hello"),
+    (0x9ddd1817_af5a_4672_a2b9_3e3dd95000a9, "Windows Hello"),
+    (0x6028b017_b1d4_4c02_b4b3_afcdafc96bb2, "Windows Hello"),
+    (0xfbfc3007_154e_4ecc_8c0b_6e020557d7bd, "iCloud Keychain"),
+    (
+        0xdd4ec289_e01d_41c9_bb89_70fa845d4bf2,
+        "iCloud Keychain (Managed)",
+    ),
+    (0x771b48fd_d3d4_4f74_9232_fc157ab0507a, "Edge on Mac"),
+    (0x53414d53_554e_4700_0000_000000000000, "Samsung Pass"),
+    (0xbada5566_a7aa_401f_bd96_45619a55120d, "1Password"),
+    (0xd548826e_79b4_db40_a3d8_11116f7e8349, "Bitwarden"),
+    (0x531126d6_e717_415c_9320_3d9aa6981239, "Dashlane"),
+    (0x0ea242b4_43c4_4a1b_8b17_dd6d0b6baec6, "Keeper"),
+    (0xb84e4048_15dc_4dd0_8640_f4f60813c8af, "NordPass"),
+];
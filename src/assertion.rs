@@ -1,7 +1,16 @@
-use crate::{ffi::NonNull, FidoError, PublicKey, Result, FIDO_OK};
+use crate::{
+    auth_data::{AuthData, AuthDataFlags},
+    cbor::Reader,
+    credential::ct_eq,
+    ffi::{bytes, to_size_t, to_usize, NonNull},
+    uv::uvm_entries,
+    validate_icon, CoseAlgorithm, CredBlobInput, CredBlobOutput, CredentialId, ExtensionResults,
+    Extensions, FidoError, HmacSecretInput, HmacSecretOutput, IconPolicy, KeyStore, PublicKey,
+    Result, RpEntity, UserEntity, UvmEntry, FIDO_OK,
+};
 use bitflags::bitflags;
 use libfido2_sys::*;
-use std::{ffi::CStr, os::raw, slice, convert::TryInto};
+use std::{collections::HashMap, ffi::CStr, mem, os::raw};
 
 // Raw assertion is initialized with NULL data
 // Only expose this type when it is properly initialized (returned from device)
@@ -9,8 +18,9 @@ pub struct Assertion {
     pub(crate) raw: NonNull<fido_assert>,
 }
 
-// Wrapper type to safely initialize the assertion with enough information to pass to a device
-pub struct AssertionCreator(Assertion);
+// Wrapper type to safely initialize the assertion with enough information to pass to a device.
+// libfido2 replaces the requested extensions at once, so they are tracked in the second field
+pub struct AssertionCreator(Assertion, raw::c_int);
 
 /// Required information to verify an [`Assertion`] from a `Device`.
 ///
@@ -19,7 +29,12 @@ pub struct AssertionCreator(Assertion);
 pub struct AssertionCreationData<'a> {
     pub allowed_credential_ids: Option<&'a [&'a [u8]]>,
     pub client_data_hash: &'a [u8],
-    pub relying_party_id: &'a CStr,
+    pub relying_party: RpEntity<'a>,
+    /// U2F app ID the credentials were registered under (`appid` extension).
+    ///
+    /// When set, it is sent in place of the relying party ID, so the `rp_id_hash` of the
+    /// statements is the hash of the app ID.
+    pub app_id: Option<&'a CStr>,
     pub options: AssertionOptions,
 }
 
@@ -31,12 +46,13 @@ impl<'a> AssertionCreationData<'a> {
     pub fn with_defaults(
         allowed_credential_ids: Option<&'a [&'a [u8]]>,
         client_data_hash: &'a [u8],
-        relying_party_id: &'a CStr,
+        relying_party: RpEntity<'a>,
     ) -> Self {
         AssertionCreationData {
             allowed_credential_ids,
             client_data_hash,
-            relying_party_id,
+            relying_party,
+            app_id: None,
             options: AssertionOptions::empty(),
         }
     }
@@ -44,12 +60,33 @@ impl<'a> AssertionCreationData<'a> {
 
 /// A statement, contained in an [`Assertion`].
 ///
+/// # Remarks
+/// - The signature algorithm is not part of the statement. It is the algorithm of the credential,
+///   see [`RegistrationRecord::algorithm`].
+///
 /// [`Assertion`]: struct.Assertion.html
+/// [`RegistrationRecord::algorithm`]: struct.RegistrationRecord.html#method.algorithm
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Statement<'a> {
+    pub credential_id: Option<&'a [u8]>,
     pub auth_data: &'a [u8],
     pub client_data_hash: &'a [u8],
     pub hmac_secret: Option<&'a [u8]>,
+    /// The key the large blob of the credential is encrypted with, if it was requested with
+    /// [`AssertionCreator::request_large_blob_key`].
+    ///
+    /// [`AssertionCreator::request_large_blob_key`]: struct.AssertionCreator.html#method.request_large_blob_key
+    #[cfg(feature = "largeblob")]
+    pub large_blob_key: Option<&'a [u8]>,
+    /// The blob stored with the credential, if it was requested with `credBlob`, see
+    /// [`AssertionCreator::set_extensions`].
+    ///
+    /// [`AssertionCreator::set_extensions`]: struct.AssertionCreator.html#method.set_extensions
+    pub cred_blob: Option<&'a [u8]>,
+    /// The signature as returned by the device, which is DER encoded for ECDSA.
+    /// See [`signature_raw`] for the fixed-width encoding.
+    ///
+    /// [`signature_raw`]: struct.Statement.html#method.signature_raw
     pub signature: &'a [u8],
     pub user_id: Option<&'a [u8]>,
     pub user_name: Option<&'a CStr>,
@@ -57,11 +94,170 @@ pub struct Statement<'a> {
     pub user_image_uri: Option<&'a CStr>,
 }
 
+impl<'a> Statement<'a> {
+    /// Groups the user fields into a [`UserEntity`], or returns `None` if the device did not
+    /// return a (valid) user ID.
+    ///
+    /// # Remarks
+    /// - Devices only return the user name, display name and icon for resident credentials,
+    ///   and only after user verification. A missing name is returned as an empty string.
+    ///
+    /// [`UserEntity`]: struct.UserEntity.html
+    pub fn user(&self) -> Option<UserEntity<'a>> {
+        let empty = unsafe { CStr::from_bytes_with_nul_unchecked(b"\0") };
+        let mut user = UserEntity::new(self.user_id?, self.user_name.unwrap_or(empty)).ok()?;
+        if let Some(display_name) = self.user_display_name {
+            user = user.with_display_name(display_name);
+        }
+        if let Some(icon) = self.user_image_uri {
+            user = user.with_icon(icon);
+        }
+        Some(user)
+    }
+
+    /// Returns `user_image_uri` if it is allowed by `policy`, as the device may have been
+    /// provisioned by another party.
+    ///
+    /// [`validate_icon`] returns why an icon is not allowed.
+    ///
+    /// [`validate_icon`]: fn.validate_icon.html
+    pub fn sanitized_user_image_uri(&self, policy: &IconPolicy<'_>) -> Option<&'a str> {
+        validate_icon(self.user_image_uri?, policy).ok()
+    }
+
+    /// Returns the signature in the fixed-width encoding many verification libraries and WebCrypto
+    /// expect: `r || s` with both integers padded to the size of the curve for ECDSA, or the
+    /// signature unchanged for the other algorithms, whose signatures have no DER encoding.
+    ///
+    /// # Arguments
+    /// - `algorithm`: Algorithm of the credential, see the remarks of `Statement`
+    ///
+    /// # Remarks
+    /// - Returns `None` if an ECDSA signature is not a valid DER encoded signature of the curve.
+    pub fn signature_raw(&self, algorithm: CoseAlgorithm) -> Option<Vec<u8>> {
+        let width = match algorithm {
+            CoseAlgorithm::ES256 | CoseAlgorithm::ES256K => 32,
+            CoseAlgorithm::ES384 => 48,
+            CoseAlgorithm::ES512 => 66,
+            _ => return Some(self.signature.to_vec()),
+        };
+        let (r, s) = parse_der_signature(self.signature)?;
+        let mut raw = vec![0; 2 * width];
+        for (integer, out) in [r, s].iter().zip(raw.chunks_mut(width)) {
+            // DER integers are signed, so they may have a leading zero byte
+            let start = integer.iter().position(|&b| b != 0).unwrap_or(integer.len());
+            let integer = &integer[start..];
+            if integer.len() > width {
+                return None;
+            }
+            out[width - integer.len()..].copy_from_slice(integer);
+        }
+        Some(raw)
+    }
+
+    /// Checks, in constant time, whether the statement was made over `client_data_hash`.
+    pub fn client_data_hash_matches(&self, client_data_hash: &[u8]) -> bool {
+        ct_eq(self.client_data_hash, client_data_hash)
+    }
+
+    /// Checks whether the credential of the statement is in the allow list of the request it
+    /// answers, to catch devices that respond with unexpected credentials.
+    ///
+    /// # Remarks
+    /// - Always `true` for requests without allow list, which any resident credential answers.
+    /// - Devices may omit the credential ID if the allow list holds a single credential,
+    ///   which then counts as that credential.
+    pub fn matches_allowed(&self, data: &AssertionCreationData<'_>) -> bool {
+        let allowed = match data.allowed_credential_ids {
+            Some(allowed) => allowed,
+            None => return true,
+        };
+        match self.credential_id {
+            Some(id) => allowed.iter().any(|allowed| ct_eq(allowed, id)),
+            None => allowed.len() == 1,
+        }
+    }
+
+    /// Returns the outputs of the extensions requested with `AssertionCreator::set_extensions`,
+    /// or its other setters.
+    ///
+    /// # Remarks
+    /// - A `credBlob` that is empty is returned as `None`, as libfido2 can't tell it apart from
+    ///   a missing one.
+    pub fn extension_results(&self) -> ExtensionResults<'a> {
+        ExtensionResults {
+            hmac_secret: self
+                .hmac_secret
+                .filter(|secret| !secret.is_empty())
+                .map(HmacSecretOutput::Secret),
+            cred_blob: self.cred_blob.map(CredBlobOutput::Blob),
+            #[cfg(feature = "largeblob")]
+            large_blob_key: self.large_blob_key,
+            #[cfg(feature = "config")]
+            min_pin_length: None,
+            cred_protect: None,
+        }
+    }
+
+    /// Returns the flags of the authenticator data, e.g. whether the user was verified, or
+    /// `None` if it is malformed.
+    ///
+    /// # Remarks
+    /// - Compare `AuthDataFlags::BACKUP_STATE` with the value stored for the credential, which
+    ///   may change between assertions, e.g. once a password manager syncs the credential.
+    pub fn flags(&self) -> Option<AuthDataFlags> {
+        // libfido2 returns the authenticator data wrapped in a CBOR byte string
+        let auth_data = Reader::new(self.auth_data).bytes()?;
+        AuthData::parse(auth_data).map(|data| data.typed_flags())
+    }
+
+    /// Returns the factors the device used to verify the user, from the `uvm` extension, e.g.
+    /// to record them for an audit.
+    ///
+    /// # Remarks
+    /// - libfido2 can't request the extension, so this is only `Some` for devices that return it
+    ///   unrequested.
+    /// - Returns `None` if the extension is missing or malformed.
+    pub fn uvm(&self) -> Option<Vec<UvmEntry>> {
+        // libfido2 returns the authenticator data wrapped in a CBOR byte string
+        uvm_entries(Reader::new(self.auth_data).bytes()?)
+    }
+}
+
+/// Splits a DER encoded ECDSA signature, a SEQUENCE of the INTEGERs `r` and `s`.
+fn parse_der_signature(der: &[u8]) -> Option<(&[u8], &[u8])> {
+    // Returns the contents of the element with `tag` and the remaining input
+    fn element(der: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+        let (len, rest) = match der {
+            [t, len @ 0..=0x7f, rest @ ..] if *t == tag => (usize::from(*len), rest),
+            // Signatures of P-521 need a long form length
+            [t, 0x81, len @ 0x80..=0xff, rest @ ..] if *t == tag => (usize::from(*len), rest),
+            _ => return None,
+        };
+        if rest.len() < len {
+            return None;
+        }
+        Some(rest.split_at(len))
+    }
+
+    let (sequence, rest) = element(der, 0x30)?;
+    let (r, sequence) = element(sequence, 0x02)?;
+    let (s, sequence) = element(sequence, 0x02)?;
+    if rest.is_empty() && sequence.is_empty() {
+        Some((r, s))
+    } else {
+        None
+    }
+}
+
 impl AssertionCreator {
     /// Makes sure the contained assertion is initialized for transfer to a device
     pub(crate) fn new(mut assertion: Assertion, data: AssertionCreationData<'_>) -> Result<Self> {
         // @TODO propagate location of error
-        assertion.set_relying_party_id(data.relying_party_id)?;
+        match data.app_id {
+            Some(app_id) => assertion.set_relying_party_id(app_id)?,
+            None => assertion.set_relying_party_id(data.relying_party.id())?,
+        }
         assertion.set_client_data_hash(data.client_data_hash)?;
         if let Some(allowed) = data.allowed_credential_ids {
             for allowed in allowed {
@@ -69,7 +265,98 @@ impl AssertionCreator {
             }
         }
         assertion.set_options(data.options)?;
-        Ok(AssertionCreator(assertion))
+        Ok(AssertionCreator(assertion, 0))
+    }
+
+    /// Replaces the client data hash of the request with the SHA-256 hash of `client_data`,
+    /// computed by libfido2.
+    pub fn set_client_data(&mut self, client_data: &[u8]) -> Result<()> {
+        self.0.set_client_data(client_data)
+    }
+
+    /// Requests the `hmac-secret` extension, which makes the device return HMAC-SHA-256 of `salt`
+    /// with a secret of the credential in `Statement::hmac_secret`.
+    ///
+    /// # Remarks
+    /// - `salt` must be 32 bytes, or 64 bytes for two outputs.
+    /// - The credential must have been created with `CredentialExtensions::HMAC_SECRET`.
+    pub fn set_hmac_salt(&mut self, salt: &[u8]) -> Result<()> {
+        self.add_extension(FIDO_EXT_HMAC_SECRET as raw::c_int)?;
+        self.0.set_hmac_salt(salt)
+    }
+
+    /// Requests the `largeBlobKey` extension, which makes the device return the key the large
+    /// blob of the credential is encrypted with in `Statement::large_blob_key`.
+    ///
+    /// # Remarks
+    /// - The credential must have been created with `CredentialExtensions::LARGE_BLOB_KEY`,
+    ///   which is only supported for resident credentials.
+    #[cfg(feature = "largeblob")]
+    pub fn request_large_blob_key(&mut self) -> Result<()> {
+        self.add_extension(FIDO_EXT_LARGEBLOB_KEY as raw::c_int)
+    }
+
+    /// Replaces the extensions of the request, including the ones set with [`set_hmac_salt`] and
+    /// [`request_large_blob_key`].
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_INVALID_ARGUMENT` for inputs of credentials: enabling `hmac-secret`, storing
+    ///   a `credBlob`, `minPinLength` or `credProtect`.
+    ///
+    /// [`set_hmac_salt`]: struct.AssertionCreator.html#method.set_hmac_salt
+    /// [`request_large_blob_key`]: struct.AssertionCreator.html#method.request_large_blob_key
+    pub fn set_extensions(&mut self, extensions: &Extensions<'_>) -> Result<()> {
+        let invalid = FidoError(FIDO_ERR_INVALID_ARGUMENT);
+        #[cfg(feature = "config")]
+        if extensions.min_pin_length {
+            return Err(invalid);
+        }
+        if extensions.cred_protect.is_some() {
+            return Err(invalid);
+        }
+        let mut mask = 0;
+        let salt = match extensions.hmac_secret {
+            Some(HmacSecretInput::Salt(salt)) => {
+                mask |= FIDO_EXT_HMAC_SECRET as raw::c_int;
+                Some(salt)
+            }
+            Some(HmacSecretInput::Enable) => return Err(invalid),
+            None => None,
+        };
+        match extensions.cred_blob {
+            Some(CredBlobInput::Get) => mask |= FIDO_EXT_CRED_BLOB as raw::c_int,
+            Some(CredBlobInput::Store(_)) => return Err(invalid),
+            None => {}
+        }
+        #[cfg(feature = "largeblob")]
+        if extensions.large_blob_key {
+            mask |= FIDO_EXT_LARGEBLOB_KEY as raw::c_int;
+        }
+        if let Some(salt) = salt {
+            self.0.set_hmac_salt(salt)?;
+        }
+        // libfido2 adds to the extensions of the request, unless they are cleared first
+        self.0.set_extensions(0)?;
+        self.1 = 0;
+        self.0.set_extensions(mask)?;
+        self.1 = mask;
+        Ok(())
+    }
+
+    fn add_extension(&mut self, extension: raw::c_int) -> Result<()> {
+        self.0.set_extensions(self.1 | extension)?;
+        self.1 |= extension;
+        Ok(())
+    }
+
+    /// Overrides the user presence option, e.g. to make a silent request.
+    pub(crate) fn set_user_presence(&mut self, up: fido_opt_t) -> Result<()> {
+        unsafe {
+            match fido_assert_set_up(self.0.raw.as_ptr_mut(), up) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        }
     }
 
     pub(crate) fn raw(&self) -> &NonNull<fido_assert> {
@@ -92,48 +379,76 @@ impl Assertion {
     /// [statements]: struct.Statement.html
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = Statement<'a>> {
         let assertion = self.raw.as_ptr();
+        // Data missing from a statement is replaced by an empty slice, which fails verification
         let client_data_hash = unsafe {
-            fido_assert_clientdata_hash_ptr(assertion)
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_assert_clientdata_hash_len(assertion).try_into().unwrap()))
-                .unwrap()
+            bytes(
+                fido_assert_clientdata_hash_ptr(assertion),
+                fido_assert_clientdata_hash_len(assertion),
+            )
+            .ok()
+            .flatten()
+            .unwrap_or(&[])
         };
-
-        (0..self.len()).map(move |i| unsafe {
-            let auth_data = fido_assert_authdata_ptr(assertion, i.try_into().unwrap())
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_assert_authdata_len(assertion, i.try_into().unwrap()).try_into().unwrap()))
-                .unwrap();
-
-            let hmac_secret = fido_assert_hmac_secret_ptr(assertion, i.try_into().unwrap())
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_assert_hmac_secret_len(assertion, i.try_into().unwrap()).try_into().unwrap()));
-
-            let signature = fido_assert_sig_ptr(assertion, i.try_into().unwrap())
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_assert_sig_len(assertion, i.try_into().unwrap()).try_into().unwrap()))
-                .unwrap();
-
-            let user_id = fido_assert_user_id_ptr(assertion, i.try_into().unwrap())
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_assert_user_id_len(assertion, i.try_into().unwrap()).try_into().unwrap()));
-
-            let user_name = fido_assert_user_name(assertion, i.try_into().unwrap())
+        let count = unsafe { fido_assert_count(assertion) };
+
+        (0..count).map(move |i| unsafe {
+            let credential_id = bytes(fido_assert_id_ptr(assertion, i), fido_assert_id_len(assertion, i))
+                .ok()
+                .flatten()
+                .filter(|id| !id.is_empty());
+
+            let auth_data = bytes(fido_assert_authdata_ptr(assertion, i), fido_assert_authdata_len(assertion, i))
+                .ok()
+                .flatten()
+                .unwrap_or(&[]);
+
+            let hmac_secret = bytes(fido_assert_hmac_secret_ptr(assertion, i), fido_assert_hmac_secret_len(assertion, i))
+                .ok()
+                .flatten();
+
+            #[cfg(feature = "largeblob")]
+            let large_blob_key = bytes(
+                fido_assert_largeblob_key_ptr(assertion, i),
+                fido_assert_largeblob_key_len(assertion, i),
+            )
+            .ok()
+            .flatten()
+            .filter(|key| !key.is_empty());
+
+            let cred_blob = bytes(fido_assert_blob_ptr(assertion, i), fido_assert_blob_len(assertion, i))
+                .ok()
+                .flatten()
+                .filter(|blob| !blob.is_empty());
+
+            let signature = bytes(fido_assert_sig_ptr(assertion, i), fido_assert_sig_len(assertion, i))
+                .ok()
+                .flatten()
+                .unwrap_or(&[]);
+
+            let user_id = bytes(fido_assert_user_id_ptr(assertion, i), fido_assert_user_id_len(assertion, i))
+                .ok()
+                .flatten();
+
+            let user_name = fido_assert_user_name(assertion, i)
                 .as_ref()
                 .map(|ptr| CStr::from_ptr(ptr));
 
-            let user_display_name = fido_assert_user_display_name(assertion, i.try_into().unwrap())
+            let user_display_name = fido_assert_user_display_name(assertion, i)
                 .as_ref()
                 .map(|ptr| CStr::from_ptr(ptr));
 
-            let user_image_uri = fido_assert_user_icon(assertion, i.try_into().unwrap())
+            let user_image_uri = fido_assert_user_icon(assertion, i)
                 .as_ref()
                 .map(|ptr| CStr::from_ptr(ptr));
 
             Statement {
+                credential_id,
                 auth_data,
                 client_data_hash,
                 hmac_secret,
+                #[cfg(feature = "largeblob")]
+                large_blob_key,
+                cred_blob,
                 signature,
                 user_id,
                 user_name,
@@ -150,17 +465,63 @@ impl Assertion {
         &'a self,
         public_key: PublicKey,
     ) -> impl Iterator<Item = (Statement<'a>, Result<()>)> {
-        let assertion = self.raw.as_ptr();
-        self.iter().enumerate().map(move |(i, statement)| unsafe {
-            match fido_assert_verify(
-                assertion,
-                i.try_into().unwrap(),
-                public_key.credential_type() as raw::c_int,
-                public_key.as_ptr(),
-            ) {
-                FIDO_OK => (statement, Ok(())),
-                err => (statement, Err(FidoError(err))),
-            }
+        self.iter()
+            .enumerate()
+            .map(move |(i, statement)| (statement, self.verify_statement(i, &public_key)))
+    }
+
+    /// Verifies the [statements] of an assertion requested with several allowed credentials.
+    ///
+    /// Every statement is matched by credential ID to one of `keys` and verified with its public key.
+    ///
+    /// # Remarks
+    /// - Keys without a matching statement map to `FIDO_ERR_NOTFOUND`.
+    ///
+    /// [statements]: struct.Statement.html
+    pub fn verify_all(
+        &self,
+        keys: &[(CredentialId, PublicKey)],
+    ) -> HashMap<CredentialId, Result<()>> {
+        keys.iter()
+            .map(|(id, public_key)| {
+                // Visit every statement, so timing doesn't reveal which one matched
+                let result = self
+                    .iter()
+                    .enumerate()
+                    .fold(None, |found, (i, statement)| {
+                        let matches = statement
+                            .credential_id
+                            .is_some_and(|statement_id| id.ct_eq(statement_id));
+                        found.or(if matches { Some(i) } else { None })
+                    })
+                    .map(|i| self.verify_statement(i, public_key))
+                    .unwrap_or(Err(FidoError(FIDO_ERR_NOTFOUND)));
+                (id.clone(), result)
+            })
+            .collect()
+    }
+
+    /// Verifies every [statement] with the public key `keys` stores for its credential ID, and
+    /// returns them as an iterator.
+    ///
+    /// # Remarks
+    /// - Statements without a credential ID, or with an ID unknown to `keys`, map to
+    ///   `FIDO_ERR_NOTFOUND`. Devices may omit the ID if only one credential was allowed, verify
+    ///   those with [`iter_verified`] instead.
+    ///
+    /// [statement]: struct.Statement.html
+    /// [`iter_verified`]: struct.Assertion.html#method.iter_verified
+    pub fn verify_against<'a, K: KeyStore + ?Sized>(
+        &'a self,
+        keys: &'a K,
+    ) -> impl Iterator<Item = (Statement<'a>, Result<()>)> {
+        self.iter().enumerate().map(move |(i, statement)| {
+            let result = statement
+                .credential_id
+                .and_then(|id| keys.public_key(id))
+                .ok_or(FidoError(FIDO_ERR_NOTFOUND))
+                .and_then(|public_key| self.verify_statement(i, &public_key));
+            (statement, result)
         })
     }
 
@@ -174,7 +535,27 @@ impl Assertion {
 
     /// Returns the amount of statements in this assertion.
     pub fn len(&self) -> usize {
-        unsafe { fido_assert_count(self.raw.as_ptr()).try_into().unwrap() }
+        // Saturates, though the statements would not fit in memory if this overflowed
+        unsafe { to_usize(fido_assert_count(self.raw.as_ptr())).unwrap_or(usize::MAX) }
+    }
+
+    /// Returns whether this assertion contains no statements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn verify_statement(&self, idx: usize, public_key: &PublicKey) -> Result<()> {
+        unsafe {
+            match fido_assert_verify(
+                self.raw.as_ptr(),
+                to_size_t(idx)?,
+                public_key.credential_type() as raw::c_int,
+                public_key.as_ptr(),
+            ) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        }
     }
 
     /*
@@ -183,7 +564,7 @@ impl Assertion {
 
     fn set_count(&mut self, n: usize) -> Result<()> {
         unsafe {
-            match fido_assert_set_count(self.raw.as_ptr_mut(), n.try_into().unwrap()) {
+            match fido_assert_set_count(self.raw.as_ptr_mut(), to_size_t(n)?) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
             }
@@ -194,9 +575,9 @@ impl Assertion {
         unsafe {
             match fido_assert_set_authdata(
                 self.raw.as_ptr_mut(),
-                idx.try_into().unwrap(),
+                to_size_t(idx)?,
                 auth_data as *const _ as *const _,
-                auth_data.len().try_into().unwrap(),
+                to_size_t(auth_data.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -208,9 +589,9 @@ impl Assertion {
         unsafe {
             match fido_assert_set_sig(
                 self.raw.as_ptr_mut(),
-                idx.try_into().unwrap(),
+                to_size_t(idx)?,
                 signature as *const _ as *const _,
-                signature.len().try_into().unwrap(),
+                to_size_t(signature.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -240,12 +621,34 @@ impl Assertion {
         }
     }
 
+    fn set_extensions(&mut self, extensions: raw::c_int) -> Result<()> {
+        unsafe {
+            match fido_assert_set_extensions(self.raw.as_ptr_mut(), extensions) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        }
+    }
+
     fn set_hmac_salt(&mut self, hmac_salt: &[u8]) -> Result<()> {
         unsafe {
             match fido_assert_set_hmac_salt(
                 self.raw.as_ptr_mut(),
                 hmac_salt as *const _ as *const _,
-                hmac_salt.len().try_into().unwrap(),
+                to_size_t(hmac_salt.len())?,
+            ) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        }
+    }
+
+    fn set_client_data(&mut self, client_data: &[u8]) -> Result<()> {
+        unsafe {
+            match fido_assert_set_clientdata(
+                self.raw.as_ptr_mut(),
+                client_data as *const _ as *const _,
+                to_size_t(client_data.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -258,7 +661,7 @@ impl Assertion {
             match fido_assert_set_clientdata_hash(
                 self.raw.as_ptr_mut(),
                 client_data_hash as *const _ as *const _,
-                client_data_hash.len().try_into().unwrap(),
+                to_size_t(client_data_hash.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -271,13 +674,49 @@ impl Assertion {
             match fido_assert_allow_cred(
                 self.raw.as_ptr_mut(),
                 id as *const _ as *const _,
-                id.len().try_into().unwrap(),
+                to_size_t(id.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
             }
         }
     }
+
+    /// Returns the underlying `fido_assert_t`, to call libfido2 functions this crate does not wrap.
+    ///
+    /// # Remarks
+    /// - The pointer is valid for as long as `self` is, and must not be freed.
+    pub fn as_raw(&self) -> *const fido_assert_t {
+        self.raw.as_ptr()
+    }
+
+    /// Returns the underlying `fido_assert_t` mutably. See [`as_raw`].
+    ///
+    /// [`as_raw`]: struct.Assertion.html#method.as_raw
+    pub fn as_raw_mut(&mut self) -> *mut fido_assert_t {
+        self.raw.as_ptr_mut()
+    }
+
+    /// Gives up ownership of the underlying `fido_assert_t`.
+    ///
+    /// # Remarks
+    /// - The caller is responsible for freeing it with `fido_assert_free`, or with [`from_raw`].
+    ///
+    /// [`from_raw`]: struct.Assertion.html#method.from_raw
+    pub fn into_raw(self) -> *mut fido_assert_t {
+        let mut assertion = mem::ManuallyDrop::new(self);
+        assertion.raw.as_ptr_mut()
+    }
+
+    /// Takes ownership of a `fido_assert_t`, which is freed on drop.
+    ///
+    /// # Safety
+    /// - `raw` must be a valid `fido_assert_t` allocated by libfido2, which is not owned by anything
+    ///   else.
+    /// - Its response fields must be set, as by a successful `fido_dev_get_assert`.
+    pub unsafe fn from_raw(raw: *mut fido_assert_t) -> Option<Self> {
+        NonNull::new(raw).map(|raw| Assertion { raw })
+    }
 }
 
 // libfido2_sys guarantees this.
@@ -289,7 +728,7 @@ impl Drop for Assertion {
         unsafe {
             let mut assertion = self.raw.as_ptr_mut();
             fido_assert_free(&mut assertion as *mut _);
-            assert!(assertion.is_null());
+            debug_assert!(assertion.is_null());
         }
     }
 }
@@ -305,3 +744,126 @@ bitflags! {
         const USER_VERIFICATION = 2;
     }
 }
+
+flag_names!(AssertionOptions {
+    "up" => USER_PRESENCE,
+    "uv" => USER_VERIFICATION,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statement(signature: &[u8]) -> Statement<'_> {
+        Statement {
+            credential_id: None,
+            auth_data: &[],
+            client_data_hash: &[],
+            hmac_secret: None,
+            #[cfg(feature = "largeblob")]
+            large_blob_key: None,
+            cred_blob: None,
+            signature,
+            user_id: None,
+            user_name: None,
+            user_display_name: None,
+            user_image_uri: None,
+        }
+    }
+
+    // Encodes `r` and `s` as a DER SEQUENCE, with long form lengths from 0x80 on
+    fn der(r: &[u8], s: &[u8]) -> Vec<u8> {
+        fn element(tag: u8, contents: &[u8], out: &mut Vec<u8>) {
+            out.push(tag);
+            if contents.len() >= 0x80 {
+                out.push(0x81);
+            }
+            out.push(contents.len() as u8);
+            out.extend_from_slice(contents);
+        }
+        let mut sequence = Vec::new();
+        element(0x02, r, &mut sequence);
+        element(0x02, s, &mut sequence);
+        let mut der = Vec::new();
+        element(0x30, &sequence, &mut der);
+        der
+    }
+
+    #[test]
+    fn pads_es256_integers() {
+        // A leading zero for a high bit, and a short `s`
+        let mut r = vec![0];
+        r.extend_from_slice(&[0x80; 32]);
+        let signature = der(&r, &[1]);
+        let raw = statement(&signature).signature_raw(CoseAlgorithm::ES256).unwrap();
+        let mut expected = vec![0x80; 32];
+        expected.extend_from_slice(&[0; 31]);
+        expected.push(1);
+        assert_eq!(raw, expected);
+
+        // Zero is an empty integer after stripping its zero bytes
+        let signature = der(&[0], &[0, 0]);
+        let raw = statement(&signature).signature_raw(CoseAlgorithm::ES256).unwrap();
+        assert_eq!(raw, vec![0; 64]);
+    }
+
+    #[test]
+    fn p521_signatures_use_long_form_lengths() {
+        let mut r = vec![0, 0x01];
+        r.extend_from_slice(&[0xff; 65]);
+        let s = [0x7f; 66];
+        let signature = der(&r, &s);
+        assert_eq!(&signature[..3], &[0x30, 0x81, 0x89]);
+        let raw = statement(&signature).signature_raw(CoseAlgorithm::ES512).unwrap();
+        assert_eq!(raw.len(), 132);
+        assert_eq!(&raw[..66], &r[1..]);
+        assert_eq!(&raw[66..], &s[..]);
+    }
+
+    #[test]
+    fn rejects_integers_wider_than_the_curve() {
+        let signature = der(&[1; 32], &[1; 33]);
+        assert_eq!(statement(&signature).signature_raw(CoseAlgorithm::ES256), None);
+        let signature = der(&[1; 67], &[1; 66]);
+        assert_eq!(statement(&signature).signature_raw(CoseAlgorithm::ES512), None);
+        let signature = der(&[1; 48], &[1; 48]);
+        assert!(statement(&signature).signature_raw(CoseAlgorithm::ES384).is_some());
+    }
+
+    #[test]
+    fn rejects_malformed_der() {
+        let signature = der(&[1; 32], &[1; 32]);
+        for len in 0..signature.len() {
+            assert_eq!(parse_der_signature(&signature[..len]), None, "{}", len);
+        }
+        let mut trailing = signature.clone();
+        trailing.push(0);
+        assert_eq!(parse_der_signature(&trailing), None);
+
+        // Lengths over-long for their input, or in long form when the short one fits
+        let mut over_long = signature.clone();
+        over_long[1] += 1;
+        assert_eq!(parse_der_signature(&over_long), None);
+        assert_eq!(parse_der_signature(&[0x30, 0x81, 0x06, 2, 1, 1, 2, 1, 1]), None);
+        assert_eq!(parse_der_signature(&[0x30, 0x82, 0x00, 0x06, 2, 1, 1, 2, 1, 1]), None);
+
+        // Wrong tags, and a third integer
+        assert_eq!(parse_der_signature(&[0x31, 0x06, 2, 1, 1, 2, 1, 1]), None);
+        assert_eq!(parse_der_signature(&[0x30, 0x06, 2, 1, 1, 4, 1, 1]), None);
+        let third = [0x30, 0x09, 2, 1, 1, 2, 1, 1, 2, 1, 1];
+        assert_eq!(parse_der_signature(&third), None);
+        assert_eq!(
+            parse_der_signature(&[0x30, 0x06, 2, 1, 1, 2, 1, 1]),
+            Some((&[1][..], &[1][..]))
+        );
+    }
+
+    #[test]
+    fn other_algorithms_are_unchanged() {
+        let signature = [0x30, 0x00];
+        for algorithm in [CoseAlgorithm::EDDSA, CoseAlgorithm::RS256, CoseAlgorithm::PS256] {
+            let raw = statement(&signature).signature_raw(algorithm);
+            assert_eq!(raw.as_deref(), Some(&signature[..]));
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Attestation verification policies for registration ceremonies.
+//!
+//! A [`Verifier`] decides whether the attestation of a newly created credential is acceptable,
+//! see `Credential::verify_attestation`.
+//!
+//! [`Verifier`]: trait.Verifier.html
+
+use crate::{openssl, Credential, FidoError};
+use std::{error, fmt};
+
+const PACKED: &[u8] = b"packed";
+
+/// A policy for the attestation of credentials.
+pub trait Verifier {
+    /// Verifies the attestation of `credential`.
+    fn verify(&self, credential: &Credential) -> Result<(), AttestationError>;
+}
+
+/// Accepts any attestation, including none at all.
+///
+/// # Remarks
+/// - Use this when the authenticator model doesn't matter, and the credential is only trusted
+///   because it was registered over an authenticated channel.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct None;
+
+impl Verifier for None {
+    fn verify(&self, _credential: &Credential) -> Result<(), AttestationError> {
+        Ok(())
+    }
+}
+
+/// Requires `packed` self attestation: a signature by the credential key itself, without
+/// certificates.
+///
+/// # Remarks
+/// - This only proves possession of the credential key, not anything about the authenticator.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelfAttestation;
+
+impl Verifier for SelfAttestation {
+    fn verify(&self, credential: &Credential) -> Result<(), AttestationError> {
+        check_format(credential, PACKED)?;
+        if !credential.x5c().is_empty() {
+            return Err(AttestationError::UnexpectedCertificate);
+        }
+        credential
+            .verify_self()
+            .map_err(AttestationError::InvalidSignature)
+    }
+}
+
+/// Requires `packed` attestation with a certificate chain up to one of the given root certificates.
+///
+/// # Remarks
+/// - The attestation signature is verified with the attestation certificate, which must chain to
+///   one of the roots through the intermediates in the attestation statement.
+/// - Certificates are verified by OpenSSL, which libfido2 depends on, including their validity
+///   period.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedWithChainTo {
+    roots: Vec<Vec<u8>>,
+}
+
+impl PackedWithChainTo {
+    /// Trusts the given DER encoded root certificates.
+    pub fn new<I, C>(root_certs: I) -> Self
+    where
+        I: IntoIterator<Item = C>,
+        C: Into<Vec<u8>>,
+    {
+        PackedWithChainTo {
+            roots: root_certs.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Verifier for PackedWithChainTo {
+    fn verify(&self, credential: &Credential) -> Result<(), AttestationError> {
+        check_format(credential, PACKED)?;
+        let chain = credential.x5c();
+        if chain.is_empty() {
+            return Err(AttestationError::MissingCertificate);
+        }
+        credential
+            .verify()
+            .map_err(AttestationError::InvalidSignature)?;
+        unsafe { openssl::verify_chain(&chain, &self.roots) }
+    }
+}
+
+/// Accepts only credentials of specific authenticator models, identified by their AAGUID, whose
+/// attestation chains to one of the pinned root certificates.
+///
+/// # Remarks
+/// - The AAGUID is claimed by the authenticator, and only trustworthy because the attestation
+///   is verified. Pin the roots of the vendors of the allowed models only.
+/// - Authenticators without attestation, e.g. U2F devices, report an all zero AAGUID, which is
+///   rejected unless it is allowed explicitly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModelPolicy {
+    aaguids: Vec<Vec<u8>>,
+    chain: PackedWithChainTo,
+}
+
+/// The decision of a [`ModelPolicy`], with its rationale for audit logs.
+///
+/// [`ModelPolicy`]: struct.ModelPolicy.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decision {
+    /// The AAGUID the credential reported.
+    pub aaguid: Vec<u8>,
+    /// Why the credential was rejected, or `Ok` if it was accepted.
+    pub result: Result<(), AttestationError>,
+}
+
+impl ModelPolicy {
+    /// Trusts the given DER encoded root certificates, for no model yet.
+    pub fn new<I, C>(root_certs: I) -> Self
+    where
+        I: IntoIterator<Item = C>,
+        C: Into<Vec<u8>>,
+    {
+        ModelPolicy {
+            aaguids: Vec::new(),
+            chain: PackedWithChainTo::new(root_certs),
+        }
+    }
+
+    /// Allows the model with the 16 byte `aaguid`.
+    pub fn with_aaguid(mut self, aaguid: impl Into<Vec<u8>>) -> Self {
+        self.aaguids.push(aaguid.into());
+        self
+    }
+
+    /// Evaluates the policy for a newly created credential, see `CreatedCredential::credential`.
+    pub fn evaluate(&self, credential: &Credential) -> Decision {
+        Decision {
+            aaguid: credential.aaguid().to_vec(),
+            result: self.verify(credential),
+        }
+    }
+}
+
+impl Verifier for ModelPolicy {
+    fn verify(&self, credential: &Credential) -> Result<(), AttestationError> {
+        let aaguid = credential.aaguid();
+        if !self.aaguids.iter().any(|allowed| allowed.as_slice() == aaguid) {
+            return Err(AttestationError::UnexpectedAaguid(aaguid.to_vec()));
+        }
+        self.chain.verify(credential)
+    }
+}
+
+impl Decision {
+    pub fn is_accepted(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    /// Returns why the credential was accepted or rejected.
+    pub fn rationale(&self) -> String {
+        match &self.result {
+            Ok(()) => format!(
+                "The authenticator model {} is allowed, and its attestation is trusted",
+                hex(&self.aaguid)
+            ),
+            Err(error) => error.to_string(),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn check_format(credential: &Credential, expected: &[u8]) -> Result<(), AttestationError> {
+    let format = credential
+        .try_as_ref()
+        .map_err(AttestationError::InvalidSignature)?
+        .format;
+    if format.to_bytes() == expected {
+        Ok(())
+    } else {
+        Err(AttestationError::UnexpectedFormat(
+            format.to_string_lossy().into_owned(),
+        ))
+    }
+}
+
+/// How much of the attestation of a created credential to keep, as the attestation conveyance
+/// preference of WebAuthn. See `Device::request_credential_creation_with_policy`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Conveyance {
+    /// The attestation is stripped, see `Credential::without_attestation`.
+    None,
+    /// The attestation is kept, and credentials of devices without one (`none`) are accepted.
+    Indirect,
+    /// The attestation is kept, and credentials of devices without one (`none`) are rejected.
+    Direct,
+}
+
+/// Reasons why an attestation was rejected by a [`Verifier`].
+///
+/// [`Verifier`]: trait.Verifier.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttestationError {
+    /// The attestation statement has a different format than the policy requires.
+    UnexpectedFormat(String),
+    /// The policy requires an attestation certificate, but there is none.
+    MissingCertificate,
+    /// The policy requires self attestation, but there is a certificate.
+    UnexpectedCertificate,
+    /// The attestation signature is invalid.
+    InvalidSignature(FidoError),
+    /// A certificate could not be parsed.
+    InvalidCertificate,
+    /// The certificate chain does not lead to a trusted root, with the reason given by OpenSSL.
+    UntrustedChain(String),
+    /// The authenticator model, identified by this AAGUID, is not allowed.
+    UnexpectedAaguid(Vec<u8>),
+}
+
+impl error::Error for AttestationError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            AttestationError::InvalidSignature(error) => Some(error),
+            _ => Option::None,
+        }
+    }
+}
+
+impl fmt::Display for AttestationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttestationError::UnexpectedFormat(format) => {
+                write!(f, "The attestation format `{}` is not allowed", format)
+            }
+            AttestationError::MissingCertificate => write!(f, "The attestation certificate is missing"),
+            AttestationError::UnexpectedCertificate => {
+                write!(f, "The attestation has a certificate, but self attestation is required")
+            }
+            AttestationError::InvalidSignature(error) => {
+                write!(f, "The attestation signature is invalid: {}", error)
+            }
+            AttestationError::InvalidCertificate => write!(f, "The attestation certificate is invalid"),
+            AttestationError::UntrustedChain(reason) => {
+                write!(f, "The attestation certificate is not trusted: {}", reason)
+            }
+            AttestationError::UnexpectedAaguid(aaguid) => {
+                write!(f, "The authenticator model {} is not allowed", hex(aaguid))
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{openssl, trace::OperationSpan, Device, FidoError};
+use std::{
+    ffi::CStr,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+// Identifies requests within the process, starting at 1
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Receives an [`AuditRecord`] for every operation of a `Device` that uses a credential or
+/// changes the device, e.g. to keep an audit trail.
+///
+/// # Remarks
+/// - Implemented for closures.
+/// - Records are delivered on the thread making the request, after the device answered. Sinks
+///   that write to slow storage should hand the record off, e.g. over a channel.
+/// - Reading data, such as the `AuthenticatorInfo`, is not recorded.
+///
+/// [`AuditRecord`]: struct.AuditRecord.html
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord<'_>);
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&AuditRecord<'_>) + Send + Sync,
+{
+    fn record(&self, record: &AuditRecord<'_>) {
+        self(record)
+    }
+}
+
+/// An operation recorded by an [`AuditSink`].
+///
+/// [`AuditSink`]: trait.AuditSink.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AuditOperation {
+    CreateCredential,
+    GetAssertion,
+    /// A silent assertion, checking whether the device holds a credential.
+    CheckCredential,
+    /// Setting or changing the PIN.
+    SetPin,
+    #[cfg(feature = "config")]
+    SetMinPinLength,
+    #[cfg(feature = "config")]
+    SetMinPinLengthRpIds,
+    #[cfg(feature = "config")]
+    ForcePinChange,
+    #[cfg(feature = "config")]
+    EnableEnterpriseAttestation,
+    #[cfg(feature = "config")]
+    ToggleAlwaysUv,
+    /// An `authenticatorConfig` request sent with `Device::send_config_command`.
+    #[cfg(feature = "config")]
+    Config(crate::ConfigSubcommand),
+    /// A raw CTAP2 command sent with `Device::ctap2_command`.
+    Ctap2Command(u8),
+    Reset,
+}
+
+impl fmt::Display for AuditOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditOperation::CreateCredential => write!(f, "create credential"),
+            AuditOperation::GetAssertion => write!(f, "get assertion"),
+            AuditOperation::CheckCredential => write!(f, "check credential"),
+            AuditOperation::SetPin => write!(f, "set PIN"),
+            #[cfg(feature = "config")]
+            AuditOperation::SetMinPinLength => write!(f, "set minimum PIN length"),
+            #[cfg(feature = "config")]
+            AuditOperation::SetMinPinLengthRpIds => write!(f, "set minimum PIN length RP IDs"),
+            #[cfg(feature = "config")]
+            AuditOperation::ForcePinChange => write!(f, "force PIN change"),
+            #[cfg(feature = "config")]
+            AuditOperation::EnableEnterpriseAttestation => {
+                write!(f, "enable enterprise attestation")
+            }
+            #[cfg(feature = "config")]
+            AuditOperation::ToggleAlwaysUv => write!(f, "toggle alwaysUv"),
+            #[cfg(feature = "config")]
+            AuditOperation::Config(subcommand) => write!(f, "config {:#04x}", subcommand.id()),
+            AuditOperation::Ctap2Command(command) => write!(f, "CTAP2 command {:#04x}", command),
+            AuditOperation::Reset => write!(f, "reset"),
+        }
+    }
+}
+
+/// A device operation, as passed to an [`AuditSink`].
+///
+/// [`AuditSink`]: trait.AuditSink.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord<'a> {
+    /// Identifies the request within the process. Requests made by one call, e.g. the assertion of
+    /// `Device::register_and_confirm`, are recorded with their own IDs.
+    pub request_id: u64,
+    pub operation: AuditOperation,
+    /// SHA-256 of the relying party ID, for operations on a credential. The ID itself is left out,
+    /// so audit logs don't reveal which services a user has accounts with.
+    pub rp_id_hash: Option<[u8; 32]>,
+    /// The credential that was created or used, if the device reported it.
+    pub credential_id: Option<&'a [u8]>,
+    pub result: Result<(), FidoError>,
+    /// When the request was sent.
+    pub started: SystemTime,
+    /// How long the device took to answer, including waiting for the user.
+    pub duration: Duration,
+}
+
+/// Collects an [`AuditRecord`] while an operation runs.
+///
+/// [`AuditRecord`]: struct.AuditRecord.html
+pub(crate) struct Audit {
+    sink: Option<Arc<dyn AuditSink>>,
+    request_id: u64,
+    operation: AuditOperation,
+    rp_id_hash: Option<[u8; 32]>,
+    started: SystemTime,
+    start: Instant,
+    span: OperationSpan,
+}
+
+impl Audit {
+    /// Starts recording `operation` of `device`, which does nothing without an audit sink, other
+    /// than its tracing span.
+    pub(crate) fn start(device: &Device, operation: AuditOperation, rp_id: Option<&CStr>) -> Self {
+        let rp_id_hash = match device.audit {
+            Some(_) => rp_id.and_then(|rp_id| openssl::sha256(rp_id.to_bytes()).ok()),
+            None => None,
+        };
+        Audit {
+            sink: device.audit.clone(),
+            request_id: NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed),
+            operation,
+            rp_id_hash,
+            started: SystemTime::now(),
+            start: Instant::now(),
+            span: OperationSpan::start(&device.span, &operation, rp_id),
+        }
+    }
+
+    /// Passes the record of the finished operation to the sink, and closes its span.
+    pub(crate) fn finish(self, credential_id: Option<&[u8]>, result: Result<(), FidoError>) {
+        self.span.finish(result);
+        if let Some(sink) = self.sink {
+            sink.record(&AuditRecord {
+                request_id: self.request_id,
+                operation: self.operation,
+                rp_id_hash: self.rp_id_hash,
+                credential_id,
+                result,
+                started: self.started,
+                duration: self.start.elapsed(),
+            });
+        }
+    }
+}
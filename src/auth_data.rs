@@ -0,0 +1,282 @@
+use crate::cbor::Reader;
+use bitflags::bitflags;
+use libfido2_sys::*;
+use std::convert::TryInto;
+
+const RP_ID_HASH_LEN: usize = 32;
+const AAGUID_LEN: usize = 16;
+
+bitflags! {
+    /// The flags of authenticator data, e.g. from `Statement::flags`.
+    ///
+    /// # Remarks
+    /// - Relying parties supporting passkeys should store `BACKUP_ELIGIBLE` and `BACKUP_STATE`
+    ///   with the credential at registration, and update `BACKUP_STATE` after authentication.
+    ///   Backed up credentials are synced, e.g. by a password manager, and not bound to a device.
+    pub struct AuthDataFlags: u8 {
+        /// The user was present, e.g. touched the device.
+        const USER_PRESENT = CTAP_AUTHDATA_USER_PRESENT as u8;
+        /// The user was verified, e.g. by PIN or fingerprint.
+        const USER_VERIFIED = CTAP_AUTHDATA_USER_VERIFIED as u8;
+        /// The credential may be backed up. Set when the credential is created, and doesn't
+        /// change afterwards.
+        const BACKUP_ELIGIBLE = 0x08;
+        /// The credential is currently backed up.
+        const BACKUP_STATE = 0x10;
+        /// Attested credential data is included, only when the credential is created.
+        const ATTESTED_CREDENTIAL_DATA = CTAP_AUTHDATA_ATT_CRED as u8;
+        /// Extension outputs are included.
+        const EXTENSION_DATA = CTAP_AUTHDATA_EXT_DATA as u8;
+    }
+}
+
+flag_names!(AuthDataFlags {
+    "up" => USER_PRESENT,
+    "uv" => USER_VERIFIED,
+    "be" => BACKUP_ELIGIBLE,
+    "bs" => BACKUP_STATE,
+    "at" => ATTESTED_CREDENTIAL_DATA,
+    "ed" => EXTENSION_DATA,
+});
+
+/// Parsed view over raw (not CBOR wrapped) authenticator data.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct AuthData<'a> {
+    pub rp_id_hash: &'a [u8],
+    pub flags: u8,
+    pub sign_count: u32,
+    pub attested_credential: Option<AttestedCredential<'a>>,
+    pub extensions: Option<&'a [u8]>,
+}
+
+/// Attested credential data, only present in authenticator data returned from credential creation.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct AttestedCredential<'a> {
+    pub aaguid: &'a [u8],
+    pub id: &'a [u8],
+    pub public_key: &'a [u8],
+}
+
+impl<'a> AuthData<'a> {
+    /// Parses raw authenticator data, returning `None` if it is malformed.
+    pub(crate) fn parse(data: &'a [u8]) -> Option<Self> {
+        let (rp_id_hash, data) = split(data, RP_ID_HASH_LEN)?;
+        let (flags, data) = split(data, 1)?;
+        let flags = flags[0];
+        let (sign_count, mut data) = split(data, 4)?;
+        let sign_count = u32::from_be_bytes(sign_count.try_into().ok()?);
+
+        let attested_credential = if flags & CTAP_AUTHDATA_ATT_CRED as u8 != 0 {
+            let (aaguid, rest) = split(data, AAGUID_LEN)?;
+            let (id_len, rest) = split(rest, 2)?;
+            let (id, rest) = split(rest, u16::from_be_bytes(id_len.try_into().ok()?).into())?;
+            let mut reader = Reader::new(rest);
+            let public_key = reader.skip()?;
+            data = reader.remaining();
+            Some(AttestedCredential {
+                aaguid,
+                id,
+                public_key,
+            })
+        } else {
+            None
+        };
+
+        let extensions = if flags & CTAP_AUTHDATA_EXT_DATA as u8 != 0 {
+            let mut reader = Reader::new(data);
+            let extensions = reader.skip()?;
+            data = reader.remaining();
+            Some(extensions)
+        } else {
+            None
+        };
+
+        // Trailing bytes are not allowed
+        if !data.is_empty() {
+            return None;
+        }
+
+        Some(AuthData {
+            rp_id_hash,
+            flags,
+            sign_count,
+            attested_credential,
+            extensions,
+        })
+    }
+
+    /// Returns the flags, without the reserved bits.
+    pub(crate) fn typed_flags(&self) -> AuthDataFlags {
+        AuthDataFlags::from_bits_truncate(self.flags)
+    }
+
+    /// Looks up the output of extension `name`, returning a reader positioned at its value.
+    pub(crate) fn extension(&self, name: &str) -> Option<Reader<'a>> {
+        Reader::new(self.extensions?).find(name)
+    }
+}
+
+/// Overwrites the AAGUID in raw authenticator data with zeros, returning whether it had one.
+pub(crate) fn clear_aaguid(data: &mut [u8]) -> bool {
+    const OFFSET: usize = RP_ID_HASH_LEN + 1 + 4;
+
+    let has_aaguid = AuthData::parse(data).is_some_and(|data| data.attested_credential.is_some());
+    if has_aaguid {
+        data[OFFSET..OFFSET + AAGUID_LEN].fill(0);
+    }
+    has_aaguid
+}
+
+fn split(data: &[u8], at: usize) -> Option<(&[u8], &[u8])> {
+    if data.len() < at {
+        None
+    } else {
+        Some(data.split_at(at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cbor::Writer;
+    use quickcheck::quickcheck;
+
+    #[test]
+    fn parses_header() {
+        fn prop(mut rp_id_hash: Vec<u8>, flags: u8, sign_count: u32) -> bool {
+            rp_id_hash.resize(RP_ID_HASH_LEN, 0);
+            // Without attested credential data and extensions, nothing follows the header
+            let flags = flags & !(CTAP_AUTHDATA_ATT_CRED | CTAP_AUTHDATA_EXT_DATA) as u8;
+            let mut data = rp_id_hash.clone();
+            data.push(flags);
+            data.extend_from_slice(&sign_count.to_be_bytes());
+            let mut trailing = data.clone();
+            trailing.push(0);
+
+            let parsed = match AuthData::parse(&data) {
+                Some(parsed) => parsed,
+                None => return false,
+            };
+            parsed.rp_id_hash == rp_id_hash.as_slice()
+                && parsed.flags == flags
+                && parsed.sign_count == sign_count
+                && parsed.attested_credential.is_none()
+                && parsed.extensions.is_none()
+                && AuthData::parse(&trailing).is_none()
+        }
+        quickcheck(prop as fn(Vec<u8>, u8, u32) -> bool);
+    }
+
+    fn header(flags: u8) -> Vec<u8> {
+        let mut data = vec![0xaa; RP_ID_HASH_LEN];
+        data.push(flags);
+        data.extend_from_slice(&7u32.to_be_bytes());
+        data
+    }
+
+    fn encode(write: impl FnOnce(&mut Writer)) -> Vec<u8> {
+        let mut writer = Writer::new();
+        write(&mut writer);
+        writer.into_bytes()
+    }
+
+    fn attested(id: &[u8]) -> Vec<u8> {
+        let mut data = header(CTAP_AUTHDATA_ATT_CRED as u8);
+        data.extend_from_slice(&[0x55; AAGUID_LEN]);
+        data.extend_from_slice(&(id.len() as u16).to_be_bytes());
+        data.extend_from_slice(id);
+        data.extend_from_slice(&encode(|w| {
+            w.map(1).int(1).int(2);
+        }));
+        data
+    }
+
+    #[test]
+    fn header_boundaries() {
+        let data = header(0);
+        assert_eq!(data.len(), 37);
+        assert!(AuthData::parse(&data[..36]).is_none());
+        assert!(AuthData::parse(&[]).is_none());
+        let parsed = AuthData::parse(&data).unwrap();
+        assert_eq!(parsed.sign_count, 7);
+        // Reserved bits are kept raw, but not typed
+        let data = header(0x22);
+        let parsed = AuthData::parse(&data).unwrap();
+        assert_eq!(parsed.flags, 0x22);
+        assert_eq!(parsed.typed_flags(), AuthDataFlags::empty());
+    }
+
+    #[test]
+    fn attested_credential_boundaries() {
+        let data = attested(&[1, 2, 3]);
+        let credential = AuthData::parse(&data).unwrap().attested_credential.unwrap();
+        assert_eq!(credential.aaguid, &[0x55; AAGUID_LEN]);
+        assert_eq!(credential.id, &[1, 2, 3]);
+        assert_eq!(credential.public_key, &[0xa1, 0x01, 0x02]);
+
+        // Truncated anywhere, including inside the public key
+        for len in 37..data.len() {
+            assert!(AuthData::parse(&data[..len]).is_none(), "{}", len);
+        }
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert!(AuthData::parse(&trailing).is_none());
+
+        // An ID length past the end of the data
+        let mut over_long = data;
+        over_long[37 + AAGUID_LEN..37 + AAGUID_LEN + 2].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(AuthData::parse(&over_long).is_none());
+
+        // The longest ID
+        let id = vec![0; u16::MAX.into()];
+        let data = attested(&id);
+        let credential = AuthData::parse(&data).unwrap().attested_credential.unwrap();
+        assert_eq!(credential.id.len(), id.len());
+    }
+
+    #[test]
+    fn extensions() {
+        let mut data = header(CTAP_AUTHDATA_EXT_DATA as u8);
+        data.extend_from_slice(&encode(|w| {
+            w.map(1).text("credProtect").uint(2);
+        }));
+        let parsed = AuthData::parse(&data).unwrap();
+        assert_eq!(parsed.extension("credProtect").unwrap().uint(), Some(2));
+        assert!(parsed.extension("hmac-secret").is_none());
+
+        // The flag without extensions, and extensions without the flag
+        assert!(AuthData::parse(&header(CTAP_AUTHDATA_EXT_DATA as u8)).is_none());
+        data[RP_ID_HASH_LEN] = 0;
+        assert!(AuthData::parse(&data).is_none());
+
+        // A map claiming more entries than there are
+        let mut data = header(CTAP_AUTHDATA_EXT_DATA as u8);
+        data.extend_from_slice(&encode(|w| {
+            w.map(2).text("credProtect").uint(2);
+        }));
+        assert!(AuthData::parse(&data).is_none());
+    }
+
+    #[test]
+    fn clears_only_present_aaguids() {
+        let mut data = attested(&[1]);
+        assert!(clear_aaguid(&mut data));
+        let credential = AuthData::parse(&data).unwrap().attested_credential.unwrap();
+        assert_eq!(credential.aaguid, &[0; AAGUID_LEN]);
+
+        let mut data = header(0);
+        data.extend_from_slice(&[0x55; AAGUID_LEN]);
+        assert!(!clear_aaguid(&mut data));
+        assert_eq!(&data[37..], &[0x55; AAGUID_LEN]);
+    }
+
+    #[test]
+    fn arbitrary_input_does_not_panic() {
+        fn prop(mut data: Vec<u8>) -> bool {
+            let _ = AuthData::parse(&data).map(|data| data.extension("credProtect"));
+            let _ = clear_aaguid(&mut data);
+            true
+        }
+        quickcheck(prop as fn(Vec<u8>) -> bool);
+    }
+}
@@ -0,0 +1,489 @@
+use crate::{
+    ffi::to_size_t, Assertion, AssertionCreator, CreatedCredential, CredentialCreator,
+    CredentialFormat, Device, FidoError, Result, FIDO_OK,
+};
+use libfido2_sys::*;
+use std::{collections::VecDeque, ffi::CStr};
+
+/// The requests that are answered by an authenticator.
+///
+/// This is implemented by [`Device`], and by [`MockAuthenticator`] for tests without a device.
+/// Code that only makes these requests can be generic over it, so it can be tested with a
+/// test double.
+///
+/// [`Device`]: struct.Device.html
+/// [`MockAuthenticator`]: struct.MockAuthenticator.html
+pub trait Authenticator {
+    /// Requests a new Credential. See `Device::request_credential_creation`.
+    fn request_credential_creation(
+        &mut self,
+        credential: CredentialCreator,
+        pin: Option<&CStr>,
+    ) -> Result<CreatedCredential>;
+
+    /// Requests an Assertion. See `Device::request_assertion_verification`.
+    fn request_assertion_verification(
+        &mut self,
+        assertion: AssertionCreator,
+        pin: Option<&CStr>,
+    ) -> Result<Assertion>;
+}
+
+impl Authenticator for Device {
+    fn request_credential_creation(
+        &mut self,
+        credential: CredentialCreator,
+        pin: Option<&CStr>,
+    ) -> Result<CreatedCredential> {
+        Device::request_credential_creation(self, credential, pin)
+    }
+
+    fn request_assertion_verification(
+        &mut self,
+        assertion: AssertionCreator,
+        pin: Option<&CStr>,
+    ) -> Result<Assertion> {
+        Device::request_assertion_verification(self, assertion, pin)
+    }
+}
+
+/// What a [`MockAuthenticator`] answers to a credential request.
+///
+/// [`MockAuthenticator`]: struct.MockAuthenticator.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockCredential {
+    /// Raw authenticator data, including the attested credential data.
+    /// The algorithm of its public key must match the requested `CredentialType`.
+    pub auth_data: Vec<u8>,
+    pub format: CredentialFormat,
+    /// Omitted if empty.
+    pub signature: Vec<u8>,
+    /// Omitted if empty.
+    pub x509_certificate: Vec<u8>,
+}
+
+/// A statement of what a [`MockAuthenticator`] answers to an assertion request.
+///
+/// # Remarks
+/// - libfido2 does not allow setting the credential ID and user of a statement,
+///   so these are always `None`.
+///
+/// [`MockAuthenticator`]: struct.MockAuthenticator.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockStatement {
+    /// Raw authenticator data.
+    pub auth_data: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// An [`Authenticator`] that answers requests with queued responses, without a device.
+///
+/// # Remarks
+/// - Responses are returned in the order they were queued. Once the queue of a request is empty,
+///   the request fails with `FIDO_ERR_INTERNAL`.
+/// - The responses are stored in the request objects by libfido2, so it must still be linked.
+///
+/// [`Authenticator`]: trait.Authenticator.html
+#[derive(Clone, Debug, Default)]
+pub struct MockAuthenticator {
+    credentials: VecDeque<std::result::Result<MockCredential, FidoError>>,
+    assertions: VecDeque<std::result::Result<Vec<MockStatement>, FidoError>>,
+    pins: Vec<Option<Vec<u8>>>,
+}
+
+impl MockAuthenticator {
+    /// Creates a mock without queued responses.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Queues the response to a credential request.
+    pub fn push_credential(&mut self, response: std::result::Result<MockCredential, FidoError>) {
+        self.credentials.push_back(response);
+    }
+
+    /// Queues the response to an assertion request.
+    pub fn push_assertion(&mut self, response: std::result::Result<Vec<MockStatement>, FidoError>) {
+        self.assertions.push_back(response);
+    }
+
+    /// Returns the PINs of all requests made so far, in order.
+    pub fn pins(&self) -> &[Option<Vec<u8>>] {
+        &self.pins
+    }
+}
+
+impl Authenticator for MockAuthenticator {
+    fn request_credential_creation(
+        &mut self,
+        mut credential: CredentialCreator,
+        pin: Option<&CStr>,
+    ) -> Result<CreatedCredential> {
+        self.pins.push(pin.map(|pin| pin.to_bytes().to_vec()));
+        let response = self
+            .credentials
+            .pop_front()
+            .unwrap_or(Err(FidoError(FIDO_ERR_INTERNAL)))?;
+
+        let raw = credential.raw_mut().as_ptr_mut();
+        unsafe {
+            check(fido_cred_set_authdata_raw(
+                raw,
+                response.auth_data.as_ptr(),
+                to_size_t(response.auth_data.len())?,
+            ))?;
+            check(fido_cred_set_fmt(raw, response.format.to_ffi()))?;
+            if !response.signature.is_empty() {
+                check(fido_cred_set_sig(
+                    raw,
+                    response.signature.as_ptr(),
+                    to_size_t(response.signature.len())?,
+                ))?;
+            }
+            if !response.x509_certificate.is_empty() {
+                check(fido_cred_set_x509(
+                    raw,
+                    response.x509_certificate.as_ptr(),
+                    to_size_t(response.x509_certificate.len())?,
+                ))?;
+            }
+        }
+        CreatedCredential::new(credential.into_inner())
+    }
+
+    fn request_assertion_verification(
+        &mut self,
+        mut assertion: AssertionCreator,
+        pin: Option<&CStr>,
+    ) -> Result<Assertion> {
+        self.pins.push(pin.map(|pin| pin.to_bytes().to_vec()));
+        let statements = self
+            .assertions
+            .pop_front()
+            .unwrap_or(Err(FidoError(FIDO_ERR_INTERNAL)))?;
+
+        let raw = assertion.raw_mut().as_ptr_mut();
+        unsafe {
+            check(fido_assert_set_count(raw, to_size_t(statements.len())?))?;
+            for (i, statement) in statements.iter().enumerate() {
+                let i = to_size_t(i)?;
+                check(fido_assert_set_authdata_raw(
+                    raw,
+                    i,
+                    statement.auth_data.as_ptr(),
+                    to_size_t(statement.auth_data.len())?,
+                ))?;
+                check(fido_assert_set_sig(
+                    raw,
+                    i,
+                    statement.signature.as_ptr(),
+                    to_size_t(statement.signature.len())?,
+                ))?;
+            }
+        }
+        Ok(assertion.into_inner())
+    }
+}
+
+fn check(code: std::os::raw::c_int) -> Result<()> {
+    match code {
+        FIDO_OK => Ok(()),
+        err => Err(FidoError(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cbor::{Reader, Writer},
+        openssl, AssertionCreationData, AuthDataFlags, CredProtect, CredentialCreationData,
+        CredentialExtensions, CredentialType, Extensions, Fido, HmacSecretInput, PublicKey,
+        RpEntity, UserEntity,
+    };
+
+    const CLIENT_DATA_HASH: [u8; 32] = [7; 32];
+    const CREDENTIAL_ID: &[u8] = b"credential";
+
+    // The generator of P-256, as a valid public key
+    const X: [u8; 32] = [
+        0x6b, 0x17, 0xd1, 0xf2, 0xe1, 0x2c, 0x42, 0x47, 0xf8, 0xbc, 0xe6, 0xe5, 0x63, 0xa4, 0x40, 0xf2,
+        0x77, 0x03, 0x7d, 0x81, 0x2d, 0xeb, 0x33, 0xa0, 0xf4, 0xa1, 0x39, 0x45, 0xd8, 0x98, 0xc2, 0x96,
+    ];
+    const Y: [u8; 32] = [
+        0x4f, 0xe3, 0x42, 0xe2, 0xfe, 0x1a, 0x7f, 0x9b, 0x8e, 0xe7, 0xeb, 0x4a, 0x7c, 0x0f, 0x9e, 0x16,
+        0x2b, 0xce, 0x33, 0x57, 0x6b, 0x31, 0x5e, 0xce, 0xcb, 0xb6, 0x40, 0x68, 0x37, 0xbf, 0x51, 0xf5,
+    ];
+
+    fn rp() -> RpEntity<'static> {
+        RpEntity::new(CStr::from_bytes_with_nul(b"example.com\0").unwrap()).unwrap()
+    }
+
+    fn user() -> UserEntity<'static> {
+        UserEntity::new(b"user", CStr::from_bytes_with_nul(b"alice\0").unwrap()).unwrap()
+    }
+
+    /// Raw authenticator data, with an ES256 credential if `attested` is set.
+    fn auth_data(flags: AuthDataFlags, sign_count: u32, attested: bool) -> Vec<u8> {
+        let mut data = vec![0x11; 32];
+        let flags = if attested {
+            flags | AuthDataFlags::ATTESTED_CREDENTIAL_DATA
+        } else {
+            flags
+        };
+        data.push(flags.bits());
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        if attested {
+            data.extend_from_slice(&[0x22; 16]);
+            data.extend_from_slice(&(CREDENTIAL_ID.len() as u16).to_be_bytes());
+            data.extend_from_slice(CREDENTIAL_ID);
+            let mut key = Writer::new();
+            key.map(5).int(1).int(2).int(3).int(-7).int(-1).int(1);
+            key.int(-2).bytes(&X).int(-3).bytes(&Y);
+            data.extend(key.into_bytes());
+        }
+        data
+    }
+
+    fn credential_creator(fido: &Fido) -> CredentialCreator {
+        let data = CredentialCreationData::with_defaults(&CLIENT_DATA_HASH, rp(), user());
+        fido.new_credential_creator(data).unwrap()
+    }
+
+    fn assertion_creator(fido: &Fido) -> AssertionCreator {
+        let data = AssertionCreationData::with_defaults(None, &CLIENT_DATA_HASH, rp());
+        fido.new_assertion_creator(data).unwrap()
+    }
+
+    #[test]
+    fn builders_default_to_es256_without_options() {
+        let data = CredentialCreationData::with_defaults(&CLIENT_DATA_HASH, rp(), user());
+        assert_eq!(data.credential_type, CredentialType::ES256);
+        assert!(data.excluded_ids.is_empty());
+        assert!(data.options.is_empty());
+        assert!(data.extensions.is_empty());
+        assert_eq!(data.cred_protect, None);
+
+        let data = AssertionCreationData::with_defaults(None, &CLIENT_DATA_HASH, rp());
+        assert_eq!(data.allowed_credential_ids, None);
+        assert_eq!(data.app_id, None);
+        assert!(data.options.is_empty());
+        assert_eq!(data.relying_party.id().to_bytes(), b"example.com");
+    }
+
+    #[test]
+    fn mock_creates_queued_credential() {
+        let fido = Fido::new(false);
+        let mut mock = MockAuthenticator::new();
+        mock.push_credential(Ok(MockCredential {
+            auth_data: auth_data(AuthDataFlags::USER_PRESENT, 3, true),
+            format: CredentialFormat::Fido2,
+            signature: Vec::new(),
+            x509_certificate: Vec::new(),
+        }));
+
+        let pin = CStr::from_bytes_with_nul(b"1234\0").unwrap();
+        let credential = mock
+            .request_credential_creation(credential_creator(&fido), Some(pin))
+            .unwrap();
+        assert_eq!(credential.id().as_bytes(), CREDENTIAL_ID);
+        assert_eq!(credential.credential_type(), CredentialType::ES256);
+        assert_eq!(credential.aaguid(), &[0x22; 16]);
+        assert_eq!(credential.sign_count(), 3);
+        assert_eq!(credential.format(), "packed");
+        assert!(credential.flags().contains(AuthDataFlags::USER_PRESENT));
+        assert_eq!(mock.pins(), &[Some(b"1234".to_vec())]);
+    }
+
+    #[test]
+    fn assertion_statements_map_to_queued_ones() {
+        let fido = Fido::new(false);
+        let mut mock = MockAuthenticator::new();
+        let statements = vec![
+            MockStatement {
+                auth_data: auth_data(AuthDataFlags::USER_PRESENT, 1, false),
+                signature: vec![1; 70],
+            },
+            MockStatement {
+                auth_data: auth_data(AuthDataFlags::USER_PRESENT | AuthDataFlags::USER_VERIFIED, 2, false),
+                signature: vec![2; 71],
+            },
+        ];
+        mock.push_assertion(Ok(statements.clone()));
+
+        let assertion = mock
+            .request_assertion_verification(assertion_creator(&fido), None)
+            .unwrap();
+        assert_eq!(assertion.len(), 2);
+        for (statement, expected) in assertion.iter().zip(&statements) {
+            // libfido2 wraps the authenticator data in a CBOR byte string
+            assert_eq!(
+                Reader::new(statement.auth_data).bytes(),
+                Some(expected.auth_data.as_slice())
+            );
+            assert_eq!(statement.signature, expected.signature.as_slice());
+            assert_eq!(statement.client_data_hash, &CLIENT_DATA_HASH);
+            assert_eq!(statement.credential_id, None);
+            assert_eq!(statement.user_id, None);
+            assert_eq!(statement.user(), None);
+        }
+        let flags = assertion
+            .iter()
+            .map(|statement| statement.flags())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            flags,
+            [
+                Some(AuthDataFlags::USER_PRESENT),
+                Some(AuthDataFlags::USER_PRESENT | AuthDataFlags::USER_VERIFIED)
+            ]
+        );
+        assert_eq!(mock.pins(), &[None]);
+    }
+
+    #[test]
+    fn mock_returns_queued_errors() {
+        const PIN_INVALID: FidoError = FidoError(FIDO_ERR_PIN_INVALID as std::os::raw::c_int);
+
+        let fido = Fido::new(false);
+        let mut mock = MockAuthenticator::new();
+        mock.push_credential(Err(PIN_INVALID));
+        mock.push_assertion(Err(FidoError(FIDO_ERR_RX)));
+
+        let err = mock
+            .request_credential_creation(credential_creator(&fido), None)
+            .unwrap_err();
+        assert_eq!(err, PIN_INVALID);
+        assert_eq!(err.kind(), crate::ErrorKind::Authenticator);
+        let err = mock
+            .request_assertion_verification(assertion_creator(&fido), None)
+            .err()
+            .unwrap();
+        assert!(err.is_transport());
+
+        // Empty queues
+        let err = mock
+            .request_credential_creation(credential_creator(&fido), None)
+            .unwrap_err();
+        assert_eq!(err, FidoError(FIDO_ERR_INTERNAL));
+        let err = mock
+            .request_assertion_verification(assertion_creator(&fido), None)
+            .err()
+            .unwrap();
+        assert_eq!(err, FidoError(FIDO_ERR_INTERNAL));
+        assert_eq!(mock.pins().len(), 4);
+    }
+
+    #[test]
+    fn malformed_responses_are_rejected_by_libfido2() {
+        let fido = Fido::new(false);
+        let mut mock = MockAuthenticator::new();
+        // Attested credential data is required
+        mock.push_credential(Ok(MockCredential {
+            auth_data: auth_data(AuthDataFlags::USER_PRESENT, 0, false),
+            format: CredentialFormat::Fido2,
+            signature: Vec::new(),
+            x509_certificate: Vec::new(),
+        }));
+        mock.push_assertion(Ok(vec![MockStatement {
+            auth_data: vec![0; 10],
+            signature: vec![1; 70],
+        }]));
+
+        let err = mock
+            .request_credential_creation(credential_creator(&fido), None)
+            .unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Library);
+        let err = mock
+            .request_assertion_verification(assertion_creator(&fido), None)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), crate::ErrorKind::Library);
+    }
+
+    /// Extensions to replace `hmac-secret` and `credProtect` with, which libfido2 doesn't expect
+    /// in the response.
+    fn replacements() -> Vec<Extensions<'static>> {
+        #[allow(unused_mut)]
+        let mut replacements = vec![Extensions::default()];
+        // libfido2 leaves largeBlobKey out of the check
+        #[cfg(feature = "largeblob")]
+        replacements.push(Extensions {
+            large_blob_key: true,
+            ..Default::default()
+        });
+        replacements
+    }
+
+    /// Authenticator data without extensions that libfido2 accepts for `example.com`.
+    fn verifiable_auth_data(attested: bool) -> Vec<u8> {
+        let mut data = auth_data(AuthDataFlags::USER_PRESENT, 1, attested);
+        data[..32].copy_from_slice(&openssl::sha256(b"example.com").unwrap());
+        data
+    }
+
+    // libfido2 checks that a response has the requested extensions before checking its
+    // signature, so a response without extensions fails only the latter if none are requested
+    #[test]
+    fn credential_extensions_are_replaced() {
+        let fido = Fido::new(false);
+        let created = |extensions: Option<&Extensions<'_>>| {
+            let mut data = CredentialCreationData::with_defaults(&CLIENT_DATA_HASH, rp(), user());
+            data.extensions = CredentialExtensions::HMAC_SECRET;
+            data.cred_protect = Some(CredProtect::Required);
+            let mut creator = fido.new_credential_creator(data).unwrap();
+            if let Some(extensions) = extensions {
+                creator.set_extensions(extensions).unwrap();
+            }
+            let mut mock = MockAuthenticator::new();
+            mock.push_credential(Ok(MockCredential {
+                auth_data: verifiable_auth_data(true),
+                format: CredentialFormat::Fido2,
+                signature: vec![1; 70],
+                x509_certificate: Vec::new(),
+            }));
+            mock.request_credential_creation(creator, None).unwrap()
+        };
+
+        let credential = created(None);
+        let err = credential.credential().verify_self().unwrap_err();
+        assert_eq!(err, FidoError(FIDO_ERR_INVALID_PARAM));
+        for extensions in replacements() {
+            let credential = created(Some(&extensions));
+            let err = credential.credential().verify_self().unwrap_err();
+            assert_eq!(err, FidoError(FIDO_ERR_INVALID_SIG), "{:?}", extensions);
+        }
+    }
+
+    #[test]
+    fn assertion_extensions_are_replaced() {
+        const HMAC_SALT: [u8; 32] = [3; 32];
+
+        let fido = Fido::new(false);
+        let key = PublicKey::new_es256(&[X, Y].concat()).unwrap();
+        let verified = |extensions: Option<&Extensions<'_>>| {
+            let mut creator = assertion_creator(&fido);
+            let hmac_secret = Extensions {
+                hmac_secret: Some(HmacSecretInput::Salt(&HMAC_SALT)),
+                ..Default::default()
+            };
+            creator.set_extensions(&hmac_secret).unwrap();
+            if let Some(extensions) = extensions {
+                creator.set_extensions(extensions).unwrap();
+            }
+            let mut mock = MockAuthenticator::new();
+            mock.push_assertion(Ok(vec![MockStatement {
+                auth_data: verifiable_auth_data(false),
+                signature: vec![1; 70],
+            }]));
+            let assertion = mock.request_assertion_verification(creator, None);
+            assertion.ok().unwrap().verify_statement(0, &key)
+        };
+
+        assert_eq!(verified(None), Err(FidoError(FIDO_ERR_INVALID_PARAM)));
+        for extensions in replacements() {
+            let result = verified(Some(&extensions));
+            assert_eq!(result, Err(FidoError(FIDO_ERR_INVALID_SIG)), "{:?}", extensions);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::AuthDataFlags;
+
+/// Whether a credential can be, and is, backed up, from the `BACKUP_ELIGIBLE` and
+/// `BACKUP_STATE` flags of its authenticator data.
+///
+/// # Remarks
+/// - WebAuthn calls credentials that can't be backed up single-device credentials, and the others
+///   multi-device credentials, which are better known as synced passkeys.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BackupState {
+    /// The credential is bound to the authenticator, e.g. a security key.
+    SingleDevice,
+    /// The credential may be backed up, but isn't yet.
+    NotBackedUp,
+    /// The credential is backed up, e.g. synced by a password manager.
+    BackedUp,
+}
+
+impl BackupState {
+    /// Reads the state from authenticator data flags, or returns `None` if `BACKUP_STATE` is set
+    /// without `BACKUP_ELIGIBLE`, which WebAuthn forbids.
+    pub fn from_flags(flags: AuthDataFlags) -> Option<Self> {
+        let eligible = flags.contains(AuthDataFlags::BACKUP_ELIGIBLE);
+        match (eligible, flags.contains(AuthDataFlags::BACKUP_STATE)) {
+            (false, false) => Some(BackupState::SingleDevice),
+            (false, true) => None,
+            (true, false) => Some(BackupState::NotBackedUp),
+            (true, true) => Some(BackupState::BackedUp),
+        }
+    }
+
+    /// Returns whether the credential may be backed up, which doesn't change after registration.
+    pub fn is_eligible(self) -> bool {
+        self != BackupState::SingleDevice
+    }
+
+    /// Returns whether the credential is backed up.
+    pub fn is_backed_up(self) -> bool {
+        self == BackupState::BackedUp
+    }
+
+    pub(crate) fn to_u64(self) -> u64 {
+        match self {
+            BackupState::SingleDevice => 0,
+            BackupState::NotBackedUp => 1,
+            BackupState::BackedUp => 2,
+        }
+    }
+
+    pub(crate) fn from_u64(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(BackupState::SingleDevice),
+            1 => Some(BackupState::NotBackedUp),
+            2 => Some(BackupState::BackedUp),
+            _ => None,
+        }
+    }
+}
+
+/// How the backup state of an assertion relates to the one previously stored for the
+/// credential.
+///
+/// # Remarks
+/// - WebAuthn requires relying parties to reject assertions whose `BACKUP_ELIGIBLE` flag
+///   differs from the stored one, or that set `BACKUP_STATE` without it, see
+///   [`is_violation`]. The other transitions are allowed, and left to the policy of the
+///   relying party, e.g. asking for a second factor once a credential is synced.
+///
+/// [`is_violation`]: enum.BackupTransition.html#method.is_violation
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BackupTransition {
+    /// The state did not change.
+    Unchanged(BackupState),
+    /// The credential was backed up since the last authentication, so it may now exist on other
+    /// devices.
+    BecameSynced,
+    /// The credential is no longer backed up, e.g. because syncing was turned off.
+    NoLongerSynced,
+    /// The credential became eligible for backup, or stopped being eligible, which a credential
+    /// can't. The assertion may be of another authenticator with the same credential ID.
+    EligibilityChanged {
+        previous: BackupState,
+        current: BackupState,
+    },
+    /// `BACKUP_STATE` is set without `BACKUP_ELIGIBLE`.
+    Invalid,
+}
+
+impl BackupTransition {
+    /// Compares the flags of a new assertion against the stored state.
+    pub fn new(previous: BackupState, current: AuthDataFlags) -> Self {
+        let current = match BackupState::from_flags(current) {
+            Some(current) => current,
+            None => return BackupTransition::Invalid,
+        };
+        match (previous, current) {
+            (previous, current) if previous == current => BackupTransition::Unchanged(current),
+            (BackupState::NotBackedUp, BackupState::BackedUp) => BackupTransition::BecameSynced,
+            (BackupState::BackedUp, BackupState::NotBackedUp) => BackupTransition::NoLongerSynced,
+            (previous, current) => BackupTransition::EligibilityChanged { previous, current },
+        }
+    }
+
+    /// Returns `true` for the transitions WebAuthn requires relying parties to reject.
+    pub fn is_violation(&self) -> bool {
+        match self {
+            BackupTransition::Unchanged(_)
+            | BackupTransition::BecameSynced
+            | BackupTransition::NoLongerSynced => false,
+            BackupTransition::EligibilityChanged { .. } | BackupTransition::Invalid => true,
+        }
+    }
+
+    /// Returns the state to store for the credential, or `None` for violations, whose state
+    /// must not replace the stored one.
+    pub fn current(&self) -> Option<BackupState> {
+        match self {
+            BackupTransition::Unchanged(state) => Some(*state),
+            BackupTransition::BecameSynced => Some(BackupState::BackedUp),
+            BackupTransition::NoLongerSynced => Some(BackupState::NotBackedUp),
+            BackupTransition::EligibilityChanged { .. } | BackupTransition::Invalid => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATES: [BackupState; 3] = [
+        BackupState::SingleDevice,
+        BackupState::NotBackedUp,
+        BackupState::BackedUp,
+    ];
+
+    fn flags(state: BackupState) -> AuthDataFlags {
+        match state {
+            BackupState::SingleDevice => AuthDataFlags::empty(),
+            BackupState::NotBackedUp => AuthDataFlags::BACKUP_ELIGIBLE,
+            BackupState::BackedUp => AuthDataFlags::BACKUP_ELIGIBLE | AuthDataFlags::BACKUP_STATE,
+        }
+    }
+
+    #[test]
+    fn reads_flags() {
+        for state in STATES {
+            assert_eq!(BackupState::from_flags(flags(state)), Some(state));
+            // Other flags don't matter
+            let other = flags(state) | AuthDataFlags::USER_PRESENT | AuthDataFlags::USER_VERIFIED;
+            assert_eq!(BackupState::from_flags(other), Some(state));
+            assert_eq!(BackupState::from_u64(state.to_u64()), Some(state));
+        }
+        assert_eq!(BackupState::from_flags(AuthDataFlags::BACKUP_STATE), None);
+        assert_eq!(BackupState::from_u64(3), None);
+        assert_eq!(BackupState::from_u64(u64::MAX), None);
+    }
+
+    #[test]
+    fn classifies_every_transition() {
+        for previous in STATES {
+            for current in STATES {
+                let transition = BackupTransition::new(previous, flags(current));
+                let expected = match (previous, current) {
+                    _ if previous == current => BackupTransition::Unchanged(current),
+                    (BackupState::NotBackedUp, BackupState::BackedUp) => {
+                        BackupTransition::BecameSynced
+                    }
+                    (BackupState::BackedUp, BackupState::NotBackedUp) => {
+                        BackupTransition::NoLongerSynced
+                    }
+                    _ => BackupTransition::EligibilityChanged { previous, current },
+                };
+                assert_eq!(transition, expected);
+                let violation = previous.is_eligible() != current.is_eligible();
+                assert_eq!(transition.is_violation(), violation);
+                assert_eq!(transition.current(), Some(current).filter(|_| !violation));
+            }
+
+            let transition = BackupTransition::new(previous, AuthDataFlags::BACKUP_STATE);
+            assert_eq!(transition, BackupTransition::Invalid);
+            assert!(transition.is_violation());
+            assert_eq!(transition.current(), None);
+        }
+    }
+}
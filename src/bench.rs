@@ -0,0 +1,74 @@
+use crate::Traffic;
+use std::time::Duration;
+
+/// Measurements of a single request, as made by [`Device::bench`].
+///
+/// [`Device::bench`]: struct.Device.html#method.bench
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RoundTrip {
+    /// Time from sending the request until the response was parsed, including waiting for the user.
+    pub duration: Duration,
+    /// `None` for devices not opened with `Fido::new_monitored_device`.
+    pub traffic: Option<Traffic>,
+}
+
+/// Round trips of the requests made by [`Device::bench`], in order.
+///
+/// # Remarks
+/// - The durations can be passed to benchmark harnesses that accept custom measurements,
+///   e.g. criterion's `iter_custom` with [`total_duration`].
+///
+/// [`Device::bench`]: struct.Device.html#method.bench
+/// [`total_duration`]: struct.BenchReport.html#method.total_duration
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BenchReport {
+    pub make_credential: Vec<RoundTrip>,
+    pub get_assertion: Vec<RoundTrip>,
+}
+
+impl BenchReport {
+    /// Returns the mean duration of the makeCredential round trips, if any.
+    pub fn mean_make_credential(&self) -> Option<Duration> {
+        mean(&self.make_credential)
+    }
+
+    /// Returns the mean duration of the getAssertion round trips, if any.
+    pub fn mean_get_assertion(&self) -> Option<Duration> {
+        mean(&self.get_assertion)
+    }
+
+    /// Returns the summed duration of all round trips.
+    pub fn total_duration(&self) -> Duration {
+        self.make_credential
+            .iter()
+            .chain(&self.get_assertion)
+            .map(|round_trip| round_trip.duration)
+            .sum()
+    }
+
+    /// Returns the summed traffic of all round trips, or `None` if the device was not monitored.
+    pub fn total_traffic(&self) -> Option<Traffic> {
+        self.make_credential
+            .iter()
+            .chain(&self.get_assertion)
+            .map(|round_trip| round_trip.traffic)
+            .try_fold(Traffic::default(), |total, traffic| {
+                let traffic = traffic?;
+                Some(Traffic {
+                    reports_sent: total.reports_sent + traffic.reports_sent,
+                    reports_received: total.reports_received + traffic.reports_received,
+                    bytes_sent: total.bytes_sent + traffic.bytes_sent,
+                    bytes_received: total.bytes_received + traffic.bytes_received,
+                    keepalives: total.keepalives + traffic.keepalives,
+                })
+            })
+    }
+}
+
+fn mean(round_trips: &[RoundTrip]) -> Option<Duration> {
+    if round_trips.is_empty() {
+        return None;
+    }
+    let total = round_trips.iter().map(|round_trip| round_trip.duration).sum::<Duration>();
+    Some(total / round_trips.len() as u32)
+}
@@ -0,0 +1,267 @@
+//! Command line utility in the spirit of `fido2-token` and `fido2-cred`.
+//!
+//! Run without arguments for usage.
+
+use libfido2::*;
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    error::Error,
+    ffi::CString,
+    hash::{BuildHasher, Hasher},
+    io::{self, BufRead},
+    process,
+};
+
+const USAGE: &str = "\
+usage: fido2-tool <command> [arguments]
+
+commands:
+  list                                      List connected devices
+  info <device>                             Show authenticator info
+  cred <device> <rp-id> <user-name> [-t type] [-o options] [-p]
+                                            Create a credential, printing its ID and public key
+  assert <device> <rp-id> [credential-id] [-o options] [-p]
+                                            Request an assertion with a resident key,
+                                            or the given hex encoded credential ID
+  reset <device>                            Reset the device, deleting all credentials
+  set-pin <device> [-p]                     Set the PIN read from stdin, or change it with -p
+
+options:
+  -t type     Credential type: es256, rs256 or eddsa
+  -o options  Credential options (rk+uv) or assertion options (up+uv)
+  -p          Read the current PIN from the first line of stdin, before a new PIN";
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if let Err(err) = run(&args) {
+        eprintln!("fido2-tool: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> CliResult<()> {
+    let fido = Fido::new(false);
+    match args.first().map(String::as_str) {
+        Some("list") => list(&fido),
+        Some("info") => info(&fido, &Args::parse(&args[1..], 1)?),
+        Some("cred") => cred(&fido, &Args::parse(&args[1..], 3)?),
+        Some("assert") => assert(&fido, &Args::parse(&args[1..], 2)?),
+        Some("reset") => reset(&fido, &Args::parse(&args[1..], 1)?),
+        Some("set-pin") => set_pin(&fido, &Args::parse(&args[1..], 1)?),
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// Positional arguments and flags of a command.
+struct Args {
+    positional: Vec<String>,
+    credential_type: Option<String>,
+    options: Option<String>,
+    read_pin: bool,
+}
+
+impl Args {
+    fn parse(args: &[String], required: usize) -> CliResult<Self> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            credential_type: None,
+            options: None,
+            read_pin: false,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-t" => parsed.credential_type = Some(args.next().ok_or(USAGE)?.clone()),
+                "-o" => parsed.options = Some(args.next().ok_or(USAGE)?.clone()),
+                "-p" => parsed.read_pin = true,
+                _ => parsed.positional.push(arg.clone()),
+            }
+        }
+        if parsed.positional.len() < required {
+            return Err(USAGE.into());
+        }
+        Ok(parsed)
+    }
+
+    fn open(&self, fido: &Fido) -> CliResult<Device> {
+        let path = DevicePathBuf::from_path(&self.positional[0])?;
+        Ok(fido.new_device(path.as_path())?)
+    }
+
+    fn pin(&self) -> CliResult<Option<CString>> {
+        if self.read_pin {
+            Ok(Some(read_line("PIN")?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+fn list(fido: &Fido) -> CliResult<()> {
+    for device in fido.try_detect_devices(64)?.iter() {
+        println!(
+            "{}: vendor=0x{:04x}, product=0x{:04x} ({} {})",
+            device.path.to_str(),
+            device.vendor_id,
+            device.product_id,
+            device.manufacturer,
+            device.product
+        );
+    }
+    Ok(())
+}
+
+fn info(fido: &Fido, args: &Args) -> CliResult<()> {
+    let mut device = args.open(fido)?;
+    let info = device.authenticator_info()?;
+    println!("mode: {}", device.mode());
+    println!("versions: {}", info.ctap_versions.join(", "));
+    println!("extensions: {}", info.extensions.join(", "));
+    println!("transports: {}", info.transports.join(", "));
+    if let Some(aaguid) = &info.aag_uid {
+        println!("aaguid: {}", hex(aaguid));
+    }
+    let mut options = info.options.iter().collect::<Vec<_>>();
+    options.sort();
+    for (option, value) in options {
+        println!("option {}: {}", option, value);
+    }
+    if let Some(min_pin_length) = info.min_pin_length {
+        println!("min pin length: {}", min_pin_length);
+    }
+    if info.options.get("clientPin") == Some(&true) {
+        println!("pin retries: {}", device.retry_count()?);
+    }
+    Ok(())
+}
+
+fn cred(fido: &Fido, args: &Args) -> CliResult<()> {
+    let rp_id = CString::new(args.positional[1].as_str())?;
+    let user_name = CString::new(args.positional[2].as_str())?;
+    let user_id = random_bytes();
+    let client_data_hash = random_bytes();
+
+    let mut data = CredentialCreationData::with_defaults(
+        &client_data_hash,
+        RpEntity::new(&rp_id)?,
+        UserEntity::new(&user_id, &user_name)?,
+    );
+    if let Some(credential_type) = &args.credential_type {
+        data.credential_type = credential_type.parse()?;
+    }
+    if let Some(options) = &args.options {
+        data.options = options.parse()?;
+    }
+
+    let pin = args.pin()?;
+    let mut device = args.open(fido)?;
+    let creator = fido.new_credential_creator(data)?;
+    let credential = interruptible(&mut device, |device| {
+        device.request_credential_creation(creator, pin.as_deref())
+    })?;
+    credential.credential().verify()?;
+    println!("credential id: {}", hex(credential.id().as_bytes()));
+    println!("type: {}", credential.credential_type());
+    println!("public key: {}", hex(credential.public_key_bytes()));
+    println!("user id: {}", hex(&user_id));
+    Ok(())
+}
+
+fn assert(fido: &Fido, args: &Args) -> CliResult<()> {
+    let rp_id = CString::new(args.positional[1].as_str())?;
+    let credential_id = args.positional.get(2).map(|id| unhex(id)).transpose()?;
+    let allowed = credential_id.as_deref().map(|id| [id]);
+    let client_data_hash = random_bytes();
+
+    let mut data = AssertionCreationData::with_defaults(
+        allowed.as_ref().map(|allowed| &allowed[..]),
+        &client_data_hash,
+        RpEntity::new(&rp_id)?,
+    );
+    if let Some(options) = &args.options {
+        data.options = options.parse()?;
+    }
+
+    let pin = args.pin()?;
+    let mut device = args.open(fido)?;
+    let creator = fido.new_assertion_creator(data)?;
+    let assertion = interruptible(&mut device, |device| {
+        device.request_assertion_verification(creator, pin.as_deref())
+    })?;
+    for statement in assertion.iter() {
+        if let Some(credential_id) = statement.credential_id {
+            println!("credential id: {}", hex(credential_id));
+        }
+        if let Some(user) = statement.user() {
+            println!("user id: {}", hex(user.id()));
+        }
+        println!("signature: {}", hex(statement.signature));
+    }
+    Ok(())
+}
+
+fn reset(fido: &Fido, args: &Args) -> CliResult<()> {
+    let mut device = args.open(fido)?;
+    eprintln!("Touch the device to confirm the reset");
+    interruptible(&mut device, Device::reset)?;
+    Ok(())
+}
+
+/// Runs a request which waits for the user, exiting when it is interrupted with Ctrl-C.
+fn interruptible<T>(
+    device: &mut Device,
+    request: impl FnOnce(&mut Device) -> Result<T, FidoError>,
+) -> CliResult<T> {
+    let token = CancellationToken::on_interrupt()?;
+    let result = device.cancellable(&token, request);
+    if token.is_cancelled() {
+        eprintln!("fido2-tool: cancelled");
+        process::exit(130);
+    }
+    Ok(result?)
+}
+
+fn set_pin(fido: &Fido, args: &Args) -> CliResult<()> {
+    let old_pin = args.pin()?;
+    let new_pin = read_line("new PIN")?;
+    let mut device = args.open(fido)?;
+    device.set_pin(&new_pin, old_pin.as_deref())?;
+    Ok(())
+}
+
+fn read_line(what: &str) -> CliResult<CString> {
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err(format!("Expected the {} on stdin", what).into());
+    }
+    let line = line.trim_end_matches(&['\r', '\n'][..]);
+    Ok(CString::new(line)?)
+}
+
+/// Returns unpredictable bytes, good enough for client data hashes and user IDs of a test tool.
+fn random_bytes() -> [u8; 32] {
+    let mut bytes = [0; 32];
+    for chunk in bytes.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(chunk.as_ptr() as usize);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(s: &str) -> CliResult<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return Err(format!("Invalid hex string `{}`", s).into());
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(Into::into))
+        .collect()
+}
@@ -0,0 +1,367 @@
+//! Access to devices through a broker, for sandboxed apps that can't open hidraw nodes.
+//!
+//! xdg-desktop-portal has no interface for FIDO devices, so sandboxed apps rely on a broker
+//! outside the sandbox, e.g. a privileged helper or a portal backend, with its Unix socket in
+//! [`BROKER_SOCKET_ENV`]. For each device, the broker receives the path of its hidraw node as a
+//! big-endian `u16` length followed by the path, and answers with one byte: `0` with the open
+//! file descriptor of the node attached as `SCM_RIGHTS`, or any other value if it denies access.
+//! Every request uses its own connection. [`serve_broker_request`] answers requests on the
+//! broker side, and the `broker` example is a reference broker built on it.
+//!
+//! [`BROKER_SOCKET_ENV`]: constant.BROKER_SOCKET_ENV.html
+//! [`serve_broker_request`]: fn.serve_broker_request.html
+
+use crate::{DevicePath, FidoError, Result};
+use libfido2_sys::*;
+use std::{
+    convert::TryFrom,
+    env,
+    ffi::OsStr,
+    fs::OpenOptions,
+    io::{self, Read, Write},
+    mem,
+    os::{
+        fd::{AsFd, BorrowedFd, FromRawFd, OwnedFd},
+        raw,
+        unix::{ffi::OsStrExt, io::AsRawFd, net::UnixStream},
+    },
+    path::{Path, PathBuf},
+    ptr,
+};
+
+/// The environment variable with the path of the socket of the broker devices are opened
+/// through, if the process can't open them itself.
+pub const BROKER_SOCKET_ENV: &str = "LIBFIDO2_BROKER";
+
+const SOL_SOCKET: raw::c_int = 1;
+const SCM_RIGHTS: raw::c_int = 1;
+const MSG_CTRUNC: raw::c_int = 0x8;
+const MSG_CMSG_CLOEXEC: raw::c_int = 0x4000_0000;
+
+/// The prefix of the paths of the nodes brokers open, as other paths aren't nodes the
+/// sandbox hides, e.g. `pcsc://` readers and `nfc:` devices.
+const HIDRAW_PREFIX: &[u8] = b"/dev/hidraw";
+
+#[repr(C)]
+struct IoVec {
+    base: *mut raw::c_void,
+    len: usize,
+}
+
+#[repr(C)]
+struct MsgHdr {
+    name: *mut raw::c_void,
+    name_len: u32,
+    iov: *mut IoVec,
+    iov_len: usize,
+    control: *mut raw::c_void,
+    control_len: usize,
+    flags: raw::c_int,
+}
+
+#[repr(C)]
+struct CmsgHdr {
+    len: usize,
+    level: raw::c_int,
+    kind: raw::c_int,
+}
+
+extern "C" {
+    fn recvmsg(fd: raw::c_int, msg: *mut MsgHdr, flags: raw::c_int) -> isize;
+    fn sendmsg(fd: raw::c_int, msg: *const MsgHdr, flags: raw::c_int) -> isize;
+}
+
+/// Room for the header and one descriptor, aligned like `cmsghdr`.
+type Control = [usize; 4];
+
+/// Only accepts the prefix followed by the number of the node, so a request can't reach other
+/// files through `/` or `..`.
+fn is_hidraw(path: &[u8]) -> bool {
+    match path.strip_prefix(HIDRAW_PREFIX) {
+        Some(number) => !number.is_empty() && number.iter().all(u8::is_ascii_digit),
+        None => false,
+    }
+}
+
+/// Returns the socket of the broker to open `path` through, if a broker is configured and the
+/// process can't open `path` itself.
+pub(crate) fn broker_for(path: DevicePath<'_>) -> Option<PathBuf> {
+    let node = path.0.to_bytes();
+    if !is_hidraw(node) {
+        return None;
+    }
+    let socket = env::var_os(BROKER_SOCKET_ENV).filter(|socket| !socket.is_empty())?;
+    let node = OsStr::from_bytes(node);
+    match OpenOptions::new().read(true).write(true).open(node) {
+        // Sandboxes hide the nodes, or don't grant access to them
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+            ) =>
+        {
+            Some(PathBuf::from(socket))
+        }
+        _ => None,
+    }
+}
+
+/// Asks the broker at `socket` to open `path`, blocking while it asks the user, if it does.
+///
+/// # Errors
+/// - `FIDO_ERR_RX` if the broker can't be reached or denies access, like libfido2 reports
+///   devices it can't open.
+pub(crate) fn open(socket: &Path, path: DevicePath<'_>) -> Result<OwnedFd> {
+    let path = path.0.to_bytes();
+    let len = u16::try_from(path.len()).map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+    match request(socket, &[&len.to_be_bytes()[..], path].concat()) {
+        Ok((0, Some(fd))) => Ok(fd),
+        _ => Err(FidoError(FIDO_ERR_RX)),
+    }
+}
+
+fn request(socket: &Path, request: &[u8]) -> io::Result<(u8, Option<OwnedFd>)> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.write_all(request)?;
+    receive(&stream)
+}
+
+/// Receives the status byte of the broker, and the file descriptor attached to it.
+fn receive(stream: &UnixStream) -> io::Result<(u8, Option<OwnedFd>)> {
+    let mut status = 0u8;
+    let mut iov = IoVec {
+        base: &mut status as *mut u8 as *mut _,
+        len: 1,
+    };
+    let mut control = Control::default();
+    let mut msg = MsgHdr {
+        name: ptr::null_mut(),
+        name_len: 0,
+        iov: &mut iov,
+        iov_len: 1,
+        control: control.as_mut_ptr() as *mut _,
+        control_len: mem::size_of_val(&control),
+        flags: 0,
+    };
+    let n = loop {
+        let n = unsafe { recvmsg(stream.as_raw_fd(), &mut msg, MSG_CMSG_CLOEXEC) };
+        if n >= 0 {
+            break n;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    };
+    if n == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    // Take ownership of the descriptors first, so they are closed if the message is rejected
+    let fd = received_fd(&control, msg.control_len);
+    if msg.flags & MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "broker sent more control data than expected",
+        ));
+    }
+    Ok((status, fd))
+}
+
+/// Returns the first descriptor of the `SCM_RIGHTS` message in `control`, closing the others.
+fn received_fd(control: &Control, control_len: usize) -> Option<OwnedFd> {
+    let header_len = mem::size_of::<CmsgHdr>();
+    let fd_len = mem::size_of::<raw::c_int>();
+    if control_len < header_len + fd_len {
+        return None;
+    }
+    let header = unsafe { &*(control.as_ptr() as *const CmsgHdr) };
+    if header.level != SOL_SOCKET || header.kind != SCM_RIGHTS || header.len < header_len + fd_len {
+        return None;
+    }
+    let count = (header.len.min(control_len) - header_len) / fd_len;
+    let data = unsafe { (control.as_ptr() as *const u8).add(header_len) as *const raw::c_int };
+    let mut fds = (0..count)
+        .map(|i| unsafe { OwnedFd::from_raw_fd(ptr::read_unaligned(data.add(i))) })
+        .collect::<Vec<_>>();
+    Some(fds.swap_remove(0))
+}
+
+/// Sends the status byte of the broker, with `fd` attached as `SCM_RIGHTS` if any.
+fn send(stream: &UnixStream, status: u8, fd: Option<BorrowedFd<'_>>) -> io::Result<()> {
+    let mut status = status;
+    let mut iov = IoVec {
+        base: &mut status as *mut u8 as *mut _,
+        len: 1,
+    };
+    let mut control = Control::default();
+    let mut msg = MsgHdr {
+        name: ptr::null_mut(),
+        name_len: 0,
+        iov: &mut iov,
+        iov_len: 1,
+        control: ptr::null_mut(),
+        control_len: 0,
+        flags: 0,
+    };
+    if let Some(fd) = fd {
+        let header_len = mem::size_of::<CmsgHdr>();
+        let fd_len = mem::size_of::<raw::c_int>();
+        let align = mem::size_of::<usize>();
+        unsafe {
+            let header = control.as_mut_ptr() as *mut CmsgHdr;
+            *header = CmsgHdr {
+                len: header_len + fd_len,
+                level: SOL_SOCKET,
+                kind: SCM_RIGHTS,
+            };
+            let data = (control.as_mut_ptr() as *mut u8).add(header_len) as *mut raw::c_int;
+            ptr::write_unaligned(data, fd.as_raw_fd());
+        }
+        msg.control = control.as_mut_ptr() as *mut _;
+        msg.control_len = header_len + fd_len.div_ceil(align) * align;
+    }
+    loop {
+        let n = unsafe { sendmsg(stream.as_raw_fd(), &msg, 0) };
+        if n >= 0 {
+            return Ok(());
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+/// Answers one request of a client on `stream`, as a broker outside the sandbox.
+///
+/// Reads the path of the hidraw node the client asks for, and sends it the open node if
+/// `allow` grants access to it, e.g. after asking the user. Paths other than hidraw nodes
+/// are denied without calling `allow`.
+///
+/// # Errors
+/// Returns an error if the request can't be read or the answer can't be sent. Nodes that
+/// can't be opened are reported to the client as denied.
+///
+/// # Example
+/// ```no_run
+/// use std::os::unix::net::UnixListener;
+///
+/// let listener = UnixListener::bind("/run/user/1000/fido-broker")?;
+/// for stream in listener.incoming() {
+///     libfido2::serve_broker_request(&mut stream?, |_| true)?;
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn serve_broker_request(
+    stream: &mut UnixStream,
+    allow: impl FnOnce(&Path) -> bool,
+) -> io::Result<()> {
+    let mut len = [0; 2];
+    stream.read_exact(&mut len)?;
+    let mut path = vec![0; usize::from(u16::from_be_bytes(len))];
+    stream.read_exact(&mut path)?;
+
+    let node = Path::new(OsStr::from_bytes(&path));
+    let file = if is_hidraw(&path) && allow(node) {
+        OpenOptions::new().read(true).write(true).open(node).ok()
+    } else {
+        None
+    };
+    match file {
+        Some(file) => send(stream, 0, Some(file.as_fd())),
+        None => send(stream, 1, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ffi::CStr, fs::File, io::Seek, thread};
+
+    #[test]
+    fn receives_sent_fd() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let mut file = tempfile();
+        file.write_all(b"broker").unwrap();
+        send(&a, 0, Some(file.as_fd())).unwrap();
+
+        let (status, fd) = receive(&b).unwrap();
+        assert_eq!(status, 0);
+        let mut received = File::from(fd.unwrap());
+        received.rewind().unwrap();
+        let mut contents = String::new();
+        received.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "broker");
+    }
+
+    #[test]
+    fn receives_status_without_fd() {
+        let (a, b) = UnixStream::pair().unwrap();
+        send(&a, 1, None).unwrap();
+        let (status, fd) = receive(&b).unwrap();
+        assert_eq!(status, 1);
+        assert!(fd.is_none());
+    }
+
+    #[test]
+    fn receive_fails_on_closed_stream() {
+        let (a, b) = UnixStream::pair().unwrap();
+        drop(a);
+        let error = receive(&b).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn serve_denies_other_paths() {
+        for path in [&b"/etc/passwd"[..], b"/dev/hidraw0/../../etc/passwd"] {
+            let (mut a, mut b) = UnixStream::pair().unwrap();
+            a.write_all(&(path.len() as u16).to_be_bytes()).unwrap();
+            a.write_all(path).unwrap();
+            let server = thread::spawn(move || serve_broker_request(&mut b, |_| panic!("asked")));
+
+            let (status, fd) = receive(&a).unwrap();
+            server.join().unwrap().unwrap();
+            assert_eq!(status, 1);
+            assert!(fd.is_none());
+        }
+    }
+
+    #[test]
+    fn hidraw_paths_are_numbered_nodes() {
+        assert!(is_hidraw(b"/dev/hidraw0"));
+        assert!(is_hidraw(b"/dev/hidraw12"));
+        assert!(!is_hidraw(b"/dev/hidraw"));
+        assert!(!is_hidraw(b"/dev/hidraw0/"));
+        assert!(!is_hidraw(b"/dev/hidraw0/../sda"));
+        assert!(!is_hidraw(b"/dev/hidraw../sda"));
+        assert!(!is_hidraw(b"/dev/hidraw-1"));
+        assert!(!is_hidraw(b"/dev/hidraw\xd9\xa1"));
+    }
+
+    #[test]
+    fn brokers_only_hidraw_paths() {
+        let path = |path: &'static [u8]| DevicePath(CStr::from_bytes_with_nul(path).unwrap());
+        env::set_var(BROKER_SOCKET_ENV, "/nonexistent/broker");
+        assert!(broker_for(path(b"pcsc://slot0\0")).is_none());
+        assert!(broker_for(path(b"nfc:/dev/nfc0\0")).is_none());
+        assert!(broker_for(path(b"/dev/hidraw0/../nonexistent\0")).is_none());
+        assert_eq!(
+            broker_for(path(b"/dev/hidraw4242\0")),
+            Some(PathBuf::from("/nonexistent/broker"))
+        );
+    }
+
+    fn tempfile() -> File {
+        let path = env::temp_dir().join(format!("libfido2-broker-{}", std::process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+        file
+    }
+}
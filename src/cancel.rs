@@ -0,0 +1,273 @@
+//! Cancelling in-flight requests from another thread, or when the user presses Ctrl-C.
+
+use crate::monitor::Monitor;
+use libfido2_sys::*;
+use std::{
+    fmt, io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+/// Cancels the requests of [`Device::cancellable`] when triggered, e.g. by a "Cancel" button or
+/// by Ctrl-C, see [`on_interrupt`].
+///
+/// # Remarks
+/// - Clones share the state, so one clone can be moved to the thread that cancels.
+/// - Once cancelled, a token stays cancelled, and fails every later request immediately.
+///
+/// [`Device::cancellable`]: struct.Device.html#method.cancellable
+/// [`on_interrupt`]: struct.CancellationToken.html#method.on_interrupt
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<TokenState>);
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    // The devices of the in-flight requests
+    devices: Mutex<Vec<Arc<Canceller>>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a token which is cancelled when the process receives SIGINT, or Ctrl-C on
+    /// Windows, so command line tools stop a request instead of leaving the device blinking.
+    ///
+    /// # Remarks
+    /// - Installs the handler on the first call, later calls return the same token.
+    /// - The first interrupt only cancels, so the process isn't terminated. Check
+    ///   [`is_cancelled`] after the request fails, and exit. A second interrupt terminates the
+    ///   process as usual.
+    ///
+    /// # Errors
+    /// - If the handler can't be installed, or the platform has no signals.
+    ///
+    /// [`is_cancelled`]: struct.CancellationToken.html#method.is_cancelled
+    pub fn on_interrupt() -> io::Result<Self> {
+        static INTERRUPT: OnceLock<Result<CancellationToken, io::ErrorKind>> = OnceLock::new();
+        INTERRUPT
+            .get_or_init(|| {
+                let token = CancellationToken::new();
+                interrupt::install(token.clone())
+                    .map(|()| token)
+                    .map_err(|error| error.kind())
+            })
+            .clone()
+            .map_err(io::Error::from)
+    }
+
+    /// Cancels the in-flight requests, and every later one.
+    ///
+    /// # Remarks
+    /// - Cancelled requests fail with `FIDO_ERR_KEEPALIVE_CANCEL`. A request the device
+    ///   finished just before may still succeed.
+    pub fn cancel(&self) {
+        let devices = self.0.devices.lock().unwrap_or_else(|e| e.into_inner());
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        for device in devices.iter() {
+            device.cancel();
+        }
+    }
+
+    /// Returns whether the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Registers a request on `device`, which is cancelled along with the token until the
+    /// returned guard is dropped, or returns `None` if the token was cancelled already.
+    pub(crate) fn register(&self, device: &Arc<Canceller>) -> Option<Registration<'_>> {
+        let mut devices = self.0.devices.lock().unwrap_or_else(|e| e.into_inner());
+        if self.is_cancelled() {
+            return None;
+        }
+        devices.push(device.clone());
+        Some(Registration {
+            token: self,
+            device: device.clone(),
+        })
+    }
+}
+
+pub(crate) struct Registration<'a> {
+    token: &'a CancellationToken,
+    device: Arc<Canceller>,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        let mut devices = self
+            .token
+            .0
+            .devices
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(i) = devices
+            .iter()
+            .position(|device| Arc::ptr_eq(device, &self.device))
+        {
+            devices.swap_remove(i);
+        }
+    }
+}
+
+/// Cancels the request in flight on a device from other threads, until the device revokes it
+/// before it is closed and freed.
+pub(crate) struct Canceller(Mutex<Option<Target>>);
+
+enum Target {
+    // Monitored devices are cancelled through their HID handle
+    Monitor(Arc<Monitor>),
+    // libfido2 only reads the device to send CTAPHID_CANCEL, which the request thread doesn't
+    // write, so this may run while another thread waits in the request
+    Device(usize),
+}
+
+impl Canceller {
+    pub(crate) fn new(device: *mut fido_dev_t, monitor: Option<Arc<Monitor>>) -> Arc<Self> {
+        let target = match monitor {
+            Some(monitor) => Target::Monitor(monitor),
+            None => Target::Device(device as usize),
+        };
+        Arc::new(Canceller(Mutex::new(Some(target))))
+    }
+
+    pub(crate) fn cancel(&self) {
+        let target = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match &*target {
+            Some(Target::Monitor(monitor)) => monitor.cancel(),
+            Some(Target::Device(device)) => unsafe {
+                let _ = fido_dev_cancel(*device as *mut fido_dev_t);
+            },
+            None => {}
+        }
+    }
+
+    /// Stops cancelling the device, waiting for a cancel in progress, so it can be closed.
+    pub(crate) fn revoke(&self) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+impl fmt::Debug for Canceller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Canceller").finish_non_exhaustive()
+    }
+}
+
+#[cfg(unix)]
+mod interrupt {
+    use super::CancellationToken;
+    use std::{
+        io,
+        os::raw,
+        sync::atomic::{AtomicI32, Ordering},
+        thread,
+    };
+
+    const SIGINT: raw::c_int = 2;
+    const SIG_DFL: usize = 0;
+    const SIG_ERR: usize = !0;
+
+    extern "C" {
+        fn signal(signum: raw::c_int, handler: usize) -> usize;
+        fn pipe(fds: *mut raw::c_int) -> raw::c_int;
+        fn read(fd: raw::c_int, buf: *mut raw::c_void, count: usize) -> isize;
+        fn write(fd: raw::c_int, buf: *const raw::c_void, count: usize) -> isize;
+    }
+
+    static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+    // Only calls `write`, as little else is safe in a signal handler
+    extern "C" fn handle(_signum: raw::c_int) {
+        unsafe {
+            let _ = write(
+                WRITE_FD.load(Ordering::SeqCst),
+                [0u8].as_ptr() as *const _,
+                1,
+            );
+        }
+    }
+
+    pub(super) fn install(token: CancellationToken) -> io::Result<()> {
+        let mut fds = [-1; 2];
+        unsafe {
+            if pipe(fds.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        WRITE_FD.store(fds[1], Ordering::SeqCst);
+        let read_fd = fds[0];
+        // Cancelling locks and talks to the devices, which the handler can't
+        thread::Builder::new()
+            .name("libfido2-interrupt".to_owned())
+            .spawn(move || {
+                let mut byte = 0u8;
+                while unsafe { read(read_fd, &mut byte as *mut u8 as *mut _, 1) } < 0 {
+                    if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                        return;
+                    }
+                }
+                unsafe { signal(SIGINT, SIG_DFL) };
+                token.cancel();
+            })?;
+        match unsafe { signal(SIGINT, handle as extern "C" fn(raw::c_int) as usize) } {
+            SIG_ERR => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod interrupt {
+    use super::CancellationToken;
+    use std::{io, sync::OnceLock};
+
+    const CTRL_C_EVENT: u32 = 0;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(
+            handler: Option<unsafe extern "system" fn(u32) -> i32>,
+            add: i32,
+        ) -> i32;
+    }
+
+    static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+
+    // Windows calls the handler on a new thread, so it can cancel directly
+    unsafe extern "system" fn handle(event: u32) -> i32 {
+        match TOKEN.get() {
+            Some(token) if event == CTRL_C_EVENT && !token.is_cancelled() => {
+                token.cancel();
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    pub(super) fn install(token: CancellationToken) -> io::Result<()> {
+        let _ = TOKEN.set(token);
+        match unsafe { SetConsoleCtrlHandler(Some(handle), 1) } {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod interrupt {
+    use super::CancellationToken;
+    use std::io;
+
+    pub(super) fn install(_token: CancellationToken) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no signals on this platform",
+        ))
+    }
+}
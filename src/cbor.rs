@@ -0,0 +1,547 @@
+use std::{
+    cmp::Ordering,
+    convert::{TryFrom, TryInto},
+    error, fmt, str,
+};
+
+// Nesting limit when skipping over items, so hostile input can't exhaust the stack
+const MAX_DEPTH: usize = 16;
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+
+/// Minimal CBOR reader for the few structures libfido2 hands back undecoded.
+///
+/// # Remarks
+/// - Indefinite length items are not supported, since CTAP2 requires definite lengths.
+/// - All methods return `None` on malformed or unexpected input.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    /// Returns the bytes that have not been read yet.
+    pub(crate) fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Reads an unsigned integer.
+    pub(crate) fn uint(&mut self) -> Option<u64> {
+        self.expect(MAJOR_UNSIGNED)
+    }
+
+    /// Reads a (possibly negative) integer.
+    pub(crate) fn int(&mut self) -> Option<i64> {
+        let (major, arg) = self.header()?;
+        match major {
+            MAJOR_UNSIGNED => i64::try_from(arg).ok(),
+            MAJOR_NEGATIVE => i64::try_from(arg).ok().map(|arg| -1 - arg),
+            _ => None,
+        }
+    }
+
+    /// Reads a boolean.
+    pub(crate) fn bool(&mut self) -> Option<bool> {
+        match self.header()? {
+            (MAJOR_SIMPLE, 20) => Some(false),
+            (MAJOR_SIMPLE, 21) => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Reads a byte string.
+    pub(crate) fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.expect(MAJOR_BYTES)?;
+        self.take(len)
+    }
+
+    /// Reads a UTF-8 text string.
+    pub(crate) fn text(&mut self) -> Option<&'a str> {
+        let len = self.expect(MAJOR_TEXT)?;
+        str::from_utf8(self.take(len)?).ok()
+    }
+
+    /// Reads the header of an array and returns the amount of items.
+    pub(crate) fn array(&mut self) -> Option<usize> {
+        self.expect(MAJOR_ARRAY)?.try_into().ok()
+    }
+
+    /// Reads the header of a map and returns the amount of key-value pairs.
+    pub(crate) fn map(&mut self) -> Option<usize> {
+        self.expect(MAJOR_MAP)?.try_into().ok()
+    }
+
+    /// Skips over the next item, including anything nested in it, and returns its encoding.
+    pub(crate) fn skip(&mut self) -> Option<&'a [u8]> {
+        let start = self.data;
+        self.skip_nested(0)?;
+        Some(&start[..start.len() - self.data.len()])
+    }
+
+    /// Searches a map with text keys for `key`, leaving the reader positioned at its value.
+    pub(crate) fn find(mut self, key: &str) -> Option<Reader<'a>> {
+        for _ in 0..self.map()? {
+            let mut peek = self;
+            if peek.text() == Some(key) {
+                return Some(peek);
+            }
+            self.skip()?;
+            self.skip()?;
+        }
+        None
+    }
+
+    fn skip_nested(&mut self, depth: usize) -> Option<()> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let (major, arg) = self.header()?;
+        match major {
+            MAJOR_UNSIGNED | MAJOR_NEGATIVE | MAJOR_SIMPLE => {}
+            MAJOR_BYTES | MAJOR_TEXT => {
+                self.take(arg)?;
+            }
+            MAJOR_ARRAY => {
+                for _ in 0..arg {
+                    self.skip_nested(depth + 1)?;
+                }
+            }
+            MAJOR_MAP => {
+                for _ in 0..arg {
+                    self.skip_nested(depth + 1)?;
+                    self.skip_nested(depth + 1)?;
+                }
+            }
+            MAJOR_TAG => self.skip_nested(depth + 1)?,
+            _ => unreachable!(),
+        }
+        Some(())
+    }
+
+    fn expect(&mut self, expected: u8) -> Option<u64> {
+        match self.header()? {
+            (major, arg) if major == expected => Some(arg),
+            _ => None,
+        }
+    }
+
+    fn header(&mut self) -> Option<(u8, u64)> {
+        let (&initial, rest) = self.data.split_first()?;
+        self.data = rest;
+        let major = initial >> 5;
+        let arg = match initial & 0x1f {
+            info @ 0..=23 => u64::from(info),
+            24 => u64::from(self.take(1)?[0]),
+            25 => u64::from(u16::from_be_bytes(self.take(2)?.try_into().ok()?)),
+            26 => u64::from(u32::from_be_bytes(self.take(4)?.try_into().ok()?)),
+            27 => u64::from_be_bytes(self.take(8)?.try_into().ok()?),
+            // Reserved values and indefinite lengths
+            _ => return None,
+        };
+        Some((major, arg))
+    }
+
+    fn take(&mut self, len: u64) -> Option<&'a [u8]> {
+        let len = usize::try_from(len).ok()?;
+        if len > self.data.len() {
+            return None;
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(taken)
+    }
+}
+
+/// Why a CBOR response is not in the CTAP2 canonical encoding, see `Device::set_strict_cbor`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CborViolation {
+    /// The response is truncated, has reserved values or invalid UTF-8, or nests too deep.
+    Malformed,
+    /// An integer, length or simple value doesn't use its shortest encoding.
+    NotShortest,
+    /// A string, array or map has an indefinite length.
+    IndefiniteLength,
+    /// An item is tagged, which CTAP2 doesn't allow.
+    Tag,
+    /// The keys of a map are not sorted by length, then bytewise.
+    UnsortedKeys,
+    /// A map has the same key twice.
+    DuplicateKey,
+    /// Bytes follow the response.
+    TrailingData,
+}
+
+impl error::Error for CborViolation {}
+
+impl fmt::Display for CborViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CborViolation::Malformed => write!(f, "The CBOR is malformed"),
+            CborViolation::NotShortest => {
+                write!(f, "A CBOR value is not encoded in its shortest form")
+            }
+            CborViolation::IndefiniteLength => write!(f, "A CBOR item has an indefinite length"),
+            CborViolation::Tag => write!(f, "A CBOR item is tagged"),
+            CborViolation::UnsortedKeys => write!(f, "The keys of a CBOR map are not sorted"),
+            CborViolation::DuplicateKey => write!(f, "A CBOR map has a duplicate key"),
+            CborViolation::TrailingData => write!(f, "Bytes follow the CBOR item"),
+        }
+    }
+}
+
+/// Checks that `data` is a single item in the CTAP2 canonical encoding: shortest headers,
+/// definite lengths, no tags, and map keys sorted by length, then bytewise, without duplicates.
+pub(crate) fn check_canonical(data: &[u8]) -> std::result::Result<(), CborViolation> {
+    let mut data = data;
+    canonical_item(&mut data, 0)?;
+    if data.is_empty() {
+        Ok(())
+    } else {
+        Err(CborViolation::TrailingData)
+    }
+}
+
+fn canonical_item(data: &mut &[u8], depth: usize) -> std::result::Result<(), CborViolation> {
+    if depth > MAX_DEPTH {
+        return Err(CborViolation::Malformed);
+    }
+    let (major, arg) = canonical_header(data)?;
+    match major {
+        MAJOR_UNSIGNED | MAJOR_NEGATIVE | MAJOR_SIMPLE => {}
+        MAJOR_BYTES => {
+            canonical_take(data, arg)?;
+        }
+        MAJOR_TEXT => {
+            str::from_utf8(canonical_take(data, arg)?).map_err(|_| CborViolation::Malformed)?;
+        }
+        MAJOR_ARRAY => {
+            for _ in 0..arg {
+                canonical_item(data, depth + 1)?;
+            }
+        }
+        MAJOR_MAP => {
+            let mut previous: Option<&[u8]> = None;
+            for _ in 0..arg {
+                let start = *data;
+                canonical_item(data, depth + 1)?;
+                let key = &start[..start.len() - data.len()];
+                if let Some(previous) = previous {
+                    // CTAP2 sorts shorter keys first, unlike RFC 8949
+                    match (previous.len(), previous).cmp(&(key.len(), key)) {
+                        Ordering::Less => {}
+                        Ordering::Equal => return Err(CborViolation::DuplicateKey),
+                        Ordering::Greater => return Err(CborViolation::UnsortedKeys),
+                    }
+                }
+                previous = Some(key);
+                canonical_item(data, depth + 1)?;
+            }
+        }
+        MAJOR_TAG => return Err(CborViolation::Tag),
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn canonical_header(data: &mut &[u8]) -> std::result::Result<(u8, u64), CborViolation> {
+    let (&initial, rest) = data.split_first().ok_or(CborViolation::Malformed)?;
+    *data = rest;
+    let major = initial >> 5;
+    let (arg, min) = match initial & 0x1f {
+        info @ 0..=23 => (u64::from(info), 0),
+        // Simple values below 32 must be encoded in the initial byte
+        24 => {
+            let min = if major == MAJOR_SIMPLE { 32 } else { 24 };
+            (u64::from(canonical_take(data, 1)?[0]), min)
+        }
+        // Floats have no shorter encoding to check for
+        25..=27 if major == MAJOR_SIMPLE => {
+            let len = 1 << ((initial & 0x1f) - 24);
+            canonical_take(data, len)?;
+            return Ok((major, 0));
+        }
+        25 => (u64::from(u16::from_be_bytes(canonical_array(data)?)), 0x100),
+        26 => (
+            u64::from(u32::from_be_bytes(canonical_array(data)?)),
+            0x1_0000,
+        ),
+        27 => (u64::from_be_bytes(canonical_array(data)?), 0x1_0000_0000),
+        31 => return Err(CborViolation::IndefiniteLength),
+        _ => return Err(CborViolation::Malformed),
+    };
+    if arg < min {
+        return Err(CborViolation::NotShortest);
+    }
+    Ok((major, arg))
+}
+
+fn canonical_array<const N: usize>(
+    data: &mut &[u8],
+) -> std::result::Result<[u8; N], CborViolation> {
+    canonical_take(data, N as u64)?
+        .try_into()
+        .map_err(|_| CborViolation::Malformed)
+}
+
+fn canonical_take<'a>(
+    data: &mut &'a [u8],
+    len: u64,
+) -> std::result::Result<&'a [u8], CborViolation> {
+    let len = usize::try_from(len).map_err(|_| CborViolation::Malformed)?;
+    if len > data.len() {
+        return Err(CborViolation::Malformed);
+    }
+    let (taken, rest) = data.split_at(len);
+    *data = rest;
+    Ok(taken)
+}
+
+/// Minimal CBOR writer, producing the canonical (shortest) encoding of every header.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn new() -> Self {
+        Writer::default()
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub(crate) fn uint(&mut self, value: u64) -> &mut Self {
+        self.header(MAJOR_UNSIGNED, value)
+    }
+
+    pub(crate) fn int(&mut self, value: i64) -> &mut Self {
+        if value < 0 {
+            // -1 - value can't overflow for negative values
+            self.header(MAJOR_NEGATIVE, (-1 - value) as u64)
+        } else {
+            self.header(MAJOR_UNSIGNED, value as u64)
+        }
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) -> &mut Self {
+        // The simple values false (20) and true (21)
+        self.header(MAJOR_SIMPLE, if value { 21 } else { 20 })
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.header(MAJOR_BYTES, value.len() as u64);
+        self.data.extend_from_slice(value);
+        self
+    }
+
+    pub(crate) fn text(&mut self, value: &str) -> &mut Self {
+        self.header(MAJOR_TEXT, value.len() as u64);
+        self.data.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    /// Appends an item that is encoded already.
+    pub(crate) fn raw(&mut self, encoded: &[u8]) -> &mut Self {
+        self.data.extend_from_slice(encoded);
+        self
+    }
+
+    /// Writes the header of an array, which must be followed by `len` items.
+    pub(crate) fn array(&mut self, len: usize) -> &mut Self {
+        self.header(MAJOR_ARRAY, len as u64)
+    }
+
+    /// Writes the header of a map, which must be followed by `len` key-value pairs.
+    pub(crate) fn map(&mut self, len: usize) -> &mut Self {
+        self.header(MAJOR_MAP, len as u64)
+    }
+
+    fn header(&mut self, major: u8, arg: u64) -> &mut Self {
+        let major = major << 5;
+        match arg {
+            0..=23 => self.data.push(major | arg as u8),
+            24..=0xff => self.data.extend_from_slice(&[major | 24, arg as u8]),
+            0x100..=0xffff => {
+                self.data.push(major | 25);
+                self.data.extend_from_slice(&(arg as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                self.data.push(major | 26);
+                self.data.extend_from_slice(&(arg as u32).to_be_bytes());
+            }
+            _ => {
+                self.data.push(major | 27);
+                self.data.extend_from_slice(&arg.to_be_bytes());
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::quickcheck;
+
+    #[test]
+    fn truncated_items_are_rejected() {
+        assert_eq!(Reader::new(&[]).uint(), None);
+        assert_eq!(Reader::new(&[0x18]).uint(), None);
+        assert_eq!(Reader::new(&[0x19, 0x01]).uint(), None);
+        assert_eq!(Reader::new(&[0x1b, 0, 0, 0, 0, 0, 0, 0]).uint(), None);
+        assert_eq!(Reader::new(&[0x45, 1, 2]).bytes(), None);
+        assert_eq!(Reader::new(&[0x82, 0x01]).skip(), None);
+        assert_eq!(Reader::new(&[0xa1, 0x01]).skip(), None);
+        assert_eq!(
+            check_canonical(&[0x45, 1, 2]),
+            Err(CborViolation::Malformed)
+        );
+    }
+
+    #[test]
+    fn over_long_lengths_are_rejected() {
+        let huge = [0xff; 8];
+        for major in [MAJOR_BYTES, MAJOR_TEXT, MAJOR_ARRAY, MAJOR_MAP] {
+            let mut data = vec![major << 5 | 27];
+            data.extend_from_slice(&huge);
+            data.push(0);
+            assert_eq!(Reader::new(&data).skip(), None);
+            assert_eq!(check_canonical(&data), Err(CborViolation::Malformed));
+        }
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| {
+            let mut data = vec![0x81; depth];
+            data.push(0);
+            data
+        };
+        let deepest = nested(MAX_DEPTH);
+        assert_eq!(Reader::new(&deepest).skip(), Some(deepest.as_slice()));
+        assert_eq!(check_canonical(&deepest), Ok(()));
+
+        let too_deep = nested(MAX_DEPTH + 1);
+        assert_eq!(Reader::new(&too_deep).skip(), None);
+        assert_eq!(check_canonical(&too_deep), Err(CborViolation::Malformed));
+
+        // Tags nest like arrays
+        let mut tags = vec![0xc1; MAX_DEPTH + 1];
+        tags.push(0);
+        assert_eq!(Reader::new(&tags).skip(), None);
+    }
+
+    #[test]
+    fn integer_boundaries() {
+        assert_eq!(Reader::new(&[0x17]).uint(), Some(23));
+        assert_eq!(Reader::new(&[0x18, 0xff]).uint(), Some(0xff));
+        assert_eq!(Reader::new(&[0x19, 0x01, 0x00]).uint(), Some(0x100));
+        let max = [0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        assert_eq!(Reader::new(&max).uint(), Some(u64::MAX));
+        assert_eq!(Reader::new(&[0x20]).int(), Some(-1));
+        let min = [0x3b, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        assert_eq!(Reader::new(&min).int(), Some(i64::MIN));
+        // Beyond i64
+        assert_eq!(Reader::new(&[0x3b, 0x80, 0, 0, 0, 0, 0, 0, 0]).int(), None);
+        assert_eq!(Reader::new(&[0x1b, 0x80, 0, 0, 0, 0, 0, 0, 0]).int(), None);
+        // Reserved additional information, and indefinite lengths
+        assert_eq!(Reader::new(&[0x1c]).uint(), None);
+        assert_eq!(Reader::new(&[0x5f, 0xff]).skip(), None);
+    }
+
+    #[test]
+    fn canonical_violations() {
+        assert_eq!(check_canonical(&[0x18, 0x17]), Err(CborViolation::NotShortest));
+        assert_eq!(
+            check_canonical(&[0x19, 0x00, 0xff]),
+            Err(CborViolation::NotShortest)
+        );
+        assert_eq!(check_canonical(&[0xf8, 0x1f]), Err(CborViolation::NotShortest));
+        assert_eq!(
+            check_canonical(&[0x5f, 0xff]),
+            Err(CborViolation::IndefiniteLength)
+        );
+        assert_eq!(check_canonical(&[0xc1, 0x00]), Err(CborViolation::Tag));
+        assert_eq!(check_canonical(&[0x00, 0x00]), Err(CborViolation::TrailingData));
+        assert_eq!(
+            check_canonical(&[0xa2, 0x01, 0x00, 0x01, 0x00]),
+            Err(CborViolation::DuplicateKey)
+        );
+        assert_eq!(
+            check_canonical(&[0xa2, 0x02, 0x00, 0x01, 0x00]),
+            Err(CborViolation::UnsortedKeys)
+        );
+        // Shorter keys sort first, so 24 (two bytes) follows -1 (one byte)
+        assert_eq!(check_canonical(&[0xa2, 0x20, 0x00, 0x18, 0x18, 0x00]), Ok(()));
+        assert_eq!(
+            check_canonical(&[0x62, 0xff, 0xfe]),
+            Err(CborViolation::Malformed)
+        );
+    }
+
+    #[test]
+    fn find_positions_at_value() {
+        // {"a": 1, "b": [2]}
+        let map = [0xa2, 0x61, b'a', 0x01, 0x61, b'b', 0x81, 0x02];
+        let mut value = Reader::new(&map).find("b").unwrap();
+        assert_eq!(value.array(), Some(1));
+        assert_eq!(value.uint(), Some(2));
+        assert!(Reader::new(&map).find("c").is_none());
+        assert!(Reader::new(&map[..5]).find("b").is_none());
+        assert!(Reader::new(&[0x01]).find("a").is_none());
+    }
+
+    #[test]
+    fn reads_back_written_items() {
+        fn prop(ints: Vec<i64>, bytes: Vec<u8>, text: String, flag: bool) -> bool {
+            let mut writer = Writer::new();
+            writer.array(ints.len() + 3);
+            for &int in &ints {
+                writer.int(int);
+            }
+            writer.bytes(&bytes).text(&text).bool(flag);
+            let encoded = writer.into_bytes();
+
+            let mut reader = Reader::new(&encoded);
+            let read = reader.array() == Some(ints.len() + 3)
+                && ints.iter().all(|&int| reader.int() == Some(int))
+                && reader.bytes() == Some(bytes.as_slice())
+                && reader.text() == Some(text.as_str())
+                && reader.bool() == Some(flag)
+                && reader.is_empty();
+            read && Reader::new(&encoded).skip() == Some(encoded.as_slice())
+                && check_canonical(&encoded).is_ok()
+        }
+        quickcheck(prop as fn(Vec<i64>, Vec<u8>, String, bool) -> bool);
+    }
+
+    #[test]
+    fn arbitrary_input_does_not_panic() {
+        fn prop(data: Vec<u8>) -> bool {
+            let mut reader = Reader::new(&data);
+            if let Some(item) = reader.skip() {
+                assert_eq!(item.len() + reader.remaining().len(), data.len());
+            }
+            let _ = Reader::new(&data).find("key");
+            let _ = check_canonical(&data);
+            true
+        }
+        quickcheck(prop as fn(Vec<u8>) -> bool);
+    }
+}
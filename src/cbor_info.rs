@@ -1,7 +1,17 @@
-use crate::ffi::*;
+use crate::{
+    ffi::*, message_size, Aaguid, AssertionCreationData, CredentialCreationData, Extensions,
+    FidoError, RequestTooLarge, Result,
+};
 use libfido2_sys::*;
-use std::{collections::HashMap, iter::FromIterator, slice, str};
-use std::convert::TryInto;
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::{Infallible, TryInto},
+    ffi::CStr,
+    fmt,
+    iter::FromIterator,
+    slice,
+    str::{self, FromStr},
+};
 
 /// Owns additional data stored as CBOR on a device.
 #[derive(PartialEq, Eq)]
@@ -15,60 +25,466 @@ pub struct CBORDataRef<'a> {
     pub pin_protocols: &'a [u8],
     pub extensions: Box<[&'a str]>,
     pub ctap_versions: Box<[&'a str]>,
+    pub transports: Box<[&'a str]>,
     pub options: HashMap<&'a str, bool>,
+    /// Minimum PIN length in Unicode code points, or `None` if the device doesn't report it.
+    pub min_pin_length: Option<usize>,
+    /// Maximum amount of credentials in an allow or exclude list (`maxCredentialCountInList`).
+    pub max_credential_count_in_list: Option<usize>,
+    /// Maximum length of credential IDs in bytes (`maxCredentialIdLength`).
+    pub max_credential_id_length: Option<usize>,
+    /// Maximum size of a CTAP2 message in bytes (`maxMsgSize`).
+    pub max_msg_size: Option<usize>,
+}
+
+/// Owned version of [`CBORDataRef`], which does not borrow from a [`CBORData`].
+///
+/// [`CBORDataRef`]: struct.CBORDataRef.html
+/// [`CBORData`]: struct.CBORData.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthenticatorInfo {
+    pub aag_uid: Option<Vec<u8>>,
+    pub pin_protocols: Vec<u8>,
+    pub extensions: Vec<String>,
+    pub ctap_versions: Vec<String>,
+    pub transports: Vec<String>,
+    pub options: HashMap<String, bool>,
+    pub min_pin_length: Option<usize>,
+    pub max_credential_count_in_list: Option<usize>,
+    pub max_credential_id_length: Option<usize>,
+    pub max_msg_size: Option<usize>,
+}
+
+/// Structured delta between two [`AuthenticatorInfo`]s, as produced by [`AuthenticatorInfo::diff`].
+///
+/// [`AuthenticatorInfo`]: struct.AuthenticatorInfo.html
+/// [`AuthenticatorInfo::diff`]: struct.AuthenticatorInfo.html#method.diff
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AuthenticatorInfoDiff {
+    /// Options that were added, removed or toggled, sorted by name.
+    pub options: Vec<OptionChange>,
+    pub ctap_versions: SetDiff,
+    pub extensions: SetDiff,
+    pub transports: SetDiff,
+}
+
+/// A CTAP2 extension, as reported by a device and used in requests.
+///
+/// # Remarks
+/// - Converts from and to the identifier used on the wire, e.g. `hmac-secret`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Extension {
+    CredBlob,
+    CredProtect,
+    HmacSecret,
+    LargeBlobKey,
+    MinPinLength,
+    /// An extension this crate has no name for.
+    Other(String),
+}
+
+impl Extension {
+    /// Returns the identifier of the extension.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Extension::CredBlob => "credBlob",
+            Extension::CredProtect => "credProtect",
+            Extension::HmacSecret => "hmac-secret",
+            Extension::LargeBlobKey => "largeBlobKey",
+            Extension::MinPinLength => "minPinLength",
+            Extension::Other(name) => name,
+        }
+    }
+}
+
+impl FromStr for Extension {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "credBlob" => Extension::CredBlob,
+            "credProtect" => Extension::CredProtect,
+            "hmac-secret" => Extension::HmacSecret,
+            "largeBlobKey" => Extension::LargeBlobKey,
+            "minPinLength" => Extension::MinPinLength,
+            other => Extension::Other(other.to_owned()),
+        })
+    }
+}
+
+impl AsRef<str> for Extension {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A CTAP version, as reported by a device.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CtapVersion {
+    /// CTAP1/U2F (`U2F_V2`).
+    U2fV2,
+    /// CTAP 2.0 (`FIDO_2_0`).
+    Fido20,
+    /// Preview of CTAP 2.1, as implemented by some CTAP 2.0 devices (`FIDO_2_1_PRE`).
+    Fido21Pre,
+    /// CTAP 2.1 (`FIDO_2_1`).
+    Fido21,
+    /// A version this crate has no name for.
+    Other(String),
+}
+
+impl CtapVersion {
+    /// Returns the identifier of the version.
+    pub fn as_str(&self) -> &str {
+        match self {
+            CtapVersion::U2fV2 => "U2F_V2",
+            CtapVersion::Fido20 => "FIDO_2_0",
+            CtapVersion::Fido21Pre => "FIDO_2_1_PRE",
+            CtapVersion::Fido21 => "FIDO_2_1",
+            CtapVersion::Other(name) => name,
+        }
+    }
+}
+
+impl FromStr for CtapVersion {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "U2F_V2" => CtapVersion::U2fV2,
+            "FIDO_2_0" => CtapVersion::Fido20,
+            "FIDO_2_1_PRE" => CtapVersion::Fido21Pre,
+            "FIDO_2_1" => CtapVersion::Fido21,
+            other => CtapVersion::Other(other.to_owned()),
+        })
+    }
+}
+
+impl AsRef<str> for CtapVersion {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for CtapVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A change of a single option. `None` means the option was not reported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OptionChange {
+    pub name: String,
+    pub old: Option<bool>,
+    pub new: Option<bool>,
+}
+
+/// Entries that were added to or removed from a list, sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SetDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl AuthenticatorInfo {
+    /// Checks whether the device supports `extension`, e.g. `Extension::HmacSecret` or `"hmac-secret"`.
+    pub fn supports_extension(&self, extension: impl AsRef<str>) -> bool {
+        self.extensions.iter().any(|e| e == extension.as_ref())
+    }
+
+    /// Checks whether the `authenticatorMakeCredential` request for `data` fits in the
+    /// `maxMsgSize` of the device, with a PIN if `pin` is set.
+    ///
+    /// # Remarks
+    /// - Devices without a reported `maxMsgSize` accept any size here. CTAP2 devices must accept
+    ///   at least 1024 bytes.
+    /// - Long user names, icon URLs and exclude lists are the usual cause of oversized requests,
+    ///   which devices reject with `FIDO_ERR_REQUEST_TOO_LARGE` or a transport error.
+    /// - `Device::request_credential_creation_with` and `request_credential_creation_with_uv`
+    ///   check this before sending the request.
+    pub fn check_credential_request(
+        &self,
+        data: &CredentialCreationData<'_>,
+        pin: bool,
+    ) -> std::result::Result<(), RequestTooLarge> {
+        let len = message_size::credential_request_len(data, None, pin);
+        RequestTooLarge::check(self.max_msg_size, len)
+    }
+
+    /// Checks whether the `authenticatorMakeCredential` request for `data`, with its extensions
+    /// replaced by `extensions` like `CredentialCreator::set_extensions` does, fits in the
+    /// `maxMsgSize` of the device, with a PIN if `pin` is set.
+    ///
+    /// # Remarks
+    /// - A `credBlob` to store can be as large as `maxCredBlobLength`, so it counts here.
+    pub fn check_credential_request_with_extensions(
+        &self,
+        data: &CredentialCreationData<'_>,
+        extensions: &Extensions<'_>,
+        pin: bool,
+    ) -> std::result::Result<(), RequestTooLarge> {
+        let len = message_size::credential_request_len(data, Some(extensions), pin);
+        RequestTooLarge::check(self.max_msg_size, len)
+    }
+
+    /// Checks whether the `authenticatorGetAssertion` request for `data` fits in the
+    /// `maxMsgSize` of the device, with a PIN if `pin` is set.
+    ///
+    /// # Remarks
+    /// - Long allow lists are the usual cause of oversized requests. See
+    ///   `Device::request_assertion_verification_chunked`.
+    /// - `Device::request_assertion_verification_with` and
+    ///   `request_assertion_verification_with_uv` check this before sending the request.
+    pub fn check_assertion_request(
+        &self,
+        data: &AssertionCreationData<'_>,
+        pin: bool,
+    ) -> std::result::Result<(), RequestTooLarge> {
+        RequestTooLarge::check(self.max_msg_size, message_size::assertion_request_len(data, pin))
+    }
+
+    /// Checks whether the device supports `transport`, e.g. `"usb"` or `"nfc"`.
+    pub fn supports_transport(&self, transport: &str) -> bool {
+        self.transports.iter().any(|t| t == transport)
+    }
+
+    /// Returns the reported extensions as [`Extension`]s.
+    ///
+    /// [`Extension`]: enum.Extension.html
+    pub fn typed_extensions(&self) -> impl Iterator<Item = Extension> + '_ {
+        self.extensions.iter().map(|e| e.parse().unwrap_or_else(|e| match e {}))
+    }
+
+    /// Returns the AAGUID as an [`Aaguid`], e.g. to look up the name of the model.
+    ///
+    /// [`Aaguid`]: struct.Aaguid.html
+    pub fn typed_aaguid(&self) -> Option<Aaguid> {
+        self.aag_uid.as_deref().and_then(Aaguid::from_slice)
+    }
+
+    /// Returns the reported CTAP versions as [`CtapVersion`]s.
+    ///
+    /// [`CtapVersion`]: enum.CtapVersion.html
+    pub fn typed_ctap_versions(&self) -> impl Iterator<Item = CtapVersion> + '_ {
+        self.ctap_versions
+            .iter()
+            .map(|v| v.parse().unwrap_or_else(|e| match e {}))
+    }
+
+    /// Checks whether the device supports `version`, e.g. `CtapVersion::Fido20` or `"FIDO_2_0"`.
+    pub fn supports_ctap_version(&self, version: impl AsRef<str>) -> bool {
+        self.ctap_versions.iter().any(|v| v == version.as_ref())
+    }
+
+    /// Checks whether the device supports CTAP2, in any version.
+    pub fn supports_ctap2(&self) -> bool {
+        self.typed_ctap_versions().any(|version| match version {
+            CtapVersion::Fido20 | CtapVersion::Fido21Pre | CtapVersion::Fido21 => true,
+            CtapVersion::U2fV2 | CtapVersion::Other(_) => false,
+        })
+    }
+
+    /// Checks whether the device supports CTAP 2.1.
+    ///
+    /// # Remarks
+    /// - Devices only implementing the preview (`FIDO_2_1_PRE`) don't count, since some commands
+    ///   differ. See [`supports_ctap21_pre`].
+    ///
+    /// [`supports_ctap21_pre`]: struct.AuthenticatorInfo.html#method.supports_ctap21_pre
+    pub fn supports_ctap21(&self) -> bool {
+        self.supports_ctap_version(CtapVersion::Fido21)
+    }
+
+    /// Checks whether the device supports the CTAP 2.1 preview (`FIDO_2_1_PRE`).
+    pub fn supports_ctap21_pre(&self) -> bool {
+        self.supports_ctap_version(CtapVersion::Fido21Pre)
+    }
+
+    /// Compares the options, CTAP versions, extensions and transports of `self` (old) against `other` (new).
+    pub fn diff(&self, other: &AuthenticatorInfo) -> AuthenticatorInfoDiff {
+        let names = self
+            .options
+            .keys()
+            .chain(other.options.keys())
+            .collect::<BTreeSet<_>>();
+        let options = names
+            .into_iter()
+            .filter_map(|name| {
+                let old = self.options.get(name).copied();
+                let new = other.options.get(name).copied();
+                if old != new {
+                    Some(OptionChange {
+                        name: name.clone(),
+                        old,
+                        new,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        AuthenticatorInfoDiff {
+            options,
+            ctap_versions: SetDiff::new(&self.ctap_versions, &other.ctap_versions),
+            extensions: SetDiff::new(&self.extensions, &other.extensions),
+            transports: SetDiff::new(&self.transports, &other.transports),
+        }
+    }
+}
+
+impl AuthenticatorInfoDiff {
+    /// Returns whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.options.is_empty()
+            && self.ctap_versions.is_empty()
+            && self.extensions.is_empty()
+            && self.transports.is_empty()
+    }
+}
+
+impl SetDiff {
+    fn new(old: &[String], new: &[String]) -> Self {
+        let old = old.iter().collect::<BTreeSet<_>>();
+        let new = new.iter().collect::<BTreeSet<_>>();
+        SetDiff {
+            added: new.difference(&old).map(|s| (*s).clone()).collect(),
+            removed: old.difference(&new).map(|s| (*s).clone()).collect(),
+        }
+    }
+
+    /// Returns whether nothing was added or removed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl CBORDataRef<'_> {
+    /// Checks whether the device supports `extension`, e.g. `Extension::HmacSecret` or `"hmac-secret"`.
+    pub fn supports_extension(&self, extension: impl AsRef<str>) -> bool {
+        self.extensions.contains(&extension.as_ref())
+    }
+
+    /// Checks whether the device supports `transport`, e.g. `"usb"` or `"nfc"`.
+    pub fn supports_transport(&self, transport: &str) -> bool {
+        self.transports.contains(&transport)
+    }
+
+    /// Copies the data so it no longer borrows from the [`CBORData`].
+    ///
+    /// [`CBORData`]: struct.CBORData.html
+    pub fn into_owned(self) -> AuthenticatorInfo {
+        AuthenticatorInfo {
+            aag_uid: self.aag_uid.map(<[u8]>::to_vec),
+            pin_protocols: self.pin_protocols.to_vec(),
+            extensions: self.extensions.iter().map(|s| (*s).to_owned()).collect(),
+            ctap_versions: self.ctap_versions.iter().map(|s| (*s).to_owned()).collect(),
+            transports: self.transports.iter().map(|s| (*s).to_owned()).collect(),
+            options: self
+                .options
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value))
+                .collect(),
+            min_pin_length: self.min_pin_length,
+            max_credential_count_in_list: self.max_credential_count_in_list,
+            max_credential_id_length: self.max_credential_id_length,
+            max_msg_size: self.max_msg_size,
+        }
+    }
 }
 
 impl CBORData {
-    pub fn as_ref<'a>(&'a self) -> CBORDataRef<'a> {
+    /// Borrows the data reported by the device.
+    ///
+    /// # Panics
+    /// - When lengths reported by libfido2 don't fit in a `usize`. See [`try_as_ref`].
+    ///
+    /// [`try_as_ref`]: struct.CBORData.html#method.try_as_ref
+    pub fn as_ref(&self) -> CBORDataRef<'_> {
+        self.try_as_ref().expect("Invalid CBOR data")
+    }
+
+    /// Borrows the data reported by the device.
+    pub fn try_as_ref(&self) -> Result<CBORDataRef<'_>> {
         unsafe {
             let cbor_info = self.raw.as_ptr();
 
-            let aag_uid = fido_cbor_info_aaguid_ptr(cbor_info)
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_cbor_info_aaguid_len(cbor_info).try_into().unwrap()));
-
-            let pin_protocols = fido_cbor_info_protocols_ptr(cbor_info)
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_cbor_info_protocols_len(cbor_info).try_into().unwrap()))
-                .unwrap_or(&[]);
-
-            let extensions = fido_cbor_info_extensions_ptr(cbor_info)
-                .as_ref()
-                .map(|ptr| convert_cstr_array_ptr(ptr, fido_cbor_info_extensions_len(cbor_info).try_into().unwrap()))
-                .unwrap_or(Box::new([]));
-
-            let ctap_versions = fido_cbor_info_versions_ptr(cbor_info)
-                .as_ref()
-                .map(|ptr| convert_cstr_array_ptr(ptr, fido_cbor_info_versions_len(cbor_info).try_into().unwrap()))
-                .unwrap_or(Box::new([]));
-
-            let options = fido_cbor_info_options_name_ptr(cbor_info)
-                .as_ref()
-                .map(|ptr| convert_cstr_array_ptr(ptr, fido_cbor_info_options_len(cbor_info).try_into().unwrap()))
-                .map(|names| {
-                    let values = fido_cbor_info_options_value_ptr(cbor_info)
-                        .as_ref()
-                        .map(|ptr| slice::from_raw_parts(ptr, names.len()))
-                        .unwrap();
-                    (names, values)
-                })
-                .map(|(names, values)| {
+            let aag_uid = bytes(fido_cbor_info_aaguid_ptr(cbor_info), fido_cbor_info_aaguid_len(cbor_info))?;
+
+            let pin_protocols = bytes(
+                fido_cbor_info_protocols_ptr(cbor_info),
+                fido_cbor_info_protocols_len(cbor_info),
+            )?
+            .unwrap_or(&[]);
+
+            let extensions = match fido_cbor_info_extensions_ptr(cbor_info).as_ref() {
+                Some(ptr) => convert_cstr_array_ptr(ptr, to_usize(fido_cbor_info_extensions_len(cbor_info))?),
+                None => Box::new([]),
+            };
+
+            let ctap_versions = match fido_cbor_info_versions_ptr(cbor_info).as_ref() {
+                Some(ptr) => convert_cstr_array_ptr(ptr, to_usize(fido_cbor_info_versions_len(cbor_info))?),
+                None => Box::new([]),
+            };
+
+            let transports = match fido_cbor_info_transports_ptr(cbor_info).as_ref() {
+                Some(ptr) => convert_cstr_array_ptr(ptr, to_usize(fido_cbor_info_transports_len(cbor_info))?),
+                None => Box::new([]),
+            };
+
+            let options_len = to_usize(fido_cbor_info_options_len(cbor_info))?;
+            let options = match (
+                fido_cbor_info_options_name_ptr(cbor_info).as_ref(),
+                fido_cbor_info_options_value_ptr(cbor_info).as_ref(),
+            ) {
+                (Some(names), Some(values)) => {
+                    let names = slice::from_raw_parts(names, options_len);
+                    let values = slice::from_raw_parts(values, options_len);
                     HashMap::from_iter(
                         names
                             .iter()
                             .zip(values)
-                            .map(|(name, value)| (*name, *value)),
+                            .filter(|(name, _)| !name.is_null())
+                            .map(|(name, value)| {
+                                (str::from_utf8_unchecked(CStr::from_ptr(*name).to_bytes()), *value)
+                            }),
                     )
-                })
-                .unwrap_or(HashMap::with_capacity(0));
+                }
+                _ => HashMap::with_capacity(0),
+            };
+
+            let min_pin_length = match fido_cbor_info_minpinlen(cbor_info) {
+                0 => None,
+                len => Some(len.try_into().map_err(|_| FidoError(FIDO_ERR_INTERNAL))?),
+            };
+            // Limits are reported as 0 if missing
+            let limit = |limit: u64| match limit {
+                0 => Ok(None),
+                limit => limit.try_into().map(Some).map_err(|_| FidoError(FIDO_ERR_INTERNAL)),
+            };
+            let max_credential_count_in_list = limit(fido_cbor_info_maxcredcntlst(cbor_info))?;
+            let max_credential_id_length = limit(fido_cbor_info_maxcredidlen(cbor_info))?;
+            let max_msg_size = limit(fido_cbor_info_maxmsgsiz(cbor_info))?;
 
-            CBORDataRef {
+            Ok(CBORDataRef {
                 aag_uid,
                 pin_protocols,
                 extensions,
                 ctap_versions,
+                transports,
                 options,
-            }
+                min_pin_length,
+                max_credential_count_in_list,
+                max_credential_id_length,
+                max_msg_size,
+            })
         }
     }
 }
@@ -82,7 +498,7 @@ impl Drop for CBORData {
         unsafe {
             let mut cbor_info = self.raw.as_ptr_mut();
             fido_cbor_info_free(&mut cbor_info as *mut _);
-            assert!(cbor_info.is_null());
+            debug_assert!(cbor_info.is_null());
         }
     }
 }
@@ -0,0 +1,416 @@
+//! The WebAuthn authentication and registration ceremonies, with each step as a state of
+//! [`Ceremony`] and [`Registration`], so steps can't be skipped or taken out of order.
+//!
+//! [`Ceremony`]: struct.Ceremony.html
+//! [`Registration`]: struct.Registration.html
+
+use crate::{
+    attestation::{AttestationError, Verifier},
+    auth_data::AuthData,
+    cbor::Reader,
+    credential::ct_eq,
+    openssl, Assertion, AssertionCreationData, Authenticator, BackupTransition, CreatedCredential,
+    CredentialCreationData, Fido, FidoError, RegistrationRecord, Result, SignCountStatus,
+};
+use libfido2_sys::*;
+use std::ffi::CStr;
+
+/// An authentication ceremony in state `S`.
+///
+/// Starts as [`ChallengeIssued`], with the client data hash and allow list of the request, and
+/// moves through [`AssertionObtained`] and [`Verified`] to [`SignCountUpdated`]:
+///
+/// ```no_run
+/// # use libfido2::*;
+/// # fn sign_in(fido: &Fido, device: &mut Device, data: AssertionCreationData<'_>,
+/// #     record: &mut RegistrationRecord) -> Result<(), FidoError> {
+/// let ceremony = Ceremony::new(data)
+///     .request_assertion(fido, device, None)?
+///     .verify(record)?
+///     .update_sign_count(record);
+/// if ceremony.sign_count_status().is_suspicious() {
+///     // Flag the account for review
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`ChallengeIssued`]: struct.ChallengeIssued.html
+/// [`AssertionObtained`]: struct.AssertionObtained.html
+/// [`Verified`]: struct.Verified.html
+/// [`SignCountUpdated`]: struct.SignCountUpdated.html
+pub struct Ceremony<'a, S> {
+    data: AssertionCreationData<'a>,
+    state: S,
+}
+
+/// The request was created, but not answered yet.
+#[derive(Copy, Clone, Debug)]
+pub struct ChallengeIssued(());
+
+/// An authenticator answered the request, with statements that are not verified yet.
+pub struct AssertionObtained(Assertion);
+
+/// The statement of the credential was verified.
+pub struct Verified {
+    assertion: Assertion,
+    statement: usize,
+    sign_count: u32,
+    backup: BackupTransition,
+}
+
+/// The sign count of the credential was checked, and stored with the backup state if it isn't
+/// suspicious.
+pub struct SignCountUpdated {
+    assertion: Assertion,
+    statement: usize,
+    status: SignCountStatus,
+    backup: BackupTransition,
+}
+
+impl<'a> Ceremony<'a, ChallengeIssued> {
+    /// Starts a ceremony for the request `data`, whose client data hash every statement must
+    /// be made over.
+    pub fn new(data: AssertionCreationData<'a>) -> Self {
+        Ceremony {
+            data,
+            state: ChallengeIssued(()),
+        }
+    }
+
+    /// Requests the assertion from `authenticator`.
+    pub fn request_assertion<A: Authenticator + ?Sized>(
+        self,
+        fido: &Fido,
+        authenticator: &mut A,
+        pin: Option<&CStr>,
+    ) -> Result<Ceremony<'a, AssertionObtained>> {
+        let creator = fido.new_assertion_creator(self.data)?;
+        let assertion = authenticator.request_assertion_verification(creator, pin)?;
+        Ok(self.with_assertion(assertion))
+    }
+
+    /// Continues with an assertion that was requested elsewhere, e.g. with extensions the
+    /// ceremony doesn't set. It is checked against the request in [`verify`].
+    ///
+    /// [`verify`]: struct.Ceremony.html#method.verify
+    pub fn with_assertion(self, assertion: Assertion) -> Ceremony<'a, AssertionObtained> {
+        Ceremony {
+            data: self.data,
+            state: AssertionObtained(assertion),
+        }
+    }
+}
+
+impl<'a> Ceremony<'a, AssertionObtained> {
+    /// Returns the unverified assertion.
+    pub fn assertion(&self) -> &Assertion {
+        &self.state.0
+    }
+
+    /// Verifies the statement of the credential of `record`.
+    ///
+    /// # Remarks
+    /// - The statement must be made over the client data hash of the request and, if the
+    ///   request had an allow list, be of an allowed credential. Statements without a
+    ///   credential ID only count if a single credential was allowed.
+    /// - The backup flags of the statement are checked against `record`, see
+    ///   `RegistrationRecord::check_backup_state`.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_NOTFOUND` if no statement is of the credential.
+    /// - `FIDO_ERR_INVALID_PARAM` if the statement doesn't match the request, its authenticator
+    ///   data is malformed, or its backup flags are a violation, see
+    ///   `BackupTransition::is_violation`.
+    /// - The error of verifying the signature, see `Assertion::iter_verified`.
+    pub fn verify(self, record: &RegistrationRecord) -> Result<Ceremony<'a, Verified>> {
+        let data = self.data;
+        let assertion = self.state.0;
+        let (statement, found) = assertion
+            .iter()
+            .enumerate()
+            .find(|(_, statement)| match statement.credential_id {
+                Some(id) => record.id.ct_eq(id),
+                None => data
+                    .allowed_credential_ids
+                    .is_some_and(|allowed| allowed.len() == 1 && record.id.ct_eq(allowed[0])),
+            })
+            .ok_or(FidoError(FIDO_ERR_NOTFOUND))?;
+        if !found.client_data_hash_matches(data.client_data_hash) || !found.matches_allowed(&data) {
+            return Err(FidoError(FIDO_ERR_INVALID_PARAM));
+        }
+        // libfido2 returns the authenticator data wrapped in a CBOR byte string
+        let auth_data = Reader::new(found.auth_data)
+            .bytes()
+            .and_then(AuthData::parse)
+            .ok_or(FidoError(FIDO_ERR_INVALID_PARAM))?;
+        let sign_count = auth_data.sign_count;
+        let backup = record.check_backup_state(auth_data.typed_flags());
+        if backup.is_violation() {
+            return Err(FidoError(FIDO_ERR_INVALID_PARAM));
+        }
+        assertion.verify_statement(statement, &record.public_key()?)?;
+        Ok(Ceremony {
+            data,
+            state: Verified {
+                assertion,
+                statement,
+                sign_count,
+                backup,
+            },
+        })
+    }
+}
+
+impl<'a> Ceremony<'a, Verified> {
+    /// Returns the assertion with the verified statement.
+    pub fn assertion(&self) -> &Assertion {
+        &self.state.assertion
+    }
+
+    /// Returns the index of the verified statement.
+    pub fn statement(&self) -> usize {
+        self.state.statement
+    }
+
+    /// Returns how the backup state of the statement relates to the stored one.
+    pub fn backup_transition(&self) -> BackupTransition {
+        self.state.backup
+    }
+
+    /// Checks the sign count of the statement against `record`, and stores it in `record`
+    /// with the backup state of the statement, unless it is suspicious, see
+    /// [`SignCountStatus::is_suspicious`].
+    ///
+    /// [`SignCountStatus::is_suspicious`]: enum.SignCountStatus.html#method.is_suspicious
+    pub fn update_sign_count(
+        self,
+        record: &mut RegistrationRecord,
+    ) -> Ceremony<'a, SignCountUpdated> {
+        let status = record.check_sign_count(self.state.sign_count);
+        if !status.is_suspicious() {
+            record.sign_count = self.state.sign_count;
+            record.backup_state = self.state.backup.current();
+        }
+        Ceremony {
+            data: self.data,
+            state: SignCountUpdated {
+                assertion: self.state.assertion,
+                statement: self.state.statement,
+                status,
+                backup: self.state.backup,
+            },
+        }
+    }
+}
+
+impl<'a> Ceremony<'a, SignCountUpdated> {
+    /// Returns the assertion with the verified statement.
+    pub fn assertion(&self) -> &Assertion {
+        &self.state.assertion
+    }
+
+    /// Returns the index of the verified statement.
+    pub fn statement(&self) -> usize {
+        self.state.statement
+    }
+
+    /// Returns how the sign count of the statement related to the stored one. The ceremony
+    /// succeeded, but relying parties may still reject suspicious counts.
+    pub fn sign_count_status(&self) -> SignCountStatus {
+        self.state.status
+    }
+
+    /// Returns how the backup state of the statement related to the stored one, e.g. to ask
+    /// for a second factor once a credential is synced.
+    pub fn backup_transition(&self) -> BackupTransition {
+        self.state.backup
+    }
+
+    /// Returns the verified assertion.
+    pub fn into_assertion(self) -> Assertion {
+        self.state.assertion
+    }
+}
+
+impl<'a, S> Ceremony<'a, S> {
+    /// Returns the request of the ceremony.
+    pub fn data(&self) -> &AssertionCreationData<'a> {
+        &self.data
+    }
+}
+
+/// A registration ceremony in state `S`, the counterpart of [`Ceremony`].
+///
+/// Starts as [`OptionsSet`], with the request, and moves through [`ResponseObtained`] and
+/// [`AttestationVerified`] to [`RecordProduced`], with the [`RegistrationRecord`] to store:
+///
+/// ```no_run
+/// # use libfido2::*;
+/// # fn register(fido: &Fido, device: &mut Device, data: CredentialCreationData<'_>,
+/// #     transports: &[String]) -> Result<RegistrationRecord, Box<dyn std::error::Error>> {
+/// let record = Registration::new(data)
+///     .request_credential(fido, device, None)?
+///     .verify_attestation(&attestation::SelfAttestation)?
+///     .produce_record(transports)
+///     .into_record();
+/// # Ok(record)
+/// # }
+/// ```
+///
+/// [`Ceremony`]: struct.Ceremony.html
+/// [`OptionsSet`]: struct.OptionsSet.html
+/// [`ResponseObtained`]: struct.ResponseObtained.html
+/// [`AttestationVerified`]: struct.AttestationVerified.html
+/// [`RecordProduced`]: struct.RecordProduced.html
+/// [`RegistrationRecord`]: struct.RegistrationRecord.html
+pub struct Registration<'a, S> {
+    data: CredentialCreationData<'a>,
+    state: S,
+}
+
+/// The request was created, but not answered yet.
+#[derive(Copy, Clone, Debug)]
+pub struct OptionsSet(());
+
+/// An authenticator created the credential, with an attestation that is not verified yet.
+pub struct ResponseObtained(CreatedCredential);
+
+/// The attestation of the credential was verified.
+pub struct AttestationVerified(CreatedCredential);
+
+/// The record of the credential was produced.
+pub struct RecordProduced {
+    credential: CreatedCredential,
+    record: RegistrationRecord,
+}
+
+impl<'a> Registration<'a, OptionsSet> {
+    /// Starts a ceremony for the request `data`.
+    pub fn new(data: CredentialCreationData<'a>) -> Self {
+        Registration {
+            data,
+            state: OptionsSet(()),
+        }
+    }
+
+    /// Requests the credential from `authenticator`.
+    pub fn request_credential<A: Authenticator + ?Sized>(
+        self,
+        fido: &Fido,
+        authenticator: &mut A,
+        pin: Option<&CStr>,
+    ) -> Result<Registration<'a, ResponseObtained>> {
+        let creator = fido.new_credential_creator(self.data)?;
+        let credential = authenticator.request_credential_creation(creator, pin)?;
+        Ok(self.with_credential(credential))
+    }
+
+    /// Continues with a credential that was requested elsewhere, e.g. with extensions the
+    /// ceremony doesn't set. It is checked against the request in [`verify_attestation`].
+    ///
+    /// [`verify_attestation`]: struct.Registration.html#method.verify_attestation
+    pub fn with_credential(
+        self,
+        credential: CreatedCredential,
+    ) -> Registration<'a, ResponseObtained> {
+        Registration {
+            data: self.data,
+            state: ResponseObtained(credential),
+        }
+    }
+}
+
+impl<'a> Registration<'a, ResponseObtained> {
+    /// Returns the credential, whose attestation is not verified yet.
+    pub fn credential(&self) -> &CreatedCredential {
+        &self.state.0
+    }
+
+    /// Verifies the attestation of the credential with `verifier`, e.g.
+    /// `attestation::PackedWithChainTo`.
+    ///
+    /// # Errors
+    /// - `AttestationError::InvalidSignature` with `FIDO_ERR_INVALID_PARAM` if the credential
+    ///   wasn't created for the client data hash, credential type, relying party and user of
+    ///   the request, as its attestation then doesn't sign this request.
+    /// - The error of `verifier`.
+    pub fn verify_attestation(
+        self,
+        verifier: &impl Verifier,
+    ) -> std::result::Result<Registration<'a, AttestationVerified>, AttestationError> {
+        let data = self.data;
+        let credential = self.state.0;
+        let rp_id = data.relying_party.id();
+        let rp_id_hash =
+            openssl::sha256(rp_id.to_bytes()).map_err(AttestationError::InvalidSignature)?;
+        let matches = credential.credential().try_as_ref().is_ok_and(|created| {
+            // libfido2 returns the authenticator data wrapped in a CBOR byte string
+            let signed_rp = Reader::new(created.auth_data)
+                .bytes()
+                .and_then(AuthData::parse)
+                .is_some_and(|auth_data| ct_eq(auth_data.rp_id_hash, &rp_id_hash));
+            ct_eq(created.client_data_hash, data.client_data_hash)
+                && created.credential_type == data.credential_type
+                && signed_rp
+                && credential.credential().relying_party_id() == Some(rp_id)
+                && ct_eq(credential.credential().user_id(), data.user.id())
+        });
+        if !matches {
+            return Err(AttestationError::InvalidSignature(FidoError(
+                FIDO_ERR_INVALID_PARAM,
+            )));
+        }
+        credential.credential().verify_attestation(verifier)?;
+        Ok(Registration {
+            data,
+            state: AttestationVerified(credential),
+        })
+    }
+}
+
+impl<'a> Registration<'a, AttestationVerified> {
+    /// Returns the credential with the verified attestation.
+    pub fn credential(&self) -> &CreatedCredential {
+        &self.state.0
+    }
+
+    /// Produces the record to store for the credential, see `RegistrationRecord::new`.
+    ///
+    /// # Arguments
+    /// - `transports`: Transports of the device that created the credential
+    pub fn produce_record(self, transports: &[String]) -> Registration<'a, RecordProduced> {
+        let record = RegistrationRecord::new(&self.state.0, transports);
+        Registration {
+            data: self.data,
+            state: RecordProduced {
+                credential: self.state.0,
+                record,
+            },
+        }
+    }
+}
+
+impl<'a> Registration<'a, RecordProduced> {
+    /// Returns the credential with the verified attestation.
+    pub fn credential(&self) -> &CreatedCredential {
+        &self.state.credential
+    }
+
+    /// Returns the record to store.
+    pub fn record(&self) -> &RegistrationRecord {
+        &self.state.record
+    }
+
+    /// Returns the record to store.
+    pub fn into_record(self) -> RegistrationRecord {
+        self.state.record
+    }
+}
+
+impl<'a, S> Registration<'a, S> {
+    /// Returns the request of the ceremony.
+    pub fn data(&self) -> &CredentialCreationData<'a> {
+        &self.data
+    }
+}
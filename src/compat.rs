@@ -0,0 +1,114 @@
+use crate::{
+    Assertion, AssertionCreationData, AuthenticatorInfo, CreatedCredential, CredentialCreationData,
+    CredentialExtensions, Device, Extension, Fido, Result,
+};
+use std::ffi::CStr;
+
+#[cfg(feature = "ctap-hid-fido2-compat")]
+pub mod ctap_hid_fido2;
+
+/// Wraps a [`Device`], adapting requests to the features the device reported, so the same
+/// request works against CTAP 2.0 and 2.1 devices alike.
+///
+/// # Remarks
+/// - libfido2 already falls back to the credential management preview and to `getPinToken`
+///   on devices without CTAP 2.1 support, so only the request data is adapted here.
+/// - See [`Fallback`] for what is adapted.
+///
+/// [`Device`]: struct.Device.html
+/// [`Fallback`]: enum.Fallback.html
+pub struct CompatDevice {
+    device: Device,
+    info: AuthenticatorInfo,
+}
+
+/// An adaptation made by a [`CompatDevice`] because the device lacks a feature.
+///
+/// [`CompatDevice`]: struct.CompatDevice.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Fallback {
+    /// The `credProtect` extension is unsupported, so the requested policy was not sent.
+    CredProtectIgnored,
+    /// The `hmac-secret` extension is unsupported, so it was not requested.
+    HmacSecretIgnored,
+}
+
+impl CompatDevice {
+    /// Wraps an opened device, requesting its [`AuthenticatorInfo`].
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    ///
+    /// [`AuthenticatorInfo`]: struct.AuthenticatorInfo.html
+    pub fn new(mut device: Device) -> Result<Self> {
+        let info = device.authenticator_info()?;
+        Ok(CompatDevice { device, info })
+    }
+
+    /// Returns the information reported by the device when it was wrapped.
+    pub fn info(&self) -> &AuthenticatorInfo {
+        &self.info
+    }
+
+    /// Borrows the wrapped device, for requests that need no adapting.
+    pub fn device(&mut self) -> &mut Device {
+        &mut self.device
+    }
+
+    /// Unwraps the device.
+    pub fn into_inner(self) -> Device {
+        self.device
+    }
+
+    /// Removes the parts of `data` the device does not support, and returns what was removed.
+    pub fn adapt_credential_creation<'a>(
+        &self,
+        mut data: CredentialCreationData<'a>,
+    ) -> (CredentialCreationData<'a>, Vec<Fallback>) {
+        let mut fallbacks = Vec::new();
+        if data.cred_protect.is_some() && !self.info.supports_extension(Extension::CredProtect) {
+            data.cred_protect = None;
+            fallbacks.push(Fallback::CredProtectIgnored);
+        }
+        if data.extensions.contains(CredentialExtensions::HMAC_SECRET)
+            && !self.info.supports_extension(Extension::HmacSecret)
+        {
+            data.extensions.remove(CredentialExtensions::HMAC_SECRET);
+            fallbacks.push(Fallback::HmacSecretIgnored);
+        }
+        (data, fallbacks)
+    }
+
+    /// Requests the device to create a new Credential, after [adapting] the request.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - Use `Credential::check_cred_protect` to find out which policy was granted.
+    ///
+    /// [adapting]: struct.CompatDevice.html#method.adapt_credential_creation
+    pub fn request_credential_creation(
+        &mut self,
+        fido: &Fido,
+        data: CredentialCreationData<'_>,
+        pin: Option<&CStr>,
+    ) -> Result<(CreatedCredential, Vec<Fallback>)> {
+        let (data, fallbacks) = self.adapt_credential_creation(data);
+        let creator = fido.new_credential_creator(data)?;
+        let credential = self.device.request_credential_creation(creator, pin)?;
+        Ok((credential, fallbacks))
+    }
+
+    /// Requests the device to verify an Assertion.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    pub fn request_assertion_verification(
+        &mut self,
+        fido: &Fido,
+        data: AssertionCreationData<'_>,
+        pin: Option<&CStr>,
+    ) -> Result<Assertion> {
+        let creator = fido.new_assertion_creator(data)?;
+        self.device.request_assertion_verification(creator, pin)
+    }
+}
@@ -0,0 +1,363 @@
+//! The most used entry points of the `ctap-hid-fido2` crate, so projects can move to libfido2,
+//! e.g. for Windows Hello or PC/SC readers, by changing their imports.
+//!
+//! # Remarks
+//! - Entry points take the arguments of `ctap-hid-fido2` 3.x, but return the types of this
+//!   crate, e.g. a `CreatedCredential` in place of an `Attestation`, and `FidoError`s.
+//! - Like `ctap-hid-fido2`, the challenge is hashed with SHA-256 into the client data hash.
+//! - `wink` is missing, as libfido2 can't send it.
+//!
+//! ```no_run
+//! # use libfido2::compat::ctap_hid_fido2::{verifier, Cfg, FidoKeyHidFactory};
+//! # fn main() -> Result<(), libfido2::FidoError> {
+//! let device = FidoKeyHidFactory::create(&Cfg::init())?;
+//! let challenge = verifier::create_challenge();
+//! let attestation = device.make_credential("example.com", &challenge, Some("1234"))?;
+//! let result = verifier::verify_attestation("example.com", &challenge, &attestation);
+//!
+//! let challenge = verifier::create_challenge();
+//! let ids = [result.credential_id.clone()];
+//! let assertion = device.get_assertion("example.com", &challenge, &ids, Some("1234"))?;
+//! let public_key = result.credential_public_key()?;
+//! assert!(verifier::verify_assertion("example.com", &public_key, &challenge, &assertion));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    openssl, Assertion, AssertionCreationData, AssertionOptions, AuthenticatorInfo,
+    CreatedCredential, CredentialCreationData, CredentialType, Device, DevicePathBuf, Fido,
+    FidoError, PublicKey, Result, RpEntity, UserEntity,
+};
+use libfido2_sys::*;
+use std::{
+    ffi::{CStr, CString},
+    slice,
+    sync::{Mutex, MutexGuard},
+};
+
+const MAX_DEVICES: usize = 64;
+const USER_ID: &[u8] = &[0x01];
+const USER_NAME: &[u8] = b"user\0";
+
+/// The configuration of a [`FidoKeyHid`], like `ctap_hid_fido2::Cfg`.
+///
+/// [`FidoKeyHid`]: struct.FidoKeyHid.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cfg {
+    /// Prints the debug output of libfido2 to stderr.
+    pub enable_log: bool,
+    /// Ignored, libfido2 falls back to the preview commands by itself.
+    pub use_pre_bio_enrollment: bool,
+    /// Ignored, libfido2 falls back to the preview commands by itself.
+    pub use_pre_credential_management: bool,
+    /// Ignored, libfido2 doesn't report when the device waits for the user.
+    pub keep_alive_msg: String,
+}
+
+impl Cfg {
+    /// Returns the defaults of `ctap-hid-fido2`.
+    pub fn init() -> Self {
+        Cfg {
+            enable_log: false,
+            use_pre_bio_enrollment: true,
+            use_pre_credential_management: true,
+            keep_alive_msg: "- Touch the sensor on the authenticator".to_owned(),
+        }
+    }
+}
+
+/// Selects a device to open, like `ctap_hid_fido2::HidParam`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HidParam {
+    VidPid {
+        vid: u16,
+        pid: u16,
+    },
+    /// The libfido2 path of the device, e.g. `/dev/hidraw0`, `pcsc://slot0` or `windows://hello`.
+    Path(String),
+}
+
+/// A found device, like `ctap_hid_fido2::HidInfo`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HidInfo {
+    pub pid: u16,
+    pub vid: u16,
+    pub product_string: String,
+    pub info: String,
+    /// Always a [`HidParam::Path`], as several devices of one model share their IDs.
+    ///
+    /// [`HidParam::Path`]: enum.HidParam.html#variant.Path
+    pub param: HidParam,
+}
+
+/// Lists the connected devices, like `ctap_hid_fido2::get_fidokey_devices`, including PC/SC
+/// readers and Windows Hello if libfido2 supports them.
+///
+/// # Remarks
+/// - Returns an empty list if the devices could not be enumerated.
+pub fn get_fidokey_devices() -> Vec<HidInfo> {
+    let fido = Fido::new(false);
+    let found = match fido.try_detect_devices(MAX_DEVICES) {
+        Ok(found) => found,
+        Err(_) => return Vec::new(),
+    };
+    found
+        .iter()
+        .map(|info| {
+            let path = info.path.as_path().to_string_lossy().into_owned();
+            HidInfo {
+                pid: info.product_id as u16,
+                vid: info.vendor_id as u16,
+                product_string: info.product.to_owned(),
+                info: format!(
+                    "manufacturer={} product={} path={}",
+                    info.manufacturer, info.product, path
+                ),
+                param: HidParam::Path(path),
+            }
+        })
+        .collect()
+}
+
+/// Opens devices, like `ctap_hid_fido2::FidoKeyHidFactory`.
+pub struct FidoKeyHidFactory;
+
+impl FidoKeyHidFactory {
+    /// Opens the only connected device.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_NOTFOUND` if no device is connected.
+    /// - `FIDO_ERR_INVALID_ARGUMENT` if several devices are connected, which `ctap-hid-fido2`
+    ///   refuses as well. Use [`create_by_params`] to pick one.
+    ///
+    /// [`create_by_params`]: struct.FidoKeyHidFactory.html#method.create_by_params
+    pub fn create(cfg: &Cfg) -> Result<FidoKeyHid> {
+        let devices = get_fidokey_devices();
+        match devices.as_slice() {
+            [] => Err(FidoError(FIDO_ERR_NOTFOUND)),
+            [device] => Self::create_by_params(slice::from_ref(&device.param), cfg),
+            _ => Err(FidoError(FIDO_ERR_INVALID_ARGUMENT)),
+        }
+    }
+
+    /// Opens the first connected device matching any of `params`.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_NOTFOUND` if no connected device matches.
+    pub fn create_by_params(params: &[HidParam], cfg: &Cfg) -> Result<FidoKeyHid> {
+        let fido = Fido::new(cfg.enable_log);
+        let found = fido.try_detect_devices(MAX_DEVICES)?;
+        let path = found
+            .iter()
+            .find(|info| {
+                params.iter().any(|param| match param {
+                    HidParam::VidPid { vid, pid } => {
+                        info.vendor_id as u16 == *vid && info.product_id as u16 == *pid
+                    }
+                    HidParam::Path(path) => info.path.as_path().as_os_str() == path.as_str(),
+                })
+            })
+            .map(|info| DevicePathBuf::from(info.path))
+            .ok_or(FidoError(FIDO_ERR_NOTFOUND))?;
+        let device = fido.new_device(path.as_path())?;
+        Ok(FidoKeyHid {
+            fido,
+            device: Mutex::new(device),
+        })
+    }
+}
+
+/// An opened device, like `ctap_hid_fido2::FidoKeyHid`.
+///
+/// # Remarks
+/// - Requests take `&self` like in `ctap-hid-fido2`, and are made one at a time.
+pub struct FidoKeyHid {
+    fido: Fido,
+    device: Mutex<Device>,
+}
+
+impl FidoKeyHid {
+    /// Requests the [`AuthenticatorInfo`] of the device.
+    ///
+    /// [`AuthenticatorInfo`]: ../../struct.AuthenticatorInfo.html
+    pub fn get_info(&self) -> Result<AuthenticatorInfo> {
+        self.lock().authenticator_info()
+    }
+
+    /// Requests the amount of PIN tries left.
+    pub fn get_pin_retries(&self) -> Result<i32> {
+        self.lock().retry_count()
+    }
+
+    /// Creates a non-resident credential for `rpid`, over the SHA-256 hash of `challenge`.
+    ///
+    /// # Remarks
+    /// - The user has the ID `[0x01]` and the name `user`.
+    pub fn make_credential(
+        &self,
+        rpid: &str,
+        challenge: &[u8],
+        pin: Option<&str>,
+    ) -> Result<CreatedCredential> {
+        let rpid = c_string(rpid)?;
+        let relying_party =
+            RpEntity::new(&rpid).map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+        let name = CStr::from_bytes_with_nul(USER_NAME).expect("nul terminated");
+        let user = UserEntity::new(USER_ID, name).expect("valid user ID");
+        let hash = openssl::sha256(challenge)?;
+        let data = CredentialCreationData::with_defaults(&hash, relying_party, user);
+        let pin = pin.map(c_string).transpose()?;
+
+        let creator = self.fido.new_credential_creator(data)?;
+        self.lock()
+            .request_credential_creation(creator, pin.as_deref())
+    }
+
+    /// Requests an assertion for `rpid` with one of `credential_ids`, over the SHA-256 hash of
+    /// `challenge`.
+    pub fn get_assertion(
+        &self,
+        rpid: &str,
+        challenge: &[u8],
+        credential_ids: &[Vec<u8>],
+        pin: Option<&str>,
+    ) -> Result<Assertion> {
+        let rpid = c_string(rpid)?;
+        let relying_party =
+            RpEntity::new(&rpid).map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+        let hash = openssl::sha256(challenge)?;
+        let ids = credential_ids.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let mut data = AssertionCreationData::with_defaults(Some(&ids), &hash, relying_party);
+        data.options = AssertionOptions::USER_PRESENCE;
+        let pin = pin.map(c_string).transpose()?;
+
+        let creator = self.fido.new_assertion_creator(data)?;
+        self.lock()
+            .request_assertion_verification(creator, pin.as_deref())
+    }
+
+    /// Borrows the device, for requests without a `ctap-hid-fido2` counterpart.
+    pub fn device(&mut self) -> &mut Device {
+        self.device.get_mut().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Device> {
+        self.device.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn c_string(value: &str) -> Result<CString> {
+    CString::new(value).map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))
+}
+
+/// Verification of credentials and assertions, like `ctap_hid_fido2::verifier`.
+pub mod verifier {
+    use super::*;
+    use crate::auth_data::AuthData;
+
+    /// The outcome of [`verify_attestation`], like
+    /// `ctap_hid_fido2::verifier::AttestationVerifyResult`.
+    ///
+    /// [`verify_attestation`]: fn.verify_attestation.html
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct AttestationVerifyResult {
+        pub is_success: bool,
+        pub credential_id: Vec<u8>,
+        pub credential_type: CredentialType,
+        /// The public key in COSE format.
+        pub credential_public_key: Vec<u8>,
+    }
+
+    impl AttestationVerifyResult {
+        /// Parses the public key of the credential, e.g. for [`verify_assertion`].
+        ///
+        /// [`verify_assertion`]: fn.verify_assertion.html
+        pub fn credential_public_key(&self) -> Result<PublicKey> {
+            PublicKey::from_bytes(self.credential_type, &self.credential_public_key)
+        }
+    }
+
+    /// Returns a random challenge of 32 bytes.
+    ///
+    /// # Panics
+    /// - When no random bytes are available.
+    pub fn create_challenge() -> [u8; 32] {
+        let mut challenge = [0; 32];
+        openssl::random_bytes(&mut challenge).expect("Unable to create a challenge");
+        challenge
+    }
+
+    /// Verifies that `attestation` was created for `rpid` and `challenge`, and its attestation
+    /// signature.
+    ///
+    /// # Remarks
+    /// - Like in `ctap-hid-fido2`, the attestation certificate itself is not verified. Use
+    ///   `CreatedCredential::verify_attestation` for a policy.
+    pub fn verify_attestation(
+        rpid: &str,
+        challenge: &[u8],
+        attestation: &CreatedCredential,
+    ) -> AttestationVerifyResult {
+        let credential = attestation.credential();
+        let is_success = credential.try_as_ref().is_ok_and(|data| {
+            matches_request(rpid, challenge, data.auth_data, data.client_data_hash)
+                && match data.format.to_bytes() {
+                    b"none" => true,
+                    b"packed" if data.x509_certificate.is_empty() => {
+                        credential.verify_self().is_ok()
+                    }
+                    _ => credential.verify().is_ok(),
+                }
+        });
+        AttestationVerifyResult {
+            is_success,
+            credential_id: attestation.id().as_bytes().to_vec(),
+            credential_type: attestation.credential_type(),
+            credential_public_key: attestation.public_key_bytes().to_vec(),
+        }
+    }
+
+    /// Verifies that the first statement of `assertion` was made for `rpid` and `challenge`,
+    /// with `public_key`.
+    pub fn verify_assertion(
+        rpid: &str,
+        public_key: &PublicKey,
+        challenge: &[u8],
+        assertion: &Assertion,
+    ) -> bool {
+        let statement = match assertion.iter().next() {
+            Some(statement) => statement,
+            None => return false,
+        };
+        matches_request(
+            rpid,
+            challenge,
+            statement.auth_data,
+            statement.client_data_hash,
+        ) && assertion.verify_statement(0, public_key).is_ok()
+    }
+
+    /// Checks the RP ID hash of CBOR wrapped `auth_data`, and the client data hash.
+    fn matches_request(
+        rpid: &str,
+        challenge: &[u8],
+        auth_data: &[u8],
+        client_data_hash: &[u8],
+    ) -> bool {
+        let rp_id_hash = crate::cbor::Reader::new(auth_data)
+            .bytes()
+            .and_then(AuthData::parse)
+            .map(|auth_data| auth_data.rp_id_hash);
+        match (
+            rp_id_hash,
+            openssl::sha256(rpid.as_bytes()),
+            openssl::sha256(challenge),
+        ) {
+            (Some(rp_id_hash), Ok(expected_rp_id_hash), Ok(expected_hash)) => {
+                rp_id_hash == expected_rp_id_hash && client_data_hash == expected_hash
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::cbor;
+use libfido2_sys::*;
+use std::{error, fmt};
+
+/// A subcommand of `authenticatorConfig` (CTAP 2.1).
+///
+/// # Remarks
+/// - The standard subcommands have dedicated methods on `Device`, such as
+///   `Device::toggle_always_uv`, which authenticate with the PIN. This is meant for subcommands
+///   without one, e.g. vendor prototypes of upcoming CTAP features.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ConfigSubcommand {
+    EnableEnterpriseAttestation,
+    ToggleAlwaysUv,
+    SetMinPinLength,
+    /// Vendor specific commands, told apart by the `vendorCommandId` parameter.
+    VendorPrototype,
+    /// A subcommand this crate has no name for.
+    Other(u8),
+}
+
+impl ConfigSubcommand {
+    /// Returns the identifier of the subcommand used on the wire.
+    pub fn id(self) -> u8 {
+        match self {
+            ConfigSubcommand::EnableEnterpriseAttestation => 0x01,
+            ConfigSubcommand::ToggleAlwaysUv => 0x02,
+            ConfigSubcommand::SetMinPinLength => 0x03,
+            ConfigSubcommand::VendorPrototype => 0xff,
+            ConfigSubcommand::Other(id) => id,
+        }
+    }
+
+    /// Returns the subcommand with the identifier `id`.
+    pub fn from_id(id: u8) -> Self {
+        match id {
+            0x01 => ConfigSubcommand::EnableEnterpriseAttestation,
+            0x02 => ConfigSubcommand::ToggleAlwaysUv,
+            0x03 => ConfigSubcommand::SetMinPinLength,
+            0xff => ConfigSubcommand::VendorPrototype,
+            id => ConfigSubcommand::Other(id),
+        }
+    }
+}
+
+/// An `authenticatorConfig` request, sent with [`Device::send_config_command`].
+///
+/// [`Device::send_config_command`]: struct.Device.html#method.send_config_command
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigCommand {
+    subcommand: ConfigSubcommand,
+    params: Option<Vec<u8>>,
+}
+
+impl ConfigCommand {
+    /// Creates a request for `subcommand` without parameters.
+    pub fn new(subcommand: ConfigSubcommand) -> Self {
+        ConfigCommand {
+            subcommand,
+            params: None,
+        }
+    }
+
+    /// Creates a vendor prototype request for the command `vendor_command_id`.
+    pub fn vendor_prototype(vendor_command_id: u64) -> Self {
+        let mut params = cbor::Writer::new();
+        params.map(1).uint(1).uint(vendor_command_id);
+        ConfigCommand {
+            subcommand: ConfigSubcommand::VendorPrototype,
+            params: Some(params.into_bytes()),
+        }
+    }
+
+    /// Sets the `subCommandParams`, replacing the `vendorCommandId` of vendor prototypes.
+    ///
+    /// # Arguments
+    /// - `params`: One CBOR encoded item, usually a map with integer keys
+    ///
+    /// # Errors
+    /// - If `params` is not exactly one well-formed CBOR item.
+    pub fn with_params(
+        mut self,
+        params: &[u8],
+    ) -> std::result::Result<Self, InvalidConfigParamsError> {
+        let mut reader = cbor::Reader::new(params);
+        match reader.skip() {
+            Some(_) if reader.is_empty() => {
+                self.params = Some(params.to_vec());
+                Ok(self)
+            }
+            _ => Err(InvalidConfigParamsError),
+        }
+    }
+
+    pub fn subcommand(&self) -> ConfigSubcommand {
+        self.subcommand
+    }
+
+    /// Returns the CBOR encoded `subCommandParams`, if any.
+    pub fn params(&self) -> Option<&[u8]> {
+        self.params.as_deref()
+    }
+
+    /// Encodes the request, including the command byte.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut request = cbor::Writer::new();
+        let subcommand = u64::from(self.subcommand.id());
+        match &self.params {
+            Some(params) => request.map(2).uint(1).uint(subcommand).uint(2).raw(params),
+            None => request.map(1).uint(1).uint(subcommand),
+        };
+        let mut bytes = vec![CTAP_CBOR_CONFIG as u8];
+        bytes.extend(request.into_bytes());
+        bytes
+    }
+}
+
+/// The parameters of a [`ConfigCommand`] are not one CBOR item.
+///
+/// [`ConfigCommand`]: struct.ConfigCommand.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InvalidConfigParamsError;
+
+impl error::Error for InvalidConfigParamsError {}
+
+impl fmt::Display for InvalidConfigParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The config parameters are invalid")
+    }
+}
@@ -0,0 +1,529 @@
+//! A scripted suite of CTAP2 requirements, run against any [`Authenticator`], e.g. a security
+//! key opened as a `Device` or a soft authenticator, reporting for each whether it passed.
+//!
+//! The suite creates credentials for the relying party `conformance.libfido2-rs.invalid`,
+//! which can't belong to a real site. Discoverable credentials it creates stay on the device.
+//!
+//! ```no_run
+//! # use libfido2::{conformance::ConformanceSuite, Fido, FidoError};
+//! # fn main() -> Result<(), FidoError> {
+//! let fido = Fido::new(false);
+//! # let path = libfido2::DevicePathBuf::from_path("/dev/hidraw0").unwrap();
+//! let mut device = fido.new_device(path.as_path())?;
+//! let report = ConformanceSuite::new().run(&fido, &mut device);
+//! print!("{}", report);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Authenticator`]: ../trait.Authenticator.html
+
+use crate::{
+    auth_data::AuthData, cbor::Reader, openssl, Assertion, AssertionCreationData, AssertionOptions,
+    AuthDataFlags, Authenticator, CreatedCredential, CredentialCreationData, CredentialOptions,
+    CredentialType, Fido, FidoError, RpEntity, SignCountStatus, UserEntity,
+};
+use libfido2_sys::*;
+use std::{ffi::CStr, fmt, os::raw};
+
+const RP_ID: &[u8] = b"conformance.libfido2-rs.invalid\0";
+const OTHER_RP_ID: &[u8] = b"other.conformance.libfido2-rs.invalid\0";
+const USER_NAME: &[u8] = b"conformance\0";
+const USER_ID: &[u8] = b"libfido2-rs conformance";
+
+/// A requirement of CTAP 2.1 the suite checks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Requirement {
+    /// A short, stable name, e.g. `MC-EXCLUDE`.
+    pub id: &'static str,
+    /// The section of CTAP 2.1 with the requirement.
+    pub section: &'static str,
+    pub description: &'static str,
+}
+
+pub const MC_CREDENTIAL: Requirement = Requirement {
+    id: "MC-CREDENTIAL",
+    section: "6.1.2",
+    description: "makeCredential returns a credential of the requested algorithm, with attested \
+                  credential data and user presence",
+};
+pub const MC_ATTESTATION: Requirement = Requirement {
+    id: "MC-ATTESTATION",
+    section: "6.1.2",
+    description: "The attestation statement is valid over the authenticator data and client data \
+                  hash",
+};
+pub const MC_EXCLUDE: Requirement = Requirement {
+    id: "MC-EXCLUDE",
+    section: "6.1.2",
+    description: "A credential of the device in the excludeList fails makeCredential with \
+                  CTAP2_ERR_CREDENTIAL_EXCLUDED",
+};
+pub const MC_DISCOVERABLE: Requirement = Requirement {
+    id: "MC-DISCOVERABLE",
+    section: "6.1.2",
+    description: "makeCredential creates a discoverable credential with the rk option and a PIN",
+};
+pub const GA_ALLOW_LIST: Requirement = Requirement {
+    id: "GA-ALLOW-LIST",
+    section: "6.2.2",
+    description: "getAssertion with an allowList returns a statement of the allowed credential, \
+                  signed by its key, with user presence",
+};
+pub const GA_SIGN_COUNT: Requirement = Requirement {
+    id: "GA-SIGN-COUNT",
+    section: "6.2.2",
+    description: "The signature counter increases with every assertion, or is always zero",
+};
+pub const GA_UNKNOWN_CREDENTIAL: Requirement = Requirement {
+    id: "GA-UNKNOWN-CREDENTIAL",
+    section: "6.2.2",
+    description: "An allowList without credentials of the device fails getAssertion with \
+                  CTAP2_ERR_NO_CREDENTIALS",
+};
+pub const GA_OTHER_RP: Requirement = Requirement {
+    id: "GA-OTHER-RP",
+    section: "6.2.2",
+    description: "A credential isn't returned for another RP ID, failing getAssertion with \
+                  CTAP2_ERR_NO_CREDENTIALS",
+};
+pub const GA_SILENT: Requirement = Requirement {
+    id: "GA-SILENT",
+    section: "6.2.2",
+    description: "getAssertion with up=false returns a statement without the UP flag",
+};
+pub const GA_DISCOVERABLE: Requirement = Requirement {
+    id: "GA-DISCOVERABLE",
+    section: "6.2.2",
+    description: "getAssertion without allowList returns the discoverable credential with its \
+                  user ID",
+};
+
+/// Every requirement of the suite, in the order they are checked.
+pub const REQUIREMENTS: &[Requirement] = &[
+    MC_CREDENTIAL,
+    MC_ATTESTATION,
+    MC_EXCLUDE,
+    GA_ALLOW_LIST,
+    GA_SIGN_COUNT,
+    GA_UNKNOWN_CREDENTIAL,
+    GA_OTHER_RP,
+    GA_SILENT,
+    MC_DISCOVERABLE,
+    GA_DISCOVERABLE,
+];
+
+/// Whether the authenticator met a [`Requirement`].
+///
+/// [`Requirement`]: struct.Requirement.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Passed,
+    /// The authenticator didn't meet the requirement, for the given reason.
+    Failed(String),
+    /// The requirement wasn't checked, e.g. without a PIN, or because an earlier request failed.
+    Skipped(String),
+}
+
+/// The [`Outcome`] of one [`Requirement`].
+///
+/// [`Outcome`]: enum.Outcome.html
+/// [`Requirement`]: struct.Requirement.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CheckResult {
+    pub requirement: Requirement,
+    pub outcome: Outcome,
+}
+
+/// The results of [`ConformanceSuite::run`], in the order of [`REQUIREMENTS`].
+///
+/// Formats as one line per requirement, e.g. `PASS  MC-EXCLUDE (6.1.2): ...`.
+///
+/// [`ConformanceSuite::run`]: struct.ConformanceSuite.html#method.run
+/// [`REQUIREMENTS`]: constant.REQUIREMENTS.html
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ConformanceReport {
+    pub results: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+    /// Returns the results of the requirements that were not met.
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results
+            .iter()
+            .filter(|result| matches!(result.outcome, Outcome::Failed(_)))
+    }
+
+    /// Returns whether every checked requirement was met. Skipped requirements don't count.
+    pub fn is_conformant(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    fn push(&mut self, requirement: Requirement, outcome: Outcome) {
+        self.results.push(CheckResult {
+            requirement,
+            outcome,
+        });
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            let (status, reason) = match &result.outcome {
+                Outcome::Passed => ("PASS", None),
+                Outcome::Failed(reason) => ("FAIL", Some(reason)),
+                Outcome::Skipped(reason) => ("SKIP", Some(reason)),
+            };
+            let requirement = result.requirement;
+            write!(
+                f,
+                "{:<5} {} ({}): {}",
+                status, requirement.id, requirement.section, requirement.description
+            )?;
+            match reason {
+                Some(reason) => writeln!(f, " - {}", reason)?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the requirements of [`REQUIREMENTS`] against an authenticator.
+///
+/// # Remarks
+/// - Physical devices have to be touched for most requests, about ten times in total.
+/// - Requirements of discoverable credentials are skipped without a PIN, see [`with_pin`].
+///
+/// [`REQUIREMENTS`]: constant.REQUIREMENTS.html
+/// [`with_pin`]: struct.ConformanceSuite.html#method.with_pin
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ConformanceSuite<'a> {
+    pin: Option<&'a CStr>,
+}
+
+impl<'a> ConformanceSuite<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the PIN of the authenticator, to also check discoverable credentials.
+    pub fn with_pin(mut self, pin: &'a CStr) -> Self {
+        self.pin = Some(pin);
+        self
+    }
+
+    /// Checks every requirement, continuing after failures.
+    ///
+    /// # Remarks
+    /// - Requirements that depend on a credential the authenticator failed to create are
+    ///   skipped.
+    pub fn run(&self, fido: &Fido, authenticator: &mut dyn Authenticator) -> ConformanceReport {
+        let mut report = ConformanceReport::default();
+        let mut run = Run {
+            fido,
+            authenticator,
+            report: &mut report,
+        };
+        run.credential_checks();
+        run.discoverable_checks(self.pin);
+        report
+    }
+}
+
+struct Run<'a, 'b> {
+    fido: &'a Fido,
+    authenticator: &'a mut dyn Authenticator,
+    report: &'b mut ConformanceReport,
+}
+
+impl Run<'_, '_> {
+    fn credential_checks(&mut self) {
+        let rp = relying_party(RP_ID);
+        let user = user();
+        let hash = client_data_hash();
+        let data = CredentialCreationData::with_defaults(&hash, rp, user);
+        let created = self.make_credential(data, None);
+        let credential = match &created {
+            Ok(credential) => credential,
+            Err(err) => {
+                self.report.push(MC_CREDENTIAL, failed(err));
+                for requirement in &REQUIREMENTS[1..8] {
+                    self.report
+                        .push(*requirement, skipped("no credential was created"));
+                }
+                return;
+            }
+        };
+        self.report.push(MC_CREDENTIAL, check_created(credential));
+        self.report
+            .push(MC_ATTESTATION, check_attestation(credential));
+
+        let id = credential.id().as_bytes();
+        let mut data = CredentialCreationData::with_defaults(&hash, rp, user);
+        data.excluded_ids = id;
+        let outcome = match self.make_credential(data, None) {
+            Ok(_) => Outcome::Failed("a credential was created".to_owned()),
+            Err(err) => expect_error(err, FIDO_ERR_CREDENTIAL_EXCLUDED),
+        };
+        self.report.push(MC_EXCLUDE, outcome);
+
+        let allowed = [id];
+        let data = AssertionCreationData::with_defaults(Some(&allowed), &hash, rp);
+        let first = self.get_assertion(data, None);
+        self.report
+            .push(GA_ALLOW_LIST, check_assertion(&first, credential));
+        let data = AssertionCreationData::with_defaults(Some(&allowed), &hash, rp);
+        let second = self.get_assertion(data, None);
+        self.report
+            .push(GA_SIGN_COUNT, check_sign_count(credential, &first, &second));
+
+        let unknown = [&[0u8; 64][..]];
+        let data = AssertionCreationData::with_defaults(Some(&unknown), &hash, rp);
+        let outcome = match self.get_assertion(data, None) {
+            Ok(_) => Outcome::Failed("a statement was returned".to_owned()),
+            Err(err) => expect_error(err, FIDO_ERR_NO_CREDENTIALS),
+        };
+        self.report.push(GA_UNKNOWN_CREDENTIAL, outcome);
+
+        let other = relying_party(OTHER_RP_ID);
+        let data = AssertionCreationData::with_defaults(Some(&allowed), &hash, other);
+        let outcome = match self.get_assertion(data, None) {
+            Ok(_) => Outcome::Failed("a statement was returned".to_owned()),
+            Err(err) => expect_error(err, FIDO_ERR_NO_CREDENTIALS),
+        };
+        self.report.push(GA_OTHER_RP, outcome);
+
+        let data = AssertionCreationData::with_defaults(Some(&allowed), &hash, rp);
+        let outcome = match self.get_silent_assertion(data) {
+            Ok(assertion) => match flags(&assertion) {
+                Some(flags) if flags.contains(AuthDataFlags::USER_PRESENT) => {
+                    Outcome::Failed("the UP flag is set".to_owned())
+                }
+                Some(_) => Outcome::Passed,
+                None => Outcome::Failed("the authenticator data is malformed".to_owned()),
+            },
+            Err(err) => failed(&err),
+        };
+        self.report.push(GA_SILENT, outcome);
+    }
+
+    fn discoverable_checks(&mut self, pin: Option<&CStr>) {
+        let pin = match pin {
+            Some(pin) => pin,
+            None => {
+                self.report
+                    .push(MC_DISCOVERABLE, skipped("no PIN was given"));
+                self.report
+                    .push(GA_DISCOVERABLE, skipped("no PIN was given"));
+                return;
+            }
+        };
+        let rp = relying_party(RP_ID);
+        let hash = client_data_hash();
+        let mut data = CredentialCreationData::with_defaults(&hash, rp, user());
+        data.options = CredentialOptions::RESIDENT_KEY;
+        let outcome = match self.make_credential(data, Some(pin)) {
+            Ok(credential) => check_created(&credential),
+            Err(err) => failed(&err),
+        };
+        let created = outcome == Outcome::Passed;
+        self.report.push(MC_DISCOVERABLE, outcome);
+        if !created {
+            self.report.push(
+                GA_DISCOVERABLE,
+                skipped("no discoverable credential was created"),
+            );
+            return;
+        }
+
+        let data = AssertionCreationData::with_defaults(None, &hash, rp);
+        let outcome = match self.get_assertion(data, Some(pin)) {
+            Ok(assertion) => {
+                let found = assertion
+                    .iter()
+                    .any(|statement| statement.user_id == Some(USER_ID));
+                if found {
+                    Outcome::Passed
+                } else {
+                    Outcome::Failed("no statement has the user ID".to_owned())
+                }
+            }
+            Err(err) => failed(&err),
+        };
+        self.report.push(GA_DISCOVERABLE, outcome);
+    }
+
+    fn make_credential(
+        &mut self,
+        data: CredentialCreationData<'_>,
+        pin: Option<&CStr>,
+    ) -> Result<CreatedCredential, FidoError> {
+        let creator = self.fido.new_credential_creator(data)?;
+        self.authenticator.request_credential_creation(creator, pin)
+    }
+
+    fn get_assertion(
+        &mut self,
+        mut data: AssertionCreationData<'_>,
+        pin: Option<&CStr>,
+    ) -> Result<Assertion, FidoError> {
+        // Empty options ask for up=false
+        data.options |= AssertionOptions::USER_PRESENCE;
+        let creator = self.fido.new_assertion_creator(data)?;
+        self.authenticator
+            .request_assertion_verification(creator, pin)
+    }
+
+    fn get_silent_assertion(
+        &mut self,
+        data: AssertionCreationData<'_>,
+    ) -> Result<Assertion, FidoError> {
+        let mut creator = self.fido.new_assertion_creator(data)?;
+        creator.set_user_presence(fido_opt_t_FIDO_OPT_FALSE)?;
+        self.authenticator
+            .request_assertion_verification(creator, None)
+    }
+}
+
+fn check_created(credential: &CreatedCredential) -> Outcome {
+    let flags = credential.flags();
+    if credential.credential_type() != CredentialType::ES256 {
+        Outcome::Failed(format!(
+            "the credential is {:?}, not ES256",
+            credential.credential_type()
+        ))
+    } else if !flags.contains(AuthDataFlags::ATTESTED_CREDENTIAL_DATA) {
+        Outcome::Failed("the AT flag is not set".to_owned())
+    } else if !flags.contains(AuthDataFlags::USER_PRESENT) {
+        Outcome::Failed("the UP flag is not set".to_owned())
+    } else if credential.public_key().is_err() {
+        Outcome::Failed("the public key is malformed".to_owned())
+    } else {
+        Outcome::Passed
+    }
+}
+
+fn check_attestation(credential: &CreatedCredential) -> Outcome {
+    let full = credential.credential();
+    let has_certificate = full
+        .try_as_ref()
+        .is_ok_and(|data| !data.x509_certificate.is_empty());
+    let result = match credential.format() {
+        "none" => return skipped("the attestation format is none"),
+        "packed" if !has_certificate => full.verify_self(),
+        _ => full.verify(),
+    };
+    match result {
+        Ok(()) => Outcome::Passed,
+        Err(err) => failed(&err),
+    }
+}
+
+fn check_assertion(
+    assertion: &Result<Assertion, FidoError>,
+    credential: &CreatedCredential,
+) -> Outcome {
+    let assertion = match assertion {
+        Ok(assertion) => assertion,
+        Err(err) => return failed(err),
+    };
+    if assertion.len() != 1 {
+        return Outcome::Failed(format!("{} statements were returned", assertion.len()));
+    }
+    let public_key = match credential.public_key() {
+        Ok(public_key) => public_key,
+        Err(err) => return failed(&err),
+    };
+    let (statement, verified) = match assertion.iter_verified(public_key).next() {
+        Some(statement) => statement,
+        None => return Outcome::Failed("no statement was returned".to_owned()),
+    };
+    let id = credential.id();
+    match (statement.credential_id, verified, flags(assertion)) {
+        (Some(returned), _, _) if !id.ct_eq(returned) => {
+            Outcome::Failed("the statement is of another credential".to_owned())
+        }
+        (_, Err(err), _) => failed(&err),
+        (_, _, Some(flags)) if !flags.contains(AuthDataFlags::USER_PRESENT) => {
+            Outcome::Failed("the UP flag is not set".to_owned())
+        }
+        (_, _, Some(_)) => Outcome::Passed,
+        (_, _, None) => Outcome::Failed("the authenticator data is malformed".to_owned()),
+    }
+}
+
+fn check_sign_count(
+    credential: &CreatedCredential,
+    first: &Result<Assertion, FidoError>,
+    second: &Result<Assertion, FidoError>,
+) -> Outcome {
+    let (first, second) = match (first, second) {
+        (Ok(first), Ok(second)) => (first, second),
+        _ => return skipped("getAssertion failed"),
+    };
+    let counts = [sign_count(first), sign_count(second)];
+    let (first, second) = match counts {
+        [Some(first), Some(second)] => (first, second),
+        _ => return Outcome::Failed("the authenticator data is malformed".to_owned()),
+    };
+    let steps = [
+        SignCountStatus::new(credential.sign_count(), first),
+        SignCountStatus::new(first, second),
+    ];
+    match steps.iter().find(|status| status.is_suspicious()) {
+        Some(status) => Outcome::Failed(format!("the counter is {:?}", status)),
+        None => Outcome::Passed,
+    }
+}
+
+fn flags(assertion: &Assertion) -> Option<AuthDataFlags> {
+    assertion.iter().next()?.flags()
+}
+
+fn sign_count(assertion: &Assertion) -> Option<u32> {
+    // libfido2 returns the authenticator data wrapped in a CBOR byte string
+    let auth_data = Reader::new(assertion.iter().next()?.auth_data).bytes()?;
+    AuthData::parse(auth_data).map(|data| data.sign_count)
+}
+
+/// Passes if the authenticator failed with the CTAP2 error `expected`.
+fn expect_error(err: FidoError, expected: u32) -> Outcome {
+    if err == FidoError(expected as raw::c_int) {
+        Outcome::Passed
+    } else {
+        Outcome::Failed(format!(
+            "failed with {}, not {}",
+            err,
+            FidoError(expected as raw::c_int)
+        ))
+    }
+}
+
+fn failed(err: &FidoError) -> Outcome {
+    Outcome::Failed(format!("failed with {}", err))
+}
+
+fn skipped(reason: &str) -> Outcome {
+    Outcome::Skipped(reason.to_owned())
+}
+
+fn relying_party(id: &'static [u8]) -> RpEntity<'static> {
+    let id = CStr::from_bytes_with_nul(id).expect("nul terminated");
+    RpEntity::new(id).expect("valid RP ID")
+}
+
+fn user() -> UserEntity<'static> {
+    let name = CStr::from_bytes_with_nul(USER_NAME).expect("nul terminated");
+    UserEntity::new(USER_ID, name).expect("valid user ID")
+}
+
+/// Returns a random client data hash, so each run is distinguishable in device logs.
+fn client_data_hash() -> [u8; 32] {
+    let mut hash = [0; 32];
+    if openssl::random_bytes(&mut hash).is_err() {
+        hash = [0x5a; 32];
+    }
+    hash
+}
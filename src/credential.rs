@@ -1,8 +1,26 @@
-use crate::{ffi::NonNull, FidoError, PublicKey, Result, FIDO_OK};
+use crate::{
+    aaguid::Aaguid,
+    attestation,
+    auth_data::{self, AuthData, AuthDataFlags},
+    cbor::Reader,
+    ffi::{allocated, bytes, opt_or_omit, to_size_t, NonNull},
+    pem,
+    uv::uvm_entries,
+    CredBlobInput, CredBlobOutput, ExtensionResults, Extensions, Fido, FidoError, HmacSecretInput,
+    HmacSecretOutput, PublicKey, Result, RpEntity, UserEntity, UvmEntry, FIDO_OK,
+};
 use bitflags::bitflags;
 use libfido2_sys::*;
-use std::{error, ffi::CStr, fmt, os::raw, ptr, slice, str::FromStr};
-use std::convert::TryInto;
+use std::{
+    borrow::Borrow,
+    convert::TryInto,
+    error,
+    ffi::{CStr, CString},
+    fmt, hint, mem,
+    os::raw,
+    ptr,
+    str::FromStr,
+};
 
 // Raw Credential is initialized with NULL data
 // Only expose this type when it is properly initialized (returned from device)
@@ -21,14 +39,67 @@ pub struct CredentialCreationData<'a> {
     pub excluded_ids: &'a [u8],
     pub credential_type: CredentialType,
     pub client_data_hash: &'a [u8],
-    pub relying_party_id: &'a CStr,
-    pub relying_party_name: &'a CStr,
-    pub user_id: &'a [u8],
-    pub user_name: &'a CStr,
-    pub user_display_name: Option<&'a CStr>,
-    pub user_image_uri: Option<&'a CStr>,
+    pub relying_party: RpEntity<'a>,
+    pub user: UserEntity<'a>,
     pub options: CredentialOptions,
     pub extensions: CredentialExtensions,
+    pub cred_protect: Option<CredProtect>,
+}
+
+/// Owned identifier of a [`Credential`], as stored by a relying party between registration and authentication.
+///
+/// [`Credential`]: struct.Credential.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CredentialId(Box<[u8]>);
+
+impl CredentialId {
+    /// Returns the raw bytes of the identifier.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Compares the identifier against `other` in constant time.
+    ///
+    /// # Remarks
+    /// - Only the length of the identifiers may leak through timing.
+    pub fn ct_eq(&self, other: &[u8]) -> bool {
+        ct_eq(&self.0, other)
+    }
+}
+
+/// Compares two byte strings in constant time with respect to their contents.
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b));
+    // Keep the optimizer from turning the fold into an early exit
+    hint::black_box(diff) == 0
+}
+
+impl From<&[u8]> for CredentialId {
+    fn from(id: &[u8]) -> Self {
+        CredentialId(id.into())
+    }
+}
+
+impl From<Vec<u8>> for CredentialId {
+    fn from(id: Vec<u8>) -> Self {
+        CredentialId(id.into_boxed_slice())
+    }
+}
+
+impl AsRef<[u8]> for CredentialId {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// Lets maps keyed by `CredentialId` be looked up with the ID of a statement
+impl Borrow<[u8]> for CredentialId {
+    fn borrow(&self) -> &[u8] {
+        &self.0
+    }
 }
 
 // Possible to retrieve after a Credential was returned from a device
@@ -48,27 +119,115 @@ impl<'a> CredentialCreationData<'a> {
     /// Constructs a new `CredentialCreationData` with given parameters and defaults.
     pub fn with_defaults(
         client_data_hash: &'a [u8],
-        relying_party_id: &'a CStr,
-        relying_party_name: &'a CStr,
-        user_id: &'a [u8],
-        user_name: &'a CStr,
+        relying_party: RpEntity<'a>,
+        user: UserEntity<'a>,
     ) -> Self {
         CredentialCreationData {
             excluded_ids: &[],
             credential_type: CredentialType::ES256,
             client_data_hash,
-            relying_party_id,
-            relying_party_name,
-            user_id,
-            user_name,
-            user_display_name: None,
-            user_image_uri: None,
+            relying_party,
+            user,
             options: CredentialOptions::empty(),
             extensions: CredentialExtensions::empty(),
+            cred_protect: None,
         }
     }
 }
 
+/// Owned copy of [`CredentialCreationData`], to send the same request to many devices, e.g. when
+/// provisioning a batch of keys for one user.
+///
+/// # Remarks
+/// - A [`CredentialCreator`] can only be sent to one device, as it receives the credential the
+///   device creates. Create one per device with [`creator`].
+///
+/// [`CredentialCreationData`]: struct.CredentialCreationData.html
+/// [`CredentialCreator`]: struct.CredentialCreator.html
+/// [`creator`]: struct.CredentialTemplate.html#method.creator
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CredentialTemplate {
+    excluded_ids: Vec<u8>,
+    credential_type: CredentialType,
+    client_data_hash: Vec<u8>,
+    relying_party_id: CString,
+    relying_party_name: Option<CString>,
+    user_id: Vec<u8>,
+    user_name: CString,
+    user_display_name: Option<CString>,
+    user_icon: Option<CString>,
+    options: CredentialOptions,
+    extensions: CredentialExtensions,
+    cred_protect: Option<CredProtect>,
+}
+
+impl CredentialTemplate {
+    /// Copies `data` into a template.
+    pub fn new(data: CredentialCreationData<'_>) -> Self {
+        let owned = |s: Option<&CStr>| s.map(CStr::to_owned);
+        CredentialTemplate {
+            excluded_ids: data.excluded_ids.to_vec(),
+            credential_type: data.credential_type,
+            client_data_hash: data.client_data_hash.to_vec(),
+            relying_party_id: data.relying_party.id().to_owned(),
+            relying_party_name: owned(data.relying_party.name()),
+            user_id: data.user.id().to_vec(),
+            user_name: data.user.name().to_owned(),
+            user_display_name: owned(data.user.display_name()),
+            user_icon: owned(data.user.icon()),
+            options: data.options,
+            extensions: data.extensions,
+            cred_protect: data.cred_protect,
+        }
+    }
+
+    /// Replaces the client data hash, e.g. to send every device its own challenge.
+    pub fn set_client_data_hash(&mut self, client_data_hash: &[u8]) {
+        self.client_data_hash = client_data_hash.to_vec();
+    }
+
+    /// Borrows the template as [`CredentialCreationData`].
+    ///
+    /// [`CredentialCreationData`]: struct.CredentialCreationData.html
+    pub fn data(&self) -> CredentialCreationData<'_> {
+        // The entities were valid when they were copied
+        let mut relying_party =
+            RpEntity::new(&self.relying_party_id).expect("the relying party ID is valid");
+        if let Some(name) = &self.relying_party_name {
+            relying_party = relying_party.with_name(name);
+        }
+        let mut user =
+            UserEntity::new(&self.user_id, &self.user_name).expect("the user ID is valid");
+        if let Some(display_name) = &self.user_display_name {
+            user = user.with_display_name(display_name);
+        }
+        if let Some(icon) = &self.user_icon {
+            user = user.with_icon(icon);
+        }
+        CredentialCreationData {
+            excluded_ids: &self.excluded_ids,
+            credential_type: self.credential_type,
+            client_data_hash: &self.client_data_hash,
+            relying_party,
+            user,
+            options: self.options,
+            extensions: self.extensions,
+            cred_protect: self.cred_protect,
+        }
+    }
+
+    /// Creates a request for one device.
+    pub fn creator(&self, fido: &Fido) -> Result<CredentialCreator> {
+        fido.new_credential_creator(self.data())
+    }
+}
+
+impl<'a> From<CredentialCreationData<'a>> for CredentialTemplate {
+    fn from(data: CredentialCreationData<'a>) -> Self {
+        CredentialTemplate::new(data)
+    }
+}
+
 impl CredentialCreator {
     /// Makes sure the contained Credential is initialized for transfer to a device
     pub(crate) fn new(
@@ -82,22 +241,75 @@ impl CredentialCreator {
         }
         credential.set_type(data.credential_type)?;
         credential.set_client_data_hash(data.client_data_hash)?;
-        credential.set_relying_party(data.relying_party_id, data.relying_party_name)?;
-        credential.set_user(
-            data.user_id,
-            data.user_name,
-            data.user_display_name,
-            data.user_image_uri,
-        )?;
+        credential.set_relying_party(data.relying_party)?;
+        credential.set_user(data.user)?;
         credential.set_options(data.options)?;
-        credential.set_extensions(data.extensions)?;
+        credential.set_extensions(data.extensions.bits())?;
+        if let Some(cred_protect) = data.cred_protect {
+            credential.set_cred_protect(cred_protect)?;
+        }
         Ok(CredentialCreator(credential))
     }
 
+    /// Replaces the [options] of the request, e.g. to request a resident (discoverable) credential.
+    ///
+    /// # Remarks
+    /// - Options that are not set are omitted from the request, leaving them up to the device.
+    ///
+    /// [options]: struct.CredentialOptions.html
+    pub fn set_options(&mut self, options: CredentialOptions) -> Result<()> {
+        self.0.set_options(options)
+    }
+
+    /// Replaces the extensions of the request, including `CredentialCreationData::extensions`
+    /// and `cred_protect`.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_INVALID_ARGUMENT` for inputs of assertions: an `hmac-secret` salt, or
+    ///   getting the `credBlob`.
+    pub fn set_extensions(&mut self, extensions: &Extensions<'_>) -> Result<()> {
+        let mut mask = 0;
+        match extensions.hmac_secret {
+            Some(HmacSecretInput::Enable) => mask |= FIDO_EXT_HMAC_SECRET as raw::c_int,
+            Some(HmacSecretInput::Salt(_)) => return Err(FidoError(FIDO_ERR_INVALID_ARGUMENT)),
+            None => {}
+        }
+        let blob = match extensions.cred_blob {
+            Some(CredBlobInput::Store(blob)) => Some(blob),
+            Some(CredBlobInput::Get) => return Err(FidoError(FIDO_ERR_INVALID_ARGUMENT)),
+            None => None,
+        };
+        #[cfg(feature = "largeblob")]
+        if extensions.large_blob_key {
+            mask |= FIDO_EXT_LARGEBLOB_KEY as raw::c_int;
+        }
+        #[cfg(feature = "config")]
+        if extensions.min_pin_length {
+            mask |= FIDO_EXT_MINPINLEN as raw::c_int;
+        }
+        // libfido2 adds to the extensions of the request, unless they are cleared first. Clearing
+        // them leaves the credProtect policy behind, which the response is checked against
+        self.0.set_extensions(0)?;
+        self.0.clear_cred_protect()?;
+        self.0.set_extensions(mask)?;
+        if let Some(cred_protect) = extensions.cred_protect {
+            self.0.set_cred_protect(cred_protect)?;
+        }
+        if let Some(blob) = blob {
+            self.0.set_cred_blob(blob)?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn raw(&self) -> &NonNull<fido_cred> {
         &self.0.raw
     }
 
+    /// Checks that the Credential was returned from a device, so `into_inner` is sound.
+    pub(crate) fn check_complete(&self) -> Result<()> {
+        self.0.try_as_ref().map(|_| ())
+    }
+
     pub(crate) fn raw_mut(&mut self) -> &mut NonNull<fido_cred> {
         &mut self.0.raw
     }
@@ -113,57 +325,183 @@ impl CredentialRef<'_> {
     ///
     /// [`PublicKey`]: enum.PublicKey.html
     pub fn public_key(&self) -> Result<PublicKey> {
-        match self.credential_type {
-            CredentialType::ES256 => PublicKey::new_es256(self.public_key),
-            CredentialType::RS256 => PublicKey::new_rs256(self.public_key),
-            CredentialType::EDDSA => PublicKey::new_eddsa(self.public_key),
-        }
+        PublicKey::from_bytes(self.credential_type, self.public_key)
+    }
+}
+
+/// Summary of a [`Credential`] that was just created by a device.
+///
+/// Owns copies of the commonly needed parts, while the full credential stays available.
+///
+/// [`Credential`]: struct.Credential.html
+pub struct CreatedCredential {
+    credential: Credential,
+    id: CredentialId,
+    credential_type: CredentialType,
+    public_key: Box<[u8]>,
+    aaguid: Box<[u8]>,
+    sign_count: u32,
+    format: String,
+}
+
+impl CreatedCredential {
+    /// Fails if the Credential was not returned from a device
+    pub(crate) fn new(credential: Credential) -> Result<Self> {
+        let (id, credential_type, public_key, format) = {
+            let data = credential.try_as_ref()?;
+            (
+                CredentialId::from(data.id),
+                data.credential_type,
+                Box::from(data.public_key),
+                data.format.to_string_lossy().into_owned(),
+            )
+        };
+        let aaguid = credential.aaguid().into();
+        let sign_count = credential.sign_count();
+
+        Ok(CreatedCredential {
+            credential,
+            id,
+            credential_type,
+            public_key,
+            aaguid,
+            sign_count,
+            format,
+        })
+    }
+
+    /// Returns the algorithm of the credential public key. See `Credential::algorithm`.
+    pub fn algorithm(&self) -> CoseAlgorithm {
+        self.credential
+            .algorithm()
+            .unwrap_or_else(|| self.credential_type.into())
+    }
+
+    /// Returns the ID of the credential.
+    pub fn id(&self) -> &CredentialId {
+        &self.id
+    }
+
+    /// Returns the type of the public key.
+    pub fn credential_type(&self) -> CredentialType {
+        self.credential_type
+    }
+
+    /// Returns the public key in COSE format.
+    pub fn public_key_bytes(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// Tries to parse the public key as a [`PublicKey`].
+    ///
+    /// [`PublicKey`]: enum.PublicKey.html
+    pub fn public_key(&self) -> Result<PublicKey> {
+        PublicKey::from_bytes(self.credential_type, &self.public_key)
+    }
+
+    /// Returns the AAGUID of the device that created the credential.
+    pub fn aaguid(&self) -> &[u8] {
+        &self.aaguid
+    }
+
+    /// Returns the AAGUID of the device that created the credential as an [`Aaguid`], e.g. to
+    /// look up the name of its model, or `None` if it isn't 16 bytes.
+    ///
+    /// [`Aaguid`]: struct.Aaguid.html
+    pub fn typed_aaguid(&self) -> Option<Aaguid> {
+        Aaguid::from_slice(&self.aaguid)
+    }
+
+    /// Returns the flags of the authenticator data, see `Credential::flags`.
+    pub fn flags(&self) -> AuthDataFlags {
+        self.credential.flags()
+    }
+
+    /// Returns the signature counter of the credential at creation.
+    pub fn sign_count(&self) -> u32 {
+        self.sign_count
+    }
+
+    /// Returns the attestation statement format, e.g. `packed`.
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    /// Returns the full credential.
+    pub fn credential(&self) -> &Credential {
+        &self.credential
+    }
+
+    /// Converts into the full credential.
+    pub fn into_credential(self) -> Credential {
+        self.credential
+    }
+}
+
+impl fmt::Debug for CreatedCredential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CreatedCredential")
+            .field("id", &self.id)
+            .field("credential_type", &self.credential_type)
+            .field("public_key", &self.public_key)
+            .field("aaguid", &self.aaguid)
+            .field("sign_count", &self.sign_count)
+            .field("format", &self.format)
+            .finish()
     }
 }
 
 impl Credential {
-    pub fn as_ref<'a>(&'a self) -> CredentialRef<'a> {
+    /// Borrows the data of a Credential returned from a device.
+    ///
+    /// # Panics
+    /// - When the credential is incomplete or of an unknown type. See [`try_as_ref`].
+    ///
+    /// [`try_as_ref`]: struct.Credential.html#method.try_as_ref
+    pub fn as_ref(&self) -> CredentialRef<'_> {
+        self.try_as_ref().expect("Incomplete credential")
+    }
+
+    /// Borrows the data of a Credential returned from a device.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_INVALID_ARGUMENT` if required data is missing from the credential.
+    /// - `FIDO_ERR_UNSUPPORTED_ALGORITHM` if the credential type is unknown.
+    pub fn try_as_ref(&self) -> Result<CredentialRef<'_>> {
+        const MISSING: FidoError = FidoError(FIDO_ERR_INVALID_ARGUMENT);
+
         unsafe {
             let credential = self.raw.as_ptr();
 
             let format = fido_cred_fmt(credential)
                 .as_ref()
                 .map(|ptr| CStr::from_ptr(ptr))
-                .unwrap();
+                .ok_or(MISSING)?;
 
-            let auth_data = fido_cred_authdata_ptr(credential)
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_cred_authdata_len(credential).try_into().unwrap()))
-                .unwrap();
+            let auth_data = bytes(fido_cred_authdata_ptr(credential), fido_cred_authdata_len(credential))?
+                .ok_or(MISSING)?;
 
-            let client_data_hash = fido_cred_clientdata_hash_ptr(credential)
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_cred_clientdata_hash_len(credential).try_into().unwrap()))
-                .unwrap();
+            let client_data_hash = bytes(
+                fido_cred_clientdata_hash_ptr(credential),
+                fido_cred_clientdata_hash_len(credential),
+            )?
+            .ok_or(MISSING)?;
 
-            let id = fido_cred_id_ptr(credential)
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_cred_id_len(credential).try_into().unwrap()))
-                .unwrap();
+            let id = bytes(fido_cred_id_ptr(credential), fido_cred_id_len(credential))?.ok_or(MISSING)?;
 
-            let credential_type = CredentialType::from_ffi(fido_cred_type(credential));
+            let credential_type = CredentialType::from_ffi(fido_cred_type(credential))
+                .ok_or(FidoError(FIDO_ERR_UNSUPPORTED_ALGORITHM as raw::c_int))?;
 
-            let public_key = fido_cred_pubkey_ptr(credential)
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_cred_pubkey_len(credential).try_into().unwrap()))
-                .unwrap();
+            let public_key = bytes(fido_cred_pubkey_ptr(credential), fido_cred_pubkey_len(credential))?
+                .ok_or(MISSING)?;
 
-            let signature = fido_cred_sig_ptr(credential)
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_cred_sig_len(credential).try_into().unwrap()))
-                .unwrap();
+            // Missing for some attestation formats
+            let signature = bytes(fido_cred_sig_ptr(credential), fido_cred_sig_len(credential))?.unwrap_or(&[]);
 
-            let x509_certificate = fido_cred_x5c_ptr(credential)
-                .as_ref()
-                .map(|ptr| slice::from_raw_parts(ptr, fido_cred_x5c_len(credential).try_into().unwrap()))
-                .unwrap();
+            let x509_certificate = bytes(fido_cred_x5c_ptr(credential), fido_cred_x5c_len(credential))?
+                .unwrap_or(&[]);
 
-            CredentialRef {
+            Ok(CredentialRef {
                 format,
                 auth_data,
                 client_data_hash,
@@ -172,7 +510,7 @@ impl Credential {
                 public_key,
                 signature,
                 x509_certificate,
-            }
+            })
         }
     }
 
@@ -189,6 +527,306 @@ impl Credential {
         }
     }
 
+    /// Verifies that the Credential was signed with its own key, for devices doing self
+    /// attestation.
+    ///
+    /// # Remarks
+    /// - Self attestation is a `packed` attestation statement without certificates (`x5c`),
+    ///   used by devices without an attestation key of their model. Credentials with
+    ///   certificates are verified with [`verify`] instead.
+    /// - This only proves possession of the credential key, not anything about the device.
+    ///   See `attestation::SelfAttestation` to also check the format.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_INVALID_SIG` if the signature was not made by the credential key.
+    /// - `FIDO_ERR_INVALID_ARGUMENT` if required data is missing from the credential.
+    ///
+    /// [`verify`]: struct.Credential.html#method.verify
+    pub fn verify_self(&self) -> Result<()> {
+        unsafe {
+            match fido_cred_verify_self(self.raw.as_ptr()) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        }
+    }
+
+    /// Returns a copy of the Credential without its attestation, as WebAuthn clients return it
+    /// for the `none` attestation conveyance preference.
+    ///
+    /// # Remarks
+    /// - The format is `none`, the signature and certificates are dropped, and the AAGUID in the
+    ///   authenticator data is replaced with zeros, so nothing identifies the device model.
+    /// - The user icon is dropped, as libfido2 doesn't return it.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_INVALID_ARGUMENT` if required data is missing from the credential.
+    pub fn without_attestation(&self) -> Result<Credential> {
+        const MISSING: FidoError = FidoError(FIDO_ERR_INVALID_ARGUMENT);
+        const NONE: &[u8] = b"none\0";
+
+        let data = self.try_as_ref()?;
+        unsafe {
+            let credential = self.raw.as_ptr();
+            let mut auth_data = bytes(
+                fido_cred_authdata_raw_ptr(credential),
+                fido_cred_authdata_raw_len(credential),
+            )?
+            .ok_or(MISSING)?
+            .to_vec();
+            auth_data::clear_aaguid(&mut auth_data);
+
+            let mut stripped = Credential {
+                raw: allocated(fido_cred_new())?,
+            };
+            stripped.set_type(data.credential_type)?;
+            stripped.set_client_data_hash(data.client_data_hash)?;
+            let raw = stripped.raw.as_ptr_mut();
+            let user_id = self.user_id();
+            let check = |result| match result {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            };
+            check(fido_cred_set_rp(
+                raw,
+                fido_cred_rp_id(credential),
+                fido_cred_rp_name(credential),
+            ))?;
+            check(fido_cred_set_user(
+                raw,
+                user_id.as_ptr(),
+                to_size_t(user_id.len())?,
+                fido_cred_user_name(credential),
+                fido_cred_display_name(credential),
+                ptr::null(),
+            ))?;
+            check(fido_cred_set_authdata_raw(
+                raw,
+                auth_data.as_ptr(),
+                to_size_t(auth_data.len())?,
+            ))?;
+            check(fido_cred_set_fmt(raw, NONE.as_ptr() as *const _))?;
+            Ok(stripped)
+        }
+    }
+
+    /// Checks the attestation of the credential against a policy, e.g.
+    /// `attestation::PackedWithChainTo`.
+    pub fn verify_attestation(
+        &self,
+        verifier: &impl attestation::Verifier,
+    ) -> std::result::Result<(), attestation::AttestationError> {
+        verifier.verify(self)
+    }
+
+    /// Returns the DER encoded attestation certificate chain, starting with the attestation certificate.
+    ///
+    /// # Remarks
+    /// - Returns an empty list for self attestation and formats without certificates.
+    pub fn x5c(&self) -> Vec<&[u8]> {
+        unsafe {
+            let credential = self.raw.as_ptr();
+            let chain = bytes(fido_cred_attstmt_ptr(credential), fido_cred_attstmt_len(credential))
+                .ok()
+                .flatten()
+                .and_then(|attestation_statement| {
+                    let mut x5c = Reader::new(attestation_statement).find("x5c")?;
+                    (0..x5c.array()?).map(|_| x5c.bytes()).collect::<Option<Vec<_>>>()
+                });
+
+            // libfido2 only exposes the first certificate otherwise
+            chain.unwrap_or_else(|| {
+                bytes(fido_cred_x5c_ptr(credential), fido_cred_x5c_len(credential))
+                    .ok()
+                    .flatten()
+                    .into_iter()
+                    .collect()
+            })
+        }
+    }
+
+    /// Returns the attestation certificate chain as PEM encoded certificates, see [`x5c`].
+    ///
+    /// [`x5c`]: struct.Credential.html#method.x5c
+    pub fn x5c_pem(&self) -> Vec<String> {
+        self.x5c()
+            .into_iter()
+            .map(|der| pem::encode("CERTIFICATE", der))
+            .collect()
+    }
+
+    /// Returns the ID of the relying party the credential was created for.
+    pub fn relying_party_id(&self) -> Option<&CStr> {
+        unsafe {
+            fido_cred_rp_id(self.raw.as_ptr())
+                .as_ref()
+                .map(|ptr| CStr::from_ptr(ptr))
+        }
+    }
+
+    /// Returns the ID of the user the credential was created for, or an empty slice if unknown.
+    pub fn user_id(&self) -> &[u8] {
+        unsafe {
+            let credential = self.raw.as_ptr();
+            bytes(fido_cred_user_id_ptr(credential), fido_cred_user_id_len(credential))
+                .ok()
+                .flatten()
+                .unwrap_or(&[])
+        }
+    }
+
+    /// Returns the name of the user the credential was created for.
+    pub fn user_name(&self) -> Option<&CStr> {
+        unsafe {
+            fido_cred_user_name(self.raw.as_ptr())
+                .as_ref()
+                .map(|ptr| CStr::from_ptr(ptr))
+        }
+    }
+
+    /// Returns the AAGUID of the device that created the credential, or an empty slice if unknown.
+    pub fn aaguid(&self) -> &[u8] {
+        unsafe {
+            let credential = self.raw.as_ptr();
+            bytes(fido_cred_aaguid_ptr(credential), fido_cred_aaguid_len(credential))
+                .ok()
+                .flatten()
+                .unwrap_or(&[])
+        }
+    }
+
+    /// Returns the flags of the authenticator data, e.g. `AuthDataFlags::BACKUP_ELIGIBLE` to
+    /// store with the credential.
+    pub fn flags(&self) -> AuthDataFlags {
+        AuthDataFlags::from_bits_truncate(unsafe { fido_cred_flags(self.raw.as_ptr()) })
+    }
+
+    /// Returns the signature counter stored in the authenticator data.
+    pub fn sign_count(&self) -> u32 {
+        unsafe { fido_cred_sigcount(self.raw.as_ptr()) }
+    }
+
+    /// Returns the key the large blob of the credential is encrypted with, if it was requested
+    /// with `CredentialExtensions::LARGE_BLOB_KEY` and the device returned one.
+    ///
+    /// # Remarks
+    /// - The key is also returned by assertions, see `Statement::large_blob_key`.
+    #[cfg(feature = "largeblob")]
+    pub fn large_blob_key(&self) -> Option<&[u8]> {
+        unsafe {
+            let credential = self.raw.as_ptr();
+            bytes(fido_cred_largeblob_key_ptr(credential), fido_cred_largeblob_key_len(credential))
+                .ok()
+                .flatten()
+                .filter(|key| !key.is_empty())
+        }
+    }
+
+    /// Returns the credential protection policy granted by the device.
+    ///
+    /// # Remarks
+    /// - This is read from the authenticator data, not from the request.
+    /// - Returns `None` if the device did not report a policy, which means it applies the default
+    ///   `CredProtect::UserVerificationOptional` policy.
+    pub fn cred_protect(&self) -> Option<CredProtect> {
+        unsafe {
+            let credential = self.raw.as_ptr();
+            let auth_data = bytes(
+                fido_cred_authdata_raw_ptr(credential),
+                fido_cred_authdata_raw_len(credential),
+            )
+            .ok()??;
+
+            AuthData::parse(auth_data)?
+                .extension("credProtect")?
+                .uint()
+                .and_then(|policy| policy.try_into().ok())
+                .and_then(CredProtect::from_ffi)
+        }
+    }
+
+    /// Returns the outputs of the extensions requested with `CredentialCreator::set_extensions`,
+    /// or with the flags of `CredentialCreationData`.
+    pub fn extension_results(&self) -> ExtensionResults<'_> {
+        let credential = self.raw.as_ptr();
+        let auth_data = unsafe {
+            bytes(
+                fido_cred_authdata_raw_ptr(credential),
+                fido_cred_authdata_raw_len(credential),
+            )
+            .ok()
+            .flatten()
+        };
+        let auth_data = auth_data.and_then(AuthData::parse);
+        let flag = |name| auth_data.as_ref()?.extension(name)?.bool();
+        ExtensionResults {
+            hmac_secret: flag("hmac-secret").map(HmacSecretOutput::Enabled),
+            cred_blob: flag("credBlob").map(CredBlobOutput::Stored),
+            #[cfg(feature = "largeblob")]
+            large_blob_key: self.large_blob_key(),
+            #[cfg(feature = "config")]
+            min_pin_length: auth_data
+                .as_ref()
+                .and_then(|auth_data| auth_data.extension("minPinLength")?.uint()),
+            cred_protect: self.cred_protect(),
+        }
+    }
+
+    /// Returns the factors the device used to verify the user, from the `uvm` extension.
+    ///
+    /// # Remarks
+    /// - libfido2 can't request the extension, so this is only `Some` for devices that return it
+    ///   unrequested. See `Statement::uvm`.
+    pub fn uvm(&self) -> Option<Vec<UvmEntry>> {
+        unsafe {
+            let credential = self.raw.as_ptr();
+            let auth_data = bytes(
+                fido_cred_authdata_raw_ptr(credential),
+                fido_cred_authdata_raw_len(credential),
+            )
+            .ok()??;
+            uvm_entries(auth_data)
+        }
+    }
+
+    /// Returns the algorithm of the credential public key.
+    ///
+    /// # Remarks
+    /// - This is read from the COSE key in the authenticator data, falling back to the type of
+    ///   the credential if the authenticator data is missing or malformed.
+    /// - Returns `None` if neither is known.
+    pub fn algorithm(&self) -> Option<CoseAlgorithm> {
+        unsafe {
+            let credential = self.raw.as_ptr();
+            bytes(
+                fido_cred_authdata_raw_ptr(credential),
+                fido_cred_authdata_raw_len(credential),
+            )
+            .ok()
+            .flatten()
+            .and_then(AuthData::parse)
+            .and_then(|auth_data| auth_data.attested_credential)
+            .and_then(|attested| CoseAlgorithm::from_cose_key(attested.public_key))
+            .or_else(|| {
+                CredentialType::from_ffi(fido_cred_type(credential)).map(CoseAlgorithm::from)
+            })
+        }
+    }
+
+    /// Compares the `requested` credential protection policy against the one granted by the device.
+    ///
+    /// Returns a [`CredProtectDowngrade`] warning if the device silently applied a weaker policy.
+    ///
+    /// [`CredProtectDowngrade`]: struct.CredProtectDowngrade.html
+    pub fn check_cred_protect(&self, requested: CredProtect) -> Option<CredProtectDowngrade> {
+        let granted = self.cred_protect();
+        if granted.unwrap_or(CredProtect::UserVerificationOptional) < requested {
+            Some(CredProtectDowngrade { requested, granted })
+        } else {
+            None
+        }
+    }
+
     /*
         Private FFI setters
     */
@@ -198,7 +836,7 @@ impl Credential {
             match fido_cred_exclude(
                 self.raw.as_ptr_mut(),
                 excluded_ids as *const _ as *const _,
-                excluded_ids.len().try_into().unwrap(),
+                to_size_t(excluded_ids.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -220,7 +858,7 @@ impl Credential {
             match fido_cred_set_clientdata_hash(
                 self.raw.as_ptr_mut(),
                 client_data_hash as *const _ as *const _,
-                client_data_hash.len().try_into().unwrap(),
+                to_size_t(client_data_hash.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -228,30 +866,28 @@ impl Credential {
         }
     }
 
-    fn set_relying_party(&mut self, id: &CStr, name: &CStr) -> Result<()> {
+    fn set_relying_party(&mut self, relying_party: RpEntity<'_>) -> Result<()> {
         unsafe {
-            match fido_cred_set_rp(self.raw.as_ptr_mut(), id.as_ptr(), name.as_ptr()) {
+            match fido_cred_set_rp(
+                self.raw.as_ptr_mut(),
+                relying_party.id().as_ptr(),
+                relying_party.name().map(CStr::as_ptr).unwrap_or(ptr::null()),
+            ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
             }
         }
     }
 
-    fn set_user(
-        &mut self,
-        id: &[u8],
-        name: &CStr,
-        display_name: Option<&CStr>,
-        image_uri: Option<&CStr>,
-    ) -> Result<()> {
+    fn set_user(&mut self, user: UserEntity<'_>) -> Result<()> {
         unsafe {
             match fido_cred_set_user(
                 self.raw.as_ptr_mut(),
-                id as *const _ as *const _,
-                id.len().try_into().unwrap(),
-                name.as_ptr(),
-                display_name.map(CStr::as_ptr).unwrap_or(ptr::null()),
-                image_uri.map(CStr::as_ptr).unwrap_or(ptr::null()),
+                user.id() as *const _ as *const _,
+                to_size_t(user.id().len())?,
+                user.name().as_ptr(),
+                user.display_name().map(CStr::as_ptr).unwrap_or(ptr::null()),
+                user.icon().map(CStr::as_ptr).unwrap_or(ptr::null()),
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -273,7 +909,7 @@ impl Credential {
             match fido_cred_set_authdata(
                 self.raw.as_ptr_mut(),
                 auth_data as *const _ as *const _,
-                auth_data.len().try_into().unwrap(),
+                to_size_t(auth_data.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -286,7 +922,7 @@ impl Credential {
             match fido_cred_set_x509(
                 self.raw.as_ptr_mut(),
                 x509_certificate as *const _ as *const _,
-                x509_certificate.len().try_into().unwrap(),
+                to_size_t(x509_certificate.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -299,7 +935,7 @@ impl Credential {
             match fido_cred_set_sig(
                 self.raw.as_ptr_mut(),
                 signature as *const _ as *const _,
-                signature.len().try_into().unwrap(),
+                to_size_t(signature.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -309,10 +945,39 @@ impl Credential {
 
     fn set_options(&mut self, options: CredentialOptions) -> Result<()> {
         unsafe {
-            match fido_cred_set_options(
+            let credential = self.raw.as_ptr_mut();
+            match fido_cred_set_rk(
+                credential,
+                opt_or_omit(options.contains(CredentialOptions::RESIDENT_KEY)),
+            ) {
+                FIDO_OK => {}
+                err => return Err(FidoError(err)),
+            }
+            match fido_cred_set_uv(
+                credential,
+                opt_or_omit(options.contains(CredentialOptions::USER_VERIFICATION)),
+            ) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        }
+    }
+
+    fn set_extensions(&mut self, extensions: raw::c_int) -> Result<()> {
+        unsafe {
+            match fido_cred_set_extensions(self.raw.as_ptr_mut(), extensions) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        }
+    }
+
+    fn set_cred_blob(&mut self, blob: &[u8]) -> Result<()> {
+        unsafe {
+            match fido_cred_set_blob(
                 self.raw.as_ptr_mut(),
-                options.contains(CredentialOptions::RESIDENT_KEY),
-                options.contains(CredentialOptions::USER_VERIFICATION),
+                blob as *const _ as *const _,
+                to_size_t(blob.len())?,
             ) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
@@ -320,14 +985,59 @@ impl Credential {
         }
     }
 
-    fn set_extensions(&mut self, extensions: CredentialExtensions) -> Result<()> {
+    fn set_cred_protect(&mut self, cred_protect: CredProtect) -> Result<()> {
+        unsafe {
+            match fido_cred_set_prot(self.raw.as_ptr_mut(), cred_protect as raw::c_int) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        }
+    }
+
+    fn clear_cred_protect(&mut self) -> Result<()> {
         unsafe {
-            match fido_cred_set_extensions(self.raw.as_ptr_mut(), extensions.bits()) {
+            match fido_cred_set_prot(self.raw.as_ptr_mut(), 0) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
             }
         }
     }
+
+    /// Returns the underlying `fido_cred_t`, to call libfido2 functions this crate does not wrap.
+    ///
+    /// # Remarks
+    /// - The pointer is valid for as long as `self` is, and must not be freed.
+    pub fn as_raw(&self) -> *const fido_cred_t {
+        self.raw.as_ptr()
+    }
+
+    /// Returns the underlying `fido_cred_t` mutably. See [`as_raw`].
+    ///
+    /// [`as_raw`]: struct.Credential.html#method.as_raw
+    pub fn as_raw_mut(&mut self) -> *mut fido_cred_t {
+        self.raw.as_ptr_mut()
+    }
+
+    /// Gives up ownership of the underlying `fido_cred_t`.
+    ///
+    /// # Remarks
+    /// - The caller is responsible for freeing it with `fido_cred_free`, or with [`from_raw`].
+    ///
+    /// [`from_raw`]: struct.Credential.html#method.from_raw
+    pub fn into_raw(self) -> *mut fido_cred_t {
+        let mut credential = mem::ManuallyDrop::new(self);
+        credential.raw.as_ptr_mut()
+    }
+
+    /// Takes ownership of a `fido_cred_t`, which is freed on drop.
+    ///
+    /// # Safety
+    /// - `raw` must be a valid `fido_cred_t` allocated by libfido2, which is not owned by anything
+    ///   else.
+    /// - Its response fields must be set, as by a successful `fido_dev_make_cred`.
+    pub unsafe fn from_raw(raw: *mut fido_cred_t) -> Option<Self> {
+        NonNull::new(raw).map(|raw| Credential { raw })
+    }
 }
 
 // libfido2_sys guarantees this.
@@ -339,7 +1049,7 @@ impl Drop for Credential {
         unsafe {
             let mut credential = self.raw.as_ptr_mut();
             fido_cred_free(&mut credential as *mut _);
-            assert!(credential.is_null());
+            debug_assert!(credential.is_null());
         }
     }
 }
@@ -351,21 +1061,97 @@ bitflags! {
     pub struct CredentialExtensions: raw::c_int {
         /// Enables the ability to generate a symmetric secret.
         const HMAC_SECRET = FIDO_EXT_HMAC_SECRET as raw::c_int;
+        /// Asks the device for a key to encrypt a large blob of the credential with.
+        /// Only supported for resident credentials.
+        #[cfg(feature = "largeblob")]
+        const LARGE_BLOB_KEY = FIDO_EXT_LARGEBLOB_KEY as raw::c_int;
     }
 }
 
+flag_names!(CredentialExtensions {
+    "hmac-secret" => HMAC_SECRET,
+    #[cfg(feature = "largeblob")]
+    "largeBlobKey" => LARGE_BLOB_KEY,
+});
+
 bitflags! {
     /// Option flags for a [`Credential`].
     ///
     /// [`Credential`]: struct.Credential.html
     pub struct CredentialOptions: u8 {
-        /// Instructs the authenticator to store the key material on the device.
+        /// Instructs the authenticator to store the key material on the device,
+        /// creating a resident (discoverable) credential.
         const RESIDENT_KEY = 1;
         /// Instructs the authenticator to require a gesture that verifies the user to complete the request.
         const USER_VERIFICATION = 2;
     }
 }
 
+flag_names!(CredentialOptions {
+    "rk" => RESIDENT_KEY,
+    "uv" => USER_VERIFICATION,
+});
+
+/// Credential protection policies of the `credProtect` extension, ordered from weakest to strongest.
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CredProtect {
+    /// The credential can be used with or without user verification.
+    UserVerificationOptional = FIDO_CRED_PROT_UV_OPTIONAL as i32,
+    /// User verification is optional only if the credential ID is part of the allow list.
+    OptionalWithCredentialIdList = FIDO_CRED_PROT_UV_OPTIONAL_WITH_ID as i32,
+    /// The credential can only be used with user verification.
+    Required = FIDO_CRED_PROT_UV_REQUIRED as i32,
+}
+
+impl CredProtect {
+    pub(crate) fn from_ffi(i: raw::c_int) -> Option<Self> {
+        match i {
+            x if x == CredProtect::UserVerificationOptional as raw::c_int => {
+                Some(CredProtect::UserVerificationOptional)
+            }
+            x if x == CredProtect::OptionalWithCredentialIdList as raw::c_int => {
+                Some(CredProtect::OptionalWithCredentialIdList)
+            }
+            x if x == CredProtect::Required as raw::c_int => Some(CredProtect::Required),
+            _ => None,
+        }
+    }
+}
+
+enum_names!(CredProtect {
+    "uv-optional" => UserVerificationOptional,
+    "uv-optional-with-id" => OptionalWithCredentialIdList,
+    "uv-required" => Required,
+});
+
+/// Warning that a device granted a weaker [`CredProtect`] policy than was requested.
+///
+/// [`CredProtect`]: enum.CredProtect.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CredProtectDowngrade {
+    pub requested: CredProtect,
+    /// `None` if the device ignored the extension altogether.
+    pub granted: Option<CredProtect>,
+}
+
+impl fmt::Display for CredProtectDowngrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.granted {
+            Some(granted) => write!(
+                f,
+                "The device downgraded credential protection from {:?} to {:?}",
+                self.requested, granted
+            ),
+            None => write!(
+                f,
+                "The device ignored the requested credential protection {:?}",
+                self.requested
+            ),
+        }
+    }
+}
+
 /// Possible data formats for a [`Credential`].
 ///
 /// [`Credential`]: struct.Credential.html
@@ -401,6 +1187,15 @@ impl FromStr for CredentialFormat {
     }
 }
 
+impl fmt::Display for CredentialFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CredentialFormat::Fido2 => Self::FIDO2_FORMAT,
+            CredentialFormat::FidoU2F => Self::FIDO_U2F_FORMAT,
+        })
+    }
+}
+
 /// Possible public key formats for a [`Credential`].
 ///
 /// [`Credential`]: struct.Credential.html
@@ -413,18 +1208,108 @@ pub enum CredentialType {
 }
 
 impl CredentialType {
-    /// # Panics
-    /// - When passed an invalid value
-    pub(crate) fn from_ffi(i: raw::c_int) -> Self {
+    pub(crate) fn from_ffi(i: raw::c_int) -> Option<Self> {
         match i {
-            COSE_ES256 => CredentialType::ES256,
-            COSE_RS256 => CredentialType::RS256,
-            COSE_EDDSA => CredentialType::EDDSA,
-            _ => panic!("Invalid credential type"),
+            COSE_ES256 => Some(CredentialType::ES256),
+            COSE_RS256 => Some(CredentialType::RS256),
+            COSE_EDDSA => Some(CredentialType::EDDSA),
+            _ => None,
         }
     }
 }
 
+/// COSE algorithm identifiers, as registered by IANA, that credentials are created with.
+///
+/// # Remarks
+/// - Unlike [`CredentialType`], this covers algorithms libfido2 can't create credentials for,
+///   which devices may still report.
+///
+/// [`CredentialType`]: enum.CredentialType.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CoseAlgorithm {
+    /// ECDSA with SHA-256 on P-256
+    ES256,
+    /// ECDSA with SHA-384 on P-384
+    ES384,
+    /// ECDSA with SHA-512 on P-521
+    ES512,
+    /// ECDSA with SHA-256 on secp256k1
+    ES256K,
+    /// EdDSA, i.e. Ed25519
+    EDDSA,
+    /// RSASSA-PKCS1-v1_5 with SHA-256
+    RS256,
+    /// RSASSA-PSS with SHA-256
+    PS256,
+    Other(i64),
+}
+
+impl CoseAlgorithm {
+    /// Returns the COSE algorithm identifier, e.g. `-7` for `ES256`.
+    pub fn id(self) -> i64 {
+        match self {
+            CoseAlgorithm::ES256 => -7,
+            CoseAlgorithm::ES384 => -35,
+            CoseAlgorithm::ES512 => -36,
+            CoseAlgorithm::ES256K => -47,
+            CoseAlgorithm::EDDSA => -8,
+            CoseAlgorithm::RS256 => -257,
+            CoseAlgorithm::PS256 => -37,
+            CoseAlgorithm::Other(id) => id,
+        }
+    }
+
+    /// Looks up the algorithm with COSE identifier `id`.
+    pub fn from_id(id: i64) -> Self {
+        match id {
+            -7 => CoseAlgorithm::ES256,
+            -35 => CoseAlgorithm::ES384,
+            -36 => CoseAlgorithm::ES512,
+            -47 => CoseAlgorithm::ES256K,
+            -8 => CoseAlgorithm::EDDSA,
+            -257 => CoseAlgorithm::RS256,
+            -37 => CoseAlgorithm::PS256,
+            id => CoseAlgorithm::Other(id),
+        }
+    }
+
+    /// Returns the matching `CredentialType`, if libfido2 supports the algorithm.
+    pub fn credential_type(self) -> Option<CredentialType> {
+        match self {
+            CoseAlgorithm::ES256 => Some(CredentialType::ES256),
+            CoseAlgorithm::RS256 => Some(CredentialType::RS256),
+            CoseAlgorithm::EDDSA => Some(CredentialType::EDDSA),
+            _ => None,
+        }
+    }
+
+    /// Reads the algorithm (label 3) of a COSE key.
+    pub(crate) fn from_cose_key(key: &[u8]) -> Option<Self> {
+        const ALG: i64 = 3;
+
+        let mut reader = Reader::new(key);
+        for _ in 0..reader.map()? {
+            if reader.int()? == ALG {
+                return reader.int().map(CoseAlgorithm::from_id);
+            }
+            reader.skip()?;
+        }
+        None
+    }
+}
+
+impl From<CredentialType> for CoseAlgorithm {
+    fn from(credential_type: CredentialType) -> Self {
+        CoseAlgorithm::from_id(credential_type as i64)
+    }
+}
+
+enum_names!(CredentialType {
+    "es256" => ES256,
+    "rs256" => RS256,
+    "eddsa" => EDDSA,
+});
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct InvalidCredentialFormatError;
 
@@ -0,0 +1,103 @@
+use crate::{
+    ffi::{bytes, to_size_t, to_usize},
+    CredProtect, CredentialId, CredentialType, Result,
+};
+use libfido2_sys::*;
+use std::{ffi::CStr, os::raw, time::SystemTime};
+
+/// A resident (discoverable) credential stored on a device, as returned by
+/// [`Device::resident_credentials`].
+///
+/// # Remarks
+/// - CTAP 2.1 credential management does not report when a credential was created or last used.
+///   Relying parties that want to order an account picker by relevance have to keep track of
+///   that themselves, see [`sort_by_last_use`].
+///
+/// [`Device::resident_credentials`]: struct.Device.html#method.resident_credentials
+/// [`sort_by_last_use`]: fn.sort_by_last_use.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResidentCredential {
+    pub id: CredentialId,
+    /// `None` for algorithms this crate does not support.
+    pub credential_type: Option<CredentialType>,
+    /// COSE encoded public key.
+    pub public_key: Vec<u8>,
+    pub user_id: Vec<u8>,
+    pub user_name: Option<String>,
+    pub user_display_name: Option<String>,
+    /// `None` if the device did not report a policy.
+    pub cred_protect: Option<CredProtect>,
+    /// Position in the enumeration of the device. Devices commonly, but not necessarily,
+    /// enumerate credentials in the order they were created.
+    pub index: usize,
+}
+
+impl ResidentCredential {
+    /// Copies the fields of a credential returned by credential management.
+    ///
+    /// # Unsafety
+    /// - `credential` must be a valid credential.
+    unsafe fn from_raw(credential: *const fido_cred_t, index: usize) -> Result<Self> {
+        let string = |ptr: *const raw::c_char| {
+            ptr.as_ref()
+                .map(|ptr| CStr::from_ptr(ptr).to_string_lossy().into_owned())
+        };
+        let owned = |data: Result<Option<&[u8]>>| data.map(|data| data.unwrap_or(&[]).to_vec());
+
+        Ok(ResidentCredential {
+            id: owned(bytes(fido_cred_id_ptr(credential), fido_cred_id_len(credential)))?.into(),
+            credential_type: CredentialType::from_ffi(fido_cred_type(credential)),
+            public_key: owned(bytes(
+                fido_cred_pubkey_ptr(credential),
+                fido_cred_pubkey_len(credential),
+            ))?,
+            user_id: owned(bytes(
+                fido_cred_user_id_ptr(credential),
+                fido_cred_user_id_len(credential),
+            ))?,
+            user_name: string(fido_cred_user_name(credential)),
+            user_display_name: string(fido_cred_display_name(credential)),
+            cred_protect: CredProtect::from_ffi(fido_cred_prot(credential)),
+            index,
+        })
+    }
+}
+
+/// Reads all credentials of a credential management response.
+///
+/// # Unsafety
+/// - `rk` must be a valid response.
+pub(crate) unsafe fn resident_credentials(
+    rk: *const fido_credman_rk_t,
+) -> Result<Vec<ResidentCredential>> {
+    (0..to_usize(fido_credman_rk_count(rk))?)
+        .filter_map(|i| {
+            let credential = match to_size_t(i) {
+                Ok(n) => fido_credman_rk(rk, n),
+                Err(err) => return Some(Err(err)),
+            };
+            if credential.is_null() {
+                None
+            } else {
+                Some(ResidentCredential::from_raw(credential, i))
+            }
+        })
+        .collect()
+}
+
+/// Sorts credentials by the time they were last used according to `last_used`, most recent
+/// first, e.g. for an account picker.
+///
+/// # Remarks
+/// - Credentials `last_used` doesn't know are placed last, in enumeration order.
+pub fn sort_by_last_use<F>(credentials: &mut [ResidentCredential], mut last_used: F)
+where
+    F: FnMut(&CredentialId) -> Option<SystemTime>,
+{
+    credentials.sort_by_cached_key(|credential| {
+        (
+            std::cmp::Reverse(last_used(&credential.id)),
+            credential.index,
+        )
+    });
+}
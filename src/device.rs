@@ -1,15 +1,51 @@
 use crate::{
-    cbor_info::CBORData, ffi::NonNull, Assertion, AssertionCreator, Credential, CredentialCreator,
-    FidoError, Result, FIDO_OK,
+    attestation,
+    audit::{Audit, AuditOperation, AuditSink},
+    cancel::Canceller,
+    cbor_info::CBORData,
+    ffi::{allocated, NonNull},
+    monitor::{self, Monitor},
+    openssl,
+    trace::{DeviceSpan, OperationSpan},
+    validate_pin, Assertion, AssertionCreationData, AssertionCreator, AssertionOptions,
+    AuthenticatorInfo, BenchReport, CancellationToken, CborViolation, CheckedRequestError,
+    CreatedCredential, CredentialCreationData, CredentialCreator, DeviceReport, Extension, Fido,
+    FidoError, KeepAliveStatus, PinChangeError, PinEntryError, PinPolicy, PinPrompt, PinProvider,
+    Result, RoundTrip, RpEntity, Traffic, UserEntity, UserVerification, UvPreference, FIDO_OK,
 };
 use bitflags::bitflags;
 use libfido2_sys::*;
-use std::{convert::AsRef, ffi::CStr, ptr, str};
+use std::{
+    collections::HashSet,
+    convert::{AsRef, TryFrom},
+    error,
+    ffi::{CStr, CString, OsStr},
+    fmt, mem,
+    os::raw,
+    path::Path,
+    ptr, str,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long [`Device::ctap2_command`] waits for the response.
+///
+/// [`Device::ctap2_command`]: struct.Device.html#method.ctap2_command
+pub const CTAP2_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// Represents a connection to a FIDO2 device.
-#[derive(PartialEq, Eq)]
 pub struct Device {
     pub(crate) raw: NonNull<fido_dev>,
+    // Only set for devices opened through our own HID I/O
+    pub(crate) monitor: Option<Arc<Monitor>>,
+    // Shared with the threads that cancel requests, and revoked before the device is closed
+    pub(crate) canceller: Arc<Canceller>,
+    pub(crate) audit: Option<Arc<dyn AuditSink>>,
+    pub(crate) span: DeviceSpan,
 }
 
 impl Device {
@@ -58,17 +94,62 @@ impl Device {
         }
     }
 
+    /// Sets how long requests wait for the device, including waiting for the user to touch it.
+    ///
+    /// # Arguments
+    /// - `timeout`: The timeout with millisecond precision, or `None` to wait indefinitely
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_INVALID_ARGUMENT` if the timeout does not fit in a C `int` of milliseconds.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        let ms = match timeout {
+            Some(timeout) => raw::c_int::try_from(timeout.as_millis())
+                .map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))?,
+            None => -1,
+        };
+        unsafe {
+            match fido_dev_set_timeout(self.raw.as_ptr_mut(), ms) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        }
+    }
+
+    /// Checks whether the device responds by sending a CTAPHID PING with a random payload,
+    /// and returns the round trip time.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block until the device answers or `timeout` passes.
+    /// - Only devices opened with [`Fido::new_monitored_device`] can be pinged,
+    ///   others return `FIDO_ERR_INVALID_ARGUMENT`.
+    /// - The device is not asked for user presence, so this is safe to call without prompting.
+    ///
+    /// [`Fido::new_monitored_device`]: struct.Fido.html#method.new_monitored_device
+    pub fn ping(&mut self, timeout: Duration) -> Result<Duration> {
+        if self.monitor.is_none() {
+            return Err(FidoError(FIDO_ERR_INVALID_ARGUMENT));
+        }
+        let payload = monitor::ping_payload();
+        let start = Instant::now();
+        unsafe {
+            monitor::ping(self.raw.as_ptr(), &payload, timeout)?;
+        }
+        Ok(start.elapsed())
+    }
+
     /// Requests additional [data] stored as CBOR from the device.
     ///
     /// # Remarks
     /// - This is synchronous and will block.
+    /// - Prefer [`authenticator_info`], which returns owned data.
     ///
     /// [data]: struct.CBORData.html
+    /// [`authenticator_info`]: struct.Device.html#method.authenticator_info
     pub fn request_cbor_data(&mut self) -> Result<CBORData> {
         unsafe {
             // Allocate empty CBOR info (called CBORData since the information has its own wrapper struct)
             let mut cbor_info = CBORData {
-                raw: NonNull::new(fido_cbor_info_new()).unwrap(),
+                raw: allocated(fido_cbor_info_new())?,
             };
 
             // Request CBOR information
@@ -79,46 +160,934 @@ impl Device {
         }
     }
 
+    /// Requests the [information] the device reports about itself (CTAP2 `authenticatorGetInfo`).
+    ///
+    /// The data is copied out of libfido2 and freed right away, so the result can be kept
+    /// around and sent to other threads.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    ///
+    /// [information]: struct.AuthenticatorInfo.html
+    pub fn authenticator_info(&mut self) -> Result<AuthenticatorInfo> {
+        Ok(self.request_cbor_data()?.try_as_ref()?.into_owned())
+    }
+
+    /// Collects everything the device reports about itself into one [`DeviceReport`].
+    ///
+    /// # Arguments
+    /// - `pin`: Current PIN, needed for the resident key metadata
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - Requests that fail are left out of the report instead of failing it.
+    /// - The resident key metadata and bio info are left out without the `credman` and `bio`
+    ///   features.
+    ///
+    /// [`DeviceReport`]: struct.DeviceReport.html
+    pub fn report(&mut self, pin: Option<&CStr>) -> DeviceReport {
+        let info = self.authenticator_info().ok();
+        let option = |name: &str| {
+            info.as_ref()
+                .and_then(|info| info.options.get(name))
+                .copied()
+        };
+        let pin_retries = match option("clientPin") {
+            Some(_) => self.retry_count().ok(),
+            None => None,
+        };
+        #[cfg(feature = "credman")]
+        let resident_keys = match pin {
+            Some(pin) if unsafe { fido_dev_supports_credman(self.raw.as_ptr()) } => {
+                self.resident_key_metadata(pin).ok()
+            }
+            _ => None,
+        };
+        #[cfg(not(feature = "credman"))]
+        let resident_keys = {
+            let _ = pin;
+            None
+        };
+        #[cfg(feature = "bio")]
+        let bio = match option("bioEnroll").or_else(|| option("userVerificationMgmtPreview")) {
+            Some(_) => self.bio_info().ok(),
+            None => None,
+        };
+        #[cfg(not(feature = "bio"))]
+        let bio = None;
+
+        DeviceReport {
+            mode: self.mode(),
+            ctap_hid: self.ctap_hid_info(),
+            info,
+            pin_retries,
+            resident_keys,
+            bio,
+        }
+    }
+
+    /// Measures `rounds` makeCredential requests, and `rounds` getAssertion requests with the
+    /// created credentials, e.g. to compare authenticators or to spot degraded USB hubs.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - The user has to touch the device for every request, which is part of the durations.
+    ///   Keep-alive messages are sent while waiting, so compare their count as well.
+    /// - Traffic is only measured for devices opened with [`Fido::new_monitored_device`].
+    ///
+    /// [`Fido::new_monitored_device`]: struct.Fido.html#method.new_monitored_device
+    pub fn bench(&mut self, fido: &Fido, rounds: usize) -> Result<BenchReport> {
+        const RP_ID: &[u8] = b"bench.libfido2-rs.invalid\0";
+        const USER_NAME: &[u8] = b"bench\0";
+        let rp_id = CStr::from_bytes_with_nul(RP_ID).expect("nul terminated");
+        let user_name = CStr::from_bytes_with_nul(USER_NAME).expect("nul terminated");
+        // Both are valid, so these can't fail
+        let relying_party =
+            RpEntity::new(rp_id).map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+        let user =
+            UserEntity::new(&[1], user_name).map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+        let client_data_hash = [0; 32];
+
+        let mut report = BenchReport::default();
+        let mut ids = Vec::with_capacity(rounds);
+        for _ in 0..rounds {
+            let data =
+                CredentialCreationData::with_defaults(&client_data_hash, relying_party, user);
+            let creator = fido.new_credential_creator(data)?;
+            let (credential, round_trip) = self.measure(|device| {
+                device.request_credential_creation(creator, None)
+            })?;
+            ids.push(credential.id().as_bytes().to_vec());
+            report.make_credential.push(round_trip);
+        }
+        for id in &ids {
+            let allowed = [&id[..]];
+            let data = AssertionCreationData::with_defaults(
+                Some(&allowed),
+                &client_data_hash,
+                relying_party,
+            );
+            let creator = fido.new_assertion_creator(data)?;
+            let (_, round_trip) = self.measure(|device| {
+                device.request_assertion_verification(creator, None)
+            })?;
+            report.get_assertion.push(round_trip);
+        }
+        Ok(report)
+    }
+
+    fn measure<T>(
+        &mut self,
+        request: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<(T, RoundTrip)> {
+        let before = self.traffic();
+        let start = Instant::now();
+        let response = request(self)?;
+        let duration = start.elapsed();
+        let traffic = match (before, self.traffic()) {
+            (Some(before), Some(after)) => Some(after.since(&before)),
+            _ => None,
+        };
+        Ok((response, RoundTrip { duration, traffic }))
+    }
+
+    /// Returns the HID traffic since the device was opened.
+    ///
+    /// # Remarks
+    /// - Only devices opened with [`Fido::new_monitored_device`] are measured, others return
+    ///   `None`.
+    ///
+    /// [`Fido::new_monitored_device`]: struct.Fido.html#method.new_monitored_device
+    pub fn traffic(&self) -> Option<Traffic> {
+        self.monitor.as_ref().map(|monitor| monitor.traffic())
+    }
+
+    /// Requests the amount of resident (discoverable) credentials on the device.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    #[cfg(feature = "credman")]
+    pub fn resident_key_metadata(&mut self, pin: &CStr) -> Result<crate::ResidentKeyMetadata> {
+        unsafe {
+            let mut metadata = allocated(fido_credman_metadata_new())?.as_ptr_mut();
+            let result =
+                match fido_credman_get_dev_metadata(self.raw.as_ptr_mut(), metadata, pin.as_ptr()) {
+                    FIDO_OK => Ok(crate::ResidentKeyMetadata {
+                        existing: fido_credman_rk_existing(metadata),
+                        remaining: fido_credman_rk_remaining(metadata),
+                    }),
+                    err => Err(FidoError(err)),
+                };
+            fido_credman_metadata_free(&mut metadata as *mut _);
+            result
+        }
+    }
+
+    /// Requests the resident (discoverable) credentials of the relying party `rp_id`.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - Requires CTAP 2.1 credential management, see `fido_dev_supports_credman`.
+    /// - See [`sort_by_last_use`] to order the credentials for an account picker.
+    ///
+    /// [`sort_by_last_use`]: fn.sort_by_last_use.html
+    #[cfg(feature = "credman")]
+    pub fn resident_credentials(
+        &mut self,
+        rp_id: &CStr,
+        pin: &CStr,
+    ) -> Result<Vec<crate::ResidentCredential>> {
+        unsafe {
+            let mut rk = allocated(fido_credman_rk_new())?.as_ptr_mut();
+            let result = match fido_credman_get_dev_rk(
+                self.raw.as_ptr_mut(),
+                rp_id.as_ptr(),
+                rk,
+                pin.as_ptr(),
+            ) {
+                FIDO_OK => crate::credman::resident_credentials(rk),
+                err => Err(FidoError(err)),
+            };
+            fido_credman_rk_free(&mut rk as *mut _);
+            result
+        }
+    }
+
+    /// Requests information about the fingerprint sensor of the device.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    #[cfg(feature = "bio")]
+    pub fn bio_info(&mut self) -> Result<crate::BioInfo> {
+        unsafe {
+            let mut info = allocated(fido_bio_info_new())?.as_ptr_mut();
+            let result = match fido_bio_dev_get_info(self.raw.as_ptr_mut(), info) {
+                FIDO_OK => Ok(crate::BioInfo {
+                    sensor_type: fido_bio_info_type(info),
+                    max_samples: fido_bio_info_max_samples(info),
+                }),
+                err => Err(FidoError(err)),
+            };
+            fido_bio_info_free(&mut info as *mut _);
+            result
+        }
+    }
+
+    /// Checks whether the device supports `extension`, e.g. `Extension::HmacSecret` or `"hmac-secret"`.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block, since it requests the [`authenticator_info`].
+    ///   Use [`AuthenticatorInfo::supports_extension`] to check several extensions at once.
+    ///
+    /// [`authenticator_info`]: struct.Device.html#method.authenticator_info
+    /// [`AuthenticatorInfo::supports_extension`]: struct.AuthenticatorInfo.html#method.supports_extension
+    pub fn supports_extension(&mut self, extension: impl AsRef<str>) -> Result<bool> {
+        Ok(self.authenticator_info()?.supports_extension(extension))
+    }
+
     /// Requests the device to create a new Credential.
     ///
     /// # Remarks
     /// - This is synchronous and will block.
+    /// - Use [`try_request_credential_creation`] to get the request back if it fails.
+    ///
+    /// [`try_request_credential_creation`]: struct.Device.html#method.try_request_credential_creation
     pub fn request_credential_creation(
+        &mut self,
+        credential: CredentialCreator,
+        pin: Option<&CStr>,
+    ) -> Result<CreatedCredential> {
+        self.try_request_credential_creation(credential, pin)
+            .map_err(FidoError::from)
+    }
+
+    /// Requests the device to create a new Credential, returning the request with the error
+    /// if it fails, so it can be sent again.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    pub fn try_request_credential_creation(
         &mut self,
         mut credential: CredentialCreator,
         pin: Option<&CStr>,
-    ) -> Result<Credential> {
-        unsafe {
-            match fido_dev_make_cred(
-                self.raw.as_ptr_mut(),
+    ) -> std::result::Result<CreatedCredential, RequestError<CredentialCreator>> {
+        let rp_id = unsafe { fido_cred_rp_id(credential.raw().as_ptr()).as_ref() }
+            .map(|rp_id| unsafe { CStr::from_ptr(rp_id) });
+        let audit = Audit::start(self, AuditOperation::CreateCredential, rp_id);
+        let result = self.watched(|device| unsafe {
+            fido_dev_make_cred(
+                device,
                 credential.raw_mut().as_ptr_mut(),
                 pin.map(CStr::as_ptr).unwrap_or(ptr::null()),
-            ) {
-                FIDO_OK => Ok(credential.into_inner()),
-                err => Err(FidoError(err)),
+            )
+        });
+        let result = match result {
+            FIDO_OK => credential.check_complete(),
+            err => Err(FidoError(err)),
+        };
+        match result {
+            Ok(()) => {
+                let created = CreatedCredential::new(credential.into_inner())
+                    .expect("the credential was checked to be complete");
+                audit.finish(Some(created.id().as_bytes()), Ok(()));
+                Ok(created)
+            }
+            Err(error) => {
+                audit.finish(None, Err(error));
+                Err(RequestError {
+                    error,
+                    request: credential,
+                })
+            }
+        }
+    }
+
+    /// Requests the device to create a new Credential, keeping as much of its attestation as
+    /// `conveyance` asks for.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - With `Conveyance::None`, the returned credential is the one of
+    ///   `Credential::without_attestation`, with format `none` and a zero AAGUID.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_UNSUPPORTED_OPTION` with `Conveyance::Direct`, if the device returned no
+    ///   attestation.
+    pub fn request_credential_creation_with_policy(
+        &mut self,
+        credential: CredentialCreator,
+        conveyance: attestation::Conveyance,
+        pin: Option<&CStr>,
+    ) -> Result<CreatedCredential> {
+        let created = self.request_credential_creation(credential, pin)?;
+        match conveyance {
+            attestation::Conveyance::None => {
+                CreatedCredential::new(created.credential().without_attestation()?)
+            }
+            attestation::Conveyance::Direct if created.format() == "none" => {
+                Err(FidoError(FIDO_ERR_UNSUPPORTED_OPTION as raw::c_int))
             }
+            attestation::Conveyance::Indirect | attestation::Conveyance::Direct => Ok(created),
         }
     }
 
+    /// Requests the device to create a new Credential, and confirms it can be used by requesting an
+    /// Assertion with it right away, as some devices create credentials they can't retrieve.
+    ///
+    /// The assertion is silent (`up=false`) and its signature is verified with the public key of
+    /// the credential.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - Devices that refuse silent assertions, like U2F devices, are sent a regular assertion, so
+    ///   the user has to touch the device a second time.
+    /// - `pin` is used for both requests, so credentials requiring user verification (e.g. with
+    ///   `CredProtect::Required`) can be confirmed.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_NO_CREDENTIALS` if the device doesn't find the credential it created.
+    /// - `FIDO_ERR_INVALID_SIG` if the signature of the assertion is invalid.
+    pub fn register_and_confirm(
+        &mut self,
+        fido: &Fido,
+        credential: CredentialCreator,
+        pin: Option<&CStr>,
+    ) -> Result<ConfirmedCredential> {
+        const UNSUPPORTED_OPTION: raw::c_int = FIDO_ERR_UNSUPPORTED_OPTION as raw::c_int;
+        const INVALID_OPTION: raw::c_int = FIDO_ERR_INVALID_OPTION as raw::c_int;
+
+        let credential = self.request_credential_creation(credential, pin)?;
+        let rp_id = credential
+            .credential()
+            .relying_party_id()
+            .ok_or(FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+        let relying_party = RpEntity::new(rp_id).map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+        let mut client_data_hash = [0; 32];
+        openssl::random_bytes(&mut client_data_hash)?;
+        let allowed = [credential.id().as_bytes()];
+        let mut data =
+            AssertionCreationData::with_defaults(Some(&allowed), &client_data_hash, relying_party);
+        data.options = AssertionOptions::empty();
+
+        let mut silent = fido.new_assertion_creator(data)?;
+        silent.set_user_presence(fido_opt_t_FIDO_OPT_FALSE)?;
+        let assertion = match self.request_assertion_verification(silent, pin) {
+            // U2F devices report a known key handle this way when user presence is not checked
+            Err(FidoError(FIDO_ERR_USER_PRESENCE_REQUIRED))
+            | Err(FidoError(UNSUPPORTED_OPTION))
+            | Err(FidoError(INVALID_OPTION)) => {
+                data.options = AssertionOptions::USER_PRESENCE;
+                self.request_assertion_verification(fido.new_assertion_creator(data)?, pin)?
+            }
+            result => result?,
+        };
+        if !assertion.verify_one(credential.public_key()?) {
+            return Err(FidoError(FIDO_ERR_INVALID_SIG));
+        }
+        Ok(ConfirmedCredential {
+            credential,
+            assertion,
+        })
+    }
+
     /// Requests the device to verify an Assertion.
     ///
     /// # Remarks
     /// - This is synchronous and will block.
+    /// - libfido2 collects all statements (`authenticatorGetNextAssertion`) before returning,
+    ///   and does not expose them one by one. Requests with an allow list only return one
+    ///   statement, so only requests for resident credentials wait for more than one.
+    /// - Use [`try_request_assertion_verification`] to get the request back if it fails.
+    ///
+    /// [`try_request_assertion_verification`]: struct.Device.html#method.try_request_assertion_verification
     pub fn request_assertion_verification(
         &mut self,
-        mut assertion: AssertionCreator,
+        assertion: AssertionCreator,
         pin: Option<&CStr>,
     ) -> Result<Assertion> {
-        unsafe {
-            match fido_dev_get_assert(
-                self.raw.as_ptr_mut(),
+        self.try_request_assertion_verification(assertion, pin)
+            .map_err(FidoError::from)
+    }
+
+    /// Requests the device to verify an Assertion, returning the request with the error
+    /// if it fails, so it can be sent again.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    pub fn try_request_assertion_verification(
+        &mut self,
+        mut assertion: AssertionCreator,
+        pin: Option<&CStr>,
+    ) -> std::result::Result<Assertion, RequestError<AssertionCreator>> {
+        let rp_id = unsafe { fido_assert_rp_id(assertion.raw().as_ptr()).as_ref() }
+            .map(|rp_id| unsafe { CStr::from_ptr(rp_id) });
+        let audit = Audit::start(self, AuditOperation::GetAssertion, rp_id);
+        let result = self.watched(|device| unsafe {
+            fido_dev_get_assert(
+                device,
                 assertion.raw_mut().as_ptr_mut(),
                 pin.map(CStr::as_ptr).unwrap_or(ptr::null()),
-            ) {
-                FIDO_OK => Ok(assertion.into_inner()),
+            )
+        });
+        match result {
+            FIDO_OK => {
+                let assertion = assertion.into_inner();
+                let credential_id = assertion.iter().next().and_then(|s| s.credential_id);
+                audit.finish(credential_id, Ok(()));
+                Ok(assertion)
+            }
+            err => {
+                audit.finish(None, Err(FidoError(err)));
+                Err(RequestError {
+                    error: FidoError(err),
+                    request: assertion,
+                })
+            }
+        }
+    }
+
+    /// Sets a watchdog which cancels credential and assertion requests if the device sends
+    /// nothing for `timeout`, failing them with [`FidoError::DEVICE_GONE`] instead of waiting
+    /// for a device that was unplugged or hangs.
+    ///
+    /// # Arguments
+    /// - `timeout`: How long the device may stay silent, or `None` to remove the watchdog
+    ///
+    /// # Remarks
+    /// - FIDO2 devices send keep-alive messages about every 100ms while they wait for the user,
+    ///   so a timeout of a few seconds is plenty. U2F devices don't, and are given up on while
+    ///   waiting for the user.
+    /// - Only devices opened with [`Fido::new_monitored_device`] can be watched, others return
+    ///   `FIDO_ERR_INVALID_ARGUMENT`.
+    ///
+    /// [`FidoError::DEVICE_GONE`]: struct.FidoError.html#associatedconstant.DEVICE_GONE
+    /// [`Fido::new_monitored_device`]: struct.Fido.html#method.new_monitored_device
+    pub fn set_watchdog(&mut self, timeout: Option<Duration>) -> Result<()> {
+        match &self.monitor {
+            Some(monitor) => {
+                monitor.set_watchdog(timeout);
+                Ok(())
+            }
+            None => Err(FidoError(FIDO_ERR_INVALID_ARGUMENT)),
+        }
+    }
+
+    /// Runs `request`, cancelling it when `token` is cancelled, e.g. by Ctrl-C with
+    /// [`CancellationToken::on_interrupt`].
+    ///
+    /// # Remarks
+    /// - Fails with `FIDO_ERR_KEEPALIVE_CANCEL` without running `request` if `token` was
+    ///   cancelled already.
+    /// - Only requests that wait for the user can be cancelled, others just finish.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use libfido2::*;
+    /// # fn f(device: &mut Device, creator: CredentialCreator) -> std::io::Result<()> {
+    /// let token = CancellationToken::on_interrupt()?;
+    /// let result = device.cancellable(&token, |device| {
+    ///     device.request_credential_creation(creator, None)
+    /// });
+    /// if token.is_cancelled() {
+    ///     std::process::exit(130);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`CancellationToken::on_interrupt`]: struct.CancellationToken.html#method.on_interrupt
+    pub fn cancellable<T>(
+        &mut self,
+        token: &CancellationToken,
+        request: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        const KEEPALIVE_CANCEL: raw::c_int = FIDO_ERR_KEEPALIVE_CANCEL as raw::c_int;
+        let _registration = token
+            .register(&self.canceller)
+            .ok_or(FidoError(KEEPALIVE_CANCEL))?;
+        request(self)
+    }
+
+    /// Runs a request, cancelling it from another thread if the watchdog of the device trips.
+    fn watched<F>(&mut self, request: F) -> raw::c_int
+    where
+        F: FnOnce(*mut fido_dev_t) -> raw::c_int,
+    {
+        let device = self.raw.as_ptr_mut();
+        let (monitor, timeout) = match &self.monitor {
+            Some(monitor) => match monitor.watchdog() {
+                Some(timeout) => (monitor.clone(), timeout),
+                None => return request(device),
+            },
+            None => return request(device),
+        };
+
+        monitor.arm();
+        let (done, stopped) = mpsc::channel::<()>();
+        let result = thread::scope(|scope| {
+            let monitor = &monitor;
+            scope.spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(monitor::WATCHDOG_INTERVAL)
+                {
+                    if monitor.idle() >= timeout {
+                        monitor.trip();
+                        monitor.cancel();
+                        break;
+                    }
+                }
+            });
+            let result = request(device);
+            drop(done);
+            result
+        });
+        // A request that succeeded as the watchdog tripped is kept
+        match (monitor.disarm(), result) {
+            (true, err) if err != FIDO_OK => FidoError::DEVICE_GONE.code(),
+            (_, result) => result,
+        }
+    }
+
+    /// Requests the device to create a new Credential, asking `pins` for the PIN if the device has one.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - A wrong PIN is asked for again like in [`prompt_pin_with`], which stops before the
+    ///   device locks itself. Cancelling, or declining the last try, fails the request with
+    ///   `FIDO_ERR_PIN_REQUIRED`, and a blocked PIN with `FIDO_ERR_PIN_BLOCKED` or
+    ///   `FIDO_ERR_PIN_AUTH_BLOCKED`.
+    ///
+    /// # Errors
+    /// - `CheckedRequestError::TooLarge` without sending the request, if it doesn't fit in the
+    ///   `maxMsgSize` of the device, see `AuthenticatorInfo::check_credential_request`.
+    ///
+    /// [`prompt_pin_with`]: struct.Device.html#method.prompt_pin_with
+    pub fn request_credential_creation_with(
+        &mut self,
+        fido: &Fido,
+        data: CredentialCreationData<'_>,
+        pins: &mut dyn PinProvider,
+    ) -> std::result::Result<CreatedCredential, CheckedRequestError> {
+        let info = self.uv_info()?;
+        let needs_pin = Self::has_pin(info.as_ref());
+        self.with_pin(needs_pin, pins, |device, pin| {
+            if let Some(info) = &info {
+                info.check_credential_request(&data, pin.is_some())?;
+            }
+            Ok(device.request_credential_creation(fido.new_credential_creator(data)?, pin)?)
+        })
+    }
+
+    /// Requests the device to verify an Assertion, asking `pins` for the PIN if user verification
+    /// is requested and the device has a PIN.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - A wrong PIN is asked for again like in [`prompt_pin_with`], which stops before the
+    ///   device locks itself. Cancelling, or declining the last try, fails the request with
+    ///   `FIDO_ERR_PIN_REQUIRED`, and a blocked PIN with `FIDO_ERR_PIN_BLOCKED` or
+    ///   `FIDO_ERR_PIN_AUTH_BLOCKED`.
+    ///
+    /// # Errors
+    /// - `CheckedRequestError::TooLarge` without sending the request, if it doesn't fit in the
+    ///   `maxMsgSize` of the device, see `AuthenticatorInfo::check_assertion_request`.
+    ///
+    /// [`prompt_pin_with`]: struct.Device.html#method.prompt_pin_with
+    pub fn request_assertion_verification_with(
+        &mut self,
+        fido: &Fido,
+        data: AssertionCreationData<'_>,
+        pins: &mut dyn PinProvider,
+    ) -> std::result::Result<Assertion, CheckedRequestError> {
+        let info = self.uv_info()?;
+        let needs_pin = data.options.contains(AssertionOptions::USER_VERIFICATION)
+            && Self::has_pin(info.as_ref());
+        self.with_pin(needs_pin, pins, |device, pin| {
+            if let Some(info) = &info {
+                info.check_assertion_request(&data, pin.is_some())?;
+            }
+            Ok(device.request_assertion_verification(fido.new_assertion_creator(data)?, pin)?)
+        })
+    }
+
+    /// Requests the device to create a new Credential, verifying the user as `uv` prefers.
+    ///
+    /// The preference is resolved with [`UvPreference::for_credential`]: devices without
+    /// built-in user verification fall back to the PIN, which is asked from `pins`.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - The `uv` option of `data` is replaced.
+    /// - If built-in user verification is blocked after too many failed attempts, the request is
+    ///   sent again with the PIN.
+    ///
+    /// # Errors
+    /// - `CheckedRequestError::TooLarge` without sending the request, if it doesn't fit in the
+    ///   `maxMsgSize` of the device, see `AuthenticatorInfo::check_credential_request`.
+    ///
+    /// [`UvPreference::for_credential`]: enum.UvPreference.html#method.for_credential
+    pub fn request_credential_creation_with_uv(
+        &mut self,
+        fido: &Fido,
+        mut data: CredentialCreationData<'_>,
+        uv: UvPreference,
+        pins: &mut dyn PinProvider,
+    ) -> std::result::Result<CreatedCredential, CheckedRequestError> {
+        let info = self.uv_info()?;
+        let verification = uv.for_credential(info.as_ref())?;
+        self.with_uv(
+            verification,
+            info.as_ref(),
+            pins,
+            |device, verification, pin| {
+                data.options = verification.credential_options(data.options);
+                if let Some(info) = &info {
+                    info.check_credential_request(&data, pin.is_some())?;
+                }
+                Ok(device.request_credential_creation(fido.new_credential_creator(data)?, pin)?)
+            },
+        )
+    }
+
+    /// Requests the device to verify an Assertion, verifying the user as `uv` prefers.
+    ///
+    /// The preference is resolved with [`UvPreference::for_assertion`]: devices without
+    /// built-in user verification fall back to the PIN, which is asked from `pins`.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - The `uv` option of `data` is replaced.
+    /// - If built-in user verification is blocked after too many failed attempts, the request is
+    ///   sent again with the PIN.
+    ///
+    /// # Errors
+    /// - `CheckedRequestError::TooLarge` without sending the request, if it doesn't fit in the
+    ///   `maxMsgSize` of the device, see `AuthenticatorInfo::check_assertion_request`.
+    ///
+    /// [`UvPreference::for_assertion`]: enum.UvPreference.html#method.for_assertion
+    pub fn request_assertion_verification_with_uv(
+        &mut self,
+        fido: &Fido,
+        mut data: AssertionCreationData<'_>,
+        uv: UvPreference,
+        pins: &mut dyn PinProvider,
+    ) -> std::result::Result<Assertion, CheckedRequestError> {
+        let info = self.uv_info()?;
+        let verification = uv.for_assertion(info.as_ref())?;
+        self.with_uv(
+            verification,
+            info.as_ref(),
+            pins,
+            |device, verification, pin| {
+                data.options = verification.assertion_options(data.options);
+                if let Some(info) = &info {
+                    info.check_assertion_request(&data, pin.is_some())?;
+                }
+                Ok(device.request_assertion_verification(fido.new_assertion_creator(data)?, pin)?)
+            },
+        )
+    }
+
+    /// Runs `request` with a PIN from `pins`, asking again after a wrong PIN, and stops before
+    /// the device locks itself.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - Every prompt shows the tries left, see [`retry_count`]. Before the last try,
+    ///   `PinProvider::confirm_last_attempt` is asked, and the PIN isn't asked for unless it
+    ///   confirms.
+    /// - `request` is only run with a PIN, even if the device doesn't require one.
+    ///
+    /// # Errors
+    /// - `PinEntryError::Blocked` if the PIN is blocked, before asking for it or after the last
+    ///   try, and `PinEntryError::AuthBlocked` if the device has to be power cycled.
+    /// - Errors of `request` other than a wrong PIN as `PinEntryError::Other`.
+    ///
+    /// [`retry_count`]: struct.Device.html#method.retry_count
+    pub fn prompt_pin_with<T>(
+        &mut self,
+        pins: &mut dyn PinProvider,
+        mut request: impl FnMut(&mut Self, &CStr) -> Result<T>,
+    ) -> std::result::Result<T, PinEntryError> {
+        const PIN_INVALID: raw::c_int = FIDO_ERR_PIN_INVALID as raw::c_int;
+        const PIN_BLOCKED: raw::c_int = FIDO_ERR_PIN_BLOCKED as raw::c_int;
+        const PIN_AUTH_BLOCKED: raw::c_int = FIDO_ERR_PIN_AUTH_BLOCKED as raw::c_int;
+
+        let mut previous_invalid = false;
+        loop {
+            let retries = match self.retry_count() {
+                Ok(0) | Err(FidoError(PIN_BLOCKED)) => return Err(PinEntryError::Blocked),
+                Ok(retries) => Some(retries),
+                Err(_) => None,
+            };
+            let prompt = PinPrompt {
+                retries,
+                previous_invalid,
+            };
+            if retries == Some(1) && !pins.confirm_last_attempt(&prompt) {
+                return Err(PinEntryError::LastAttemptDeclined);
+            }
+            let pin = pins.pin(&prompt).ok_or(PinEntryError::Cancelled)?;
+            match request(self, &pin) {
+                Ok(value) => return Ok(value),
+                Err(FidoError(PIN_INVALID)) => previous_invalid = true,
+                Err(FidoError(PIN_BLOCKED)) => return Err(PinEntryError::Blocked),
+                Err(FidoError(PIN_AUTH_BLOCKED)) => {
+                    let retries = self.retry_count().ok();
+                    return Err(PinEntryError::AuthBlocked { retries });
+                }
+                Err(error) => return Err(PinEntryError::Other(error)),
+            }
+        }
+    }
+
+    // U2F devices don't answer authenticatorGetInfo
+    fn uv_info(&mut self) -> Result<Option<AuthenticatorInfo>> {
+        match self.mode() {
+            DeviceMode::Fido2 => self.authenticator_info().map(Some),
+            DeviceMode::FidoU2F => Ok(None),
+        }
+    }
+
+    fn with_uv<T>(
+        &mut self,
+        verification: UserVerification,
+        info: Option<&AuthenticatorInfo>,
+        pins: &mut dyn PinProvider,
+        mut request: impl FnMut(
+            &mut Self,
+            UserVerification,
+            Option<&CStr>,
+        ) -> std::result::Result<T, CheckedRequestError>,
+    ) -> std::result::Result<T, CheckedRequestError> {
+        const UV_BLOCKED: raw::c_int = FIDO_ERR_UV_BLOCKED as raw::c_int;
+
+        let needs_pin = verification == UserVerification::Pin;
+        let result = self.with_pin(needs_pin, pins, |device, pin| {
+            request(device, verification, pin)
+        });
+        match result {
+            Err(CheckedRequestError::Fido(FidoError(UV_BLOCKED)))
+                if verification == UserVerification::BuiltIn && Self::has_pin(info) =>
+            {
+                self.with_pin(true, pins, |device, pin| {
+                    request(device, UserVerification::Pin, pin)
+                })
+            }
+            result => result,
+        }
+    }
+
+    // Without info, i.e. for U2F devices, there is no PIN
+    fn has_pin(info: Option<&AuthenticatorInfo>) -> bool {
+        info.is_some_and(|info| info.options.get("clientPin") == Some(&true))
+    }
+
+    fn with_pin<T>(
+        &mut self,
+        needs_pin: bool,
+        pins: &mut dyn PinProvider,
+        mut request: impl FnMut(
+            &mut Self,
+            Option<&CStr>,
+        ) -> std::result::Result<T, CheckedRequestError>,
+    ) -> std::result::Result<T, CheckedRequestError> {
+        if !needs_pin {
+            return request(self, None);
+        }
+        // Stops asking when the request is too large, keeping its size for the caller
+        let mut too_large = None;
+        let result = self.prompt_pin_with(pins, |device, pin| {
+            request(device, Some(pin)).map_err(|error| {
+                if let CheckedRequestError::TooLarge(error) = error {
+                    too_large = Some(error);
+                }
+                error.fido_error()
+            })
+        });
+        result.map_err(|error| match too_large {
+            Some(too_large) => CheckedRequestError::TooLarge(too_large),
+            None => CheckedRequestError::Fido(error.into()),
+        })
+    }
+
+    /// Checks whether the device holds the credential `credential_id` for `relying_party`,
+    /// without asking the user for consent.
+    ///
+    /// This is a silent assertion (`up=false`) with only that credential in the allow list.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - Devices that refuse silent assertions result in `CredentialPresence::Unsupported`.
+    /// - Credentials created with credProtect `userVerificationRequired` are hidden from
+    ///   assertions without user verification, so the device answers like for a credential it
+    ///   doesn't hold, and they are reported as `CredentialPresence::Absent`. Only a positive
+    ///   answer is certain for devices supporting credProtect.
+    pub fn has_credential(
+        &mut self,
+        relying_party: RpEntity<'_>,
+        credential_id: &[u8],
+    ) -> Result<CredentialPresence> {
+        let allowed = [credential_id];
+        self.probe(AssertionCreationData::with_defaults(Some(&allowed), &[], relying_party))
+    }
+
+    /// Sends a silent assertion for the allow list of `data`.
+    fn probe(&mut self, mut data: AssertionCreationData<'_>) -> Result<CredentialPresence> {
+        // The statement is thrown away, so the client data doesn't matter
+        const CLIENT_DATA_HASH: [u8; 32] = [0; 32];
+
+        data.client_data_hash = &CLIENT_DATA_HASH;
+        data.options = AssertionOptions::empty();
+        let mut assertion = AssertionCreator::new(
+            Assertion {
+                raw: allocated(unsafe { fido_assert_new() })?,
+            },
+            data,
+        )?;
+        assertion.set_user_presence(fido_opt_t_FIDO_OPT_FALSE)?;
+
+        const NO_CREDENTIALS: raw::c_int = FIDO_ERR_NO_CREDENTIALS as raw::c_int;
+        const UNSUPPORTED_OPTION: raw::c_int = FIDO_ERR_UNSUPPORTED_OPTION as raw::c_int;
+        const INVALID_OPTION: raw::c_int = FIDO_ERR_INVALID_OPTION as raw::c_int;
+        let audit = Audit::start(
+            self,
+            AuditOperation::CheckCredential,
+            Some(data.app_id.unwrap_or_else(|| data.relying_party.id())),
+        );
+        let result = unsafe {
+            fido_dev_get_assert(
+                self.raw.as_ptr_mut(),
+                assertion.raw_mut().as_ptr_mut(),
+                ptr::null(),
+            )
+        };
+        let credential_id = match data.allowed_credential_ids {
+            Some([id]) => Some(*id),
+            _ => None,
+        };
+        audit.finish(
+            credential_id,
+            match result {
+                FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
+            },
+        );
+        match result {
+            // U2F devices report a known key handle this way when user presence is not checked
+            FIDO_OK | FIDO_ERR_USER_PRESENCE_REQUIRED => Ok(CredentialPresence::Present),
+            NO_CREDENTIALS => Ok(CredentialPresence::Absent),
+            UNSUPPORTED_OPTION | INVALID_OPTION => Ok(CredentialPresence::Unsupported),
+            err => Err(FidoError(err)),
+        }
+    }
+
+    /// Requests the device to verify an Assertion, splitting an allow list that is too long for the
+    /// device into several requests.
+    ///
+    /// The limits are taken from the `AuthenticatorInfo` of the device: credential IDs longer
+    /// than `max_credential_id_length` can't belong to it and are left out, and the remaining IDs
+    /// are sent in chunks of `max_credential_count_in_list`. Duplicate IDs are only sent once.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - Every chunk is first checked with a silent assertion, so the user is only asked once,
+    ///   for the chunk holding a credential. Devices that refuse silent assertions are sent each
+    ///   chunk until one succeeds, which may ask the user more than once. So are devices that
+    ///   support credProtect, for requests with user verification, as silent assertions don't
+    ///   see credentials with credProtect `userVerificationRequired`.
+    /// - The assertion of the chunk holding a credential is returned. Devices return one statement
+    ///   for requests with an allow list, so nothing is lost.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_NO_CREDENTIALS` if no chunk holds a credential of the device.
+    pub fn request_assertion_verification_chunked(
+        &mut self,
+        fido: &Fido,
+        data: AssertionCreationData<'_>,
+        pin: Option<&CStr>,
+    ) -> Result<Assertion> {
+        const NO_CREDENTIALS: raw::c_int = FIDO_ERR_NO_CREDENTIALS as raw::c_int;
+
+        let allowed = match data.allowed_credential_ids {
+            // libfido2 sends U2F devices one key handle at a time already
+            Some(allowed) if self.mode() == DeviceMode::Fido2 => allowed,
+            _ => return self.request_assertion_verification(fido.new_assertion_creator(data)?, pin),
+        };
+        let info = self.authenticator_info()?;
+        let mut seen = HashSet::new();
+        let ids = allowed
+            .iter()
+            .copied()
+            .filter(|id| info.max_credential_id_length.map_or(true, |max| id.len() <= max))
+            .filter(|id| seen.insert(*id))
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            return Err(FidoError(NO_CREDENTIALS));
+        }
+        let chunk_len = info.max_credential_count_in_list.unwrap_or(ids.len()).max(1);
+
+        // Silent assertions don't see credentials requiring user verification, so they only
+        // rule out chunks for requests that can't see those either
+        let verified = pin.is_some() || data.options.contains(AssertionOptions::USER_VERIFICATION);
+        let hidden = verified && info.supports_extension(Extension::CredProtect);
+        let mut probe = ids.len() > chunk_len && !hidden;
+        for chunk in ids.chunks(chunk_len) {
+            let mut data = data;
+            data.allowed_credential_ids = Some(chunk);
+            if probe {
+                match self.probe(data)? {
+                    CredentialPresence::Absent => continue,
+                    CredentialPresence::Present => {}
+                    CredentialPresence::Unsupported => probe = false,
+                }
+            }
+            match self.request_assertion_verification(fido.new_assertion_creator(data)?, pin) {
+                Err(FidoError(NO_CREDENTIALS)) => continue,
+                result => return result,
             }
         }
+        Err(FidoError(NO_CREDENTIALS))
     }
 
     /// Sets the PIN of the device.
@@ -131,7 +1100,8 @@ impl Device {
     /// - This is synchronous and will block.
     /// - Too many invalid PINs will lock the device.
     pub fn set_pin(&mut self, new_pin: &CStr, old_pin: Option<&CStr>) -> Result<()> {
-        unsafe {
+        let audit = Audit::start(self, AuditOperation::SetPin, None);
+        let result = unsafe {
             match fido_dev_set_pin(
                 self.raw.as_ptr_mut(),
                 new_pin.as_ptr(),
@@ -140,7 +1110,233 @@ impl Device {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
             }
+        };
+        audit.finish(None, result);
+        result
+    }
+
+    /// Changes the PIN of the device.
+    ///
+    /// The new PIN is checked against the [`PinPolicy`] of the device before it is sent,
+    /// and failures are translated into a [`PinChangeError`] containing the remaining retries.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - Too many invalid PINs will lock the device.
+    ///
+    /// [`PinPolicy`]: struct.PinPolicy.html
+    /// [`PinChangeError`]: enum.PinChangeError.html
+    pub fn change_pin(
+        &mut self,
+        old_pin: &CStr,
+        new_pin: &CStr,
+    ) -> std::result::Result<(), PinChangeError> {
+        let info = self
+            .authenticator_info()
+            .map_err(|error| PinChangeError::Other {
+                error,
+                retries: None,
+            })?;
+        validate_pin(new_pin, &PinPolicy::for_device(&info))?;
+
+        self.set_pin(new_pin, Some(old_pin)).map_err(|error| {
+            let retries = self.retry_count().ok();
+            PinChangeError::from_fido_error(error, retries)
+        })
+    }
+
+    /// Raises the minimum PIN length of the device (CTAP 2.1 `setMinPINLength`).
+    ///
+    /// # Arguments
+    /// - `min_length`: New minimum PIN length in Unicode code points
+    /// - `pin`: Current PIN, if the device requires it
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - The minimum PIN length can only be raised, not lowered, until the device is reset.
+    /// - If the current PIN is shorter, the device will require a PIN change before it can be used.
+    #[cfg(feature = "config")]
+    pub fn set_min_pin_length(&mut self, min_length: usize, pin: Option<&CStr>) -> Result<()> {
+        let min_length = crate::ffi::to_size_t(min_length)?;
+        let audit = Audit::start(self, AuditOperation::SetMinPinLength, None);
+        let result = unsafe {
+            match fido_dev_set_pin_minlen(
+                self.raw.as_ptr_mut(),
+                min_length,
+                pin.map(CStr::as_ptr).unwrap_or(ptr::null()),
+            ) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        };
+        audit.finish(None, result);
+        result
+    }
+
+    /// Sets the relying parties that may read the minimum PIN length through the `minPinLength`
+    /// extension during credential creation (CTAP 2.1 `setMinPINLength`).
+    ///
+    /// # Arguments
+    /// - `relying_parties`: Relying parties to allow, of which only the ID is used
+    /// - `pin`: Current PIN, if the device requires it
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - Devices limit the amount of relying parties, see `maxRPIDsForSetMinPINLength` in the CBOR data.
+    #[cfg(feature = "config")]
+    pub fn set_min_pin_length_rp_ids(
+        &mut self,
+        relying_parties: &[RpEntity<'_>],
+        pin: Option<&CStr>,
+    ) -> Result<()> {
+        let ids = relying_parties
+            .iter()
+            .map(|relying_party| relying_party.id().as_ptr())
+            .collect::<Vec<_>>();
+        let len = crate::ffi::to_size_t(ids.len())?;
+        let audit = Audit::start(self, AuditOperation::SetMinPinLengthRpIds, None);
+        let result = unsafe {
+            match fido_dev_set_pin_minlen_rpid(
+                self.raw.as_ptr_mut(),
+                ids.as_ptr(),
+                len,
+                pin.map(CStr::as_ptr).unwrap_or(ptr::null()),
+            ) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        };
+        audit.finish(None, result);
+        result
+    }
+
+    /// Requires the PIN to be changed before the device can be used again
+    /// (`forceChangePin` of CTAP 2.1 `setMinPINLength`).
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    #[cfg(feature = "config")]
+    pub fn force_pin_change(&mut self, pin: Option<&CStr>) -> Result<()> {
+        let audit = Audit::start(self, AuditOperation::ForcePinChange, None);
+        let result = unsafe {
+            match fido_dev_force_pin_change(
+                self.raw.as_ptr_mut(),
+                pin.map(CStr::as_ptr).unwrap_or(ptr::null()),
+            ) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        };
+        audit.finish(None, result);
+        result
+    }
+
+    /// Enables enterprise attestation (CTAP 2.1 `enableEnterpriseAttestation`), so credentials can
+    /// be created with attestation that identifies the individual device.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - Devices without enterprise attestation return `FIDO_ERR_INVALID_COMMAND`.
+    #[cfg(feature = "config")]
+    pub fn enable_enterprise_attestation(&mut self, pin: Option<&CStr>) -> Result<()> {
+        let operation = AuditOperation::EnableEnterpriseAttestation;
+        let audit = Audit::start(self, operation, None);
+        let result = unsafe {
+            match fido_dev_enable_entattest(
+                self.raw.as_ptr_mut(),
+                pin.map(CStr::as_ptr).unwrap_or(ptr::null()),
+            ) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        };
+        audit.finish(None, result);
+        result
+    }
+
+    /// Toggles whether the device requires user verification for every request
+    /// (CTAP 2.1 `toggleAlwaysUv`).
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - The current state is the `alwaysUv` option of the [`authenticator_info`].
+    ///
+    /// [`authenticator_info`]: struct.Device.html#method.authenticator_info
+    #[cfg(feature = "config")]
+    pub fn toggle_always_uv(&mut self, pin: Option<&CStr>) -> Result<()> {
+        let audit = Audit::start(self, AuditOperation::ToggleAlwaysUv, None);
+        let result = unsafe {
+            match fido_dev_toggle_always_uv(
+                self.raw.as_ptr_mut(),
+                pin.map(CStr::as_ptr).unwrap_or(ptr::null()),
+            ) {
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        };
+        audit.finish(None, result);
+        result
+    }
+
+    /// Sends an `authenticatorConfig` request, and returns the CBOR encoded response, which is
+    /// empty for the standard subcommands.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block until the device answers or `timeout` passes.
+    /// - Only devices opened with `Fido::new_monitored_device` can be sent raw requests, others
+    ///   return `FIDO_ERR_INVALID_ARGUMENT`.
+    /// - The request is not authenticated with a `pinUvAuthParam`. Devices with a PIN, or with
+    ///   `alwaysUv` enabled, reject it with `CTAP2_ERR_PUAT_REQUIRED` (0x36).
+    ///
+    /// # Errors
+    /// - The CTAP2 status code, if the device rejects the request. Devices without
+    ///   `authnrCfg` return `FIDO_ERR_INVALID_COMMAND`.
+    #[cfg(feature = "config")]
+    pub fn send_config_command(
+        &mut self,
+        command: &crate::ConfigCommand,
+        timeout: Duration,
+    ) -> Result<Vec<u8>> {
+        if self.monitor.is_none() {
+            return Err(FidoError(FIDO_ERR_INVALID_ARGUMENT));
+        }
+        let operation = AuditOperation::Config(command.subcommand());
+        let audit = Audit::start(self, operation, None);
+        let result = unsafe { monitor::cbor(self.raw.as_ptr(), &command.to_bytes(), timeout) };
+        audit.finish(None, result.as_ref().map(|_| ()).map_err(|error| *error));
+        result
+    }
+
+    /// Sends a CTAP2 command this crate does not model, and returns the CBOR encoded response.
+    ///
+    /// # Arguments
+    /// - `command`: The command byte, e.g. `0x0a` for `authenticatorSelection`
+    /// - `payload`: The CBOR encoded parameters, or empty for commands without
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block until the device answers, for at most
+    ///   [`CTAP2_COMMAND_TIMEOUT`], which leaves the user time to touch the device.
+    /// - Only devices opened with `Fido::new_monitored_device` can be sent raw commands, as
+    ///   libfido2 can't send them. Others return `FIDO_ERR_INVALID_ARGUMENT`.
+    /// - The payload is sent as is. Commands that need a `pinUvAuthParam` must compute it.
+    ///
+    /// # Errors
+    /// - The CTAP2 status code, if the device rejects the command, e.g.
+    ///   `FIDO_ERR_INVALID_COMMAND` for commands it doesn't know.
+    ///
+    /// [`CTAP2_COMMAND_TIMEOUT`]: constant.CTAP2_COMMAND_TIMEOUT.html
+    pub fn ctap2_command(&mut self, command: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        if self.monitor.is_none() {
+            return Err(FidoError(FIDO_ERR_INVALID_ARGUMENT));
         }
+        let mut message = Vec::with_capacity(1 + payload.len());
+        message.push(command);
+        message.extend_from_slice(payload);
+        let operation = AuditOperation::Ctap2Command(command);
+        let audit = Audit::start(self, operation, None);
+        let result = unsafe { monitor::cbor(self.raw.as_ptr(), &message, CTAP2_COMMAND_TIMEOUT) };
+        audit.finish(None, result.as_ref().map(|_| ()).map_err(|error| *error));
+        result
     }
 
     /// Resets the device.
@@ -148,15 +1344,18 @@ impl Device {
     /// # Remarks
     /// - This is synchronous and will block.
     /// - The process to reset a device is outside the FIDO2 specification and is authenticator dependent.
-    /// Yubico authenticators will return `FIDO_ERR_NOT_ALLOWED` if a reset is issued later than 5 seconds after power-up,
-    /// and `FIDO_ERR_ACTION_TIMEOUT` if the user fails to confirm the reset by touching the key within 30 seconds.
+    ///   Yubico authenticators will return `FIDO_ERR_NOT_ALLOWED` if a reset is issued later than 5 seconds after power-up,
+    ///   and `FIDO_ERR_ACTION_TIMEOUT` if the user fails to confirm the reset by touching the key within 30 seconds.
     pub fn reset(&mut self) -> Result<()> {
-        unsafe {
+        let audit = Audit::start(self, AuditOperation::Reset, None);
+        let result = unsafe {
             match fido_dev_reset(self.raw.as_ptr_mut()) {
                 FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
             }
-        }
+        };
+        audit.finish(None, result);
+        result
     }
 
     /// Returns the amount of PIN tries left before the device locks itself.
@@ -164,46 +1363,244 @@ impl Device {
     /// # Remarks
     /// - This is synchronous and will block.
     pub fn retry_count(&mut self) -> Result<i32> {
-        unsafe {
-            let mut amount = 0;
+        let span = OperationSpan::start(&self.span, &"get PIN retries", None);
+        let mut amount = 0;
+        let result = unsafe {
             match fido_dev_get_retry_count(self.raw.as_ptr_mut(), &mut amount as *mut _) {
-                FIDO_OK => Ok(amount),
+                FIDO_OK => Ok(()),
+                err => Err(FidoError(err)),
+            }
+        };
+        span.finish(result);
+        result.map(|()| amount)
+    }
+
+    /// Closes the connection to the device, reporting the failures that dropping it ignores.
+    ///
+    /// # Remarks
+    /// - The device is freed even if closing fails, e.g. because it was unplugged.
+    pub fn close(mut self) -> Result<()> {
+        self.canceller.revoke();
+        unsafe {
+            // Drop closes again, which libfido2 rejects without touching the device
+            match fido_dev_close(self.raw.as_ptr_mut()) {
+                FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
             }
         }
     }
+
+    /// Registers a handler which is called with every keep-alive status the device sends while it
+    /// processes a request, replacing any previous handler.
+    ///
+    /// # Remarks
+    /// - Only devices opened with [`Fido::new_monitored_device`] report keep-alive messages,
+    ///   others return `FIDO_ERR_INVALID_ARGUMENT`.
+    /// - The handler is called on the thread making the request, while it blocks.
+    ///   To update a UI on another thread, send the status over a channel.
+    ///
+    /// [`Fido::new_monitored_device`]: struct.Fido.html#method.new_monitored_device
+    pub fn set_keepalive_handler<F>(&mut self, handler: F) -> Result<()>
+    where
+        F: FnMut(KeepAliveStatus) + Send + 'static,
+    {
+        match &self.monitor {
+            Some(monitor) => {
+                monitor.set_handler(Some(Box::new(handler)));
+                Ok(())
+            }
+            None => Err(FidoError(FIDO_ERR_INVALID_ARGUMENT)),
+        }
+    }
+
+    /// Registers a handler which is called with the `numberOfCredentials` of every assertion
+    /// the device answers, replacing any previous handler.
+    ///
+    /// # Remarks
+    /// - libfido2 collects all statements before returning the `Assertion`. The handler is called
+    ///   as soon as the first statement arrives, before the others are requested, so a UI can
+    ///   prepare a chooser while they are transferred, or expect a single statement.
+    /// - Devices omit the count for a single credential, which is reported as 1.
+    /// - Only devices opened with [`Fido::new_monitored_device`] report the count, others return
+    ///   `FIDO_ERR_INVALID_ARGUMENT`.
+    /// - The handler is called on the thread making the request, while it blocks.
+    ///
+    /// [`Fido::new_monitored_device`]: struct.Fido.html#method.new_monitored_device
+    pub fn set_credential_count_handler<F>(&mut self, handler: F) -> Result<()>
+    where
+        F: FnMut(usize) + Send + 'static,
+    {
+        match &self.monitor {
+            Some(monitor) => {
+                monitor.set_count_handler(Some(Box::new(handler)));
+                Ok(())
+            }
+            None => Err(FidoError(FIDO_ERR_INVALID_ARGUMENT)),
+        }
+    }
+
+    /// Sets whether CTAP2 responses are rejected unless they are in the canonical CBOR encoding
+    /// of CTAP2 and free of duplicate map keys, before libfido2 parses them. Useful to catch
+    /// non-conforming devices in conformance tests, as libfido2 accepts some encodings CTAP2
+    /// forbids.
+    ///
+    /// # Remarks
+    /// - Disabled by default, see `FidoBuilder::strict_cbor` to enable it for every device.
+    /// - Rejected responses fail the request with `FIDO_ERR_RX`, and [`cbor_violation`] returns
+    ///   why.
+    /// - Only devices opened with [`Fido::new_monitored_device`] can check responses, others
+    ///   return `FIDO_ERR_INVALID_ARGUMENT`.
+    ///
+    /// [`cbor_violation`]: struct.Device.html#method.cbor_violation
+    /// [`Fido::new_monitored_device`]: struct.Fido.html#method.new_monitored_device
+    pub fn set_strict_cbor(&mut self, strict: bool) -> Result<()> {
+        match &self.monitor {
+            Some(monitor) => {
+                monitor.set_strict_cbor(strict);
+                Ok(())
+            }
+            None => Err(FidoError(FIDO_ERR_INVALID_ARGUMENT)),
+        }
+    }
+
+    /// Returns why the response to the last request was rejected with strict CBOR checks, see
+    /// [`set_strict_cbor`], or `None` if it wasn't.
+    ///
+    /// [`set_strict_cbor`]: struct.Device.html#method.set_strict_cbor
+    pub fn cbor_violation(&self) -> Option<CborViolation> {
+        self.monitor.as_ref().and_then(|monitor| monitor.cbor_violation())
+    }
+
+    /// Sets the sink which records the operations of the device, replacing the one inherited from
+    /// the `Fido` it was opened with. See [`AuditSink`].
+    ///
+    /// [`AuditSink`]: trait.AuditSink.html
+    pub fn set_audit_sink(&mut self, sink: Option<Arc<dyn AuditSink>>) {
+        self.audit = sink;
+    }
+
+    /// Removes the handler registered with [`set_keepalive_handler`], if any.
+    ///
+    /// [`set_keepalive_handler`]: struct.Device.html#method.set_keepalive_handler
+    pub fn clear_keepalive_handler(&mut self) {
+        if let Some(monitor) = &self.monitor {
+            monitor.set_handler(None);
+        }
+    }
+
+    /// Returns the underlying `fido_dev_t`, to call libfido2 functions this crate does not wrap.
+    ///
+    /// # Remarks
+    /// - The pointer is valid for as long as `self` is, and must not be freed.
+    pub fn as_raw(&self) -> *const fido_dev_t {
+        self.raw.as_ptr()
+    }
+
+    /// Returns the underlying `fido_dev_t` mutably. See [`as_raw`].
+    ///
+    /// [`as_raw`]: struct.Device.html#method.as_raw
+    pub fn as_raw_mut(&mut self) -> *mut fido_dev_t {
+        self.raw.as_ptr_mut()
+    }
+
+    /// Gives up ownership of the underlying `fido_dev_t`.
+    ///
+    /// # Remarks
+    /// - The caller is responsible for closing it with `fido_dev_close` and freeing it with
+    ///   `fido_dev_free`, or with [`from_raw`].
+    /// - Keep-alive handlers stay registered until the device is closed.
+    ///
+    /// [`from_raw`]: struct.Device.html#method.from_raw
+    pub fn into_raw(self) -> *mut fido_dev_t {
+        let mut device = mem::ManuallyDrop::new(self);
+        // The HID handle holds its own reference
+        drop(device.monitor.take());
+        device.canceller.revoke();
+        unsafe { ptr::drop_in_place(&mut device.canceller) };
+        drop(device.audit.take());
+        drop(mem::replace(&mut device.span, DeviceSpan::none()));
+        device.raw.as_ptr_mut()
+    }
+
+    /// Takes ownership of a `fido_dev_t`, which is freed on drop.
+    ///
+    /// # Safety
+    /// - `raw` must be a valid `fido_dev_t` allocated by libfido2, which is not owned by anything
+    ///   else.
+    ///
+    /// # Remarks
+    /// - Keep-alive messages are not reported for devices created this way.
+    pub unsafe fn from_raw(raw: *mut fido_dev_t) -> Option<Self> {
+        let canceller = Canceller::new(raw, None);
+        NonNull::new(raw).map(|raw| Device {
+            raw,
+            monitor: None,
+            canceller,
+            audit: None,
+            span: DeviceSpan::none(),
+        })
+    }
+}
+
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
 }
 
+impl Eq for Device {}
+
 // libfido2_sys guarantees this.
 unsafe impl Send for Device {}
 unsafe impl Sync for Device {}
 
 impl Drop for Device {
     fn drop(&mut self) {
+        self.canceller.revoke();
         unsafe {
             let mut device = self.raw.as_ptr_mut();
             // This can return an error
             // If we are not opened yet, this is a NOOP
             let _ = fido_dev_close(device);
             fido_dev_free(&mut device as *mut _);
-            assert!(device.is_null());
+            // Panicking in drop may abort the process, so this is only checked in debug builds
+            debug_assert!(device.is_null());
         }
     }
 }
 
 /// Wrapper that represents an OS-specific path to a device.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+///
+/// # Remarks
+/// - Paths are passed to libfido2 as C strings, so they must be valid UTF-8 without NUL bytes.
+///   Use [`DevicePathBuf::from_path`] to convert a `Path` or `OsStr`.
+///
+/// [`DevicePathBuf::from_path`]: struct.DevicePathBuf.html#method.from_path
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct DevicePath<'a>(pub(crate) &'a CStr);
 
 impl<'a> DevicePath<'a> {
     /// Creates a new DevicePath from given `CStr`.
     ///
-    /// # Unsafety
+    /// # Safety
     /// The given `CStr` must contain valid UTF-8.
     pub unsafe fn from_cstr(path: &'a CStr) -> Self {
         DevicePath(path)
     }
 
+    /// Creates a new DevicePath from given `CStr`, checking that it is valid UTF-8.
+    pub fn try_from_cstr(path: &'a CStr) -> std::result::Result<Self, InvalidDevicePathError> {
+        match path.to_str() {
+            Ok(_) => Ok(DevicePath(path)),
+            Err(_) => Err(InvalidDevicePathError),
+        }
+    }
+
+    /// Borrows the path as a `Path`.
+    pub fn as_path(&self) -> &'a Path {
+        Path::new(unsafe { str::from_utf8_unchecked(self.0.to_bytes()) })
+    }
+
     /// Converts the path to a `&str`.
     pub fn to_str(&self) -> &str {
         unsafe { str::from_utf8_unchecked(self.0.to_bytes()) }
@@ -216,12 +1613,165 @@ impl AsRef<str> for DevicePath<'_> {
     }
 }
 
+impl AsRef<Path> for DevicePath<'_> {
+    fn as_ref(&self) -> &Path {
+        self.as_path()
+    }
+}
+
+/// Owned version of [`DevicePath`].
+///
+/// [`DevicePath`]: struct.DevicePath.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DevicePathBuf(pub(crate) CString);
+
+impl DevicePathBuf {
+    /// Converts an OS path, e.g. `/dev/hidraw0`, for passing it to libfido2.
+    ///
+    /// # Errors
+    /// - When the path is not valid UTF-8 or contains a NUL byte, since libfido2 can't open it.
+    pub fn from_path(path: impl AsRef<Path>) -> std::result::Result<Self, InvalidDevicePathError> {
+        let path = path.as_ref().to_str().ok_or(InvalidDevicePathError)?;
+        CString::new(path)
+            .map(DevicePathBuf)
+            .map_err(|_| InvalidDevicePathError)
+    }
+
+    /// Borrows the path as a [`DevicePath`], which can be used to connect to a device.
+    ///
+    /// [`DevicePath`]: struct.DevicePath.html
+    pub fn as_path(&self) -> DevicePath<'_> {
+        DevicePath(&self.0)
+    }
+
+    /// Converts the path to a `&str`.
+    pub fn to_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(self.0.to_bytes()) }
+    }
+}
+
+impl From<DevicePath<'_>> for DevicePathBuf {
+    fn from(path: DevicePath<'_>) -> Self {
+        DevicePathBuf(path.0.to_owned())
+    }
+}
+
+impl AsRef<str> for DevicePathBuf {
+    fn as_ref(&self) -> &str {
+        self.to_str()
+    }
+}
+
+impl AsRef<Path> for DevicePathBuf {
+    fn as_ref(&self) -> &Path {
+        Path::new(self.to_str())
+    }
+}
+
+impl TryFrom<&Path> for DevicePathBuf {
+    type Error = InvalidDevicePathError;
+
+    fn try_from(path: &Path) -> std::result::Result<Self, Self::Error> {
+        DevicePathBuf::from_path(path)
+    }
+}
+
+impl TryFrom<&OsStr> for DevicePathBuf {
+    type Error = InvalidDevicePathError;
+
+    fn try_from(path: &OsStr) -> std::result::Result<Self, Self::Error> {
+        DevicePathBuf::from_path(path)
+    }
+}
+
+/// Error returned when a path can't be represented as a [`DevicePath`].
+///
+/// [`DevicePath`]: struct.DevicePath.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidDevicePathError;
+
+impl error::Error for InvalidDevicePathError {}
+
+impl fmt::Display for InvalidDevicePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The device path is invalid")
+    }
+}
+
+/// Error of a failed request, which contains the request so it can be sent again
+/// without rebuilding it.
+pub struct RequestError<T> {
+    pub error: FidoError,
+    pub request: T,
+}
+
+impl<T> RequestError<T> {
+    /// Returns the request, to send it again.
+    pub fn into_request(self) -> T {
+        self.request
+    }
+}
+
+impl<T> From<RequestError<T>> for FidoError {
+    fn from(error: RequestError<T>) -> Self {
+        error.error
+    }
+}
+
+impl<T> fmt::Debug for RequestError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestError")
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl<T> fmt::Display for RequestError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl<T> error::Error for RequestError<T> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Answer of [`Device::has_credential`].
+///
+/// [`Device::has_credential`]: struct.Device.html#method.has_credential
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CredentialPresence {
+    /// The device holds the credential.
+    Present,
+    /// The device does not hold the credential, or holds it with credProtect
+    /// `userVerificationRequired`, which a silent assertion can't tell apart.
+    Absent,
+    /// The device can't be asked without user consent.
+    Unsupported,
+}
+
+/// A credential and the Assertion it was confirmed with. See [`Device::register_and_confirm`].
+///
+/// [`Device::register_and_confirm`]: struct.Device.html#method.register_and_confirm
+pub struct ConfirmedCredential {
+    pub credential: CreatedCredential,
+    /// The silent assertion, whose signature was verified with the public key of `credential`.
+    pub assertion: Assertion,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum DeviceMode {
     Fido2,
     FidoU2F,
 }
 
+enum_names!(DeviceMode {
+    "fido2" => Fido2,
+    "u2f" => FidoU2F,
+});
+
 /// CTAP HID information.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct CTAPHIDInfo {
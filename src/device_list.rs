@@ -1,7 +1,20 @@
-use crate::{device::DevicePath, ffi::NonNull};
+use crate::{
+    device::{DevicePath, DevicePathBuf},
+    ffi::{to_size_t, NonNull},
+    FidoError, Result,
+};
 use libfido2_sys::*;
-use std::{ffi::CStr, str};
-use std::convert::TryInto;
+use std::{
+    ffi::CStr,
+    fmt, fs,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    str,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+};
 
 /// Owns a list of [information] about found devices.
 ///
@@ -10,7 +23,7 @@ use std::convert::TryInto;
 pub struct DeviceList {
     pub(crate) raw: NonNull<fido_dev_info>,
     // Length of allocation (may contain uninitialized memory)
-    pub(crate) length: usize,
+    pub(crate) length: size_t,
     // Length of found devices
     pub(crate) found: usize,
 }
@@ -22,44 +35,234 @@ pub struct DeviceInformation<'a> {
     pub path: DevicePath<'a>,
     pub product_id: i16,
     pub vendor_id: i16,
+    /// Manufacturer reported by the device, empty if it is missing or not valid UTF-8.
     pub manufacturer: &'a str,
+    /// Product name reported by the device, empty if it is missing or not valid UTF-8.
     pub product: &'a str,
 }
 
+/// Entry of a [`DeviceList`], before its strings are converted.
+///
+/// [`DeviceList`]: struct.DeviceList.html
+struct RawEntry<'a> {
+    path: DevicePath<'a>,
+    product_id: i16,
+    vendor_id: i16,
+    manufacturer: Option<&'a CStr>,
+    product: Option<&'a CStr>,
+}
+
+/// Owned version of [`DeviceInformation`], which does not borrow from a [`DeviceList`].
+///
+/// [`DeviceInformation`]: struct.DeviceInformation.html
+/// [`DeviceList`]: struct.DeviceList.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OwnedDeviceInformation {
+    pub path: DevicePathBuf,
+    pub product_id: i16,
+    pub vendor_id: i16,
+    pub manufacturer: String,
+    pub product: String,
+}
+
+impl DeviceInformation<'_> {
+    /// Copies the information so it no longer borrows from the [`DeviceList`].
+    ///
+    /// # Remarks
+    /// - Manufacturer and product strings that are not valid UTF-8 stay empty. Use
+    ///   [`DeviceList::into_vec`] to keep them.
+    ///
+    /// [`DeviceList`]: struct.DeviceList.html
+    /// [`DeviceList::into_vec`]: struct.DeviceList.html#method.into_vec
+    pub fn into_owned(self) -> OwnedDeviceInformation {
+        OwnedDeviceInformation {
+            path: self.path.into(),
+            product_id: self.product_id,
+            vendor_id: self.vendor_id,
+            manufacturer: self.manufacturer.to_owned(),
+            product: self.product.to_owned(),
+        }
+    }
+
+    /// Returns the serial number of the device, without opening it. See
+    /// [`OwnedDeviceInformation::serial`].
+    ///
+    /// [`OwnedDeviceInformation::serial`]: struct.OwnedDeviceInformation.html#method.serial
+    pub fn serial(&self) -> Option<String> {
+        usb_serial(self.path.as_path())
+    }
+
+    /// Returns the transports the device is connected through, without opening it. See
+    /// [`OwnedDeviceInformation::transports`].
+    ///
+    /// [`OwnedDeviceInformation::transports`]: struct.OwnedDeviceInformation.html#method.transports
+    pub fn transports(&self) -> &'static [&'static str] {
+        transports(self.path.0.to_bytes())
+    }
+}
+
+/// Identifies a device across reconnections, which change its path. See
+/// [`Fido::wait_for_device`].
+///
+/// # Remarks
+/// - FIDO devices have no identity exposed before they are opened, so this is the model and,
+///   on Linux, the USB serial number. Many devices have no USB serial number, in which case every
+///   device of the same model matches.
+///
+/// [`Fido::wait_for_device`]: struct.Fido.html#method.wait_for_device
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StableDeviceId {
+    pub vendor_id: i16,
+    pub product_id: i16,
+    pub manufacturer: String,
+    pub product: String,
+    pub serial: Option<String>,
+}
+
+impl OwnedDeviceInformation {
+    /// Returns the identity of the device, which stays the same when it is reconnected.
+    pub fn stable_id(&self) -> StableDeviceId {
+        StableDeviceId {
+            vendor_id: self.vendor_id,
+            product_id: self.product_id,
+            manufacturer: self.manufacturer.clone(),
+            product: self.product.clone(),
+            serial: self.serial(),
+        }
+    }
+
+    /// Returns the serial number of the device, without opening it, e.g. to look up the asset
+    /// tag of a key.
+    ///
+    /// # Remarks
+    /// - libfido2 doesn't report serial numbers of found devices, so this is the USB serial number
+    ///   from sysfs, and only available on Linux. Many devices have no USB serial number.
+    pub fn serial(&self) -> Option<String> {
+        usb_serial(self.path.as_ref())
+    }
+
+    /// Returns the transports the device is connected through, e.g. `["usb"]`, with the names
+    /// of the `transports` of `AuthenticatorInfo`.
+    ///
+    /// # Remarks
+    /// - libfido2 doesn't report transports of found devices, so they are derived from the path:
+    ///   PC/SC and NFC readers are `"nfc"`, Windows Hello is `"internal"`, and HID devices are
+    ///   `"usb"`. Bluetooth HID devices are reported as `"usb"` as well.
+    pub fn transports(&self) -> &'static [&'static str] {
+        transports(self.path.0.to_bytes())
+    }
+}
+
+/// Derives the transports of a device from the scheme of its path.
+pub(crate) fn transports(path: &[u8]) -> &'static [&'static str] {
+    if path.starts_with(b"pcsc://") || path.starts_with(b"nfc:") {
+        &["nfc"]
+    } else if path.starts_with(b"windows://") {
+        &["internal"]
+    } else {
+        &["usb"]
+    }
+}
+
+/// Returns the sysfs directory of the USB device a hidraw node belongs to, which is the same for
+/// every interface of the device.
+#[cfg(target_os = "linux")]
+pub(crate) fn usb_device(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?;
+    let device = Path::new("/sys/class/hidraw").join(name).join("device");
+    // hidraw -> HID device -> USB interface -> USB device, which has the vendor ID
+    let usb_device = device
+        .canonicalize()
+        .ok()?
+        .ancestors()
+        .take(4)
+        .find(|dir| dir.join("idVendor").exists())?
+        .to_path_buf();
+    Some(usb_device)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn usb_device(_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+/// Reads the serial number of the USB device a hidraw node belongs to.
+pub(crate) fn usb_serial(path: &Path) -> Option<String> {
+    let serial = fs::read_to_string(usb_device(path)?.join("serial")).ok()?;
+    Some(serial.trim().to_owned()).filter(|serial| !serial.is_empty())
+}
+
 impl DeviceList {
     /// Creates an iterator over [information] about found devices.
     ///
     /// [information]: struct.DeviceInformation.html
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = DeviceInformation<'a>> {
+        // The strings are reported by the device, and left empty if they are not UTF-8
+        let to_str = |string: Option<&'a CStr>| string.and_then(|s| s.to_str().ok()).unwrap_or("");
+        self.entries().map(move |entry| DeviceInformation {
+            path: entry.path,
+            product_id: entry.product_id,
+            vendor_id: entry.vendor_id,
+            manufacturer: to_str(entry.manufacturer),
+            product: to_str(entry.product),
+        })
+    }
+
+    /// Copies the [information] about found devices, so the list can be dropped, or the devices
+    /// opened on other threads.
+    ///
+    /// # Remarks
+    /// - Unlike [`iter`], manufacturer and product strings that are not valid UTF-8 are
+    ///   converted lossily instead of being left empty, so the [stable IDs] of devices with such
+    ///   strings still differ.
+    ///
+    /// [information]: struct.OwnedDeviceInformation.html
+    /// [`iter`]: struct.DeviceList.html#method.iter
+    /// [stable IDs]: struct.StableDeviceId.html
+    pub fn into_vec(self) -> Vec<OwnedDeviceInformation> {
+        let to_string = |string: Option<&CStr>| {
+            string.map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+        };
+        self.entries()
+            .map(|entry| OwnedDeviceInformation {
+                path: entry.path.into(),
+                product_id: entry.product_id,
+                vendor_id: entry.vendor_id,
+                manufacturer: to_string(entry.manufacturer),
+                product: to_string(entry.product),
+            })
+            .collect()
+    }
+
+    /// Returns the entries of found devices, with the strings as libfido2 reports them.
+    fn entries<'a>(&'a self) -> impl Iterator<Item = RawEntry<'a>> {
         let device_list = self.raw.as_ptr();
-        (0..self.found).map(move |i| unsafe {
+        (0..self.found).filter_map(move |i| unsafe {
             // Obtain pointer to entry in list (0 based)
-            let device_info = fido_dev_info_ptr(device_list, i.try_into().unwrap());
-            assert!(!device_info.is_null());
+            let device_info = fido_dev_info_ptr(device_list, to_size_t(i).ok()?);
+            if device_info.is_null() {
+                return None;
+            }
 
             // Acquire information from this entry
-            let path = fido_dev_info_path(device_info);
-            assert!(!path.is_null());
-            let path = DevicePath(CStr::from_ptr(path));
-
-            let product_id = fido_dev_info_product(device_info);
-            let vendor_id = fido_dev_info_vendor(device_info);
-
-            let manufacturer = fido_dev_info_manufacturer_string(device_info);
-            assert!(!manufacturer.is_null());
-            let manufacturer = str::from_utf8_unchecked(CStr::from_ptr(manufacturer).to_bytes());
+            // Entries without a (UTF-8) path can't be connected to, so they are skipped
+            let path = fido_dev_info_path(device_info).as_ref()?;
+            let path = DevicePath::try_from_cstr(CStr::from_ptr(path)).ok()?;
 
-            let product = fido_dev_info_product_string(device_info);
-            assert!(!product.is_null());
-            let product = str::from_utf8_unchecked(CStr::from_ptr(product).to_bytes());
+            let manufacturer = fido_dev_info_manufacturer_string(device_info)
+                .as_ref()
+                .map(|ptr| CStr::from_ptr(ptr));
+            let product = fido_dev_info_product_string(device_info)
+                .as_ref()
+                .map(|ptr| CStr::from_ptr(ptr));
 
-            DeviceInformation {
+            Some(RawEntry {
                 path,
-                product_id,
-                vendor_id,
+                product_id: fido_dev_info_product(device_info),
+                vendor_id: fido_dev_info_vendor(device_info),
                 manufacturer,
                 product,
-            }
+            })
         })
     }
 
@@ -82,8 +285,69 @@ impl Drop for DeviceList {
     fn drop(&mut self) {
         unsafe {
             let mut device_list = self.raw.as_ptr_mut();
-            fido_dev_info_free(&mut device_list as *mut _, self.length.try_into().unwrap());
-            assert!(device_list.is_null());
+            fido_dev_info_free(&mut device_list as *mut _, self.length);
+            debug_assert!(device_list.is_null());
+        }
+    }
+}
+
+/// A future resolving to the devices detected by [`Fido::detect_devices_async`].
+///
+/// [`Fido::detect_devices_async`]: struct.Fido.html#method.detect_devices_async
+#[derive(Debug)]
+pub struct DetectDevices(Arc<Mutex<DetectState>>);
+
+#[derive(Default)]
+struct DetectState {
+    result: Option<Result<DeviceList>>,
+    waker: Option<Waker>,
+}
+
+impl DetectDevices {
+    /// Runs `detect` on a new thread, resolving to its result.
+    pub(crate) fn spawn<F>(detect: F) -> Self
+    where
+        F: FnOnce() -> Result<DeviceList> + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(DetectState::default()));
+        let shared = state.clone();
+        let spawned = thread::Builder::new()
+            .name("libfido2-detect".to_owned())
+            .spawn(move || {
+                let result = detect();
+                let mut state = shared.lock().unwrap_or_else(|e| e.into_inner());
+                state.result = Some(result);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+        if spawned.is_err() {
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            state.result = Some(Err(FidoError(FIDO_ERR_INTERNAL)));
         }
+        DetectDevices(state)
+    }
+}
+
+impl Future for DetectDevices {
+    type Output = Result<DeviceList>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl fmt::Debug for DetectState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DetectState")
+            .field("done", &self.result.is_some())
+            .finish()
     }
 }
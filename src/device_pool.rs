@@ -0,0 +1,387 @@
+use crate::{
+    device::{DevicePath, DevicePathBuf},
+    device_list::{transports, usb_device, usb_serial},
+    Aaguid, Assertion, AssertionCreationData, Device, DeviceMode, Fido, FidoError, Result,
+};
+use libfido2_sys::*;
+use std::{ffi::CStr, fmt, os::raw, path::PathBuf, sync::mpsc, thread};
+
+/// A set of opened devices, which can be asked to make the same request at once.
+///
+/// # Remarks
+/// - Requests are sent to every device from its own thread. Once a device succeeds,
+///   the request is cancelled on the others.
+pub struct DevicePool {
+    members: Vec<(DevicePathBuf, Device)>,
+    // Read on demand by `add_unique`, in the order of `members`
+    identities: Vec<Option<KeyIdentity>>,
+    merge_across_transports: bool,
+}
+
+/// Identifies the physical key behind an opened device, to recognize a key reachable through
+/// several paths, e.g. several HID interfaces, or USB and NFC at once.
+///
+/// # Remarks
+/// - FIDO devices expose no identity of their own, so this is the USB device the path belongs
+///   to and its serial number, see `DeviceInformation::serial`, and the AAGUID of the device,
+///   which only identifies its model.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyIdentity {
+    /// The sysfs directory of the USB device, which encodes its bus number and port path.
+    pub usb_device: Option<PathBuf>,
+    pub serial: Option<String>,
+    pub aaguid: Option<Aaguid>,
+    pub transports: &'static [&'static str],
+}
+
+/// What a single device of a [`DevicePool`] answered.
+///
+/// [`DevicePool`]: struct.DevicePool.html
+pub enum DeviceOutcome {
+    /// The device returned an assertion.
+    Success(Assertion),
+    /// The device holds none of the allowed credentials.
+    NoCredentials,
+    /// The user did not confirm the request on the device in time.
+    Timeout,
+    /// The request was cancelled because another device answered first.
+    Cancelled,
+    /// The request failed for another reason.
+    Error(FidoError),
+}
+
+/// The outcome of a request for every device in a [`DevicePool`], in the order the devices were added.
+///
+/// [`DevicePool`]: struct.DevicePool.html
+#[derive(Debug)]
+pub struct PoolReport {
+    pub outcomes: Vec<(DevicePathBuf, DeviceOutcome)>,
+}
+
+impl DevicePool {
+    /// Creates an empty pool.
+    pub fn new() -> Self {
+        DevicePool {
+            members: Vec::new(),
+            identities: Vec::new(),
+            merge_across_transports: false,
+        }
+    }
+
+    /// Sets whether [`add_unique`] also takes keys of the same model on different transports,
+    /// e.g. one over USB and one over NFC, for one key. Disabled by default.
+    ///
+    /// # Remarks
+    /// - Only keys without a serial number on at least one of the paths are matched like this,
+    ///   see [`KeyIdentity::is_same_model_elsewhere`]. Two keys of the same model, one over USB
+    ///   and one over NFC, are then only asked once.
+    ///
+    /// [`add_unique`]: struct.DevicePool.html#method.add_unique
+    /// [`KeyIdentity::is_same_model_elsewhere`]: struct.KeyIdentity.html#method.is_same_model_elsewhere
+    pub fn set_merge_across_transports(&mut self, merge: bool) {
+        self.merge_across_transports = merge;
+    }
+
+    /// Adds an opened device, with the path it was opened from to identify it in reports.
+    pub fn add(&mut self, path: DevicePathBuf, device: Device) {
+        self.members.push((path, device));
+        self.identities.push(None);
+    }
+
+    /// Adds an opened device like [`add`], unless it is another path of a key already in the
+    /// pool, so the user isn't asked to touch the same key twice. Returns the device if it wasn't
+    /// added.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block while the [`KeyIdentity`] of the device, and of
+    ///   devices added through [`add`], is requested.
+    ///
+    /// [`add`]: struct.DevicePool.html#method.add
+    /// [`KeyIdentity`]: struct.KeyIdentity.html
+    pub fn add_unique(
+        &mut self,
+        path: DevicePathBuf,
+        mut device: Device,
+    ) -> Option<(DevicePathBuf, Device)> {
+        let identity = KeyIdentity::new(path.as_path(), &mut device);
+        let members = self.members.iter_mut().zip(self.identities.iter_mut());
+        for ((member_path, member), known) in members {
+            let known =
+                known.get_or_insert_with(|| KeyIdentity::new(member_path.as_path(), member));
+            if known.is_same_key(&identity)
+                || (self.merge_across_transports && known.is_same_model_elsewhere(&identity))
+            {
+                return Some((path, device));
+            }
+        }
+        self.members.push((path, device));
+        self.identities.push(Some(identity));
+        None
+    }
+
+    /// Returns the amount of devices in the pool.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns whether the pool contains no devices.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Removes all devices from the pool and returns them.
+    pub fn into_devices(self) -> Vec<(DevicePathBuf, Device)> {
+        self.members
+    }
+
+    /// Requests an assertion from every device in the pool and reports how each of them answered.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block until every device answered.
+    /// - Without a device timeout, devices that are not touched only stop waiting when another
+    ///   device succeeds. A timeout set through `Device::set_timeout` may be reported as
+    ///   `FIDO_ERR_RX` instead of [`DeviceOutcome::Timeout`].
+    ///
+    /// [`DeviceOutcome::Timeout`]: enum.DeviceOutcome.html#variant.Timeout
+    pub fn request_assertion_verification(
+        &mut self,
+        fido: &Fido,
+        data: AssertionCreationData<'_>,
+        pin: Option<&CStr>,
+    ) -> Result<PoolReport> {
+        let creators = self
+            .members
+            .iter()
+            .map(|_| fido.new_assertion_creator(data))
+            .collect::<Result<Vec<_>>>()?;
+        // For cancelling from this thread, while the devices are borrowed by the workers
+        let cancellers = self
+            .members
+            .iter()
+            .map(|(_, device)| device.canceller.clone())
+            .collect::<Vec<_>>();
+
+        let mut results = thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for (i, ((_, device), creator)) in self.members.iter_mut().zip(creators).enumerate() {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let _ = sender.send((i, device.request_assertion_verification(creator, pin)));
+                });
+            }
+            drop(sender);
+
+            let mut results = cancellers.iter().map(|_| None).collect::<Vec<_>>();
+            let mut cancelled = false;
+            for (i, result) in receiver {
+                if result.is_ok() && !cancelled {
+                    cancelled = true;
+                    for (j, canceller) in cancellers.iter().enumerate() {
+                        if j != i && results[j].is_none() {
+                            canceller.cancel();
+                        }
+                    }
+                }
+                results[i] = Some(result);
+            }
+            results
+        });
+
+        let outcomes = self
+            .members
+            .iter()
+            .zip(results.iter_mut())
+            .map(|((path, _), result)| {
+                // Every worker sends a result, or the scope propagates its panic
+                let outcome = result
+                    .take()
+                    .map(DeviceOutcome::from_result)
+                    .unwrap_or(DeviceOutcome::Error(FidoError(FIDO_ERR_INTERNAL)));
+                (path.clone(), outcome)
+            })
+            .collect();
+
+        Ok(PoolReport { outcomes })
+    }
+}
+
+impl Default for DevicePool {
+    fn default() -> Self {
+        DevicePool::new()
+    }
+}
+
+impl KeyIdentity {
+    /// Reads the identity of `device`, which was opened from `path`.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block while the AAGUID is requested from FIDO2 devices.
+    pub fn new(path: DevicePath<'_>, device: &mut Device) -> Self {
+        let aaguid = match device.mode() {
+            DeviceMode::Fido2 => device
+                .authenticator_info()
+                .ok()
+                .and_then(|info| info.typed_aaguid()),
+            DeviceMode::FidoU2F => None,
+        };
+        KeyIdentity {
+            usb_device: usb_device(path.as_path()),
+            serial: usb_serial(path.as_path()),
+            aaguid,
+            transports: transports(path.0.to_bytes()),
+        }
+    }
+
+    /// Returns whether both identities are of the same physical key.
+    ///
+    /// # Remarks
+    /// - Paths of the same USB device, e.g. several of its HID interfaces, are of the same key.
+    ///   So are paths with the same serial number and AAGUID.
+    pub fn is_same_key(&self, other: &KeyIdentity) -> bool {
+        if let (Some(usb_device), Some(other_usb_device)) = (&self.usb_device, &other.usb_device) {
+            if usb_device == other_usb_device {
+                return true;
+            }
+        }
+        match (&self.serial, &other.serial) {
+            (Some(serial), Some(other_serial)) => {
+                serial == other_serial && self.aaguid == other.aaguid
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns whether both identities are of the same model on different transports, which is
+    /// likely one key connected twice, e.g. over USB and NFC.
+    ///
+    /// # Remarks
+    /// - Only identities with a non-zero AAGUID and no serial number on at least one of them
+    ///   are matched, as several keys of one model may be connected over USB. Two keys of the
+    ///   same model, one over USB and one over NFC, are taken for one key.
+    pub fn is_same_model_elsewhere(&self, other: &KeyIdentity) -> bool {
+        if self.serial.is_some() && other.serial.is_some() {
+            return false;
+        }
+        let same_model = match (self.aaguid, other.aaguid) {
+            (Some(aaguid), Some(other_aaguid)) => aaguid == other_aaguid && !aaguid.is_zero(),
+            _ => false,
+        };
+        let shared_transport = self
+            .transports
+            .iter()
+            .any(|transport| other.transports.contains(transport));
+        same_model && !shared_transport
+    }
+}
+
+impl DeviceOutcome {
+    fn from_result(result: Result<Assertion>) -> Self {
+        const NO_CREDENTIALS: raw::c_int = FIDO_ERR_NO_CREDENTIALS as raw::c_int;
+        const ACTION_TIMEOUT: raw::c_int = FIDO_ERR_ACTION_TIMEOUT as raw::c_int;
+        const USER_ACTION_TIMEOUT: raw::c_int = FIDO_ERR_USER_ACTION_TIMEOUT as raw::c_int;
+        const TIMEOUT: raw::c_int = FIDO_ERR_TIMEOUT as raw::c_int;
+        const KEEPALIVE_CANCEL: raw::c_int = FIDO_ERR_KEEPALIVE_CANCEL as raw::c_int;
+
+        match result {
+            Ok(assertion) => DeviceOutcome::Success(assertion),
+            Err(FidoError(NO_CREDENTIALS)) => DeviceOutcome::NoCredentials,
+            Err(FidoError(ACTION_TIMEOUT)) | Err(FidoError(USER_ACTION_TIMEOUT)) | Err(FidoError(TIMEOUT)) => {
+                DeviceOutcome::Timeout
+            }
+            Err(FidoError(KEEPALIVE_CANCEL)) => DeviceOutcome::Cancelled,
+            Err(err) => DeviceOutcome::Error(err),
+        }
+    }
+}
+
+impl fmt::Debug for DeviceOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceOutcome::Success(assertion) => f
+                .debug_struct("Success")
+                .field("statements", &assertion.len())
+                .finish(),
+            DeviceOutcome::NoCredentials => f.write_str("NoCredentials"),
+            DeviceOutcome::Timeout => f.write_str("Timeout"),
+            DeviceOutcome::Cancelled => f.write_str("Cancelled"),
+            DeviceOutcome::Error(err) => f.debug_tuple("Error").field(err).finish(),
+        }
+    }
+}
+
+impl PoolReport {
+    /// Returns the device that answered with an assertion and the assertion, if any did.
+    pub fn assertion(&self) -> Option<(&DevicePathBuf, &Assertion)> {
+        self.outcomes.iter().find_map(|(path, outcome)| match outcome {
+            DeviceOutcome::Success(assertion) => Some((path, assertion)),
+            _ => None,
+        })
+    }
+
+    /// Converts into the assertion of the device that answered, if any did.
+    pub fn into_assertion(self) -> Option<Assertion> {
+        self.outcomes
+            .into_iter()
+            .find_map(|(_, outcome)| match outcome {
+                DeviceOutcome::Success(assertion) => Some(assertion),
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(
+        usb_device: Option<&str>,
+        serial: Option<&str>,
+        aaguid: u8,
+        transports: &'static [&'static str],
+    ) -> KeyIdentity {
+        KeyIdentity {
+            usb_device: usb_device.map(PathBuf::from),
+            serial: serial.map(str::to_owned),
+            aaguid: Some(Aaguid([aaguid; 16])),
+            transports,
+        }
+    }
+
+    const USB_DEVICE: &str = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2";
+
+    #[test]
+    fn interfaces_without_serial_are_one_key() {
+        let a = identity(Some(USB_DEVICE), None, 1, &["usb"]);
+        let b = identity(Some(USB_DEVICE), None, 1, &["usb"]);
+        assert!(a.is_same_key(&b));
+    }
+
+    #[test]
+    fn keys_of_one_model_on_other_ports_differ() {
+        let a = identity(Some(USB_DEVICE), None, 1, &["usb"]);
+        let other_port = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-3";
+        let b = identity(Some(other_port), None, 1, &["usb"]);
+        assert!(!a.is_same_key(&b));
+        assert!(!a.is_same_model_elsewhere(&b));
+    }
+
+    #[test]
+    fn serials_identify_keys() {
+        let a = identity(None, Some("1234"), 1, &["usb"]);
+        assert!(a.is_same_key(&identity(None, Some("1234"), 1, &["usb"])));
+        assert!(!a.is_same_key(&identity(None, Some("5678"), 1, &["usb"])));
+        assert!(!a.is_same_key(&identity(None, Some("1234"), 2, &["usb"])));
+    }
+
+    #[test]
+    fn models_match_across_transports_only() {
+        let usb = identity(Some(USB_DEVICE), Some("1234"), 1, &["usb"]);
+        let nfc = identity(None, None, 1, &["nfc"]);
+        assert!(!usb.is_same_key(&nfc));
+        assert!(usb.is_same_model_elsewhere(&nfc));
+        assert!(!nfc.is_same_model_elsewhere(&identity(None, None, 1, &["nfc"])));
+        assert!(!nfc.is_same_model_elsewhere(&identity(None, None, 2, &["usb"])));
+        let unknown_model = identity(None, None, 0, &["nfc"]);
+        assert!(!unknown_model.is_same_model_elsewhere(&identity(None, None, 0, &["usb"])));
+    }
+}
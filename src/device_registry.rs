@@ -0,0 +1,112 @@
+use crate::{device_list::OwnedDeviceInformation, Fido, Result};
+use std::{fmt, time::Instant};
+
+type Listener<'a> = Box<dyn FnMut(&DeviceChange) + 'a>;
+
+/// Caches the devices found by [`Fido::detect_devices`], so they can be polled cheaply.
+///
+/// Devices are only re-enumerated when [`refresh`] is called.
+/// Changes found during a refresh are reported to all registered listeners.
+///
+/// [`Fido::detect_devices`]: struct.Fido.html#method.detect_devices
+/// [`refresh`]: struct.DeviceRegistry.html#method.refresh
+pub struct DeviceRegistry<'a> {
+    fido: &'a Fido,
+    max_length: usize,
+    devices: Vec<OwnedDeviceInformation>,
+    generation: u64,
+    last_refresh: Option<Instant>,
+    listeners: Vec<Listener<'a>>,
+}
+
+/// A change in the set of connected devices, as observed by a [`DeviceRegistry`].
+///
+/// [`DeviceRegistry`]: struct.DeviceRegistry.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceChange {
+    Added(OwnedDeviceInformation),
+    Removed(OwnedDeviceInformation),
+}
+
+impl<'a> DeviceRegistry<'a> {
+    pub(crate) fn new(fido: &'a Fido, max_length: usize) -> Self {
+        DeviceRegistry {
+            fido,
+            max_length,
+            devices: Vec::new(),
+            generation: 0,
+            last_refresh: None,
+            listeners: Vec::new(),
+        }
+    }
+
+    /// Returns the cached devices, as found during the last refresh.
+    pub fn devices(&self) -> &[OwnedDeviceInformation] {
+        &self.devices
+    }
+
+    /// Returns a counter that is incremented every time a refresh changes the cached devices.
+    ///
+    /// # Remarks
+    /// - Comparing this against a previously seen value is a cheap way of polling for changes.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns when the devices were last enumerated, or `None` if they never were.
+    pub fn last_refresh(&self) -> Option<Instant> {
+        self.last_refresh
+    }
+
+    /// Registers a listener which is called for every change found during a refresh.
+    pub fn on_change<F>(&mut self, listener: F)
+    where
+        F: FnMut(&DeviceChange) + 'a,
+    {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Re-enumerates the connected devices and returns the changes since the last refresh.
+    ///
+    /// # Remarks
+    /// - Devices are matched by path.
+    /// - On failure, the cached devices are left untouched.
+    pub fn refresh(&mut self) -> Result<Vec<DeviceChange>> {
+        let found = self.fido.try_detect_devices(self.max_length)?.into_vec();
+        self.last_refresh = Some(Instant::now());
+
+        let removed = self
+            .devices
+            .iter()
+            .filter(|old| found.iter().all(|new| new.path != old.path))
+            .cloned()
+            .map(DeviceChange::Removed);
+        let added = found
+            .iter()
+            .filter(|new| self.devices.iter().all(|old| old.path != new.path))
+            .cloned()
+            .map(DeviceChange::Added);
+        let changes = removed.chain(added).collect::<Vec<_>>();
+
+        if !changes.is_empty() {
+            self.devices = found;
+            self.generation += 1;
+            for listener in &mut self.listeners {
+                changes.iter().for_each(&mut *listener);
+            }
+        }
+
+        Ok(changes)
+    }
+}
+
+impl fmt::Debug for DeviceRegistry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceRegistry")
+            .field("max_length", &self.max_length)
+            .field("devices", &self.devices)
+            .field("generation", &self.generation)
+            .field("last_refresh", &self.last_refresh)
+            .finish()
+    }
+}
@@ -0,0 +1,327 @@
+use crate::{libfido2_version, DevicePath, Fido, FidoError};
+use libfido2_sys::*;
+use std::{error, fmt};
+
+/// Opening a device failed. See [`Fido::open_device`].
+///
+/// [`Fido::open_device`]: struct.Fido.html#method.open_device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OpenError {
+    /// The operating system likely denied access to the device.
+    PermissionDenied {
+        error: FidoError,
+        /// What to change, e.g. which udev rules to install, suitable for users.
+        hint: String,
+    },
+    /// Opening the device failed for another reason.
+    Fido(FidoError),
+}
+
+impl OpenError {
+    /// Returns the error libfido2 reported.
+    pub fn fido_error(&self) -> FidoError {
+        match self {
+            OpenError::PermissionDenied { error, .. } | OpenError::Fido(error) => *error,
+        }
+    }
+}
+
+impl From<OpenError> for FidoError {
+    fn from(error: OpenError) -> Self {
+        error.fido_error()
+    }
+}
+
+impl error::Error for OpenError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            OpenError::PermissionDenied { error, .. } | OpenError::Fido(error) => Some(error),
+        }
+    }
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::PermissionDenied { hint, .. } => {
+                write!(f, "Access to the device was denied. {}", hint)
+            }
+            OpenError::Fido(error) => fmt::Display::fmt(error, f),
+        }
+    }
+}
+
+/// Explains why opening `path` failed, if it is likely a lack of permission.
+///
+/// libfido2 reports every failure to open the device as `FIDO_ERR_RX`, so the cause is found
+/// by inspecting the platform.
+pub(crate) fn classify_open_error(path: DevicePath<'_>, error: FidoError) -> OpenError {
+    if error.code() != FIDO_ERR_RX {
+        return OpenError::Fido(error);
+    }
+    match platform::permission_hint(path) {
+        Some(hint) => OpenError::PermissionDenied { error, hint },
+        None => OpenError::Fido(error),
+    }
+}
+
+/// The result of a [`Diagnostic`] check.
+///
+/// [`Diagnostic`]: struct.Diagnostic.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    /// The condition can't be checked, e.g. because the platform doesn't allow querying it.
+    Unknown,
+}
+
+/// One check of the environment made by [`doctor`].
+///
+/// [`doctor`]: fn.doctor.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// What was checked, e.g. `udev rules`.
+    pub check: &'static str,
+    pub status: CheckStatus,
+    /// What was found, or what to change if the check failed.
+    pub detail: String,
+}
+
+impl Diagnostic {
+    fn new(check: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Diagnostic {
+            check,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            CheckStatus::Passed => "ok",
+            CheckStatus::Failed => "FAILED",
+            CheckStatus::Unknown => "unknown",
+        };
+        write!(f, "[{}] {}: {}", status, self.check, self.detail)
+    }
+}
+
+/// Checks whether the environment allows using FIDO devices, e.g. for a `--doctor` flag of a
+/// command line tool.
+///
+/// # Remarks
+/// - Every detected device is opened, which is harmless but may make it blink.
+pub fn doctor(fido: &Fido) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    diagnostics.push(match libfido2_version() {
+        Some(version) => Diagnostic::new("libfido2", CheckStatus::Passed, version.to_string()),
+        None => Diagnostic::new(
+            "libfido2",
+            CheckStatus::Unknown,
+            "The version can't be detected on this platform",
+        ),
+    });
+
+    match fido.try_detect_devices(64) {
+        Ok(devices) if devices.is_empty() => diagnostics.push(Diagnostic::new(
+            "devices",
+            CheckStatus::Failed,
+            "No FIDO devices were found. Plug in a security key.",
+        )),
+        Ok(devices) => {
+            diagnostics.push(Diagnostic::new(
+                "devices",
+                CheckStatus::Passed,
+                format!("{} found", devices.len()),
+            ));
+            for info in devices.iter() {
+                let diagnostic = match fido.open_device(info.path) {
+                    Ok(_) => Diagnostic::new(
+                        "device access",
+                        CheckStatus::Passed,
+                        info.path.to_str().to_owned(),
+                    ),
+                    Err(error) => Diagnostic::new(
+                        "device access",
+                        CheckStatus::Failed,
+                        format!("{}: {}", info.path.to_str(), error),
+                    ),
+                };
+                diagnostics.push(diagnostic);
+            }
+        }
+        Err(error) => diagnostics.push(Diagnostic::new(
+            "devices",
+            CheckStatus::Failed,
+            format!("Detecting devices failed: {}", error),
+        )),
+    }
+
+    platform::check(&mut diagnostics);
+    diagnostics
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{CheckStatus, Diagnostic};
+    use crate::DevicePath;
+    use std::{
+        fs::{self, OpenOptions},
+        io,
+        path::Path,
+    };
+
+    const RULES_DIRS: &[&str] = &[
+        "/etc/udev/rules.d",
+        "/usr/lib/udev/rules.d",
+        "/lib/udev/rules.d",
+    ];
+
+    /// Returns whether rules for FIDO devices are installed, e.g. `70-u2f.rules` of libfido2.
+    fn has_udev_rules() -> bool {
+        RULES_DIRS
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .any(|entry| {
+                let name = entry.file_name().to_string_lossy().to_lowercase();
+                name.ends_with(".rules") && (name.contains("u2f") || name.contains("fido"))
+            })
+    }
+
+    fn is_denied(path: &Path) -> bool {
+        match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(_) => false,
+            Err(error) => error.kind() == io::ErrorKind::PermissionDenied,
+        }
+    }
+
+    pub(super) fn permission_hint(path: DevicePath<'_>) -> Option<String> {
+        if !is_denied(path.as_path()) {
+            return None;
+        }
+        Some(if has_udev_rules() {
+            format!(
+                "{} is not accessible to this user, although udev rules for FIDO devices are \
+                 installed. Replug the device, and check that the rules match it with \
+                 `udevadm info {}`.",
+                path.to_str(),
+                path.to_str()
+            )
+        } else {
+            format!(
+                "{} is not accessible to this user, and no udev rules for FIDO devices are \
+                 installed. Install the udev rules of libfido2 (70-u2f.rules) into \
+                 /etc/udev/rules.d, and replug the device.",
+                path.to_str()
+            )
+        })
+    }
+
+    pub(super) fn check(diagnostics: &mut Vec<Diagnostic>) {
+        diagnostics.push(if has_udev_rules() {
+            Diagnostic::new("udev rules", CheckStatus::Passed, "Rules for FIDO devices found")
+        } else {
+            Diagnostic::new(
+                "udev rules",
+                CheckStatus::Failed,
+                "No udev rules for FIDO devices are installed. Install the udev rules of \
+                 libfido2 (70-u2f.rules) into /etc/udev/rules.d.",
+            )
+        });
+
+        // libfido2 skips devices it can't open, so they are never detected
+        let denied = fs::read_dir("/dev")
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("hidraw"))
+            .filter(|entry| is_denied(&entry.path()))
+            .count();
+        diagnostics.push(match denied {
+            0 => Diagnostic::new("hidraw", CheckStatus::Passed, "All HID devices are accessible"),
+            n => Diagnostic::new(
+                "hidraw",
+                CheckStatus::Unknown,
+                format!(
+                    "{} HID devices are not accessible, which is normal for keyboards and mice. \
+                     If a security key is missing, it is likely one of them.",
+                    n
+                ),
+            ),
+        });
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{CheckStatus, Diagnostic};
+    use crate::DevicePath;
+
+    const INPUT_MONITORING: &str = "macOS may block access to HID devices until the application \
+        is allowed under System Settings > Privacy & Security > Input Monitoring.";
+
+    // Permissions of the transparency, consent and control (TCC) framework can't be queried
+    pub(super) fn permission_hint(_path: DevicePath<'_>) -> Option<String> {
+        Some(INPUT_MONITORING.to_owned())
+    }
+
+    pub(super) fn check(diagnostics: &mut Vec<Diagnostic>) {
+        diagnostics.push(Diagnostic::new(
+            "input monitoring",
+            CheckStatus::Unknown,
+            INPUT_MONITORING,
+        ));
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::{CheckStatus, Diagnostic};
+    use crate::DevicePath;
+
+    const NOT_ADMINISTRATOR: &str = "Windows only allows administrators to access FIDO devices \
+        directly. Run as administrator, or use the Windows Hello device `windows://hello`.";
+
+    #[link(name = "shell32")]
+    extern "system" {
+        fn IsUserAnAdmin() -> i32;
+    }
+
+    fn is_administrator() -> bool {
+        unsafe { IsUserAnAdmin() != 0 }
+    }
+
+    pub(super) fn permission_hint(path: DevicePath<'_>) -> Option<String> {
+        if path.to_str().starts_with("windows://") || is_administrator() {
+            None
+        } else {
+            Some(NOT_ADMINISTRATOR.to_owned())
+        }
+    }
+
+    pub(super) fn check(diagnostics: &mut Vec<Diagnostic>) {
+        diagnostics.push(if is_administrator() {
+            Diagnostic::new("administrator", CheckStatus::Passed, "Running as administrator")
+        } else {
+            Diagnostic::new("administrator", CheckStatus::Failed, NOT_ADMINISTRATOR)
+        });
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod platform {
+    use super::Diagnostic;
+    use crate::DevicePath;
+
+    pub(super) fn permission_hint(_path: DevicePath<'_>) -> Option<String> {
+        None
+    }
+
+    pub(super) fn check(_diagnostics: &mut Vec<Diagnostic>) {}
+}
@@ -0,0 +1,301 @@
+use std::{error, ffi::CStr, fmt};
+
+/// Maximum length of a user ID, in bytes.
+pub const MAX_USER_ID_LENGTH: usize = 64;
+
+/// Icon URI schemes [`IconPolicy::default`] allows.
+///
+/// [`IconPolicy::default`]: struct.IconPolicy.html#method.default
+pub const DEFAULT_ICON_SCHEMES: &[&str] = &["https", "data"];
+
+/// Maximum length of an icon URI [`IconPolicy::default`] allows, in bytes.
+///
+/// [`IconPolicy::default`]: struct.IconPolicy.html#method.default
+pub const DEFAULT_MAX_ICON_LENGTH: usize = 2048;
+
+/// The relying party a credential is scoped to, shared by credential creation and assertions.
+///
+/// # Remarks
+/// - The `id` is checked for obvious mistakes, such as passing an origin (`https://example.com`)
+///   instead of a domain. With the `rp-id-domain` feature, it must also be a valid domain name.
+/// - OpenSSH application IDs, `ssh:` optionally followed by a name, are accepted as well.
+/// - The `name` is only used during credential creation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RpEntity<'a> {
+    id: &'a CStr,
+    name: Option<&'a CStr>,
+}
+
+impl<'a> RpEntity<'a> {
+    /// Constructs a new `RpEntity` without name.
+    ///
+    /// # Errors
+    /// - When `id` is empty, not UTF-8, or contains a scheme, port, path or whitespace.
+    /// - With the `rp-id-domain` feature, when `id` is not a valid domain name.
+    pub fn new(id: &'a CStr) -> Result<Self, InvalidRpIdError> {
+        let valid = id.to_str().map(is_valid_rp_id).unwrap_or(false);
+        if !valid {
+            return Err(InvalidRpIdError);
+        }
+        Ok(RpEntity { id, name: None })
+    }
+
+    /// Sets the human-friendly name of the relying party.
+    pub fn with_name(mut self, name: &'a CStr) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Returns the relying party ID.
+    pub fn id(&self) -> &'a CStr {
+        self.id
+    }
+
+    /// Returns the human-friendly name of the relying party, if set.
+    pub fn name(&self) -> Option<&'a CStr> {
+        self.name
+    }
+}
+
+fn is_valid_rp_id(id: &str) -> bool {
+    let invalid = |c: char| c.is_whitespace() || c == '/' || c == ':';
+    if let Some(application) = id.strip_prefix("ssh:") {
+        return !application.contains(invalid);
+    }
+    !id.is_empty() && !id.contains(invalid) && (!cfg!(feature = "rp-id-domain") || is_domain(id))
+}
+
+// Effective domain rules: up to 253 characters in dot separated labels of 1 to 63 ASCII
+// alphanumerics or hyphens, which may not start or end with a hyphen
+fn is_domain(id: &str) -> bool {
+    id.len() <= 253
+        && id.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+/// The user account a credential is created for, as shown by authenticators and returned in assertions.
+///
+/// # Remarks
+/// - `name` is the account identifier, e.g. an email address. `display_name` is a human-friendly,
+///   possibly localized name for the account, e.g. "John Doe".
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UserEntity<'a> {
+    id: &'a [u8],
+    name: &'a CStr,
+    display_name: Option<&'a CStr>,
+    icon: Option<&'a CStr>,
+}
+
+impl<'a> UserEntity<'a> {
+    /// Constructs a new `UserEntity` without display name or icon.
+    ///
+    /// # Errors
+    /// - When `id` is empty or longer than [`MAX_USER_ID_LENGTH`] bytes.
+    ///
+    /// [`MAX_USER_ID_LENGTH`]: constant.MAX_USER_ID_LENGTH.html
+    pub fn new(id: &'a [u8], name: &'a CStr) -> Result<Self, InvalidUserIdError> {
+        if id.is_empty() || id.len() > MAX_USER_ID_LENGTH {
+            return Err(InvalidUserIdError { length: id.len() });
+        }
+        Ok(UserEntity {
+            id,
+            name,
+            display_name: None,
+            icon: None,
+        })
+    }
+
+    /// Sets the human-friendly name of the account.
+    pub fn with_display_name(mut self, display_name: &'a CStr) -> Self {
+        self.display_name = Some(display_name);
+        self
+    }
+
+    /// Sets the URI of an image for the account.
+    pub fn with_icon(mut self, icon: &'a CStr) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+
+    /// Returns the user handle, which identifies the account to the relying party.
+    pub fn id(&self) -> &'a [u8] {
+        self.id
+    }
+
+    /// Returns the account identifier.
+    pub fn name(&self) -> &'a CStr {
+        self.name
+    }
+
+    /// Returns the human-friendly name of the account, if set.
+    pub fn display_name(&self) -> Option<&'a CStr> {
+        self.display_name
+    }
+
+    /// Returns the URI of an image for the account, if set.
+    ///
+    /// # Remarks
+    /// - Icons returned by a device are whatever it was provisioned with, possibly by another
+    ///   party. See [`sanitized_icon`] before displaying them.
+    ///
+    /// [`sanitized_icon`]: struct.UserEntity.html#method.sanitized_icon
+    pub fn icon(&self) -> Option<&'a CStr> {
+        self.icon
+    }
+
+    /// Returns the URI of an image for the account, if set and allowed by `policy`.
+    pub fn sanitized_icon(&self, policy: &IconPolicy<'_>) -> Option<&'a str> {
+        validate_icon(self.icon?, policy).ok()
+    }
+}
+
+impl fmt::Display for UserEntity<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.display_name {
+            Some(display_name) => write!(
+                f,
+                "{} ({})",
+                display_name.to_string_lossy(),
+                self.name.to_string_lossy()
+            ),
+            None => write!(f, "{}", self.name.to_string_lossy()),
+        }
+    }
+}
+
+/// The rules [`validate_icon`] checks an icon URI against.
+///
+/// [`validate_icon`]: fn.validate_icon.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IconPolicy<'a> {
+    /// Allowed URI schemes, compared without case, e.g. `https`.
+    ///
+    /// `data` only allows `data:image/` URIs, so other media types can't be embedded.
+    pub schemes: &'a [&'a str],
+    /// Maximum length in bytes.
+    pub max_length: usize,
+}
+
+impl Default for IconPolicy<'static> {
+    /// Allows [`DEFAULT_ICON_SCHEMES`] up to [`DEFAULT_MAX_ICON_LENGTH`] bytes.
+    ///
+    /// [`DEFAULT_ICON_SCHEMES`]: constant.DEFAULT_ICON_SCHEMES.html
+    /// [`DEFAULT_MAX_ICON_LENGTH`]: constant.DEFAULT_MAX_ICON_LENGTH.html
+    fn default() -> Self {
+        IconPolicy {
+            schemes: DEFAULT_ICON_SCHEMES,
+            max_length: DEFAULT_MAX_ICON_LENGTH,
+        }
+    }
+}
+
+/// Rules of an [`IconPolicy`] an icon URI breaks.
+///
+/// [`IconPolicy`]: struct.IconPolicy.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IconViolation {
+    /// The URI is not valid UTF-8.
+    NotUtf8,
+    /// The URI is longer than the policy allows.
+    TooLong { max_length: usize, length: usize },
+    /// The URI has no scheme, or contains whitespace or control characters.
+    Malformed,
+    /// The scheme of the URI is not allowed by the policy.
+    SchemeNotAllowed,
+}
+
+impl error::Error for IconViolation {}
+
+impl fmt::Display for IconViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IconViolation::NotUtf8 => write!(f, "The icon URI must be valid UTF-8"),
+            IconViolation::TooLong { max_length, .. } => {
+                write!(f, "The icon URI must be at most {} bytes long", max_length)
+            }
+            IconViolation::Malformed => write!(f, "The icon URI is malformed"),
+            IconViolation::SchemeNotAllowed => {
+                write!(f, "The scheme of the icon URI is not allowed")
+            }
+        }
+    }
+}
+
+/// Checks an icon URI against `policy`, e.g. before displaying the icon a device returned, and
+/// returns it as a string.
+///
+/// # Remarks
+/// - Only the scheme is checked, the URI is not parsed further. Relying parties still have to
+///   fetch and render it like any untrusted URI.
+pub fn validate_icon<'a>(
+    icon: &'a CStr,
+    policy: &IconPolicy<'_>,
+) -> Result<&'a str, IconViolation> {
+    let icon = icon.to_str().map_err(|_| IconViolation::NotUtf8)?;
+    if icon.len() > policy.max_length {
+        return Err(IconViolation::TooLong {
+            max_length: policy.max_length,
+            length: icon.len(),
+        });
+    }
+    if icon.contains(|c: char| c.is_whitespace() || c.is_control()) {
+        return Err(IconViolation::Malformed);
+    }
+    // scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." ), as of RFC 3986
+    let (scheme, rest) = icon.split_once(':').ok_or(IconViolation::Malformed)?;
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    if !valid {
+        return Err(IconViolation::Malformed);
+    }
+    let allowed = policy
+        .schemes
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(scheme));
+    let image = !scheme.eq_ignore_ascii_case("data")
+        || rest
+            .get(..6)
+            .is_some_and(|media| media.eq_ignore_ascii_case("image/"));
+    if allowed && image {
+        Ok(icon)
+    } else {
+        Err(IconViolation::SchemeNotAllowed)
+    }
+}
+
+/// Error returned when a relying party ID is malformed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidRpIdError;
+
+impl error::Error for InvalidRpIdError {}
+
+impl fmt::Display for InvalidRpIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The relying party ID is malformed")
+    }
+}
+
+/// Error returned when a user ID is empty or too long.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidUserIdError {
+    pub length: usize,
+}
+
+impl error::Error for InvalidUserIdError {}
+
+impl fmt::Display for InvalidUserIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The user ID must be 1 to {} bytes long, got {}",
+            MAX_USER_ID_LENGTH, self.length
+        )
+    }
+}
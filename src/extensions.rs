@@ -0,0 +1,86 @@
+//! Extension inputs and outputs, with one field per extension, so a request and its response
+//! read the same.
+
+use crate::CredProtect;
+
+/// The extensions of a credential or assertion request.
+///
+/// Fields marked as registration or authentication only fail the request of the other ceremony
+/// with `FIDO_ERR_INVALID_ARGUMENT`, see `CredentialCreator::set_extensions` and
+/// `AssertionCreator::set_extensions`. The outputs are returned as [`ExtensionResults`].
+///
+/// [`ExtensionResults`]: struct.ExtensionResults.html
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Extensions<'a> {
+    /// `hmac-secret`: enables it for the credential, or requests its output for a salt.
+    pub hmac_secret: Option<HmacSecretInput<'a>>,
+    /// `credBlob`: stores a small blob with the credential, or requests it back.
+    pub cred_blob: Option<CredBlobInput<'a>>,
+    /// `largeBlobKey`: requests the key the large blob of the credential is encrypted with.
+    /// Only supported for resident credentials.
+    #[cfg(feature = "largeblob")]
+    pub large_blob_key: bool,
+    /// `minPinLength`: requests the minimum PIN length of the device. Registration only, and
+    /// only returned to relying parties allowed by `Device::set_min_pin_length_rp_ids`.
+    #[cfg(feature = "config")]
+    pub min_pin_length: bool,
+    /// `credProtect`: the protection policy of the credential. Registration only.
+    pub cred_protect: Option<CredProtect>,
+}
+
+/// Input of the `hmac-secret` extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HmacSecretInput<'a> {
+    /// Registration: creates the credential with a secret for HMACs.
+    Enable,
+    /// Authentication: requests HMAC-SHA-256 of the salt, which must be 32 bytes, or 64 bytes for
+    /// two outputs.
+    Salt(&'a [u8]),
+}
+
+/// Input of the `credBlob` extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CredBlobInput<'a> {
+    /// Registration: stores the blob, up to the `maxCredBlobLength` of the device, which is at
+    /// least 32 bytes.
+    Store(&'a [u8]),
+    /// Authentication: requests the stored blob.
+    Get,
+}
+
+/// The extension outputs of a credential or assertion, with the fields of [`Extensions`].
+///
+/// # Remarks
+/// - A field is `None` if the extension was not requested, or the device did not return it.
+///
+/// [`Extensions`]: struct.Extensions.html
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtensionResults<'a> {
+    pub hmac_secret: Option<HmacSecretOutput<'a>>,
+    pub cred_blob: Option<CredBlobOutput<'a>>,
+    #[cfg(feature = "largeblob")]
+    pub large_blob_key: Option<&'a [u8]>,
+    /// The minimum PIN length of the device.
+    #[cfg(feature = "config")]
+    pub min_pin_length: Option<u64>,
+    /// The protection policy the device granted, which may be weaker than requested.
+    pub cred_protect: Option<CredProtect>,
+}
+
+/// Output of the `hmac-secret` extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HmacSecretOutput<'a> {
+    /// Registration: whether the credential has a secret for HMACs.
+    Enabled(bool),
+    /// Authentication: the HMAC of the salt, 32 or 64 bytes.
+    Secret(&'a [u8]),
+}
+
+/// Output of the `credBlob` extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CredBlobOutput<'a> {
+    /// Registration: whether the blob was stored.
+    Stored(bool),
+    /// Authentication: the stored blob.
+    Blob(&'a [u8]),
+}
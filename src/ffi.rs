@@ -1,28 +1,77 @@
+use crate::{FidoError, Result};
+use libfido2_sys::*;
 use std::{
     cmp::{Eq, PartialEq},
+    convert::TryFrom,
     ffi::CStr,
     os::raw::c_char,
     slice, str,
 };
 
+/// Converts a length or index to `size_t`.
+///
+/// # Errors
+/// - `FIDO_ERR_INVALID_ARGUMENT` if it does not fit.
+pub(crate) fn to_size_t(n: usize) -> Result<size_t> {
+    size_t::try_from(n).map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))
+}
+
+/// Converts a length or index returned by libfido2 to `usize`.
+///
+/// # Errors
+/// - `FIDO_ERR_INTERNAL` if it does not fit.
+pub(crate) fn to_usize(n: size_t) -> Result<usize> {
+    usize::try_from(n).map_err(|_| FidoError(FIDO_ERR_INTERNAL))
+}
+
+/// Takes a pointer to a newly allocated libfido2 object.
+///
+/// # Errors
+/// - `FIDO_ERR_INTERNAL` if the allocation failed.
+pub(crate) fn allocated<T>(ptr: *mut T) -> Result<NonNull<T>> {
+    NonNull::new(ptr).ok_or(FidoError(FIDO_ERR_INTERNAL))
+}
+
+/// Borrows a byte array owned by libfido2, returning `None` if `ptr` is NULL.
+///
+/// # Unsafety
+/// - `ptr` must be valid for `len` bytes during `'a`.
+pub(crate) unsafe fn bytes<'a>(ptr: *const u8, len: size_t) -> Result<Option<&'a [u8]>> {
+    if ptr.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(slice::from_raw_parts(ptr, to_usize(len)?)))
+    }
+}
+
 /// Converts a `*const *mut c_char` to a boxed array of `&str`s.
 ///
 /// # Unsafety
 /// - Contained strings must be valid UTF-8.
+///
+/// # Remarks
+/// - NULL entries are skipped.
 pub(crate) unsafe fn convert_cstr_array_ptr<'a>(
     array: *const *mut c_char,
     len: usize,
 ) -> Box<[&'a str]> {
     slice::from_raw_parts(array, len)
         .iter()
-        .map(|ptr| {
-            assert!(!ptr.is_null());
-            str::from_utf8_unchecked(CStr::from_ptr(*ptr).to_bytes())
-        })
+        .filter(|ptr| !ptr.is_null())
+        .map(|ptr| str::from_utf8_unchecked(CStr::from_ptr(*ptr).to_bytes()))
         .collect::<Vec<&'a str>>()
         .into_boxed_slice()
 }
 
+/// Converts an option flag to a `fido_opt_t`, omitting the option from the request when it is not set.
+pub(crate) fn opt_or_omit(set: bool) -> fido_opt_t {
+    if set {
+        fido_opt_t_FIDO_OPT_TRUE
+    } else {
+        fido_opt_t_FIDO_OPT_OMIT
+    }
+}
+
 /// Alternative to `std::ptr::NonNull`, with separate getter methods for `*const` and `*mut`,
 /// requiring `&self` and `&mut self` respectively.
 pub struct NonNull<T: ?Sized>(std::ptr::NonNull<T>);
@@ -43,7 +92,7 @@ impl<T: ?Sized> NonNull<T> {
 
 impl<T: ?Sized> PartialEq for NonNull<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.0.eq(&other.0)
+        std::ptr::addr_eq(self.0.as_ptr(), other.0.as_ptr())
     }
 }
 
@@ -0,0 +1,269 @@
+//! Symmetric keys bound to a device, derived from the `hmac-secret` extension.
+//!
+//! This packages the pattern of storing data encrypted with a key only a FIDO2 device can
+//! reproduce, as used by `systemd-cryptenroll` to unlock disks:
+//!
+//! 1. Create a credential with `CredentialExtensions::HMAC_SECRET`, and store its ID.
+//! 2. Generate a [`Salt`], and store it next to the protected data.
+//! 3. Derive the key with a [`SecretDeriver`], whenever it is needed.
+//!
+//! The salt is not secret, but it must not change: a different salt derives a different key.
+//! To rotate the key, generate a new salt, derive the new key, and re-encrypt the data with it.
+//!
+//! [`Salt`]: struct.Salt.html
+//! [`SecretDeriver`]: struct.SecretDeriver.html
+
+use crate::{
+    openssl, AssertionCreationData, AssertionOptions, Device, Fido, FidoError, Result, RpEntity,
+};
+use libfido2_sys::*;
+use std::{convert::TryFrom, error, ffi::CStr, fmt, ops::Deref, os::raw};
+
+/// Length of the salt sent to the device, as required by `hmac-secret`.
+pub const SALT_LEN: usize = 32;
+
+/// Length of derived keys.
+pub const KEY_LEN: usize = 32;
+
+// Separates keys of different derivation versions and from other uses of the same credential
+const INFO_V1: &[u8] = b"libfido2-rs hmac-secret key v1";
+
+/// The salt a key is derived with, including the version of the derivation.
+///
+/// # Remarks
+/// - Encodes to [`ENCODED_LEN`] bytes: the version, followed by the salt.
+///
+/// [`ENCODED_LEN`]: struct.Salt.html#associatedconstant.ENCODED_LEN
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Salt {
+    version: u8,
+    salt: [u8; SALT_LEN],
+}
+
+impl Salt {
+    /// The derivation version of newly generated salts.
+    pub const VERSION: u8 = 1;
+
+    /// Length of the encoded salt.
+    pub const ENCODED_LEN: usize = 1 + SALT_LEN;
+
+    /// Generates a random salt for the current derivation version.
+    pub fn generate() -> Result<Self> {
+        let mut salt = [0; SALT_LEN];
+        openssl::random_bytes(&mut salt)?;
+        Ok(Salt {
+            version: Self::VERSION,
+            salt,
+        })
+    }
+
+    /// Returns the derivation version the salt was generated for.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Returns the salt that is sent to the device.
+    pub fn salt(&self) -> &[u8; SALT_LEN] {
+        &self.salt
+    }
+
+    /// Encodes the salt for storage.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0; Self::ENCODED_LEN];
+        bytes[0] = self.version;
+        bytes[1..].copy_from_slice(&self.salt);
+        bytes
+    }
+
+    /// Decodes a salt encoded with [`to_bytes`].
+    ///
+    /// # Errors
+    /// - If the length is wrong, or the version is unknown to this version of the crate.
+    ///
+    /// [`to_bytes`]: struct.Salt.html#method.to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, InvalidSaltError> {
+        match bytes {
+            [Self::VERSION, salt @ ..] => Ok(Salt {
+                version: Self::VERSION,
+                salt: <[u8; SALT_LEN]>::try_from(salt).map_err(|_| InvalidSaltError)?,
+            }),
+            _ => Err(InvalidSaltError),
+        }
+    }
+}
+
+/// A key derived by a [`SecretDeriver`], which is zeroed when dropped.
+///
+/// [`SecretDeriver`]: struct.SecretDeriver.html
+pub struct SecretKey([u8; KEY_LEN]);
+
+impl SecretKey {
+    pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey(..)")
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        openssl::cleanse(&mut self.0);
+    }
+}
+
+/// Derives keys from the `hmac-secret` output of a credential.
+///
+/// The device computes HMAC-SHA-256 of the salt with a secret that never leaves it, which is
+/// expanded into the key with HKDF-SHA-256.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SecretDeriver<'a> {
+    relying_party: RpEntity<'a>,
+    credential_id: &'a [u8],
+    options: AssertionOptions,
+    context: &'a [u8],
+}
+
+impl<'a> SecretDeriver<'a> {
+    /// Derives keys of the credential `credential_id`, which was created for `relying_party`
+    /// with `CredentialExtensions::HMAC_SECRET`.
+    pub fn new(relying_party: RpEntity<'a>, credential_id: &'a [u8]) -> Self {
+        SecretDeriver {
+            relying_party,
+            credential_id,
+            options: AssertionOptions::empty(),
+            context: &[],
+        }
+    }
+
+    /// Sets the options of the assertion, e.g. `AssertionOptions::USER_VERIFICATION` to require
+    /// the PIN. Devices return a different secret with and without user verification.
+    pub fn with_options(mut self, options: AssertionOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets an application specific context, so independent keys can be derived from one
+    /// credential and salt.
+    pub fn with_context(mut self, context: &'a [u8]) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Requests the `hmac-secret` output of the credential for `salt` from `device`, and derives
+    /// the key from it.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block until the user touches the device.
+    /// - The signature of the assertion is not verified, as the output is only known to the
+    ///   device.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_UNSUPPORTED_EXTENSION` if the device did not return an output, e.g. because the
+    ///   credential was created without `hmac-secret`.
+    pub fn derive(
+        &self,
+        fido: &Fido,
+        device: &mut Device,
+        salt: &Salt,
+        pin: Option<&CStr>,
+    ) -> Result<SecretKey> {
+        let request = SecretRequest {
+            relying_party: self.relying_party,
+            credential_id: self.credential_id,
+            options: self.options,
+            user_presence: None,
+        };
+        let secret = request_secret(fido, device, &request, salt.salt(), pin)?;
+        self.expand(&secret, salt)
+    }
+
+    /// Expands an `hmac-secret` output into the key, as [`derive`] does.
+    ///
+    /// [`derive`]: struct.SecretDeriver.html#method.derive
+    pub fn expand(&self, secret: &[u8], salt: &Salt) -> Result<SecretKey> {
+        let info = [INFO_V1, self.context].concat();
+        let mut key = SecretKey([0; KEY_LEN]);
+        openssl::hkdf_sha256(secret, salt.salt(), &info, &mut key.0)?;
+        Ok(key)
+    }
+}
+
+/// An assertion that only requests the `hmac-secret` output of a credential.
+pub(crate) struct SecretRequest<'a> {
+    pub(crate) relying_party: RpEntity<'a>,
+    pub(crate) credential_id: &'a [u8],
+    pub(crate) options: AssertionOptions,
+    /// Overrides the user presence option, `Some(false)` for silent requests.
+    pub(crate) user_presence: Option<bool>,
+}
+
+/// The `hmac-secret` output of a credential, which is wiped on drop.
+pub(crate) struct Secret(Vec<u8>);
+
+impl Deref for Secret {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        openssl::cleanse(&mut self.0);
+    }
+}
+
+/// Returns the `hmac-secret` output of a credential for `salt`.
+pub(crate) fn request_secret(
+    fido: &Fido,
+    device: &mut Device,
+    request: &SecretRequest<'_>,
+    salt: &[u8],
+    pin: Option<&CStr>,
+) -> Result<Secret> {
+    let mut client_data_hash = [0; 32];
+    openssl::random_bytes(&mut client_data_hash)?;
+    let allowed = [request.credential_id];
+    let mut data = AssertionCreationData::with_defaults(
+        Some(&allowed),
+        &client_data_hash,
+        request.relying_party,
+    );
+    data.options = request.options;
+
+    let mut creator = fido.new_assertion_creator(data)?;
+    creator.set_hmac_salt(salt)?;
+    match request.user_presence {
+        Some(true) => creator.set_user_presence(fido_opt_t_FIDO_OPT_TRUE)?,
+        Some(false) => creator.set_user_presence(fido_opt_t_FIDO_OPT_FALSE)?,
+        None => {}
+    }
+    let assertion = device.request_assertion_verification(creator, pin)?;
+    let unsupported = FidoError(FIDO_ERR_UNSUPPORTED_EXTENSION as raw::c_int);
+    let statement = assertion
+        .iter()
+        .find(|statement| statement.matches_allowed(&data))
+        .ok_or(unsupported)?;
+    statement
+        .hmac_secret
+        .filter(|secret| !secret.is_empty())
+        .map(|secret| Secret(secret.to_vec()))
+        .ok_or(unsupported)
+}
+
+/// The encoded salt is invalid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InvalidSaltError;
+
+impl error::Error for InvalidSaltError {}
+
+impl fmt::Display for InvalidSaltError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The salt is invalid")
+    }
+}
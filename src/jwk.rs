@@ -0,0 +1,364 @@
+//! JSON Web Keys (RFC 7517) of public keys, for backends that store them as JWKs.
+
+use crate::{
+    json::{self, Value},
+    pem,
+};
+use std::{error, fmt};
+
+/// Length of the coordinates of P-256 and of Ed25519 keys.
+const COORDINATE_LEN: usize = 32;
+
+/// Length of the RSA moduli libfido2 supports.
+const RSA_MODULUS_LEN: usize = 256;
+
+/// Length of the RSA public exponents libfido2 supports.
+const RSA_EXPONENT_LEN: usize = 3;
+
+/// Returns the contents of the DER element with `tag` and the remaining input.
+fn element(der: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = match der {
+        [t, len @ 0..=0x7f, rest @ ..] if *t == tag => (usize::from(*len), rest),
+        [t, 0x81, len, rest @ ..] if *t == tag => (usize::from(*len), rest),
+        [t, 0x82, hi, lo, rest @ ..] if *t == tag => {
+            (usize::from(*hi) << 8 | usize::from(*lo), rest)
+        }
+        _ => return None,
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
+
+/// Returns the key of a DER encoded `SubjectPublicKeyInfo`, the contents of its BIT STRING.
+fn subject_public_key(spki: &[u8]) -> Option<&[u8]> {
+    let (sequence, _) = element(spki, 0x30)?;
+    let (_algorithm, sequence) = element(sequence, 0x30)?;
+    match element(sequence, 0x03)? {
+        // No unused bits
+        ([0, key @ ..], _) => Some(key),
+        _ => None,
+    }
+}
+
+/// Strips the leading zero bytes of a DER INTEGER, which are only there for the sign.
+fn unsigned(integer: &[u8]) -> &[u8] {
+    let start = integer
+        .iter()
+        .position(|&b| b != 0)
+        .unwrap_or(integer.len());
+    &integer[start..]
+}
+
+/// Left-pads `integer` with zeros to `len` bytes, or returns `None` if it is longer.
+fn pad(integer: &[u8], len: usize) -> Option<Vec<u8>> {
+    let integer = unsigned(integer);
+    if integer.len() > len {
+        return None;
+    }
+    let mut padded = vec![0; len - integer.len()];
+    padded.extend_from_slice(integer);
+    Some(padded)
+}
+
+fn member(name: &str, value: &str) -> String {
+    format!("{}:{}", json::string(name), json::string(value))
+}
+
+/// Encodes the P-256 key of a `SubjectPublicKeyInfo` as a JWK.
+pub(crate) fn es256(spki: &[u8]) -> Option<String> {
+    match subject_public_key(spki)? {
+        // Uncompressed point
+        [4, point @ ..] if point.len() == 2 * COORDINATE_LEN => {
+            let (x, y) = point.split_at(COORDINATE_LEN);
+            Some(format!(
+                "{{{},{},{},{},{}}}",
+                member("kty", "EC"),
+                member("crv", "P-256"),
+                member("x", &pem::base64url(x)),
+                member("y", &pem::base64url(y)),
+                member("alg", "ES256")
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Encodes the RSA key of a `SubjectPublicKeyInfo` as a JWK.
+pub(crate) fn rs256(spki: &[u8]) -> Option<String> {
+    let (sequence, _) = element(subject_public_key(spki)?, 0x30)?;
+    let (n, sequence) = element(sequence, 0x02)?;
+    let (e, _) = element(sequence, 0x02)?;
+    Some(format!(
+        "{{{},{},{},{}}}",
+        member("kty", "RSA"),
+        member("n", &pem::base64url(unsigned(n))),
+        member("e", &pem::base64url(unsigned(e))),
+        member("alg", "RS256")
+    ))
+}
+
+/// Encodes the Ed25519 key of a `SubjectPublicKeyInfo` as a JWK.
+pub(crate) fn eddsa(spki: &[u8]) -> Option<String> {
+    let key = subject_public_key(spki).filter(|key| key.len() == COORDINATE_LEN)?;
+    Some(format!(
+        "{{{},{},{},{}}}",
+        member("kty", "OKP"),
+        member("crv", "Ed25519"),
+        member("x", &pem::base64url(key)),
+        member("alg", "EdDSA")
+    ))
+}
+
+/// A key decoded from a JWK, in the raw form libfido2 reads keys from.
+pub(crate) enum RawKey {
+    /// `x || y`
+    Es256(Vec<u8>),
+    /// `n || e`, padded to [`RSA_MODULUS_LEN`] and [`RSA_EXPONENT_LEN`] bytes.
+    Rs256(Vec<u8>),
+    Eddsa(Vec<u8>),
+}
+
+/// Decodes a JWK of a P-256, RSA or Ed25519 public key.
+///
+/// # Remarks
+/// - If the JWK has an `alg`, or a `use` or `key_ops` that excludes verification, it must match
+///   the key.
+/// - Duplicate members are rejected, as RFC 7517 requires, so none can hide another.
+pub(crate) fn parse(jwk: &str) -> Option<RawKey> {
+    let jwk = json::parse(jwk)?;
+    match &jwk {
+        Value::Object(members) => {
+            for (i, (name, _)) in members.iter().enumerate() {
+                if members[..i].iter().any(|(other, _)| other == name) {
+                    return None;
+                }
+            }
+        }
+        _ => return None,
+    }
+    let base64url = |name| pem::base64url_decode(jwk.get(name)?.as_str()?);
+    let alg_matches = |alg| {
+        jwk.get("alg")
+            .map_or(true, |value| value.as_str() == Some(alg))
+    };
+    let for_verification = jwk
+        .get("use")
+        .map_or(true, |value| value.as_str() == Some("sig"))
+        && jwk.get("key_ops").map_or(true, |ops| {
+            ops.as_array()
+                .is_some_and(|ops| ops.iter().any(|op| op.as_str() == Some("verify")))
+        });
+    if !for_verification {
+        return None;
+    }
+    let crv = jwk.get("crv").and_then(Value::as_str);
+    match jwk.get("kty")?.as_str()? {
+        "EC" if crv == Some("P-256") && alg_matches("ES256") => {
+            let x = base64url("x").filter(|x| x.len() == COORDINATE_LEN)?;
+            let y = base64url("y").filter(|y| y.len() == COORDINATE_LEN)?;
+            Some(RawKey::Es256([x, y].concat()))
+        }
+        "RSA" if alg_matches("RS256") => {
+            // A 2048 bit modulus has its top bit set, shorter ones are not padded
+            let n = base64url("n").filter(|n| n.len() == RSA_MODULUS_LEN && n[0] & 0x80 != 0)?;
+            let e = base64url("e").filter(|e| e.first().is_some_and(|&b| b != 0))?;
+            let e = pad(&e, RSA_EXPONENT_LEN)?;
+            Some(RawKey::Rs256([n, e].concat()))
+        }
+        "OKP" if crv == Some("Ed25519") && alg_matches("EdDSA") => {
+            let x = base64url("x").filter(|x| x.len() == COORDINATE_LEN)?;
+            Some(RawKey::Eddsa(x))
+        }
+        _ => None,
+    }
+}
+
+/// The JWK is invalid, or is not of a key type libfido2 supports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InvalidJwkError;
+
+impl error::Error for InvalidJwkError {}
+
+impl fmt::Display for InvalidJwkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The JWK is invalid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spki(algorithm: &[u8], key: &[u8]) -> Vec<u8> {
+        fn element(tag: u8, contents: &[u8]) -> Vec<u8> {
+            let mut der = vec![tag];
+            match contents.len() {
+                len @ 0..=0x7f => der.push(len as u8),
+                len @ 0x80..=0xff => der.extend_from_slice(&[0x81, len as u8]),
+                len => der.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]),
+            }
+            der.extend_from_slice(contents);
+            der
+        }
+        let bit_string = element(0x03, &[&[0][..], key].concat());
+        element(0x30, &[element(0x30, algorithm), bit_string].concat())
+    }
+
+    fn es256_spki(x: &[u8], y: &[u8]) -> Vec<u8> {
+        // id-ecPublicKey, prime256v1
+        let algorithm =
+            b"\x06\x07\x2a\x86\x48\xce\x3d\x02\x01\x06\x08\x2a\x86\x48\xce\x3d\x03\x01\x07";
+        spki(algorithm, &[&[4][..], x, y].concat())
+    }
+
+    fn rs256_spki(n: &[u8], e: &[u8]) -> Vec<u8> {
+        // rsaEncryption, NULL parameters
+        let algorithm = b"\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x01\x05\x00";
+        let integer = |i: &[u8]| {
+            let header = [0x02, 0x82, (i.len() >> 8) as u8, i.len() as u8];
+            [&header[..], i].concat()
+        };
+        let sequence = [integer(n), integer(e)].concat();
+        let len = sequence.len();
+        let key = [&[0x30, 0x82, (len >> 8) as u8, len as u8][..], &sequence].concat();
+        spki(algorithm, &key)
+    }
+
+    fn eddsa_spki(key: &[u8]) -> Vec<u8> {
+        spki(b"\x06\x03\x2b\x65\x70", key)
+    }
+
+    #[test]
+    fn round_trips_es256() {
+        let (x, y) = ([0xfc; COORDINATE_LEN], [0x03; COORDINATE_LEN]);
+        let jwk = es256(&es256_spki(&x, &y)).unwrap();
+        assert_eq!(
+            jwk,
+            format!(
+                r#"{{"kty":"EC","crv":"P-256","x":"{}","y":"{}","alg":"ES256"}}"#,
+                pem::base64url(&x),
+                pem::base64url(&y)
+            )
+        );
+        match parse(&jwk) {
+            Some(RawKey::Es256(key)) => assert_eq!(key, [x, y].concat()),
+            _ => panic!("not an ES256 key"),
+        }
+    }
+
+    #[test]
+    fn round_trips_rs256() {
+        // DER integers of a modulus with its top bit set have a leading zero
+        let mut n = vec![0];
+        n.extend_from_slice(&[0xc1; RSA_MODULUS_LEN]);
+        let jwk = rs256(&rs256_spki(&n, &[1, 0, 1])).unwrap();
+        assert!(jwk.contains(r#""e":"AQAB""#));
+        match parse(&jwk) {
+            Some(RawKey::Rs256(key)) => {
+                assert_eq!(&key[..RSA_MODULUS_LEN], &n[1..]);
+                assert_eq!(&key[RSA_MODULUS_LEN..], &[1, 0, 1]);
+            }
+            _ => panic!("not an RS256 key"),
+        }
+
+        // Short exponents are padded
+        let jwk = rs256(&rs256_spki(&n, &[3])).unwrap();
+        match parse(&jwk) {
+            Some(RawKey::Rs256(key)) => assert_eq!(&key[RSA_MODULUS_LEN..], &[0, 0, 3]),
+            _ => panic!("not an RS256 key"),
+        }
+    }
+
+    #[test]
+    fn round_trips_eddsa() {
+        let key = [0x5a; COORDINATE_LEN];
+        let jwk = eddsa(&eddsa_spki(&key)).unwrap();
+        match parse(&jwk) {
+            Some(RawKey::Eddsa(parsed)) => assert_eq!(parsed, key),
+            _ => panic!("not an EdDSA key"),
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_spki() {
+        let spkis = [
+            es256_spki(&[1; COORDINATE_LEN], &[2; COORDINATE_LEN]),
+            rs256_spki(&[0x80; RSA_MODULUS_LEN], &[1, 0, 1]),
+            eddsa_spki(&[1; COORDINATE_LEN]),
+        ];
+        for spki in &spkis {
+            for len in 0..spki.len() {
+                let spki = &spki[..len];
+                assert!(es256(spki).is_none() && rs256(spki).is_none() && eddsa(spki).is_none());
+            }
+        }
+        // A compressed point, and coordinates of the wrong size
+        let mut compressed = spki(b"", &[&[2][..], &[1; COORDINATE_LEN]].concat());
+        assert_eq!(es256(&compressed), None);
+        assert_eq!(es256(&es256_spki(&[1; COORDINATE_LEN], &[2; 33])), None);
+        assert_eq!(eddsa(&eddsa_spki(&[1; COORDINATE_LEN + 1])), None);
+        // A BIT STRING with unused bits
+        let unused = compressed.len() - COORDINATE_LEN - 2;
+        compressed[unused] = 1;
+        assert_eq!(eddsa(&compressed), None);
+        // An element longer than the input
+        assert_eq!(element(&[0x30, 0x82, 0xff, 0xff, 0], 0x30), None);
+        assert_eq!(element(&[0x30, 0x83, 0, 0, 0], 0x30), None);
+    }
+
+    #[test]
+    fn rejects_mismatched_jwks() {
+        let x = pem::base64url(&[1; COORDINATE_LEN]);
+        let okp = |members: &str| {
+            format!(r#"{{"kty":"OKP","crv":"Ed25519","x":"{}"{}}}"#, x, members)
+        };
+        assert!(parse(&okp("")).is_some());
+        assert!(parse(&okp(r#","use":"sig","key_ops":["sign","verify"]"#)).is_some());
+        for members in [
+            r#","alg":"ES256""#,
+            r#","use":"enc""#,
+            r#","key_ops":["sign"]"#,
+            r#","key_ops":"verify""#,
+            // Duplicates, which JSON parsers resolve differently
+            r#","crv":"X25519""#,
+            r#","x":"AQ""#,
+        ] {
+            assert!(parse(&okp(members)).is_none(), "{}", members);
+        }
+
+        // Coordinates one byte short or long, and not base64url
+        let short = pem::base64url(&[1; COORDINATE_LEN - 1]);
+        let long = pem::base64url(&[1; COORDINATE_LEN + 1]);
+        let padded = pem::base64(&[1; COORDINATE_LEN]);
+        for x in [&short, &long, &padded] {
+            let jwk = format!(r#"{{"kty":"OKP","crv":"Ed25519","x":"{}"}}"#, x);
+            assert!(parse(&jwk).is_none(), "{}", x);
+        }
+        assert!(parse(r#"{"kty":"EC","crv":"P-256","x":"AQ"}"#).is_none());
+        assert!(parse("").is_none());
+        assert!(parse("[]").is_none());
+    }
+
+    #[test]
+    fn rejects_unsupported_rsa_keys() {
+        let rsa = |n: &[u8], e: &[u8]| {
+            format!(
+                r#"{{"kty":"RSA","n":"{}","e":"{}"}}"#,
+                pem::base64url(n),
+                pem::base64url(e)
+            )
+        };
+        let n = [0x80; RSA_MODULUS_LEN];
+        assert!(parse(&rsa(&n, &[1, 0, 1])).is_some());
+        // Moduli of other sizes, or without their top bit set
+        assert!(parse(&rsa(&n[1..], &[1, 0, 1])).is_none());
+        assert!(parse(&rsa(&[0x80; RSA_MODULUS_LEN + 1], &[1, 0, 1])).is_none());
+        assert!(parse(&rsa(&[0x7f; RSA_MODULUS_LEN], &[1, 0, 1])).is_none());
+        // Exponents that are empty, have leading zeros or are too long
+        for e in [&[][..], &[0, 1, 0, 1], &[0, 3], &[1, 0, 0, 1]] {
+            assert!(parse(&rsa(&n, e)).is_none(), "{:?}", e);
+        }
+    }
+}
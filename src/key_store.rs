@@ -0,0 +1,43 @@
+use crate::{CredentialId, PublicKey, RegistrationRecord};
+use std::collections::HashMap;
+
+/// Public keys of credentials, looked up by credential ID when verifying an [`Assertion`].
+///
+/// Implement this for the storage of a relying party, e.g. a database table of
+/// [`RegistrationRecord`]s, and pass it to [`Assertion::verify_against`].
+///
+/// [`Assertion`]: struct.Assertion.html
+/// [`Assertion::verify_against`]: struct.Assertion.html#method.verify_against
+/// [`RegistrationRecord`]: struct.RegistrationRecord.html
+pub trait KeyStore {
+    /// Returns the public key of the credential `credential_id`, or `None` if it is unknown.
+    fn public_key(&self, credential_id: &[u8]) -> Option<PublicKey>;
+}
+
+impl<K: KeyStore + ?Sized> KeyStore for &K {
+    fn public_key(&self, credential_id: &[u8]) -> Option<PublicKey> {
+        (**self).public_key(credential_id)
+    }
+}
+
+/// Records with a public key that fails to parse are treated as unknown.
+impl KeyStore for [RegistrationRecord] {
+    fn public_key(&self, credential_id: &[u8]) -> Option<PublicKey> {
+        self.iter()
+            .find(|record| record.id.ct_eq(credential_id))
+            .and_then(|record| record.public_key().ok())
+    }
+}
+
+impl KeyStore for Vec<RegistrationRecord> {
+    fn public_key(&self, credential_id: &[u8]) -> Option<PublicKey> {
+        self.as_slice().public_key(credential_id)
+    }
+}
+
+impl KeyStore for HashMap<CredentialId, RegistrationRecord> {
+    fn public_key(&self, credential_id: &[u8]) -> Option<PublicKey> {
+        self.get(credential_id)
+            .and_then(|record| record.public_key().ok())
+    }
+}
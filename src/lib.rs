@@ -1,70 +1,453 @@
+//! A safe interface to the libfido2 C library.
+//!
+//! # Panics
+//! Operations that can fail return a `Result` instead of panicking, including lengths that don't
+//! fit the C types and failed allocations. The few accessors that return data directly, such as
+//! [`Credential::as_ref`], document when they panic and have a panic-free `try_` variant.
+//!
+//! [`Credential::as_ref`]: struct.Credential.html#method.as_ref
+
 #![allow(dead_code)]
 
+#[macro_use]
+mod names;
+
+pub mod attestation;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+pub mod hmac;
+pub mod platform;
+pub mod prelude;
+pub mod provision;
+pub mod systemd;
+
+mod aaguid;
 mod assertion;
+mod audit;
+mod auth_data;
+mod authenticator;
+mod backup;
+mod bench;
+#[cfg(target_os = "linux")]
+mod broker;
+mod cancel;
+mod cbor;
 mod cbor_info;
+mod ceremony;
+pub mod compat;
+#[cfg(feature = "config")]
+mod config;
 mod credential;
+#[cfg(feature = "credman")]
+mod credman;
 mod device;
 mod device_list;
+mod device_pool;
+mod device_registry;
+mod diagnostics;
+mod entity;
+mod extensions;
 mod ffi;
+mod json;
+#[cfg(feature = "jose")]
+mod jwk;
+mod key_store;
+mod message_size;
+mod messages;
+mod monitor;
+mod openssl;
+mod pem;
+mod pin;
+#[cfg(feature = "pin-cache")]
+mod pin_cache;
 mod public_key;
+mod registration;
+mod report;
+mod sign_count;
+mod snapshot;
+mod trace;
+#[cfg(feature = "u2f-compat")]
+mod u2f;
+mod uv;
+mod version;
 
+pub use aaguid::Aaguid;
 pub use assertion::*;
+pub use audit::{AuditOperation, AuditRecord, AuditSink};
+pub use auth_data::AuthDataFlags;
+pub use authenticator::*;
+pub use backup::*;
+pub use bench::*;
+#[cfg(target_os = "linux")]
+pub use broker::{serve_broker_request, BROKER_SOCKET_ENV};
+pub use cancel::CancellationToken;
+pub use cbor::CborViolation;
 pub use cbor_info::*;
+pub use ceremony::*;
+pub use compat::*;
+#[cfg(feature = "config")]
+pub use config::*;
 pub use credential::*;
+#[cfg(feature = "credman")]
+pub use credman::*;
 pub use device::*;
 pub use device_list::*;
+pub use device_pool::*;
+pub use device_registry::*;
+pub use diagnostics::*;
+pub use entity::*;
+pub use extensions::*;
+#[cfg(feature = "jose")]
+pub use jwk::InvalidJwkError;
+pub use key_store::*;
+pub use message_size::{CheckedRequestError, RequestTooLarge};
+pub use messages::{register_message_catalog, MessageCatalog, DEFAULT_LOCALE};
+pub use monitor::{KeepAliveStatus, Traffic};
+pub use names::InvalidNameError;
+pub use pin::*;
+#[cfg(feature = "pin-cache")]
+pub use pin_cache::*;
 pub use public_key::*;
+pub use registration::*;
+pub use report::*;
+pub use sign_count::*;
+pub use snapshot::*;
+#[cfg(feature = "u2f-compat")]
+pub use u2f::*;
+pub use uv::*;
+pub use version::*;
 
-use ffi::NonNull;
+use cancel::Canceller;
+use ffi::{allocated, to_size_t, to_usize};
 use libfido2_sys::*;
-use std::{error, ffi::CStr, fmt, os::raw, str};
-use std::convert::TryInto;
+use std::{
+    error,
+    ffi::CStr,
+    fmt,
+    os::raw,
+    str,
+    sync::{mpsc, Arc, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+use trace::{DeviceSpan, OperationSpan};
 
 const FIDO_DEBUG: raw::c_int = libfido2_sys::FIDO_DEBUG as raw::c_int;
 const FIDO_OK: raw::c_int = libfido2_sys::FIDO_OK as raw::c_int;
 
 type Result<T> = std::result::Result<T, FidoError>;
 
+// Flags libfido2 was initialized with. fido_init sets process wide state, so it is only
+// called once, by whichever thread creates the first Fido
+static INIT_FLAGS: OnceLock<raw::c_int> = OnceLock::new();
+
 /// The entry point of the library.
 /// All access to FIDO2 dongles goes through methods of this struct.
+///
+/// # Remarks
+/// - libfido2 is initialized once per process, when the first `Fido` is created. Creating more
+///   is cheap and can be done from any thread.
 pub struct Fido {
-    // Each thread must call fido_init, so Fido must be !Send !Sync
-    // Can be replaced with negative trait impl when it is stable
-    _private: *const (),
+    _private: (),
+    timeout: Option<Duration>,
+    lock_devices: bool,
+    strict_cbor: bool,
+    audit: Option<Arc<dyn AuditSink>>,
+}
+
+/// Configures the library before it is initialized, as created by [`Fido::builder`].
+///
+/// [`Fido::builder`]: struct.Fido.html#method.builder
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FidoBuilder {
+    debug: bool,
+    timeout: Option<Duration>,
+    lock_devices: bool,
+    strict_cbor: bool,
+}
+
+impl Default for FidoBuilder {
+    fn default() -> Self {
+        FidoBuilder {
+            debug: false,
+            timeout: None,
+            lock_devices: true,
+            strict_cbor: false,
+        }
+    }
+}
+
+impl FidoBuilder {
+    /// Enables debug output of libfido2 on stderr.
+    ///
+    /// # Remarks
+    /// - Only has an effect for the first [`Fido`] of the process, see [`Fido::debug`].
+    ///
+    /// [`Fido`]: struct.Fido.html
+    /// [`Fido::debug`]: struct.Fido.html#method.debug
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Sets how long devices opened through the [`Fido`] wait for the device during a request,
+    /// including waiting for the user to touch it.
+    ///
+    /// # Remarks
+    /// - By default, requests wait indefinitely.
+    /// - libfido2 blocks on HID reads instead of polling, so this is the only knob it offers.
+    ///   Shorter timeouts give up sooner, but don't change CPU usage or how fast a touch is detected.
+    /// - See [`Device::set_timeout`] to change the timeout of a single device.
+    ///
+    /// [`Fido`]: struct.Fido.html
+    /// [`Device::set_timeout`]: struct.Device.html#method.set_timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets whether monitored devices are locked while they are open, so other processes
+    /// using this crate or libfido2 can't interleave CTAPHID transactions with ours.
+    ///
+    /// # Remarks
+    /// - Enabled by default. The lock is advisory (`flock` on the hidraw node) and opening a device
+    ///   another process holds fails instead of waiting.
+    /// - Only applies to devices opened with [`Fido::new_monitored_device`]. libfido2 always locks
+    ///   the devices it opens itself on Linux, and doesn't lock them on other platforms.
+    ///
+    /// [`Fido::new_monitored_device`]: struct.Fido.html#method.new_monitored_device
+    pub fn lock_devices(mut self, lock_devices: bool) -> Self {
+        self.lock_devices = lock_devices;
+        self
+    }
+
+    /// Sets whether monitored devices reject CTAP2 responses that aren't in the canonical CBOR
+    /// encoding, see [`Device::set_strict_cbor`]. Disabled by default.
+    ///
+    /// [`Device::set_strict_cbor`]: struct.Device.html#method.set_strict_cbor
+    pub fn strict_cbor(mut self, strict_cbor: bool) -> Self {
+        self.strict_cbor = strict_cbor;
+        self
+    }
+
+    /// Initializes the FIDO2 library, unless it already was.
+    pub fn build(self) -> Fido {
+        let flags = if self.debug { FIDO_DEBUG } else { 0 };
+        INIT_FLAGS.get_or_init(|| {
+            unsafe { fido_init(flags) };
+            flags
+        });
+        Fido {
+            _private: (),
+            timeout: self.timeout,
+            lock_devices: self.lock_devices,
+            strict_cbor: self.strict_cbor,
+            audit: None,
+        }
+    }
 }
 
 impl Fido {
     /// Initializes the FIDO2 library.
     pub fn new(debug: bool) -> Self {
-        unsafe {
-            if debug {
-                fido_init(FIDO_DEBUG);
-            } else {
-                fido_init(0);
-            }
-        }
-        Fido { _private: &() }
+        FidoBuilder::default().debug(debug).build()
+    }
+
+    /// Returns whether libfido2 was initialized with debug output enabled.
+    pub fn debug(&self) -> bool {
+        INIT_FLAGS.get().is_some_and(|flags| flags & FIDO_DEBUG != 0)
+    }
+
+    /// Sets the sink which records the operations of every device opened afterwards, e.g. to
+    /// keep an audit trail. See [`AuditSink`].
+    ///
+    /// [`AuditSink`]: trait.AuditSink.html
+    pub fn with_audit_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.audit = Some(sink);
+        self
+    }
+
+    /// Creates a [`FidoBuilder`] to configure the library.
+    ///
+    /// [`FidoBuilder`]: struct.FidoBuilder.html
+    pub fn builder() -> FidoBuilder {
+        FidoBuilder::default()
     }
 
     /// Opens a new [`Device`] located at [`path`].
     ///
+    /// # Remarks
+    /// - On Linux, devices the process can't open, e.g. in a Flatpak sandbox, are opened through
+    ///   the broker in [`BROKER_SOCKET_ENV`] if it is set, and are then monitored like with
+    ///   [`new_monitored_device`].
+    ///
     /// [`Device`]: struct.Device.html
     /// [`path`]: struct.DevicePath.html
+    /// [`BROKER_SOCKET_ENV`]: constant.BROKER_SOCKET_ENV.html
+    /// [`new_monitored_device`]: struct.Fido.html#method.new_monitored_device
     pub fn new_device(&self, path: DevicePath<'_>) -> Result<Device> {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(socket) = broker::broker_for(path) {
+                return self.new_device_from_fd(broker::open(&socket, path)?);
+            }
+        }
         unsafe {
             // Allocate closed device
+            let raw = fido_dev_new();
             let mut device = Device {
-                raw: NonNull::new(fido_dev_new()).unwrap(),
+                raw: allocated(raw)?,
+                monitor: None,
+                canceller: Canceller::new(raw, None),
+                audit: self.audit.clone(),
+                span: DeviceSpan::new(path.0),
             };
+            device.set_timeout(self.timeout)?;
 
             // Try to open the device
-            match fido_dev_open(device.raw.as_ptr_mut(), path.0.as_ptr()) {
-                FIDO_OK => Ok(device),
+            let span = OperationSpan::start(&device.span, &"open", None);
+            let result = match fido_dev_open(device.raw.as_ptr_mut(), path.0.as_ptr()) {
+                FIDO_OK => Ok(()),
                 err => Err(FidoError(err)),
-            }
+            };
+            span.finish(result);
+            result.map(|()| device)
         }
     }
 
+    /// Opens a new [`Device`] located at [`path`], watching its HID traffic for keep-alive messages.
+    ///
+    /// # Remarks
+    /// - Only available on Linux, where the device is accessed through hidraw by this crate
+    ///   instead of libfido2.
+    /// - See [`Device::set_keepalive_handler`].
+    /// - Devices the process can't open are opened through a broker, like with [`new_device`].
+    ///
+    /// [`Device`]: struct.Device.html
+    /// [`path`]: struct.DevicePath.html
+    /// [`Device::set_keepalive_handler`]: struct.Device.html#method.set_keepalive_handler
+    /// [`new_device`]: struct.Fido.html#method.new_device
+    #[cfg(target_os = "linux")]
+    pub fn new_monitored_device(&self, path: DevicePath<'_>) -> Result<Device> {
+        if let Some(socket) = broker::broker_for(path) {
+            return self.new_device_from_fd(broker::open(&socket, path)?);
+        }
+        let io = if self.lock_devices {
+            &monitor::hidraw::IO
+        } else {
+            &monitor::hidraw::IO_UNLOCKED
+        };
+        unsafe { self.open_monitored(io, path.0) }
+    }
+
+    /// Opens a new [`Device`] from a file descriptor of its hidraw node, for sandboxes where the
+    /// process can't open the node itself, e.g. a device passed by a Flatpak portal or a
+    /// privileged broker.
+    ///
+    /// # Remarks
+    /// - Only available on Linux. The device is monitored like with
+    ///   [`new_monitored_device`], and locked unless [`lock_devices`] is disabled.
+    /// - `fd` must be open for reading and writing. It is closed when the device is dropped,
+    ///   or right away if opening fails.
+    ///
+    /// [`Device`]: struct.Device.html
+    /// [`new_monitored_device`]: struct.Fido.html#method.new_monitored_device
+    /// [`lock_devices`]: struct.Fido.html#method.lock_devices
+    #[cfg(target_os = "linux")]
+    pub fn new_device_from_fd(&self, fd: std::os::fd::OwnedFd) -> Result<Device> {
+        use std::os::fd::AsRawFd;
+
+        let io = if self.lock_devices {
+            &monitor::hidraw::FD_IO
+        } else {
+            &monitor::hidraw::FD_IO_UNLOCKED
+        };
+        let path = std::ffi::CString::new(monitor::hidraw::fd_path(fd.as_raw_fd()))
+            .map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+        // The device opens a duplicate, so `fd` is closed at the end either way
+        unsafe { self.open_monitored(io, &path) }
+    }
+
+    /// Opens a device at `path` with the hidraw I/O functions `io`.
+    #[cfg(target_os = "linux")]
+    unsafe fn open_monitored(&self, io: &fido_dev_io_t, path: &CStr) -> Result<Device> {
+        // Allocate closed device
+        let raw = fido_dev_new();
+        let mut device = Device {
+            raw: allocated(raw)?,
+            monitor: None,
+            canceller: Canceller::new(raw, None),
+            audit: self.audit.clone(),
+            span: DeviceSpan::new(path),
+        };
+        device.set_timeout(self.timeout)?;
+
+        // Route I/O through our own functions, then try to open the device
+        match fido_dev_set_io_functions(device.raw.as_ptr_mut(), io) {
+            FIDO_OK => {}
+            err => return Err(FidoError(err)),
+        }
+        let span = OperationSpan::start(&device.span, &"open", None);
+        let result = match fido_dev_open(device.raw.as_ptr_mut(), path.as_ptr()) {
+            FIDO_OK => Ok(()),
+            err => Err(FidoError(err)),
+        };
+        span.finish(result);
+        result?;
+        device.monitor = monitor::hidraw::monitor_of(device.raw.as_ptr());
+        device.canceller = Canceller::new(device.raw.as_ptr_mut(), device.monitor.clone());
+        if let Some(monitor) = &device.monitor {
+            monitor.set_strict_cbor(self.strict_cbor);
+        }
+        Ok(device)
+    }
+
+    /// Opens a new [`Device`] located at [`path`], like [`new_device`], explaining failures
+    /// caused by missing permissions.
+    ///
+    /// # Remarks
+    /// - libfido2 doesn't report why opening failed, so the platform is inspected: for a missing
+    ///   udev rule on Linux, missing administrator rights on Windows, and Input Monitoring on
+    ///   macOS. On macOS, every failure to open a device is reported as a possible lack of
+    ///   permission. See [`doctor`] to check the environment up front.
+    ///
+    /// [`Device`]: struct.Device.html
+    /// [`path`]: struct.DevicePath.html
+    /// [`new_device`]: struct.Fido.html#method.new_device
+    /// [`doctor`]: fn.doctor.html
+    pub fn open_device(&self, path: DevicePath<'_>) -> std::result::Result<Device, OpenError> {
+        self.new_device(path)
+            .map_err(|error| diagnostics::classify_open_error(path, error))
+    }
+
+    /// Opens the device located at [`path`], captures a [`DeviceInfoSnapshot`] and closes it
+    /// again.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block while the device is queried, but doesn't ask for
+    ///   user presence.
+    /// - The information is only requested from FIDO2 devices.
+    ///
+    /// [`path`]: struct.DevicePath.html
+    /// [`DeviceInfoSnapshot`]: struct.DeviceInfoSnapshot.html
+    pub fn snapshot_device(&self, path: DevicePath<'_>) -> Result<DeviceInfoSnapshot> {
+        let mut device = self.new_device(path)?;
+        let mode = device.mode();
+        let info = match mode {
+            DeviceMode::Fido2 => Some(device.authenticator_info()?),
+            DeviceMode::FidoU2F => None,
+        };
+        let snapshot = DeviceInfoSnapshot {
+            path: path.into(),
+            mode,
+            ctap_hid: device.ctap_hid_info(),
+            info,
+        };
+        // Closed right away, so other applications can use it
+        device.close()?;
+        Ok(snapshot)
+    }
+
     /// Creates a new [`CredentialCreator`].
     ///
     /// # Remarks
@@ -78,7 +461,7 @@ impl Fido {
         unsafe {
             CredentialCreator::new(
                 Credential {
-                    raw: NonNull::new(fido_cred_new()).unwrap(),
+                    raw: allocated(fido_cred_new())?,
                 },
                 data,
             )
@@ -95,7 +478,7 @@ impl Fido {
         unsafe {
             AssertionCreator::new(
                 Assertion {
-                    raw: NonNull::new(fido_assert_new()).unwrap(),
+                    raw: allocated(fido_assert_new())?,
                 },
                 data,
             )
@@ -107,38 +490,345 @@ impl Fido {
     /// # Arguments
     /// - `max_length`: The maximum amount of devices to list.
     ///
+    /// # Panics
+    /// - When the devices could not be enumerated. See [`try_detect_devices`].
+    ///
     /// [`DeviceList`]: struct.DeviceList.html
+    /// [`try_detect_devices`]: struct.Fido.html#method.try_detect_devices
     pub fn detect_devices(&self, max_length: usize) -> DeviceList {
-        unsafe {
-            // Allocate empty device list
-            let mut device_list = DeviceList {
-                raw: NonNull::new(fido_dev_info_new(max_length.try_into().unwrap())).unwrap(),
-                length: max_length,
-                found: 0,
+        self.try_detect_devices(max_length)
+            .expect("Unable to detect devices")
+    }
+
+    /// Detects any connected FIDO2 devices and returns them as a [`DeviceList`].
+    ///
+    /// # Arguments
+    /// - `max_length`: The maximum amount of devices to list.
+    ///
+    /// [`DeviceList`]: struct.DeviceList.html
+    pub fn try_detect_devices(&self, max_length: usize) -> Result<DeviceList> {
+        detect_devices(max_length)
+    }
+
+    /// Detects any connected FIDO2 devices like [`try_detect_devices`], but gives up after
+    /// `timeout`, so a misbehaving HID stack can't stall the caller.
+    ///
+    /// # Arguments
+    /// - `max_length`: The maximum amount of devices to list.
+    ///
+    /// # Remarks
+    /// - Enumeration runs on another thread. After a timeout, it is left to finish in the
+    ///   background, as libfido2 can't abort it.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_TIMEOUT` if the devices were not enumerated within `timeout`.
+    ///
+    /// [`try_detect_devices`]: struct.Fido.html#method.try_detect_devices
+    pub fn try_detect_devices_with_timeout(
+        &self,
+        max_length: usize,
+        timeout: Duration,
+    ) -> Result<DeviceList> {
+        detect_devices_within(max_length, timeout)
+    }
+
+    /// Detects any connected FIDO2 devices on a blocking thread, returning a future which
+    /// resolves to the [`DeviceList`], so async services don't block their executor.
+    ///
+    /// # Arguments
+    /// - `max_length`: The maximum amount of devices to list.
+    /// - `timeout`: How long enumeration may take, see [`try_detect_devices_with_timeout`],
+    ///   or `None` to wait indefinitely
+    ///
+    /// # Remarks
+    /// - The future works with any executor. Enumeration starts right away, not when the
+    ///   future is first polled.
+    ///
+    /// [`DeviceList`]: struct.DeviceList.html
+    /// [`try_detect_devices_with_timeout`]: struct.Fido.html#method.try_detect_devices_with_timeout
+    pub fn detect_devices_async(
+        &self,
+        max_length: usize,
+        timeout: Option<Duration>,
+    ) -> DetectDevices {
+        DetectDevices::spawn(move || match timeout {
+            Some(timeout) => detect_devices_within(max_length, timeout),
+            None => detect_devices(max_length),
+        })
+    }
+
+    /// Opens every connected device into a [`DevicePool`], with one path for each physical key,
+    /// so a key with several HID interfaces is asked once. See [`DevicePool::add_unique`].
+    ///
+    /// # Arguments
+    /// - `max_length`: The maximum amount of devices to list.
+    /// - `merge_across_transports`: Whether keys of the same model over USB and NFC are taken
+    ///   for one key, see [`DevicePool::set_merge_across_transports`].
+    ///
+    /// # Remarks
+    /// - USB paths are preferred over NFC ones, as the key stays powered without being held to
+    ///   the reader.
+    /// - Devices that can't be opened are left out.
+    ///
+    /// [`DevicePool`]: struct.DevicePool.html
+    /// [`DevicePool::add_unique`]: struct.DevicePool.html#method.add_unique
+    /// [`DevicePool::set_merge_across_transports`]: struct.DevicePool.html#method.set_merge_across_transports
+    pub fn open_device_pool(
+        &self,
+        max_length: usize,
+        merge_across_transports: bool,
+    ) -> Result<DevicePool> {
+        let mut found = self.try_detect_devices(max_length)?.into_vec();
+        // Stable, so libfido2's order is kept otherwise
+        found.sort_by_key(|info| info.transports() == ["nfc"]);
+        let mut pool = DevicePool::new();
+        pool.set_merge_across_transports(merge_across_transports);
+        for info in found {
+            if let Ok(device) = self.new_device(info.path.as_path()) {
+                pool.add_unique(info.path, device);
+            }
+        }
+        Ok(pool)
+    }
+
+    /// Creates a [`DeviceRegistry`], which caches detected devices until it is refreshed.
+    ///
+    /// # Arguments
+    /// - `max_length`: The maximum amount of devices to list.
+    ///
+    /// [`DeviceRegistry`]: struct.DeviceRegistry.html
+    pub fn device_registry(&self, max_length: usize) -> DeviceRegistry<'_> {
+        DeviceRegistry::new(self, max_length)
+    }
+
+    /// Waits until the device with `id` is connected, e.g. to ask the user to reconnect it after
+    /// a reset or a transport error, and returns its new path.
+    ///
+    /// # Arguments
+    /// - `timeout`: How long to wait, or `None` to wait indefinitely
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block, polling the connected devices.
+    /// - Returns immediately if the device is still connected.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_NOTFOUND` if the device was not connected within `timeout`.
+    pub fn wait_for_device(
+        &self,
+        id: &StableDeviceId,
+        timeout: Option<Duration>,
+    ) -> Result<OwnedDeviceInformation> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(250);
+        const MAX_DEVICES: usize = 64;
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let found = self
+                .try_detect_devices(MAX_DEVICES)?
+                .into_vec()
+                .into_iter()
+                .find(|info| info.stable_id() == *id);
+            if let Some(info) = found {
+                return Ok(info);
+            }
+            let remaining = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => POLL_INTERVAL,
             };
+            if remaining.is_zero() {
+                return Err(FidoError(FIDO_ERR_NOTFOUND));
+            }
+            thread::sleep(remaining.min(POLL_INTERVAL));
+        }
+    }
+}
 
-            // Fill list with found devices
-            // This should always return FIDO_OK
-            assert_eq!(
-                fido_dev_info_manifest(
-                    device_list.raw.as_ptr_mut(),
-                    max_length.try_into().unwrap(),
-                    &mut device_list.found.try_into().unwrap() as *mut _
-                ),
-                FIDO_OK
-            );
+/// Enumerates the connected devices, for the detection methods of `Fido`.
+fn detect_devices(max_length: usize) -> Result<DeviceList> {
+    let length = to_size_t(max_length)?;
+    unsafe {
+        // Allocate empty device list
+        let mut device_list = DeviceList {
+            raw: allocated(fido_dev_info_new(length))?,
+            length,
+            found: 0,
+        };
 
-            device_list
+        // Fill list with found devices
+        let mut found: size_t = 0;
+        match fido_dev_info_manifest(device_list.raw.as_ptr_mut(), length, &mut found as *mut _) {
+            FIDO_OK => {
+                device_list.found = to_usize(found)?;
+                Ok(device_list)
+            }
+            err => Err(FidoError(err)),
         }
     }
 }
 
+/// Enumerates the connected devices on another thread, giving up after `timeout`.
+fn detect_devices_within(max_length: usize, timeout: Duration) -> Result<DeviceList> {
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name("libfido2-detect".to_owned())
+        .spawn(move || {
+            let _ = sender.send(detect_devices(max_length));
+        })
+        .map_err(|_| FidoError(FIDO_ERR_INTERNAL))?;
+    receiver
+        .recv_timeout(timeout)
+        .unwrap_or(Err(FidoError(FIDO_ERR_TIMEOUT as raw::c_int)))
+}
+
 /// Contains a FIDO2 error.
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct FidoError(raw::c_int);
 
+/// Where a [`FidoError`] originated, so transport failures can be told apart from rejections.
+///
+/// [`FidoError`]: struct.FidoError.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Communicating with the device failed, e.g. because of a timeout, an invalid channel or
+    /// a malformed response. Retrying the request may succeed.
+    Transport,
+    /// The authenticator rejected the request with a CTAP2 status code.
+    Authenticator,
+    /// libfido2 refused the request before sending it, or an operation such as verification failed locally.
+    Library,
+}
+
+/// Why a request ended before the device answered it, so callers can react to each case
+/// differently. See [`FidoError::interruption`].
+///
+/// # Remarks
+/// - `Display` shows guidance suitable for users.
+///
+/// [`FidoError::interruption`]: struct.FidoError.html#method.interruption
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Interruption {
+    /// The user did not touch the device in time (`FIDO_ERR_ACTION_TIMEOUT`,
+    /// `FIDO_ERR_USER_ACTION_TIMEOUT`). Asking again is fine.
+    UserAbsent,
+    /// The request was cancelled on the host, e.g. through `fido_dev_cancel` or because another
+    /// device answered first (`FIDO_ERR_KEEPALIVE_CANCEL`). Usually nothing to show.
+    Cancelled,
+    /// The device stopped responding in the middle of a message (`FIDO_ERR_TIMEOUT`), or was
+    /// given up on by the watchdog (`FidoError::DEVICE_GONE`). Reopening it may help.
+    DeviceUnresponsive,
+}
+
+impl Interruption {
+    /// Returns guidance for the user.
+    pub fn guidance(self) -> &'static str {
+        match self {
+            Interruption::UserAbsent => {
+                "The security key was not touched in time. Try again, and touch it when it blinks."
+            }
+            Interruption::Cancelled => "The request was cancelled.",
+            Interruption::DeviceUnresponsive => {
+                "The security key stopped responding. Unplug it, plug it back in and try again."
+            }
+        }
+    }
+}
+
+impl fmt::Display for Interruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.guidance())
+    }
+}
+
+// Outside the range of libfido2 error codes
+const DEVICE_GONE: raw::c_int = -0x100;
+
 impl FidoError {
+    /// The device stopped responding during a request and was given up on by the watchdog, see
+    /// [`Device::set_watchdog`].
+    ///
+    /// [`Device::set_watchdog`]: struct.Device.html#method.set_watchdog
+    pub const DEVICE_GONE: FidoError = FidoError(DEVICE_GONE);
+
+    /// Creates an error from a raw libfido2 error code, e.g. for an [`Authenticator`] test double.
+    ///
+    /// [`Authenticator`]: trait.Authenticator.html
+    pub fn from_code(code: raw::c_int) -> Self {
+        FidoError(code)
+    }
+
+    /// Returns the raw libfido2 error code.
+    pub fn code(self) -> raw::c_int {
+        self.0
+    }
+
+    /// Classifies the error by where it originated.
+    ///
+    /// # Remarks
+    /// - The CTAPHID status codes `FIDO_ERR_TIMEOUT`, `FIDO_ERR_CHANNEL_BUSY`,
+    ///   `FIDO_ERR_INVALID_SEQ` and `FIDO_ERR_INVALID_CHANNEL` are reported by the device, but
+    ///   describe failures of the transport, so they are classified as `Transport`. Others, e.g.
+    ///   `FIDO_ERR_INVALID_PARAMETER`, reject the request itself and are `Authenticator` errors.
+    pub fn kind(self) -> ErrorKind {
+        const TIMEOUT: raw::c_int = FIDO_ERR_TIMEOUT as raw::c_int;
+        const CHANNEL_BUSY: raw::c_int = FIDO_ERR_CHANNEL_BUSY as raw::c_int;
+        const INVALID_SEQ: raw::c_int = FIDO_ERR_INVALID_SEQ as raw::c_int;
+        const INVALID_CHANNEL: raw::c_int = FIDO_ERR_INVALID_CHANNEL as raw::c_int;
+
+        match self.0 {
+            FIDO_ERR_TX | FIDO_ERR_RX | FIDO_ERR_RX_NOT_CBOR | FIDO_ERR_RX_INVALID_CBOR => ErrorKind::Transport,
+            TIMEOUT | CHANNEL_BUSY | INVALID_SEQ | INVALID_CHANNEL | DEVICE_GONE => ErrorKind::Transport,
+            code if code > 0 => ErrorKind::Authenticator,
+            _ => ErrorKind::Library,
+        }
+    }
+
+    /// Returns whether the error is a transport failure. See [`kind`].
+    ///
+    /// [`kind`]: struct.FidoError.html#method.kind
+    pub fn is_transport(self) -> bool {
+        self.kind() == ErrorKind::Transport
+    }
+
+    /// Returns why the request was interrupted, or `None` if the error is not an interruption.
+    pub fn interruption(self) -> Option<Interruption> {
+        const ACTION_TIMEOUT: raw::c_int = FIDO_ERR_ACTION_TIMEOUT as raw::c_int;
+        const USER_ACTION_TIMEOUT: raw::c_int = FIDO_ERR_USER_ACTION_TIMEOUT as raw::c_int;
+        const KEEPALIVE_CANCEL: raw::c_int = FIDO_ERR_KEEPALIVE_CANCEL as raw::c_int;
+        const TIMEOUT: raw::c_int = FIDO_ERR_TIMEOUT as raw::c_int;
+
+        match self.0 {
+            ACTION_TIMEOUT | USER_ACTION_TIMEOUT => Some(Interruption::UserAbsent),
+            KEEPALIVE_CANCEL => Some(Interruption::Cancelled),
+            TIMEOUT | DEVICE_GONE => Some(Interruption::DeviceUnresponsive),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the device stopped responding, see [`DEVICE_GONE`].
+    ///
+    /// [`DEVICE_GONE`]: struct.FidoError.html#associatedconstant.DEVICE_GONE
+    pub fn is_device_gone(self) -> bool {
+        self == FidoError::DEVICE_GONE
+    }
+
+    /// Returns a message explaining the error to users, in the language of `locale`.
+    ///
+    /// # Arguments
+    /// - `locale`: A BCP 47 tag, e.g. `de-CH`, or a POSIX locale, e.g. `de_CH.UTF-8`
+    ///
+    /// # Remarks
+    /// - Looks up the catalog of the locale, then of its language, then the built-in English
+    ///   messages. See [`register_message_catalog`].
+    ///
+    /// [`register_message_catalog`]: fn.register_message_catalog.html
+    pub fn user_message(self, locale: &str) -> String {
+        messages::user_message(self, locale)
+    }
+
     pub(crate) fn as_str(self) -> &'static str {
+        if self.is_device_gone() {
+            return "FIDO_ERR_DEVICE_GONE";
+        }
         unsafe {
             let error_str = fido_strerr(self.0);
             assert!(!error_str.is_null());
@@ -162,3 +852,33 @@ impl fmt::Display for FidoError {
         f.write_str(self.as_str())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_kinds() {
+        let cases = [
+            (FIDO_ERR_TX, ErrorKind::Transport),
+            (FIDO_ERR_RX, ErrorKind::Transport),
+            (FIDO_ERR_RX_NOT_CBOR, ErrorKind::Transport),
+            (FIDO_ERR_RX_INVALID_CBOR, ErrorKind::Transport),
+            (FIDO_ERR_TIMEOUT as raw::c_int, ErrorKind::Transport),
+            (FIDO_ERR_CHANNEL_BUSY as raw::c_int, ErrorKind::Transport),
+            (FIDO_ERR_INVALID_SEQ as raw::c_int, ErrorKind::Transport),
+            (FIDO_ERR_INVALID_CHANNEL as raw::c_int, ErrorKind::Transport),
+            (DEVICE_GONE, ErrorKind::Transport),
+            (FIDO_ERR_INVALID_COMMAND as raw::c_int, ErrorKind::Authenticator),
+            (FIDO_ERR_INVALID_PARAMETER as raw::c_int, ErrorKind::Authenticator),
+            (FIDO_ERR_INVALID_LENGTH as raw::c_int, ErrorKind::Authenticator),
+            (FIDO_ERR_LOCK_REQUIRED as raw::c_int, ErrorKind::Authenticator),
+            (FIDO_ERR_PIN_INVALID as raw::c_int, ErrorKind::Authenticator),
+            (FIDO_ERR_INVALID_ARGUMENT, ErrorKind::Library),
+            (FIDO_ERR_INTERNAL, ErrorKind::Library),
+        ];
+        for (code, kind) in cases {
+            assert_eq!(FidoError(code).kind(), kind, "{}", code);
+        }
+    }
+}
@@ -0,0 +1,414 @@
+//! Sizes of the CTAP2 messages libfido2 sends, to check them against the `maxMsgSize` of a
+//! device before sending them.
+
+use crate::{
+    cbor::Writer, AssertionCreationData, AssertionOptions, CoseAlgorithm, CredBlobInput,
+    CredentialCreationData, CredentialExtensions, CredentialOptions, Extensions, FidoError,
+    HmacSecretInput,
+};
+use libfido2_sys::*;
+use std::{borrow::Cow, error, ffi::CStr, fmt};
+
+// The largest pinUvAuthParam, of PIN/UV auth protocol 2
+const PIN_UV_AUTH_PARAM_LEN: usize = 32;
+const PIN_UV_AUTH_PROTOCOL: u64 = 2;
+
+const PUBLIC_KEY: &str = "public-key";
+
+/// A request is larger than the device accepts. See `AuthenticatorInfo::check_credential_request`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RequestTooLarge {
+    /// The `maxMsgSize` of the device, in bytes.
+    pub limit: usize,
+    /// The size of the request, in bytes.
+    pub actual: usize,
+}
+
+impl RequestTooLarge {
+    pub(crate) fn check(limit: Option<usize>, actual: usize) -> Result<(), Self> {
+        match limit {
+            Some(limit) if actual > limit => Err(RequestTooLarge { limit, actual }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl error::Error for RequestTooLarge {}
+
+impl From<RequestTooLarge> for FidoError {
+    fn from(_: RequestTooLarge) -> Self {
+        FidoError(FIDO_ERR_REQUEST_TOO_LARGE as std::os::raw::c_int)
+    }
+}
+
+/// A request that is checked against the `maxMsgSize` of the device before it is sent failed,
+/// e.g. one of `Device::request_credential_creation_with`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CheckedRequestError {
+    /// The request is larger than the device accepts, and wasn't sent.
+    TooLarge(RequestTooLarge),
+    /// The request failed for another reason.
+    Fido(FidoError),
+}
+
+impl CheckedRequestError {
+    /// Returns the error code, which is `FIDO_ERR_REQUEST_TOO_LARGE` for `TooLarge`.
+    pub fn fido_error(&self) -> FidoError {
+        match self {
+            CheckedRequestError::TooLarge(error) => FidoError::from(*error),
+            CheckedRequestError::Fido(error) => *error,
+        }
+    }
+}
+
+impl From<RequestTooLarge> for CheckedRequestError {
+    fn from(error: RequestTooLarge) -> Self {
+        CheckedRequestError::TooLarge(error)
+    }
+}
+
+impl From<FidoError> for CheckedRequestError {
+    fn from(error: FidoError) -> Self {
+        CheckedRequestError::Fido(error)
+    }
+}
+
+impl From<CheckedRequestError> for FidoError {
+    fn from(error: CheckedRequestError) -> Self {
+        error.fido_error()
+    }
+}
+
+impl error::Error for CheckedRequestError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CheckedRequestError::TooLarge(error) => Some(error),
+            CheckedRequestError::Fido(error) => Some(error),
+        }
+    }
+}
+
+impl fmt::Display for CheckedRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckedRequestError::TooLarge(error) => fmt::Display::fmt(error, f),
+            CheckedRequestError::Fido(error) => fmt::Display::fmt(error, f),
+        }
+    }
+}
+
+/// The value of an extension in a request.
+enum ExtensionInput<'a> {
+    True,
+    Uint(u64),
+    Bytes(&'a [u8]),
+}
+
+impl fmt::Display for RequestTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The request is {} bytes, but the device only accepts {} bytes",
+            self.actual, self.limit
+        )
+    }
+}
+
+/// Returns the size of the `authenticatorMakeCredential` message for `data`, including the
+/// command byte, with a `pinUvAuthParam` if `pin` is set.
+///
+/// `extensions` replace the extensions of `data`, like `CredentialCreator::set_extensions`.
+pub(crate) fn credential_request_len(
+    data: &CredentialCreationData<'_>,
+    extensions: Option<&Extensions<'_>>,
+    pin: bool,
+) -> usize {
+    let extensions = match extensions {
+        Some(extensions) => credential_extensions(extensions),
+        None => credential_extensions(&Extensions {
+            hmac_secret: Some(HmacSecretInput::Enable)
+                .filter(|_| data.extensions.contains(CredentialExtensions::HMAC_SECRET)),
+            #[cfg(feature = "largeblob")]
+            large_blob_key: data
+                .extensions
+                .contains(CredentialExtensions::LARGE_BLOB_KEY),
+            cred_protect: data.cred_protect,
+            ..Extensions::default()
+        }),
+    };
+    let options = [
+        ("rk", data.options.contains(CredentialOptions::RESIDENT_KEY)),
+        (
+            "uv",
+            data.options.contains(CredentialOptions::USER_VERIFICATION),
+        ),
+    ];
+    let options = options
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    let excluded = !data.excluded_ids.is_empty();
+
+    let mut request = Writer::new();
+    request.map(
+        4 + usize::from(excluded)
+            + usize::from(!extensions.is_empty())
+            + usize::from(!options.is_empty())
+            + 2 * usize::from(pin),
+    );
+    request.uint(1).bytes(data.client_data_hash);
+
+    let rp = [
+        ("id", Some(data.relying_party.id())),
+        ("name", data.relying_party.name()),
+    ];
+    request.uint(2);
+    text_map(&mut request, &[], &rp);
+
+    let user = data.user;
+    let fields = [
+        ("name", Some(user.name())),
+        ("displayName", user.display_name()),
+        ("icon", user.icon()),
+    ];
+    request.uint(3);
+    text_map(&mut request, &[("id", user.id())], &fields);
+
+    let algorithm = CoseAlgorithm::from(data.credential_type).id();
+    request.uint(4).array(1).map(2);
+    request
+        .text("alg")
+        .int(algorithm)
+        .text("type")
+        .text(PUBLIC_KEY);
+
+    if excluded {
+        request.uint(5).array(1);
+        descriptor(&mut request, data.excluded_ids);
+    }
+    if !extensions.is_empty() {
+        request.uint(6).map(extensions.len());
+        for (extension, value) in extensions {
+            request.text(extension);
+            match value {
+                ExtensionInput::True => request.bool(true),
+                ExtensionInput::Uint(value) => request.uint(value),
+                ExtensionInput::Bytes(value) => request.bytes(value),
+            };
+        }
+    }
+    if !options.is_empty() {
+        request.uint(7).map(options.len());
+        for option in options {
+            request.text(option).bool(true);
+        }
+    }
+    if pin {
+        request.uint(8).bytes(&[0; PIN_UV_AUTH_PARAM_LEN]);
+        request.uint(9).uint(PIN_UV_AUTH_PROTOCOL);
+    }
+    1 + request.into_bytes().len()
+}
+
+/// Returns the registration extensions of `extensions` libfido2 sends.
+fn credential_extensions<'a>(
+    extensions: &Extensions<'a>,
+) -> Vec<(&'static str, ExtensionInput<'a>)> {
+    let mut inputs = Vec::new();
+    if let Some(CredBlobInput::Store(blob)) = extensions.cred_blob {
+        inputs.push(("credBlob", ExtensionInput::Bytes(blob)));
+    }
+    if let Some(cred_protect) = extensions.cred_protect {
+        inputs.push(("credProtect", ExtensionInput::Uint(cred_protect as u64)));
+    }
+    if let Some(HmacSecretInput::Enable) = extensions.hmac_secret {
+        inputs.push(("hmac-secret", ExtensionInput::True));
+    }
+    #[cfg(feature = "largeblob")]
+    if extensions.large_blob_key {
+        inputs.push(("largeBlobKey", ExtensionInput::True));
+    }
+    #[cfg(feature = "config")]
+    if extensions.min_pin_length {
+        inputs.push(("minPinLength", ExtensionInput::True));
+    }
+    inputs
+}
+
+/// Returns the size of the `authenticatorGetAssertion` message for `data`, including the
+/// command byte, with a `pinUvAuthParam` if `pin` is set.
+pub(crate) fn assertion_request_len(data: &AssertionCreationData<'_>, pin: bool) -> usize {
+    let options = [
+        ("up", data.options.contains(AssertionOptions::USER_PRESENCE)),
+        (
+            "uv",
+            data.options.contains(AssertionOptions::USER_VERIFICATION),
+        ),
+    ];
+    let options = options
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    let allowed = data
+        .allowed_credential_ids
+        .filter(|allowed| !allowed.is_empty());
+    let rp_id = data.app_id.unwrap_or_else(|| data.relying_party.id());
+
+    let mut request = Writer::new();
+    request.map(
+        2 + usize::from(allowed.is_some())
+            + usize::from(!options.is_empty())
+            + 2 * usize::from(pin),
+    );
+    request.uint(1).text(&text(rp_id));
+    request.uint(2).bytes(data.client_data_hash);
+    if let Some(allowed) = allowed {
+        request.uint(3).array(allowed.len());
+        for id in allowed {
+            descriptor(&mut request, id);
+        }
+    }
+    if !options.is_empty() {
+        request.uint(5).map(options.len());
+        for option in options {
+            request.text(option).bool(true);
+        }
+    }
+    if pin {
+        request.uint(6).bytes(&[0; PIN_UV_AUTH_PARAM_LEN]);
+        request.uint(7).uint(PIN_UV_AUTH_PROTOCOL);
+    }
+    1 + request.into_bytes().len()
+}
+
+/// Writes a `PublicKeyCredentialDescriptor`.
+fn descriptor(request: &mut Writer, id: &[u8]) {
+    request
+        .map(2)
+        .text("id")
+        .bytes(id)
+        .text("type")
+        .text(PUBLIC_KEY);
+}
+
+/// Writes a map of the byte string fields followed by the text fields that are set.
+fn text_map(request: &mut Writer, bytes: &[(&str, &[u8])], texts: &[(&str, Option<&CStr>)]) {
+    let texts = texts
+        .iter()
+        .filter_map(|(name, value)| Some((*name, (*value)?)))
+        .collect::<Vec<_>>();
+    request.map(bytes.len() + texts.len());
+    for (name, value) in bytes {
+        request.text(name).bytes(value);
+    }
+    for (name, value) in texts {
+        request.text(name).text(&text(value));
+    }
+}
+
+fn text(s: &CStr) -> Cow<'_, str> {
+    s.to_string_lossy()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CredProtect, RpEntity, UserEntity};
+
+    fn cstr(bytes: &[u8]) -> &CStr {
+        CStr::from_bytes_with_nul(bytes).unwrap()
+    }
+
+    fn data() -> CredentialCreationData<'static> {
+        CredentialCreationData::with_defaults(
+            &[0; 32],
+            RpEntity::new(cstr(b"example.com\0")).unwrap(),
+            UserEntity::new(b"user", cstr(b"name\0")).unwrap(),
+        )
+    }
+
+    #[test]
+    fn checked_request_errors_keep_the_sizes() {
+        let too_large = RequestTooLarge::check(Some(1024), 1025).unwrap_err();
+        assert_eq!(
+            too_large,
+            RequestTooLarge {
+                limit: 1024,
+                actual: 1025
+            }
+        );
+        let error = CheckedRequestError::from(too_large);
+        assert_eq!(error, CheckedRequestError::TooLarge(too_large));
+        assert_eq!(
+            FidoError::from(error),
+            FidoError(FIDO_ERR_REQUEST_TOO_LARGE as std::os::raw::c_int)
+        );
+        assert_eq!(RequestTooLarge::check(Some(1024), 1024), Ok(()));
+        assert_eq!(RequestTooLarge::check(None, usize::MAX), Ok(()));
+    }
+
+    #[test]
+    fn cred_blob_counts() {
+        let blob = [0; 100];
+        let with_blob = Extensions {
+            cred_blob: Some(CredBlobInput::Store(&blob)),
+            ..Extensions::default()
+        };
+        let base = credential_request_len(&data(), None, false);
+        // Key 6 with the extension map, "credBlob", and the byte string with its header
+        let expected = base + 1 + 1 + 9 + 2 + blob.len();
+        assert_eq!(
+            credential_request_len(&data(), Some(&with_blob), false),
+            expected
+        );
+    }
+
+    #[test]
+    fn extensions_replace_those_of_data() {
+        let mut data = data();
+        data.extensions = CredentialExtensions::HMAC_SECRET;
+        data.cred_protect = Some(CredProtect::Required);
+        let same = Extensions {
+            hmac_secret: Some(HmacSecretInput::Enable),
+            cred_protect: data.cred_protect,
+            ..Extensions::default()
+        };
+        let len = credential_request_len(&data, None, false);
+        assert_eq!(credential_request_len(&data, Some(&same), false), len);
+        let none = credential_request_len(&data, Some(&Extensions::default()), false);
+        assert!(none < len);
+    }
+
+    #[test]
+    fn pin_adds_auth_param() {
+        let len = credential_request_len(&data(), None, false);
+        // Keys 8 and 9, the 32 byte pinUvAuthParam and the protocol
+        assert_eq!(
+            credential_request_len(&data(), None, true),
+            len + 1 + 2 + 32 + 1 + 1
+        );
+    }
+
+    #[test]
+    fn too_large_requests_are_rejected() {
+        assert_eq!(RequestTooLarge::check(None, 10_000), Ok(()));
+        assert_eq!(RequestTooLarge::check(Some(1024), 1024), Ok(()));
+        assert_eq!(
+            RequestTooLarge::check(Some(1024), 1025),
+            Err(RequestTooLarge {
+                limit: 1024,
+                actual: 1025
+            })
+        );
+        let error = FidoError::from(RequestTooLarge {
+            limit: 1024,
+            actual: 1025,
+        });
+        assert_eq!(
+            error,
+            FidoError(FIDO_ERR_REQUEST_TOO_LARGE as std::os::raw::c_int)
+        );
+    }
+}
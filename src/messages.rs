@@ -0,0 +1,216 @@
+//! Messages for users explaining a [`FidoError`], in the language of the application.
+//!
+//! [`FidoError`]: struct.FidoError.html
+
+use crate::{ErrorKind, FidoError, DEVICE_GONE};
+use libfido2_sys::*;
+use std::{
+    collections::HashMap,
+    os::raw,
+    sync::{OnceLock, RwLock},
+};
+
+/// The locale of the built-in messages, which every other locale falls back to.
+pub const DEFAULT_LOCALE: &str = "en";
+
+const fn code(error: u32) -> raw::c_int {
+    error as raw::c_int
+}
+
+const ENGLISH: &[(raw::c_int, &str)] = &[
+    (code(FIDO_ERR_PIN_INVALID), "The PIN is wrong."),
+    (
+        code(FIDO_ERR_PIN_BLOCKED),
+        "The PIN is blocked after too many wrong attempts. Reset the security key to use it again, \
+         which deletes its credentials.",
+    ),
+    (
+        code(FIDO_ERR_PIN_AUTH_BLOCKED),
+        "The PIN was entered wrong too often. Unplug the security key and plug it back in to try \
+         again.",
+    ),
+    (code(FIDO_ERR_PIN_REQUIRED), "The security key requires its PIN."),
+    (code(FIDO_ERR_PIN_NOT_SET), "The security key has no PIN. Set one first."),
+    (
+        code(FIDO_ERR_PIN_POLICY_VIOLATION),
+        "The PIN does not meet the requirements of the security key, e.g. it is too short.",
+    ),
+    (
+        code(FIDO_ERR_UV_BLOCKED),
+        "Fingerprint verification is blocked after too many failed attempts. Use the PIN instead.",
+    ),
+    (code(FIDO_ERR_UV_INVALID), "The fingerprint was not recognized. Try again."),
+    (
+        code(FIDO_ERR_NO_CREDENTIALS),
+        "This security key is not registered. Use the security key you registered with.",
+    ),
+    (
+        code(FIDO_ERR_CREDENTIAL_EXCLUDED),
+        "This security key is already registered.",
+    ),
+    (
+        code(FIDO_ERR_ACTION_TIMEOUT),
+        "The security key was not touched in time. Try again, and touch it when it blinks.",
+    ),
+    (
+        code(FIDO_ERR_USER_ACTION_TIMEOUT),
+        "The security key was not touched in time. Try again, and touch it when it blinks.",
+    ),
+    (code(FIDO_ERR_KEEPALIVE_CANCEL), "The request was cancelled."),
+    (
+        code(FIDO_ERR_OPERATION_DENIED),
+        "The request was denied on the security key.",
+    ),
+    (code(FIDO_ERR_NOT_ALLOWED), "The security key does not allow this request."),
+    (
+        code(FIDO_ERR_KEY_STORE_FULL),
+        "The security key has no room for more credentials. Delete some to make room.",
+    ),
+    (
+        code(FIDO_ERR_UNSUPPORTED_ALGORITHM),
+        "The security key does not support the requested algorithm.",
+    ),
+    (
+        code(FIDO_ERR_UNSUPPORTED_OPTION),
+        "The security key does not support the requested option.",
+    ),
+    (
+        code(FIDO_ERR_UNSUPPORTED_EXTENSION),
+        "The security key does not support the requested extension.",
+    ),
+    (
+        code(FIDO_ERR_TIMEOUT),
+        "The security key stopped responding. Unplug it, plug it back in and try again.",
+    ),
+    (
+        DEVICE_GONE,
+        "The security key stopped responding. Unplug it, plug it back in and try again.",
+    ),
+    (FIDO_ERR_NOTFOUND, "No security key was found. Plug one in."),
+    (FIDO_ERR_INVALID_SIG, "The response of the security key could not be verified."),
+    (
+        FIDO_ERR_USER_PRESENCE_REQUIRED,
+        "Touch the security key to confirm the request.",
+    ),
+];
+
+/// Messages for errors that are not listed individually, by where they originated.
+fn english_kind(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::Transport => {
+            "Communicating with the security key failed. Unplug it, plug it back in and try again."
+        }
+        ErrorKind::Authenticator => "The security key rejected the request.",
+        ErrorKind::Library => "The request failed.",
+    }
+}
+
+/// Translations of error messages for one locale.
+///
+/// Messages are looked up by the error, then by its [`ErrorKind`]. Errors without a message fall
+/// back to the catalog of the language of the locale, and then to the built-in English messages.
+/// Messages of an `ErrorKind` are followed by the name of the error from `fido_strerr`, e.g.
+/// `(FIDO_ERR_RX)`, so they can still be told apart in support requests.
+///
+/// [`ErrorKind`]: enum.ErrorKind.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MessageCatalog {
+    errors: HashMap<raw::c_int, String>,
+    kinds: HashMap<ErrorKind, String>,
+}
+
+impl MessageCatalog {
+    /// Creates an empty catalog, which falls back to other catalogs for every error.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a catalog with the built-in English messages, to base a translation on.
+    pub fn english() -> Self {
+        let kinds = [
+            ErrorKind::Transport,
+            ErrorKind::Authenticator,
+            ErrorKind::Library,
+        ];
+        MessageCatalog {
+            errors: ENGLISH
+                .iter()
+                .map(|(code, message)| (*code, (*message).to_owned()))
+                .collect(),
+            kinds: kinds
+                .iter()
+                .map(|kind| (*kind, english_kind(*kind).to_owned()))
+                .collect(),
+        }
+    }
+
+    /// Sets the message of `error`.
+    pub fn with_message(mut self, error: FidoError, message: impl Into<String>) -> Self {
+        self.errors.insert(error.code(), message.into());
+        self
+    }
+
+    /// Sets the message of errors of `kind` without their own message.
+    pub fn with_kind_message(mut self, kind: ErrorKind, message: impl Into<String>) -> Self {
+        self.kinds.insert(kind, message.into());
+        self
+    }
+
+    /// Returns the message of `error`, without falling back to other catalogs.
+    pub fn message(&self, error: FidoError) -> Option<String> {
+        match self.errors.get(&error.code()) {
+            Some(message) => Some(message.clone()),
+            None => self
+                .kinds
+                .get(&error.kind())
+                .map(|message| format!("{} ({})", message, error.as_str())),
+        }
+    }
+}
+
+type Catalogs = RwLock<HashMap<String, MessageCatalog>>;
+
+fn catalogs() -> &'static Catalogs {
+    static CATALOGS: OnceLock<Catalogs> = OnceLock::new();
+    CATALOGS.get_or_init(Default::default)
+}
+
+/// Normalizes a BCP 47 tag or POSIX locale, e.g. `de_CH.UTF-8` to `de-ch`.
+fn normalize(locale: &str) -> String {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    locale.replace('_', "-").to_ascii_lowercase()
+}
+
+/// Registers the messages for `locale`, replacing any previous catalog of the locale.
+///
+/// # Arguments
+/// - `locale`: A language, e.g. `de`, or a language and region, e.g. `de-CH`. Case and
+///   `de_CH` style separators don't matter.
+///
+/// # Remarks
+/// - Catalogs are shared by the whole process, so libraries should leave registering them to
+///   the application.
+pub fn register_message_catalog(locale: &str, catalog: MessageCatalog) {
+    let mut catalogs = catalogs().write().unwrap_or_else(|e| e.into_inner());
+    catalogs.insert(normalize(locale), catalog);
+}
+
+/// Returns the message of `error` for `locale`, see `FidoError::user_message`.
+pub(crate) fn user_message(error: FidoError, locale: &str) -> String {
+    let locale = normalize(locale);
+    let language = locale.split('-').next().unwrap_or_default();
+    {
+        let catalogs = catalogs().read().unwrap_or_else(|e| e.into_inner());
+        let found = [locale.as_str(), language, DEFAULT_LOCALE]
+            .iter()
+            .filter_map(|locale| catalogs.get(*locale))
+            .find_map(|catalog| catalog.message(error));
+        if let Some(message) = found {
+            return message;
+        }
+    }
+    match ENGLISH.iter().find(|(code, _)| *code == error.code()) {
+        Some((_, message)) => (*message).to_owned(),
+        None => format!("{} ({})", english_kind(error.kind()), error.as_str()),
+    }
+}
@@ -0,0 +1,844 @@
+use crate::{
+    cbor::{self, CborViolation, Reader},
+    Result,
+};
+use libfido2_sys::*;
+use std::{
+    collections::hash_map::RandomState,
+    convert::TryFrom,
+    hash::{BuildHasher, Hasher},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+#[cfg(target_os = "linux")]
+use std::sync::{OnceLock, Weak};
+
+/// How often the watchdog checks a pending request, and how long waits for reports last at most
+/// while it is set.
+pub(crate) const WATCHDOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Status sent by a device through CTAPHID keep-alive messages while it processes a request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KeepAliveStatus {
+    /// The device is still processing the request.
+    Processing,
+    /// The device is waiting for the user to touch it.
+    UserPresenceNeeded,
+    /// A status not defined by CTAP2.
+    Unknown(u8),
+}
+
+impl KeepAliveStatus {
+    fn from_raw(status: u8) -> Self {
+        match status {
+            1 => KeepAliveStatus::Processing,
+            2 => KeepAliveStatus::UserPresenceNeeded,
+            other => KeepAliveStatus::Unknown(other),
+        }
+    }
+}
+
+/// Amount of CTAPHID traffic of a monitored device, counted since it was opened.
+///
+/// # Remarks
+/// - Reports are counted with their full length on the wire, including headers and padding.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Traffic {
+    pub reports_sent: u64,
+    pub reports_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Keep-alive messages received, which are also counted as reports.
+    pub keepalives: u64,
+}
+
+impl Traffic {
+    /// Returns the traffic since `earlier`, a previous snapshot of the same device.
+    pub fn since(&self, earlier: &Traffic) -> Traffic {
+        Traffic {
+            reports_sent: self.reports_sent.saturating_sub(earlier.reports_sent),
+            reports_received: self.reports_received.saturating_sub(earlier.reports_received),
+            bytes_sent: self.bytes_sent.saturating_sub(earlier.bytes_sent),
+            bytes_received: self.bytes_received.saturating_sub(earlier.bytes_received),
+            keepalives: self.keepalives.saturating_sub(earlier.keepalives),
+        }
+    }
+}
+
+pub(crate) type KeepAliveHandler = Box<dyn FnMut(KeepAliveStatus) + Send>;
+
+pub(crate) type CredentialCountHandler = Box<dyn FnMut(usize) + Send>;
+
+const FRAME_CBOR: u8 = (CTAP_FRAME_INIT | CTAP_CMD_CBOR) as u8;
+
+/// The response to a CTAP2 request, reassembled from its reports to check its encoding, or to
+/// read the `numberOfCredentials` of an assertion before libfido2 requests the other statements.
+struct CborResponse {
+    // The CTAP2 command of the request
+    command: u8,
+    // Set once the initialization frame arrived
+    len: Option<usize>,
+    data: Vec<u8>,
+}
+
+/// State shared between a `Device` and the HID handle it was opened with.
+#[derive(Default)]
+pub(crate) struct Monitor {
+    handler: Mutex<Option<KeepAliveHandler>>,
+    count_handler: Mutex<Option<CredentialCountHandler>>,
+    cbor_response: Mutex<Option<CborResponse>>,
+    strict_cbor: AtomicBool,
+    cbor_violation: Mutex<Option<CborViolation>>,
+    traffic: Mutex<Traffic>,
+    watchdog: Mutex<Option<Duration>>,
+    // Time of the last received report, only set while a watched request is pending
+    last_report: Mutex<Option<Instant>>,
+    tripped: AtomicBool,
+    // Weak, so the monitor doesn't keep the device open after libfido2 closed it
+    #[cfg(target_os = "linux")]
+    handle: OnceLock<Weak<hidraw::Handle>>,
+}
+
+impl Monitor {
+    pub(crate) fn set_handler(&self, handler: Option<KeepAliveHandler>) {
+        if let Ok(mut current) = self.handler.lock() {
+            *current = handler;
+        }
+    }
+
+    pub(crate) fn set_count_handler(&self, handler: Option<CredentialCountHandler>) {
+        if let Ok(mut current) = self.count_handler.lock() {
+            *current = handler;
+        }
+    }
+
+    pub(crate) fn set_strict_cbor(&self, strict: bool) {
+        self.strict_cbor.store(strict, Ordering::SeqCst);
+    }
+
+    /// Returns why the last response was rejected in strict mode, if it was.
+    pub(crate) fn cbor_violation(&self) -> Option<CborViolation> {
+        self.cbor_violation.lock().ok().and_then(|violation| *violation)
+    }
+
+    pub(crate) fn traffic(&self) -> Traffic {
+        self.traffic.lock().map(|traffic| *traffic).unwrap_or_default()
+    }
+
+    pub(crate) fn set_watchdog(&self, timeout: Option<Duration>) {
+        if let Ok(mut watchdog) = self.watchdog.lock() {
+            *watchdog = timeout;
+        }
+    }
+
+    pub(crate) fn watchdog(&self) -> Option<Duration> {
+        self.watchdog.lock().ok().and_then(|watchdog| *watchdog)
+    }
+
+    /// Starts watching a request.
+    pub(crate) fn arm(&self) {
+        self.tripped.store(false, Ordering::SeqCst);
+        if let Ok(mut last_report) = self.last_report.lock() {
+            *last_report = Some(Instant::now());
+        }
+    }
+
+    /// Returns how long ago the last report arrived, or the request started.
+    pub(crate) fn idle(&self) -> Duration {
+        self.last_report
+            .lock()
+            .ok()
+            .and_then(|last_report| *last_report)
+            .map(|last_report| last_report.elapsed())
+            .unwrap_or_default()
+    }
+
+    /// Gives up on the pending request, which ends waits for reports.
+    pub(crate) fn trip(&self) {
+        self.tripped.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_tripped(&self) -> bool {
+        self.tripped.load(Ordering::SeqCst)
+    }
+
+    /// Cancels the pending request from any thread, through the HID handle rather than the
+    /// `fido_dev_t` the request thread is using.
+    pub(crate) fn cancel(&self) {
+        #[cfg(target_os = "linux")]
+        if let Some(handle) = self.handle.get().and_then(Weak::upgrade) {
+            handle.cancel();
+        }
+    }
+
+    /// Stops watching a request, returning whether the watchdog tripped.
+    pub(crate) fn disarm(&self) -> bool {
+        if let Ok(mut last_report) = self.last_report.lock() {
+            *last_report = None;
+        }
+        self.tripped.swap(false, Ordering::SeqCst)
+    }
+
+    /// Counts a sent HID report.
+    fn observe_sent(&self, report: &[u8]) {
+        if let Ok(mut traffic) = self.traffic.lock() {
+            traffic.reports_sent += 1;
+            traffic.bytes_sent += report.len() as u64;
+        }
+        // Report ID (1), then the initialization frame of a CTAP2 request
+        let command = match report {
+            [_, _, _, _, _, FRAME_CBOR, _, _, command, ..] => *command,
+            _ => return,
+        };
+        let counted = command == CTAP_CBOR_ASSERT as u8
+            && self
+                .count_handler
+                .lock()
+                .is_ok_and(|handler| handler.is_some());
+        let strict = self.strict_cbor.load(Ordering::SeqCst);
+        if let Ok(mut violation) = self.cbor_violation.lock() {
+            *violation = None;
+        }
+        if counted || strict {
+            if let Ok(mut response) = self.cbor_response.lock() {
+                *response = Some(CborResponse {
+                    command,
+                    len: None,
+                    data: Vec::new(),
+                });
+            }
+        }
+    }
+
+    /// Inspects a received HID report, returning whether it is passed on to libfido2.
+    fn observe_report(&self, report: &[u8]) -> bool {
+        // Initialization frame: CID (4), CMD (1), BCNTH (1), BCNTL (1), DATA
+        const KEEPALIVE: u8 = (CTAP_FRAME_INIT | CTAP_KEEPALIVE) as u8;
+        let keepalive = report.len() > 7 && report[4] == KEEPALIVE;
+        if let Ok(mut last_report) = self.last_report.lock() {
+            if last_report.is_some() {
+                *last_report = Some(Instant::now());
+            }
+        }
+        if let Ok(mut traffic) = self.traffic.lock() {
+            traffic.reports_received += 1;
+            traffic.bytes_received += report.len() as u64;
+            traffic.keepalives += u64::from(keepalive);
+        }
+        if keepalive {
+            let status = KeepAliveStatus::from_raw(report[7]);
+            if let Ok(mut handler) = self.handler.lock() {
+                if let Some(handler) = handler.as_mut() {
+                    // We are called from C, so a panicking handler must not unwind
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| handler(status)));
+                }
+            }
+            true
+        } else {
+            self.observe_cbor_response(report)
+        }
+    }
+
+    /// Collects the reports of a pending CTAP2 response. Once it is complete, calls the count
+    /// handler for assertions, and in strict mode, rejects it if its encoding isn't canonical.
+    fn observe_cbor_response(&self, report: &[u8]) -> bool {
+        let mut pending = match self.cbor_response.lock() {
+            Ok(pending) => pending,
+            Err(_) => return true,
+        };
+        let response = match pending.as_mut() {
+            Some(response) => response,
+            None => return true,
+        };
+        match (response.len, report) {
+            (None, [_, _, _, _, FRAME_CBOR, hi, lo, data @ ..]) => {
+                let len = usize::from(u16::from_be_bytes([*hi, *lo]));
+                response.len = Some(len);
+                response.data.extend_from_slice(&data[..data.len().min(len)]);
+            }
+            // Any other message, e.g. an error, ends the request
+            (None, [_, _, _, _, cmd, ..]) if *cmd & CTAP_FRAME_INIT as u8 != 0 => {
+                *pending = None;
+                return true;
+            }
+            (Some(len), [_, _, _, _, seq, data @ ..]) if *seq & CTAP_FRAME_INIT as u8 == 0 => {
+                let missing = len - response.data.len();
+                response.data.extend_from_slice(&data[..data.len().min(missing)]);
+            }
+            _ => return true,
+        }
+        if response.len != Some(response.data.len()) {
+            return true;
+        }
+        let response = match pending.take() {
+            Some(response) => response,
+            None => return true,
+        };
+        drop(pending);
+
+        if self.strict_cbor.load(Ordering::SeqCst) {
+            // Errors and empty responses have nothing to check
+            if let [0, payload @ ..] = response.data.as_slice() {
+                if let Err(violation) = check_payload(payload) {
+                    if let Ok(mut current) = self.cbor_violation.lock() {
+                        *current = Some(violation);
+                    }
+                    return false;
+                }
+            }
+        }
+        let count = if response.command == CTAP_CBOR_ASSERT as u8 {
+            number_of_credentials(&response.data)
+        } else {
+            None
+        };
+        if let (Some(count), Ok(mut handler)) = (count, self.count_handler.lock()) {
+            if let Some(handler) = handler.as_mut() {
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| handler(count)));
+            }
+        }
+        true
+    }
+}
+
+/// Checks the encoding of the payload of a successful CTAP2 response, which may be empty.
+fn check_payload(payload: &[u8]) -> std::result::Result<(), CborViolation> {
+    if payload.is_empty() {
+        Ok(())
+    } else {
+        cbor::check_canonical(payload)
+    }
+}
+
+/// Returns the `numberOfCredentials` (0x05) of a successful `authenticatorGetAssertion`
+/// response, which devices omit for a single credential.
+fn number_of_credentials(response: &[u8]) -> Option<usize> {
+    let (status, response) = response.split_first()?;
+    if *status != 0 {
+        return None;
+    }
+    let mut reader = Reader::new(response);
+    for _ in 0..reader.map()? {
+        if reader.uint()? == 5 {
+            return reader.uint().and_then(|count| usize::try_from(count).ok());
+        }
+        reader.skip()?;
+    }
+    Some(1)
+}
+
+/// Returns a payload for a CTAPHID PING, which only has to be unpredictable enough to tell
+/// replies apart, so the randomly keyed std hasher is sufficient.
+pub(crate) fn ping_payload() -> [u8; 16] {
+    let mut payload = [0; 16];
+    for chunk in payload.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(chunk.as_ptr() as usize);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    payload
+}
+
+/// Sends a CTAPHID PING with `payload` and waits for the echo.
+///
+/// # Unsafety
+/// - The device must be open and monitored.
+#[cfg(target_os = "linux")]
+pub(crate) unsafe fn ping(device: *const fido_dev_t, payload: &[u8], timeout: Duration) -> Result<()> {
+    hidraw::ping(device, payload, timeout)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) unsafe fn ping(_: *const fido_dev_t, _: &[u8], _: Duration) -> Result<()> {
+    Err(crate::FidoError(FIDO_ERR_INVALID_ARGUMENT))
+}
+
+/// Sends a CTAP2 command, the command byte followed by its CBOR parameters, and returns the
+/// CBOR response.
+///
+/// # Unsafety
+/// - The device must be open and monitored.
+#[cfg(target_os = "linux")]
+pub(crate) unsafe fn cbor(
+    device: *const fido_dev_t,
+    command: &[u8],
+    timeout: Duration,
+) -> Result<Vec<u8>> {
+    hidraw::cbor(device, command, timeout)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) unsafe fn cbor(_: *const fido_dev_t, _: &[u8], _: Duration) -> Result<Vec<u8>> {
+    Err(crate::FidoError(FIDO_ERR_INVALID_ARGUMENT))
+}
+
+/// HID I/O through hidraw, used instead of the libfido2 implementation to observe the traffic.
+#[cfg(target_os = "linux")]
+pub(crate) mod hidraw {
+    use super::{Monitor, WATCHDOG_INTERVAL};
+    use crate::{FidoError, Result};
+    use libfido2_sys::*;
+    use std::{
+        convert::{TryFrom, TryInto},
+        ffi::{CStr, OsStr},
+        fs::{File, OpenOptions},
+        io::{Read, Write},
+        os::{
+            fd::BorrowedFd,
+            raw,
+            unix::{ffi::OsStrExt, io::AsRawFd},
+        },
+        ptr, slice,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    const REPORT_LEN: usize = CTAP_MAX_REPORT_LEN as usize;
+    const INIT_HEADER_LEN: usize = CTAP_INIT_HEADER_LEN as usize;
+    const CONT_HEADER_LEN: usize = CTAP_CONT_HEADER_LEN as usize;
+    const BROADCAST: [u8; 4] = CTAP_CID_BROADCAST.to_be_bytes();
+    const CMD_INIT: u8 = (CTAP_FRAME_INIT | CTAP_CMD_INIT) as u8;
+    const CMD_PING: u8 = (CTAP_FRAME_INIT | CTAP_CMD_PING) as u8;
+    const CMD_CANCEL: u8 = (CTAP_FRAME_INIT | CTAP_CMD_CANCEL) as u8;
+    const CMD_CBOR: u8 = super::FRAME_CBOR;
+    const CMD_KEEPALIVE: u8 = (CTAP_FRAME_INIT | CTAP_KEEPALIVE) as u8;
+
+    /// Takes an advisory lock on the device, like libfido2 does, so other processes can't
+    /// interleave transactions.
+    pub(crate) const IO: fido_dev_io_t = fido_dev_io {
+        open: Some(open_locked),
+        close: Some(close),
+        read: Some(read),
+        write: Some(write),
+    };
+
+    /// Shares the device with other processes.
+    pub(crate) const IO_UNLOCKED: fido_dev_io_t = fido_dev_io {
+        open: Some(open),
+        close: Some(close),
+        read: Some(read),
+        write: Some(write),
+    };
+
+    /// Like `IO`, but opens devices that are already open, from a path of [`fd_path`].
+    pub(crate) const FD_IO: fido_dev_io_t = fido_dev_io {
+        open: Some(open_fd_locked),
+        close: Some(close),
+        read: Some(read),
+        write: Some(write),
+    };
+
+    /// Like `IO_UNLOCKED`, but opens devices that are already open, from a path of [`fd_path`].
+    pub(crate) const FD_IO_UNLOCKED: fido_dev_io_t = fido_dev_io {
+        open: Some(open_fd),
+        close: Some(close),
+        read: Some(read),
+        write: Some(write),
+    };
+
+    const FD_PREFIX: &str = "fd:";
+
+    /// Returns the path `FD_IO` opens `fd` from, as libfido2 only passes paths to `open`.
+    pub(crate) fn fd_path(fd: raw::c_int) -> String {
+        format!("{}{}", FD_PREFIX, fd)
+    }
+
+    /// Only ever borrowed shared, as the watchdog and cancellation tokens write to it from other
+    /// threads while a request reads from it.
+    pub(crate) struct Handle {
+        file: File,
+        monitor: Arc<Monitor>,
+        // Channel allocated by the device when libfido2 initialized it
+        channel: Mutex<Option<[u8; 4]>>,
+    }
+
+    impl Handle {
+        fn channel(&self) -> Option<[u8; 4]> {
+            self.channel.lock().ok().and_then(|channel| *channel)
+        }
+
+        /// Sends CTAPHID_CANCEL on the channel libfido2 uses, which fails the pending request with
+        /// `FIDO_ERR_KEEPALIVE_CANCEL`.
+        pub(super) fn cancel(&self) {
+            if let Some(channel) = self.channel() {
+                let _ = self.send(channel, CMD_CANCEL, &[]);
+            }
+        }
+
+        /// Waits up to `ms` milliseconds, or indefinitely if negative, for a report to arrive.
+        fn wait(&self, ms: raw::c_int) -> bool {
+            if self.monitor.watchdog().is_none() {
+                return self.poll(ms);
+            }
+            // Wait in slices, so a tripped watchdog ends the wait of a device that is gone
+            let deadline = u64::try_from(ms)
+                .ok()
+                .map(|ms| Instant::now() + Duration::from_millis(ms));
+            let interval = WATCHDOG_INTERVAL.as_millis() as raw::c_int;
+            loop {
+                if self.monitor.is_tripped() {
+                    return false;
+                }
+                let slice = match deadline {
+                    Some(deadline) => {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        raw::c_int::try_from(remaining.as_millis())
+                            .unwrap_or(interval)
+                            .min(interval)
+                    }
+                    None => interval,
+                };
+                if self.poll(slice) {
+                    return true;
+                }
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return false;
+                }
+            }
+        }
+
+        fn poll(&self, ms: raw::c_int) -> bool {
+            let mut fd = PollFd {
+                fd: self.file.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            };
+            unsafe { poll(&mut fd, 1, ms) > 0 }
+        }
+
+        fn read_report(&self, report: &mut [u8], deadline: Instant) -> Result<usize> {
+            let remaining = deadline.saturating_duration_since(Instant::now()).as_millis();
+            let ms = raw::c_int::try_from(remaining).unwrap_or(raw::c_int::MAX);
+            if ms == 0 || !self.wait(ms) {
+                return Err(FidoError(FIDO_ERR_RX));
+            }
+            let n = (&self.file).read(report).map_err(|_| FidoError(FIDO_ERR_RX))?;
+            // Strict mode applies to our own requests too, like `read` fails libfido2's
+            if !self.monitor.observe_report(&report[..n]) {
+                return Err(FidoError(FIDO_ERR_RX));
+            }
+            Ok(n)
+        }
+
+        fn write_report(&self, report: &[u8]) -> Result<()> {
+            self.monitor.observe_sent(report);
+            match (&self.file).write(report) {
+                Ok(n) if n == report.len() => Ok(()),
+                _ => Err(FidoError(FIDO_ERR_TX)),
+            }
+        }
+
+        /// Sends a message split into an initialization frame and continuation frames.
+        fn send(&self, channel: [u8; 4], cmd: u8, data: &[u8]) -> Result<()> {
+            let len = u16::try_from(data.len()).map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+            let (first, rest) = data.split_at(data.len().min(REPORT_LEN - INIT_HEADER_LEN));
+
+            // Reports are prefixed with the report ID, which is always 0
+            let mut report = [0; 1 + REPORT_LEN];
+            report[1..5].copy_from_slice(&channel);
+            report[5] = cmd;
+            report[6..8].copy_from_slice(&len.to_be_bytes());
+            report[1 + INIT_HEADER_LEN..][..first.len()].copy_from_slice(first);
+            self.write_report(&report)?;
+
+            for (seq, chunk) in rest.chunks(REPORT_LEN - CONT_HEADER_LEN).enumerate() {
+                let mut report = [0; 1 + REPORT_LEN];
+                report[1..5].copy_from_slice(&channel);
+                report[5] = u8::try_from(seq)
+                    .ok()
+                    .filter(|seq| *seq < CTAP_FRAME_INIT as u8)
+                    .ok_or(FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+                report[1 + CONT_HEADER_LEN..][..chunk.len()].copy_from_slice(chunk);
+                self.write_report(&report)?;
+            }
+            Ok(())
+        }
+
+        /// Receives a message, skipping reports for other channels and keep-alives.
+        fn receive(&self, channel: [u8; 4], cmd: u8, deadline: Instant) -> Result<Vec<u8>> {
+            let mut report = [0; REPORT_LEN];
+            let (len, n) = loop {
+                let n = self.read_report(&mut report, deadline)?;
+                if n < INIT_HEADER_LEN || report[..4] != channel {
+                    continue;
+                }
+                match report[4] {
+                    CMD_KEEPALIVE => continue,
+                    c if c == cmd => break (usize::from(u16::from_be_bytes([report[5], report[6]])), n),
+                    _ => return Err(FidoError(FIDO_ERR_RX)),
+                }
+            };
+
+            let mut data = Vec::with_capacity(len);
+            data.extend(report[INIT_HEADER_LEN..n].iter().take(len));
+            let mut seq = 0;
+            while data.len() < len {
+                let n = self.read_report(&mut report, deadline)?;
+                if n < CONT_HEADER_LEN || report[..4] != channel {
+                    continue;
+                }
+                if report[4] != seq {
+                    return Err(FidoError(FIDO_ERR_RX));
+                }
+                let missing = len - data.len();
+                data.extend(report[CONT_HEADER_LEN..n].iter().take(missing));
+                seq += 1;
+            }
+            Ok(data)
+        }
+    }
+
+    #[repr(C)]
+    struct PollFd {
+        fd: raw::c_int,
+        events: raw::c_short,
+        revents: raw::c_short,
+    }
+
+    const POLLIN: raw::c_short = 1;
+    const LOCK_EX: raw::c_int = 2;
+    const LOCK_NB: raw::c_int = 4;
+
+    extern "C" {
+        fn poll(fds: *mut PollFd, nfds: raw::c_ulong, timeout: raw::c_int) -> raw::c_int;
+        fn flock(fd: raw::c_int, operation: raw::c_int) -> raw::c_int;
+    }
+
+    /// Returns the monitor of a device opened with `IO`.
+    ///
+    /// # Unsafety
+    /// - The device must be open and use `IO`.
+    pub(crate) unsafe fn monitor_of(device: *const fido_dev_t) -> Option<Arc<Monitor>> {
+        (fido_dev_io_handle(device) as *const Handle)
+            .as_ref()
+            .map(|handle| handle.monitor.clone())
+    }
+
+    /// Sends a CTAPHID PING with `payload` on the channel libfido2 uses, and waits for the echo.
+    ///
+    /// # Unsafety
+    /// - The device must be open and use `IO`.
+    pub(crate) unsafe fn ping(device: *const fido_dev_t, payload: &[u8], timeout: Duration) -> Result<()> {
+        let handle = (fido_dev_io_handle(device) as *const Handle)
+            .as_ref()
+            .ok_or(FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+        let channel = handle
+            .channel()
+            .ok_or(FidoError(FIDO_ERR_INVALID_CHANNEL as raw::c_int))?;
+        let deadline = Instant::now() + timeout;
+
+        handle.send(channel, CMD_PING, payload)?;
+        if handle.receive(channel, CMD_PING, deadline)? == payload {
+            Ok(())
+        } else {
+            Err(FidoError(FIDO_ERR_RX))
+        }
+    }
+
+    /// Sends a CTAPHID CBOR message on the channel libfido2 uses, and waits for the response.
+    ///
+    /// # Unsafety
+    /// - The device must be open and use `IO`.
+    pub(crate) unsafe fn cbor(
+    device: *const fido_dev_t,
+    command: &[u8],
+    timeout: Duration,
+) -> Result<Vec<u8>> {
+        let handle = (fido_dev_io_handle(device) as *const Handle)
+            .as_ref()
+            .ok_or(FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+        let channel = handle
+            .channel()
+            .ok_or(FidoError(FIDO_ERR_INVALID_CHANNEL as raw::c_int))?;
+        let deadline = Instant::now() + timeout;
+
+        handle.send(channel, CMD_CBOR, command)?;
+        let mut response = handle.receive(channel, CMD_CBOR, deadline)?;
+        // The CTAP2 status codes are the error codes of libfido2
+        match response.first() {
+            Some(0) => Ok(response.split_off(1)),
+            Some(&status) => Err(FidoError(raw::c_int::from(status))),
+            None => Err(FidoError(FIDO_ERR_RX)),
+        }
+    }
+
+    /// Returns the channel allocated in an INIT response: nonce (8), CID (4), ...
+    fn allocated_channel(report: &[u8]) -> Option<[u8; 4]> {
+        if report.len() >= INIT_HEADER_LEN + 12 && report[..4] == BROADCAST && report[4] == CMD_INIT {
+            report[INIT_HEADER_LEN + 8..INIT_HEADER_LEN + 12].try_into().ok()
+        } else {
+            None
+        }
+    }
+
+    unsafe extern "C" fn open(path: *const raw::c_char) -> *mut raw::c_void {
+        open_file(path, false)
+    }
+
+    unsafe extern "C" fn open_locked(path: *const raw::c_char) -> *mut raw::c_void {
+        open_file(path, true)
+    }
+
+    unsafe fn open_file(path: *const raw::c_char, lock: bool) -> *mut raw::c_void {
+        if path.is_null() {
+            return ptr::null_mut();
+        }
+        let path = OsStr::from_bytes(CStr::from_ptr(path).to_bytes());
+        match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => new_handle(file, lock),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    unsafe extern "C" fn open_fd(path: *const raw::c_char) -> *mut raw::c_void {
+        duplicate_fd(path, false)
+    }
+
+    unsafe extern "C" fn open_fd_locked(path: *const raw::c_char) -> *mut raw::c_void {
+        duplicate_fd(path, true)
+    }
+
+    /// Opens a duplicate of the file descriptor in a path of `fd_path`, so the caller keeps
+    /// owning the original.
+    unsafe fn duplicate_fd(path: *const raw::c_char, lock: bool) -> *mut raw::c_void {
+        if path.is_null() {
+            return ptr::null_mut();
+        }
+        let fd = CStr::from_ptr(path)
+            .to_str()
+            .ok()
+            .and_then(|path| path.strip_prefix(FD_PREFIX))
+            .and_then(|fd| fd.parse::<raw::c_int>().ok())
+            .filter(|&fd| fd >= 0);
+        let fd = match fd {
+            Some(fd) => BorrowedFd::borrow_raw(fd),
+            None => return ptr::null_mut(),
+        };
+        match fd.try_clone_to_owned() {
+            Ok(fd) => new_handle(File::from(fd), lock),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn new_handle(file: File, lock: bool) -> *mut raw::c_void {
+        // Fail instead of waiting if another process holds the device; the lock is released on close
+        if lock && flock(file.as_raw_fd(), LOCK_EX | LOCK_NB) != 0 {
+            return ptr::null_mut();
+        }
+        let handle = Arc::new(Handle {
+            file,
+            monitor: Arc::default(),
+            channel: Mutex::default(),
+        });
+        let _ = handle.monitor.handle.set(Arc::downgrade(&handle));
+        Arc::into_raw(handle) as *mut _
+    }
+
+    unsafe extern "C" fn close(handle: *mut raw::c_void) {
+        if !handle.is_null() {
+            drop(Arc::from_raw(handle as *const Handle));
+        }
+    }
+
+    unsafe extern "C" fn read(
+        handle: *mut raw::c_void,
+        buf: *mut raw::c_uchar,
+        len: size_t,
+        ms: raw::c_int,
+    ) -> raw::c_int {
+        let handle = match (handle as *const Handle).as_ref() {
+            Some(handle) => handle,
+            None => return -1,
+        };
+
+        if !handle.wait(ms) {
+            return -1;
+        }
+
+        let buf = match usize::try_from(len) {
+            Ok(len) => slice::from_raw_parts_mut(buf, len),
+            Err(_) => return -1,
+        };
+        match (&handle.file).read(buf) {
+            Ok(n) => {
+                // Fails the request, which libfido2 reports as FIDO_ERR_RX
+                if !handle.monitor.observe_report(&buf[..n]) {
+                    return -1;
+                }
+                if let (Some(channel), Ok(mut current)) =
+                    (allocated_channel(&buf[..n]), handle.channel.lock())
+                {
+                    *current = Some(channel);
+                }
+                raw::c_int::try_from(n).unwrap_or(-1)
+            }
+            Err(_) => -1,
+        }
+    }
+
+    unsafe extern "C" fn write(
+        handle: *mut raw::c_void,
+        buf: *const raw::c_uchar,
+        len: size_t,
+    ) -> raw::c_int {
+        let handle = match (handle as *const Handle).as_ref() {
+            Some(handle) => handle,
+            None => return -1,
+        };
+        let buf = match usize::try_from(len) {
+            Ok(len) => slice::from_raw_parts(buf, len),
+            Err(_) => return -1,
+        };
+        handle.monitor.observe_sent(buf);
+        match (&handle.file).write(buf) {
+            Ok(n) => raw::c_int::try_from(n).unwrap_or(-1),
+            Err(_) => -1,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::CborViolation;
+        use std::os::{fd::OwnedFd, unix::net::UnixStream};
+
+        const CHANNEL: [u8; 4] = [1, 2, 3, 4];
+
+        #[test]
+        fn strict_mode_rejects_responses_to_own_requests() {
+            let (ours, device) = UnixStream::pair().unwrap();
+            let handle = Handle {
+                file: File::from(OwnedFd::from(ours)),
+                monitor: Arc::default(),
+                channel: Mutex::new(Some(CHANNEL)),
+            };
+            handle.monitor.set_strict_cbor(true);
+            handle
+                .send(CHANNEL, CMD_CBOR, &[CTAP_CBOR_GETINFO as u8])
+                .unwrap();
+
+            // Success, then {2: 0, 1: 0}
+            let response = [0, 0xa2, 0x02, 0x00, 0x01, 0x00];
+            let mut report = [0; REPORT_LEN];
+            report[..4].copy_from_slice(&CHANNEL);
+            report[4] = CMD_CBOR;
+            report[5..7].copy_from_slice(&(response.len() as u16).to_be_bytes());
+            report[INIT_HEADER_LEN..][..response.len()].copy_from_slice(&response);
+            (&device).write_all(&report).unwrap();
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            assert_eq!(
+                handle.receive(CHANNEL, CMD_CBOR, deadline),
+                Err(FidoError(FIDO_ERR_RX))
+            );
+            assert_eq!(
+                handle.monitor.cbor_violation(),
+                Some(CborViolation::UnsortedKeys)
+            );
+        }
+    }
+}
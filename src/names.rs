@@ -0,0 +1,93 @@
+// Textual names of option flags and enums, e.g. `up+uv`, for use as command-line arguments
+
+use std::{error, fmt};
+
+/// Implements `Display` and `FromStr` for a bitflags type, naming flags joined by `+`.
+/// The empty set is named `none`.
+macro_rules! flag_names {
+    ($flags:ty { $($(#[$attr:meta])* $name:literal => $flag:ident),+ $(,)? }) => {
+        impl std::fmt::Display for $flags {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let mut first = true;
+                $(
+                    $(#[$attr])*
+                    if self.contains(<$flags>::$flag) {
+                        if !first {
+                            f.write_str("+")?;
+                        }
+                        f.write_str($name)?;
+                        first = false;
+                    }
+                )+
+                if first {
+                    f.write_str("none")?;
+                }
+                Ok(())
+            }
+        }
+
+        impl std::str::FromStr for $flags {
+            type Err = crate::InvalidNameError;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                let mut flags = <$flags>::empty();
+                if s.trim() == "none" {
+                    return Ok(flags);
+                }
+                for name in s.split('+').map(str::trim) {
+                    flags |= match name {
+                        $($(#[$attr])* $name => <$flags>::$flag,)+
+                        _ => return Err(crate::InvalidNameError::new(name)),
+                    };
+                }
+                Ok(flags)
+            }
+        }
+    };
+}
+
+/// Implements `Display` and `FromStr` for a fieldless enum.
+macro_rules! enum_names {
+    ($ty:ty { $($name:literal => $variant:ident),+ $(,)? }) => {
+        impl std::fmt::Display for $ty {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(match self {
+                    $(<$ty>::$variant => $name,)+
+                })
+            }
+        }
+
+        impl std::str::FromStr for $ty {
+            type Err = crate::InvalidNameError;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                match s.trim() {
+                    $($name => Ok(<$ty>::$variant),)+
+                    name => Err(crate::InvalidNameError::new(name)),
+                }
+            }
+        }
+    };
+}
+
+/// Error returned when parsing an option or enum from a name that is unknown.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidNameError {
+    pub name: String,
+}
+
+impl InvalidNameError {
+    pub(crate) fn new(name: &str) -> Self {
+        InvalidNameError {
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl error::Error for InvalidNameError {}
+
+impl fmt::Display for InvalidNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The name `{}` is invalid", self.name)
+    }
+}
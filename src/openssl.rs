@@ -0,0 +1,348 @@
+//! Functions of OpenSSL's libcrypto, which libfido2 depends on, so it is always available.
+
+use crate::{attestation::AttestationError, FidoError};
+use libfido2_sys::*;
+use std::{convert::TryFrom, ffi::CStr, os::raw, ptr};
+
+#[repr(C)]
+struct X509 {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+struct X509Store {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+struct X509StoreCtx {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+struct Stack {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+struct EvpMd {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+struct EvpMdCtx {
+    _unused: [u8; 0],
+}
+
+#[cfg_attr(windows, link(name = "libcrypto"))]
+#[cfg_attr(not(windows), link(name = "crypto"))]
+extern "C" {
+    fn d2i_X509(a: *mut *mut X509, pp: *mut *const u8, length: raw::c_long) -> *mut X509;
+    fn X509_free(a: *mut X509);
+    fn X509_STORE_new() -> *mut X509Store;
+    fn X509_STORE_free(store: *mut X509Store);
+    fn X509_STORE_add_cert(store: *mut X509Store, x: *mut X509) -> raw::c_int;
+    fn X509_STORE_CTX_new() -> *mut X509StoreCtx;
+    fn X509_STORE_CTX_free(ctx: *mut X509StoreCtx);
+    fn X509_STORE_CTX_init(
+        ctx: *mut X509StoreCtx,
+        store: *mut X509Store,
+        target: *mut X509,
+        untrusted: *mut Stack,
+    ) -> raw::c_int;
+    fn X509_STORE_CTX_get_error(ctx: *mut X509StoreCtx) -> raw::c_int;
+    fn X509_verify_cert_error_string(n: raw::c_long) -> *const raw::c_char;
+    fn X509_verify_cert(ctx: *mut X509StoreCtx) -> raw::c_int;
+    fn OPENSSL_sk_new_null() -> *mut Stack;
+    fn OPENSSL_sk_push(stack: *mut Stack, data: *const raw::c_void) -> raw::c_int;
+    fn OPENSSL_sk_free(stack: *mut Stack);
+    fn EVP_sha256() -> *const EvpMd;
+    fn HMAC(
+        evp_md: *const EvpMd,
+        key: *const raw::c_void,
+        key_len: raw::c_int,
+        d: *const u8,
+        n: size_t,
+        md: *mut u8,
+        md_len: *mut raw::c_uint,
+    ) -> *mut u8;
+    fn RAND_bytes(buf: *mut u8, num: raw::c_int) -> raw::c_int;
+    fn SHA256(d: *const u8, n: size_t, md: *mut u8) -> *mut u8;
+    fn i2d_PUBKEY(a: *const EVP_PKEY, pp: *mut *mut u8) -> raw::c_int;
+    fn EVP_PKEY_free(pkey: *mut EVP_PKEY);
+    fn EVP_MD_CTX_new() -> *mut EvpMdCtx;
+    fn EVP_MD_CTX_free(ctx: *mut EvpMdCtx);
+    fn EVP_DigestVerifyInit(
+        ctx: *mut EvpMdCtx,
+        pctx: *mut *mut raw::c_void,
+        md: *const EvpMd,
+        engine: *mut raw::c_void,
+        pkey: *mut EVP_PKEY,
+    ) -> raw::c_int;
+    fn EVP_DigestVerify(
+        ctx: *mut EvpMdCtx,
+        sig: *const u8,
+        sig_len: size_t,
+        data: *const u8,
+        data_len: size_t,
+    ) -> raw::c_int;
+}
+
+/// Length of a SHA-256 digest.
+pub(crate) const SHA256_LEN: usize = 32;
+
+/// Encodes `pkey` as a DER `SubjectPublicKeyInfo`, and frees it.
+///
+/// # Safety
+/// - `pkey` must be null, or a valid `EVP_PKEY` that is not owned by anything else.
+pub(crate) unsafe fn subject_public_key_info(pkey: *mut EVP_PKEY) -> Result<Vec<u8>, FidoError> {
+    if pkey.is_null() {
+        return Err(FidoError(FIDO_ERR_INTERNAL));
+    }
+    let mut der = Vec::new();
+    let len = i2d_PUBKEY(pkey, ptr::null_mut());
+    if len > 0 {
+        der.resize(len as usize, 0);
+        let mut out = der.as_mut_ptr();
+        if i2d_PUBKEY(pkey, &mut out) != len {
+            der.clear();
+        }
+    }
+    EVP_PKEY_free(pkey);
+    if der.is_empty() {
+        return Err(FidoError(FIDO_ERR_INTERNAL));
+    }
+    Ok(der)
+}
+
+/// Verifies `signature` over `data` with `pkey`, and frees it.
+///
+/// # Arguments
+/// - `sha256`: Whether `data` is hashed with SHA-256 first, as for ECDSA and RSA. EdDSA signs
+///   the message itself.
+///
+/// # Safety
+/// - `pkey` must be null, or a valid `EVP_PKEY` that is not owned by anything else.
+pub(crate) unsafe fn verify_signature(
+    pkey: *mut EVP_PKEY,
+    sha256: bool,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<(), FidoError> {
+    if pkey.is_null() {
+        return Err(FidoError(FIDO_ERR_INTERNAL));
+    }
+    let ctx = EVP_MD_CTX_new();
+    let md = if sha256 { EVP_sha256() } else { ptr::null() };
+    let result = if ctx.is_null()
+        || EVP_DigestVerifyInit(ctx, ptr::null_mut(), md, ptr::null_mut(), pkey) != 1
+    {
+        Err(FidoError(FIDO_ERR_INTERNAL))
+    } else {
+        let sig_len = size_t::try_from(signature.len()).map_err(|_| FidoError(FIDO_ERR_INTERNAL));
+        let data_len = size_t::try_from(data.len()).map_err(|_| FidoError(FIDO_ERR_INTERNAL));
+        match (sig_len, data_len) {
+            (Ok(sig_len), Ok(data_len)) => {
+                match EVP_DigestVerify(ctx, signature.as_ptr(), sig_len, data.as_ptr(), data_len) {
+                    1 => Ok(()),
+                    _ => Err(FidoError(FIDO_ERR_INVALID_SIG)),
+                }
+            }
+            (Err(error), _) | (_, Err(error)) => Err(error),
+        }
+    };
+    // Freeing a null context is a no-op
+    EVP_MD_CTX_free(ctx);
+    EVP_PKEY_free(pkey);
+    result
+}
+
+/// Computes the SHA-256 digest of `data`.
+pub(crate) fn sha256(data: &[u8]) -> Result<[u8; SHA256_LEN], FidoError> {
+    let len = size_t::try_from(data.len()).map_err(|_| FidoError(FIDO_ERR_INTERNAL))?;
+    let mut md = [0; SHA256_LEN];
+    if unsafe { SHA256(data.as_ptr(), len, md.as_mut_ptr()) }.is_null() {
+        return Err(FidoError(FIDO_ERR_INTERNAL));
+    }
+    Ok(md)
+}
+
+/// Computes HMAC-SHA-256 of the concatenation of `data` with `key`.
+pub(crate) fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> Result<[u8; SHA256_LEN], FidoError> {
+    let internal = FidoError(FIDO_ERR_INTERNAL);
+    let key_len = raw::c_int::try_from(key.len()).map_err(|_| internal)?;
+    let mut data = data.concat();
+    let mut md = [0; SHA256_LEN];
+    let mut md_len = 0;
+    let result = unsafe {
+        HMAC(
+            EVP_sha256(),
+            key.as_ptr() as *const _,
+            key_len,
+            data.as_ptr(),
+            size_t::try_from(data.len()).map_err(|_| internal)?,
+            md.as_mut_ptr(),
+            &mut md_len,
+        )
+    };
+    // The data may be key material, like the input of HKDF
+    cleanse(&mut data);
+    if result.is_null() || md_len as usize != SHA256_LEN {
+        return Err(internal);
+    }
+    Ok(md)
+}
+
+/// Derives `out.len()` bytes with HKDF-SHA-256 (RFC 5869).
+pub(crate) fn hkdf_sha256(
+    ikm: &[u8],
+    salt: &[u8],
+    info: &[u8],
+    out: &mut [u8],
+) -> Result<(), FidoError> {
+    if out.len() > 255 * SHA256_LEN {
+        return Err(FidoError(FIDO_ERR_INVALID_ARGUMENT));
+    }
+    let mut prk = hmac_sha256(salt, &[ikm])?;
+    let mut previous = [0; SHA256_LEN];
+    let result = (|| {
+        for (i, chunk) in out.chunks_mut(SHA256_LEN).enumerate() {
+            // The first block is computed without a previous one
+            let len = if i == 0 { 0 } else { SHA256_LEN };
+            previous = hmac_sha256(&prk, &[&previous[..len], info, &[i as u8 + 1]])?;
+            chunk.copy_from_slice(&previous[..chunk.len()]);
+        }
+        Ok(())
+    })();
+    cleanse(&mut prk);
+    cleanse(&mut previous);
+    result
+}
+
+/// Overwrites `buf` with zeros, e.g. key material before it is freed.
+pub(crate) fn cleanse(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        // Volatile, so the compiler can't remove the writes to memory that is freed anyway
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
+/// Fills `buf` from the OpenSSL CSPRNG.
+pub(crate) fn random_bytes(buf: &mut [u8]) -> Result<(), FidoError> {
+    let len = raw::c_int::try_from(buf.len()).map_err(|_| FidoError(FIDO_ERR_INVALID_ARGUMENT))?;
+    match unsafe { RAND_bytes(buf.as_mut_ptr(), len) } {
+        1 => Ok(()),
+        _ => Err(FidoError(FIDO_ERR_INTERNAL)),
+    }
+}
+
+/// Owns everything allocated while verifying, so all return paths free it.
+struct Allocations {
+    certificates: Vec<*mut X509>,
+    store: *mut X509Store,
+    untrusted: *mut Stack,
+    ctx: *mut X509StoreCtx,
+}
+
+impl Drop for Allocations {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.ctx.is_null() {
+                X509_STORE_CTX_free(self.ctx);
+            }
+            if !self.untrusted.is_null() {
+                OPENSSL_sk_free(self.untrusted);
+            }
+            if !self.store.is_null() {
+                // Takes its own references to the roots
+                X509_STORE_free(self.store);
+            }
+            for certificate in &self.certificates {
+                X509_free(*certificate);
+            }
+        }
+    }
+}
+
+unsafe fn parse(der: &[u8], allocations: &mut Allocations) -> Result<*mut X509, AttestationError> {
+    let length = raw::c_long::try_from(der.len()).map_err(|_| AttestationError::InvalidCertificate)?;
+    let mut data = der.as_ptr();
+    let certificate = d2i_X509(ptr::null_mut(), &mut data, length);
+    if certificate.is_null() {
+        return Err(AttestationError::InvalidCertificate);
+    }
+    allocations.certificates.push(certificate);
+    Ok(certificate)
+}
+
+/// Verifies that `chain[0]` chains to one of `roots` through the rest of `chain`.
+pub(crate) unsafe fn verify_chain(chain: &[&[u8]], roots: &[Vec<u8>]) -> Result<(), AttestationError> {
+    let internal = || AttestationError::InvalidSignature(FidoError(FIDO_ERR_INTERNAL));
+    let mut allocations = Allocations {
+        certificates: Vec::new(),
+        store: X509_STORE_new(),
+        untrusted: OPENSSL_sk_new_null(),
+        ctx: X509_STORE_CTX_new(),
+    };
+    if allocations.store.is_null() || allocations.untrusted.is_null() || allocations.ctx.is_null() {
+        return Err(internal());
+    }
+
+    for root in roots {
+        let root = parse(root, &mut allocations)?;
+        if X509_STORE_add_cert(allocations.store, root) != 1 {
+            return Err(internal());
+        }
+    }
+    let leaf = parse(chain[0], &mut allocations)?;
+    for intermediate in &chain[1..] {
+        let intermediate = parse(intermediate, &mut allocations)?;
+        if OPENSSL_sk_push(allocations.untrusted, intermediate as *const _) == 0 {
+            return Err(internal());
+        }
+    }
+
+    if X509_STORE_CTX_init(allocations.ctx, allocations.store, leaf, allocations.untrusted) != 1 {
+        return Err(internal());
+    }
+    if X509_verify_cert(allocations.ctx) == 1 {
+        Ok(())
+    } else {
+        let error = X509_STORE_CTX_get_error(allocations.ctx);
+        let reason = X509_verify_cert_error_string(error.into());
+        let reason = if reason.is_null() {
+            format!("error {}", error)
+        } else {
+            CStr::from_ptr(reason).to_string_lossy().into_owned()
+        };
+        Err(AttestationError::UntrustedChain(reason))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hkdf_sha256_matches_rfc_5869() {
+        // Test case 1 of RFC 5869, appendix A
+        let ikm = [0x0b; 22];
+        let salt: Vec<u8> = (0x00..=0x0c).collect();
+        let info: Vec<u8> = (0xf0..=0xf9).collect();
+        let mut okm = [0; 42];
+        hkdf_sha256(&ikm, &salt, &info, &mut okm).unwrap();
+        let expected = "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf\
+                        34007208d5b887185865";
+        let hex: String = okm.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(hex, expected);
+    }
+
+    #[test]
+    fn hkdf_sha256_rejects_long_outputs() {
+        let mut okm = [0; 255 * SHA256_LEN + 1];
+        let err = hkdf_sha256(b"ikm", b"salt", b"info", &mut okm).unwrap_err();
+        assert_eq!(err, FidoError(FIDO_ERR_INVALID_ARGUMENT));
+    }
+
+    #[test]
+    fn cleanse_zeroes_the_buffer() {
+        let mut buf = [0xa5; 16];
+        cleanse(&mut buf);
+        assert_eq!(buf, [0; 16]);
+    }
+}
@@ -0,0 +1,162 @@
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// RFC 7468 requires lines of exactly 64 characters, except for the last one
+const LINE_LENGTH: usize = 64;
+
+/// Encodes DER data as PEM with the given label, e.g. `CERTIFICATE`.
+pub(crate) fn encode(label: &str, der: &[u8]) -> String {
+    let base64 = base64(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in base64.as_bytes().chunks(LINE_LENGTH) {
+        pem.extend(line.iter().map(|&b| char::from(b)));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+/// Encodes data as padded base64 (RFC 4648).
+pub(crate) fn base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes padded base64 (RFC 4648), returning `None` if it is not canonical.
+pub(crate) fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.as_bytes();
+    if encoded.len() % 4 != 0 {
+        return None;
+    }
+    let mut data = Vec::with_capacity(encoded.len() / 4 * 3);
+    for (i, chunk) in encoded.chunks(4).enumerate() {
+        let last = i == encoded.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0;
+        for &b in &chunk[..4 - padding] {
+            let value = ALPHABET.iter().position(|&a| a == b)?;
+            n = n << 6 | value as u32;
+        }
+        n <<= 6 * padding;
+        let bytes = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        // Bits that the padding drops must be zero, so every input has one encoding
+        if bytes[3 - padding..].iter().any(|&b| b != 0) {
+            return None;
+        }
+        data.extend_from_slice(&bytes[..3 - padding]);
+    }
+    Some(data)
+}
+
+/// Encodes data as unpadded base64url (RFC 4648 section 5), as used by JOSE.
+#[cfg(feature = "jose")]
+pub(crate) fn base64url(data: &[u8]) -> String {
+    base64(data)
+        .trim_end_matches('=')
+        .chars()
+        .map(|c| match c {
+            '+' => '-',
+            '/' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Decodes unpadded base64url (RFC 4648 section 5), returning `None` if it is not canonical.
+#[cfg(feature = "jose")]
+pub(crate) fn base64url_decode(encoded: &str) -> Option<Vec<u8>> {
+    if encoded.contains(['+', '/', '=']) {
+        return None;
+    }
+    let mut padded = encoded.replace('-', "+").replace('_', "/");
+    while padded.len() % 4 != 0 {
+        padded.push('=');
+    }
+    base64_decode(&padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4648 section 10
+    const VECTORS: [(&str, &str); 7] = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
+    #[test]
+    fn base64_vectors() {
+        for (data, encoded) in VECTORS {
+            assert_eq!(base64(data.as_bytes()), encoded);
+            assert_eq!(base64_decode(encoded).as_deref(), Some(data.as_bytes()));
+        }
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(base64_decode(&base64(&all)), Some(all));
+    }
+
+    #[test]
+    fn base64_decode_rejects_non_canonical() {
+        for encoded in [
+            // Truncated, or over-long by a character
+            "Zg=", "Zg", "Z", "Zm9vY", "Zm9vYmFyZ",
+            // Padding that is too long, or not at the end
+            "Z===", "====", "Zg==Zg==", "Zm=v",
+            // Bits set that the padding drops
+            "Zh==", "Zm9=",
+            // Characters outside the alphabet
+            "Zm9 ", "Zm9\n", "Zm9-", "Zm9_", "Zm9\u{e9}",
+        ] {
+            assert_eq!(base64_decode(encoded), None, "{:?}", encoded);
+        }
+    }
+
+    #[test]
+    fn pem_lines() {
+        assert_eq!(encode("EMPTY", &[]), "-----BEGIN EMPTY-----\n-----END EMPTY-----\n");
+
+        // 48 bytes fill a line exactly, one more starts another
+        for (len, lines) in [(47, 1), (48, 1), (49, 2), (96, 2), (97, 3)] {
+            let pem = encode("CERTIFICATE", &vec![0xa5; len]);
+            let body: Vec<&str> = pem.lines().skip(1).take_while(|l| !l.starts_with("-")).collect();
+            assert_eq!(body.len(), lines, "{}", len);
+            assert!(body.iter().all(|line| line.len() <= LINE_LENGTH));
+            assert!(body[..lines - 1].iter().all(|line| line.len() == LINE_LENGTH));
+            assert_eq!(base64_decode(&body.concat()), Some(vec![0xa5; len]));
+            assert!(pem.ends_with("-----END CERTIFICATE-----\n"));
+        }
+    }
+
+    #[cfg(feature = "jose")]
+    #[test]
+    fn base64url_vectors() {
+        for (data, encoded) in VECTORS {
+            let encoded = encoded.trim_end_matches('=');
+            assert_eq!(base64url(data.as_bytes()), encoded);
+            assert_eq!(base64url_decode(encoded).as_deref(), Some(data.as_bytes()));
+        }
+        assert_eq!(base64url(&[0xfb, 0xff]), "-_8");
+        assert_eq!(base64url_decode("-_8"), Some(vec![0xfb, 0xff]));
+        for encoded in ["+/8", "Zg==", "Zg=", "Z", "Zh", "Zm9vY"] {
+            assert_eq!(base64url_decode(encoded), None, "{:?}", encoded);
+        }
+    }
+}
@@ -0,0 +1,729 @@
+use crate::{AuthenticatorInfo, FidoError};
+use libfido2_sys::*;
+use std::{
+    error,
+    ffi::{CStr, CString, OsString},
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    os::raw,
+    process::{Command, Stdio},
+};
+
+/// Minimum PIN length mandated by CTAP2 for devices that don't report their own.
+pub const DEFAULT_MIN_PIN_LENGTH: usize = 4;
+
+/// Maximum length of a PIN in bytes of UTF-8, mandated by CTAP2.
+pub const MAX_PIN_BYTES: usize = 63;
+
+/// The rules [`validate_pin`] checks a new PIN against.
+///
+/// [`validate_pin`]: fn.validate_pin.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PinPolicy {
+    /// Minimum amount of Unicode code points, as a device reports in `minPINLength`.
+    pub min_length: usize,
+}
+
+impl PinPolicy {
+    /// Creates a policy with a minimum length, which is raised to the CTAP2 minimum of
+    /// [`DEFAULT_MIN_PIN_LENGTH`].
+    ///
+    /// [`DEFAULT_MIN_PIN_LENGTH`]: constant.DEFAULT_MIN_PIN_LENGTH.html
+    pub fn new(min_length: usize) -> Self {
+        PinPolicy {
+            min_length: min_length.max(DEFAULT_MIN_PIN_LENGTH),
+        }
+    }
+
+    /// Creates the policy of a device, from its `AuthenticatorInfo`.
+    pub fn for_device(info: &AuthenticatorInfo) -> Self {
+        PinPolicy::new(info.min_pin_length.unwrap_or(DEFAULT_MIN_PIN_LENGTH))
+    }
+}
+
+impl Default for PinPolicy {
+    fn default() -> Self {
+        PinPolicy::new(DEFAULT_MIN_PIN_LENGTH)
+    }
+}
+
+/// Rules of a [`PinPolicy`] a new PIN breaks.
+///
+/// [`PinPolicy`]: struct.PinPolicy.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PinViolation {
+    /// The PIN is not valid UTF-8.
+    NotUtf8,
+    /// The PIN has fewer code points than the policy requires.
+    TooShort { min_length: usize, length: usize },
+    /// The PIN is longer than [`MAX_PIN_BYTES`] bytes.
+    ///
+    /// [`MAX_PIN_BYTES`]: constant.MAX_PIN_BYTES.html
+    TooLong { max_bytes: usize, bytes: usize },
+}
+
+impl error::Error for PinViolation {}
+
+impl fmt::Display for PinViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinViolation::NotUtf8 => write!(f, "The PIN must be valid UTF-8"),
+            PinViolation::TooShort { min_length, .. } => {
+                write!(f, "The PIN must be at least {} characters long", min_length)
+            }
+            PinViolation::TooLong { max_bytes, .. } => {
+                write!(f, "The PIN must be at most {} bytes long", max_bytes)
+            }
+        }
+    }
+}
+
+/// Checks a new PIN against the CTAP2 PIN rules and `policy`, before sending it to a device.
+///
+/// # Remarks
+/// - The length is counted in Unicode code points, like devices do. A character made of
+///   several code points, e.g. an emoji with a skin tone, counts more than once.
+/// - Devices may reject PINs for other reasons, e.g. a list of common PINs.
+pub fn validate_pin(new_pin: &CStr, policy: &PinPolicy) -> Result<(), PinViolation> {
+    let pin = new_pin.to_str().map_err(|_| PinViolation::NotUtf8)?;
+    let length = pin.chars().count();
+    if length < policy.min_length {
+        return Err(PinViolation::TooShort {
+            min_length: policy.min_length,
+            length,
+        });
+    }
+    if pin.len() > MAX_PIN_BYTES {
+        return Err(PinViolation::TooLong {
+            max_bytes: MAX_PIN_BYTES,
+            bytes: pin.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Reasons why changing the PIN of a `Device` failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PinChangeError {
+    /// The new PIN is shorter than the device allows. Nothing was sent to the device.
+    TooShort { min_length: usize },
+    /// The new PIN is longer than CTAP2 allows. Nothing was sent to the device.
+    TooLong { max_bytes: usize },
+    /// The new PIN is not valid UTF-8. Nothing was sent to the device.
+    NotUtf8,
+    /// The device rejected the new PIN because of its PIN policy.
+    PolicyViolation { retries: Option<i32> },
+    /// The old PIN is wrong.
+    Invalid { retries: Option<i32> },
+    /// Too many wrong PINs were entered and the device has to be reset.
+    Blocked,
+    /// Too many wrong PINs were entered in a row and the device has to be power cycled.
+    AuthBlocked { retries: Option<i32> },
+    /// Any other error.
+    Other {
+        error: FidoError,
+        retries: Option<i32>,
+    },
+}
+
+impl PinChangeError {
+    /// Translates an error returned while changing the PIN, given the retries left afterwards.
+    pub(crate) fn from_fido_error(error: FidoError, retries: Option<i32>) -> Self {
+        const POLICY_VIOLATION: raw::c_int = FIDO_ERR_PIN_POLICY_VIOLATION as raw::c_int;
+        const PIN_INVALID: raw::c_int = FIDO_ERR_PIN_INVALID as raw::c_int;
+        const PIN_BLOCKED: raw::c_int = FIDO_ERR_PIN_BLOCKED as raw::c_int;
+        const PIN_AUTH_BLOCKED: raw::c_int = FIDO_ERR_PIN_AUTH_BLOCKED as raw::c_int;
+
+        match error.0 {
+            POLICY_VIOLATION => PinChangeError::PolicyViolation { retries },
+            PIN_INVALID => PinChangeError::Invalid { retries },
+            PIN_BLOCKED => PinChangeError::Blocked,
+            PIN_AUTH_BLOCKED => PinChangeError::AuthBlocked { retries },
+            _ => PinChangeError::Other { error, retries },
+        }
+    }
+
+    /// Returns the amount of PIN tries left, if it is known.
+    pub fn retries(&self) -> Option<i32> {
+        match *self {
+            PinChangeError::TooShort { .. }
+            | PinChangeError::TooLong { .. }
+            | PinChangeError::NotUtf8 => None,
+            PinChangeError::Blocked => Some(0),
+            PinChangeError::PolicyViolation { retries }
+            | PinChangeError::Invalid { retries }
+            | PinChangeError::AuthBlocked { retries }
+            | PinChangeError::Other { retries, .. } => retries,
+        }
+    }
+}
+
+impl From<PinViolation> for PinChangeError {
+    fn from(violation: PinViolation) -> Self {
+        match violation {
+            PinViolation::NotUtf8 => PinChangeError::NotUtf8,
+            PinViolation::TooShort { min_length, .. } => PinChangeError::TooShort { min_length },
+            PinViolation::TooLong { max_bytes, .. } => PinChangeError::TooLong { max_bytes },
+        }
+    }
+}
+
+impl error::Error for PinChangeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PinChangeError::Other { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PinChangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinChangeError::TooShort { min_length } => {
+                write!(
+                    f,
+                    "The new PIN must be at least {} characters long",
+                    min_length
+                )
+            }
+            PinChangeError::TooLong { max_bytes } => {
+                write!(f, "The new PIN must be at most {} bytes long", max_bytes)
+            }
+            PinChangeError::NotUtf8 => write!(f, "The new PIN must be valid UTF-8"),
+            PinChangeError::PolicyViolation { .. } => {
+                write!(
+                    f,
+                    "The new PIN does not satisfy the PIN policy of the device"
+                )
+            }
+            PinChangeError::Invalid { .. } => write!(f, "The current PIN is wrong"),
+            PinChangeError::Blocked => {
+                write!(f, "The PIN is blocked, the device has to be reset")
+            }
+            PinChangeError::AuthBlocked { .. } => write!(
+                f,
+                "Too many wrong PINs were entered, reinsert the device to try again"
+            ),
+            PinChangeError::Other { error, .. } => fmt::Display::fmt(error, f),
+        }?;
+        match self.retries() {
+            Some(retries) if retries > 0 => write!(f, " ({} tries left)", retries),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Reasons why `Device::prompt_pin_with` gave up asking for the PIN.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PinEntryError {
+    /// The [`PinProvider`] cancelled.
+    ///
+    /// [`PinProvider`]: trait.PinProvider.html
+    Cancelled,
+    /// Only one try is left, and the `PinProvider` didn't confirm using it. Nothing was sent to
+    /// the device.
+    LastAttemptDeclined,
+    /// Too many wrong PINs were entered and the device has to be reset.
+    Blocked,
+    /// Too many wrong PINs were entered in a row and the device has to be power cycled.
+    AuthBlocked { retries: Option<i32> },
+    /// Any other error of the request.
+    Other(FidoError),
+}
+
+impl PinEntryError {
+    /// Returns the error code of the request: `FIDO_ERR_PIN_REQUIRED` if no PIN was entered,
+    /// and `FIDO_ERR_PIN_BLOCKED` or `FIDO_ERR_PIN_AUTH_BLOCKED` if the PIN is blocked.
+    pub fn fido_error(&self) -> FidoError {
+        match self {
+            PinEntryError::Cancelled | PinEntryError::LastAttemptDeclined => {
+                FidoError(FIDO_ERR_PIN_REQUIRED as raw::c_int)
+            }
+            PinEntryError::Blocked => FidoError(FIDO_ERR_PIN_BLOCKED as raw::c_int),
+            PinEntryError::AuthBlocked { .. } => FidoError(FIDO_ERR_PIN_AUTH_BLOCKED as raw::c_int),
+            PinEntryError::Other(error) => *error,
+        }
+    }
+}
+
+impl From<PinEntryError> for FidoError {
+    fn from(error: PinEntryError) -> Self {
+        error.fido_error()
+    }
+}
+
+impl error::Error for PinEntryError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PinEntryError::Other(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PinEntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinEntryError::Cancelled => write!(f, "Entering the PIN was cancelled"),
+            PinEntryError::LastAttemptDeclined => write!(
+                f,
+                "Only one PIN try is left, a wrong PIN would block the device"
+            ),
+            PinEntryError::Blocked => {
+                write!(f, "The PIN is blocked, the device has to be reset")
+            }
+            PinEntryError::AuthBlocked { retries } => {
+                write!(
+                    f,
+                    "Too many wrong PINs were entered, reinsert the device to try again"
+                )?;
+                match retries {
+                    Some(retries) if *retries > 0 => write!(f, " ({} tries left)", retries),
+                    _ => Ok(()),
+                }
+            }
+            PinEntryError::Other(error) => fmt::Display::fmt(error, f),
+        }
+    }
+}
+
+/// Details of a PIN request, passed to a [`PinProvider`].
+///
+/// Its `Display` implementation is a prompt for the user, e.g. "Enter the PIN of the security
+/// key (8 tries left)".
+///
+/// [`PinProvider`]: trait.PinProvider.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PinPrompt {
+    /// The amount of PIN tries left, if it is known.
+    pub retries: Option<i32>,
+    /// Whether the previously provided PIN was wrong.
+    pub previous_invalid: bool,
+}
+
+impl fmt::Display for PinPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.previous_invalid {
+            write!(f, "Wrong PIN. ")?;
+        }
+        write!(f, "Enter the PIN of the security key")?;
+        match self.retries {
+            Some(retries) => write!(f, " ({} tries left)", retries),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Source of PINs, consulted by requests such as `Device::request_credential_creation_with`
+/// when the device requires a PIN.
+///
+/// # Remarks
+/// - Implemented for closures, and by [`StaticPin`], [`TtyPin`] and [`Pinentry`].
+///
+/// [`StaticPin`]: struct.StaticPin.html
+/// [`TtyPin`]: struct.TtyPin.html
+/// [`Pinentry`]: struct.Pinentry.html
+pub trait PinProvider {
+    /// Returns the PIN, or `None` to cancel the request.
+    fn pin(&mut self, prompt: &PinPrompt) -> Option<CString>;
+
+    /// Returns whether to use the last PIN try, after which a wrong PIN blocks the device until
+    /// it is reset. Asked by `Device::prompt_pin_with` before [`pin`].
+    ///
+    /// # Remarks
+    /// - Declines by default. [`TtyPin`] and [`Pinentry`] ask the user.
+    ///
+    /// [`pin`]: trait.PinProvider.html#tymethod.pin
+    /// [`TtyPin`]: struct.TtyPin.html
+    /// [`Pinentry`]: struct.Pinentry.html
+    fn confirm_last_attempt(&mut self, _prompt: &PinPrompt) -> bool {
+        false
+    }
+}
+
+impl<F> PinProvider for F
+where
+    F: FnMut(&PinPrompt) -> Option<CString>,
+{
+    fn pin(&mut self, prompt: &PinPrompt) -> Option<CString> {
+        self(prompt)
+    }
+}
+
+/// Provides a fixed PIN.
+///
+/// # Remarks
+/// - If the PIN turns out to be wrong, the request is cancelled instead of trying the same PIN
+///   again, which would only use up retries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StaticPin(pub CString);
+
+impl PinProvider for StaticPin {
+    fn pin(&mut self, prompt: &PinPrompt) -> Option<CString> {
+        if prompt.previous_invalid {
+            None
+        } else {
+            Some(self.0.clone())
+        }
+    }
+}
+
+/// Prompts for the PIN on the controlling terminal, without echoing it.
+///
+/// # Remarks
+/// - Only available on unix. Echo is turned off with `stty`.
+/// - Cancels the request when the terminal can't be opened or an empty PIN is entered.
+#[cfg(unix)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TtyPin;
+
+#[cfg(unix)]
+impl TtyPin {
+    fn open() -> io::Result<std::fs::File> {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+    }
+
+    fn confirm(prompt: &PinPrompt) -> io::Result<bool> {
+        let mut tty = Self::open()?;
+        write!(tty, "{} [y/N] ", last_attempt_warning(prompt))?;
+        tty.flush()?;
+        let mut line = String::new();
+        BufReader::new(tty).read_line(&mut line)?;
+        Ok(matches!(line.trim(), "y" | "Y" | "yes"))
+    }
+
+    fn prompt(prompt: &PinPrompt) -> io::Result<String> {
+        let mut tty = Self::open()?;
+        write!(tty, "{}: ", prompt)?;
+        tty.flush()?;
+
+        let stty = |arg: &str| -> io::Result<()> {
+            Command::new("stty")
+                .arg(arg)
+                .stdin(tty.try_clone()?)
+                .status()
+                .map(|_| ())
+        };
+        stty("-echo")?;
+        let mut line = String::new();
+        let read = BufReader::new(tty.try_clone()?).read_line(&mut line);
+        // Restore echo even if reading failed
+        let restored = stty("echo");
+        writeln!(tty)?;
+        read?;
+        restored?;
+        Ok(line.trim_end_matches(&['\r', '\n'][..]).to_owned())
+    }
+}
+
+#[cfg(unix)]
+impl PinProvider for TtyPin {
+    fn pin(&mut self, prompt: &PinPrompt) -> Option<CString> {
+        TtyPin::prompt(prompt)
+            .ok()
+            .filter(|pin| !pin.is_empty())
+            .and_then(|pin| CString::new(pin).ok())
+    }
+
+    fn confirm_last_attempt(&mut self, prompt: &PinPrompt) -> bool {
+        TtyPin::confirm(prompt).unwrap_or(false)
+    }
+}
+
+/// Asks for the PIN with a `pinentry` program, e.g. `pinentry-gnome3` or `pinentry-qt`,
+/// speaking the Assuan protocol.
+///
+/// # Remarks
+/// - By default the `pinentry` program found in `PATH` is used, which most desktops point
+///   to their own variant.
+/// - Cancels the request when the program can't be run or the user cancelled the dialog.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pinentry {
+    program: OsString,
+    title: String,
+}
+
+impl Pinentry {
+    /// Uses the `pinentry` program found in `PATH`.
+    pub fn new() -> Self {
+        Pinentry::with_program("pinentry")
+    }
+
+    /// Uses the given `pinentry` program.
+    pub fn with_program(program: impl Into<OsString>) -> Self {
+        Pinentry {
+            program: program.into(),
+            title: "Security key".to_owned(),
+        }
+    }
+
+    /// Sets the title of the dialog.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Runs the program and talks to it with `converse`.
+    fn run<T>(
+        &self,
+        converse: impl FnOnce(&mut dyn Write, &mut dyn BufRead) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut child = Command::new(&self.program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let result = converse(
+            child.stdin.as_mut().expect("piped stdin"),
+            &mut BufReader::new(child.stdout.as_mut().expect("piped stdout")),
+        );
+        drop(child.stdin.take());
+        let _ = child.wait();
+        result
+    }
+
+    fn prompt(&self, prompt: &PinPrompt) -> io::Result<Option<String>> {
+        self.run(|input, output| Self::converse(&self.title, prompt, input, output))
+    }
+
+    fn confirm(&self, prompt: &PinPrompt) -> io::Result<bool> {
+        self.run(|input, output| {
+            expect_ok(output)?;
+            writeln!(input, "SETTITLE {}", assuan_escape(&self.title))?;
+            expect_ok(output)?;
+            let description = last_attempt_warning(prompt);
+            writeln!(input, "SETDESC {}", assuan_escape(&description))?;
+            expect_ok(output)?;
+            writeln!(input, "CONFIRM")?;
+            // Declined or cancelled by the user
+            let confirmed = expect_ok(output).is_ok();
+            let _ = writeln!(input, "BYE");
+            Ok(confirmed)
+        })
+    }
+
+    fn converse(
+        title: &str,
+        prompt: &PinPrompt,
+        input: &mut dyn Write,
+        output: &mut dyn BufRead,
+    ) -> io::Result<Option<String>> {
+        expect_ok(output)?;
+        writeln!(input, "SETTITLE {}", assuan_escape(title))?;
+        expect_ok(output)?;
+        writeln!(input, "SETDESC {}", assuan_escape(&prompt.to_string()))?;
+        expect_ok(output)?;
+        writeln!(input, "SETPROMPT PIN:")?;
+        expect_ok(output)?;
+        writeln!(input, "GETPIN")?;
+
+        let mut pin = None;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if output.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            if let Some(data) = line.strip_prefix("D ") {
+                pin = Some(assuan_unescape(data));
+            } else if line.starts_with("OK") {
+                break;
+            } else if line.starts_with("ERR") {
+                // Cancelled by the user
+                return Ok(None);
+            }
+        }
+        let _ = writeln!(input, "BYE");
+        Ok(pin)
+    }
+}
+
+impl Default for Pinentry {
+    fn default() -> Self {
+        Pinentry::new()
+    }
+}
+
+impl PinProvider for Pinentry {
+    fn pin(&mut self, prompt: &PinPrompt) -> Option<CString> {
+        self.prompt(prompt)
+            .ok()
+            .flatten()
+            .filter(|pin| !pin.is_empty())
+            .and_then(|pin| CString::new(pin).ok())
+    }
+
+    fn confirm_last_attempt(&mut self, prompt: &PinPrompt) -> bool {
+        self.confirm(prompt).unwrap_or(false)
+    }
+}
+
+/// Asks whether to use the last try, see `PinProvider::confirm_last_attempt`.
+fn last_attempt_warning(prompt: &PinPrompt) -> String {
+    format!(
+        "{}Only one PIN try is left, a wrong PIN blocks the security key until it is reset. \
+         Continue?",
+        if prompt.previous_invalid {
+            "Wrong PIN. "
+        } else {
+            ""
+        }
+    )
+}
+
+/// Reads Assuan responses up to `OK`, failing on `ERR`.
+fn expect_ok(output: &mut dyn BufRead) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if output.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if line.starts_with("OK") {
+            return Ok(());
+        }
+        if line.starts_with("ERR") {
+            return Err(io::Error::other(line.trim_end().to_owned()));
+        }
+    }
+}
+
+fn assuan_escape(s: &str) -> String {
+    s.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+fn assuan_unescape(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            // `from_str_radix` also takes a sign, e.g. `%+1`
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(pin: &str, min_length: usize) -> Result<(), PinViolation> {
+        validate_pin(&CString::new(pin).unwrap(), &PinPolicy::new(min_length))
+    }
+
+    #[test]
+    fn policy_keeps_the_ctap2_minimum() {
+        assert_eq!(PinPolicy::new(0).min_length, DEFAULT_MIN_PIN_LENGTH);
+        assert_eq!(PinPolicy::default().min_length, DEFAULT_MIN_PIN_LENGTH);
+        assert_eq!(PinPolicy::new(8).min_length, 8);
+    }
+
+    #[test]
+    fn length_boundaries() {
+        assert_eq!(
+            validate("123", 0),
+            Err(PinViolation::TooShort {
+                min_length: 4,
+                length: 3
+            })
+        );
+        assert_eq!(validate("1234", 0), Ok(()));
+        assert_eq!(validate(&"1".repeat(MAX_PIN_BYTES), 0), Ok(()));
+        assert_eq!(
+            validate(&"1".repeat(MAX_PIN_BYTES + 1), 0),
+            Err(PinViolation::TooLong {
+                max_bytes: MAX_PIN_BYTES,
+                bytes: MAX_PIN_BYTES + 1
+            })
+        );
+        // A minimum no PIN can reach is reported as too short first
+        assert_eq!(
+            validate(&"1".repeat(MAX_PIN_BYTES + 1), 100),
+            Err(PinViolation::TooShort {
+                min_length: 100,
+                length: MAX_PIN_BYTES + 1
+            })
+        );
+        assert_eq!(
+            validate("", 0),
+            Err(PinViolation::TooShort {
+                min_length: 4,
+                length: 0
+            })
+        );
+    }
+
+    #[test]
+    fn counts_code_points_and_bytes() {
+        // 4 code points of 3 bytes each
+        assert_eq!(validate("€€€€", 4), Ok(()));
+        assert_eq!(
+            validate("€€€€", 5),
+            Err(PinViolation::TooShort {
+                min_length: 5,
+                length: 4
+            })
+        );
+        // 21 code points of 3 bytes each fit exactly, 22 don't
+        assert_eq!(validate(&"€".repeat(21), 0), Ok(()));
+        assert_eq!(
+            validate(&"€".repeat(22), 0),
+            Err(PinViolation::TooLong {
+                max_bytes: MAX_PIN_BYTES,
+                bytes: 66
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        for pin in [&b"\xff234"[..], b"12\xc3", b"\xed\xa0\x80123"] {
+            let pin = CString::new(pin).unwrap();
+            assert_eq!(
+                validate_pin(&pin, &PinPolicy::default()),
+                Err(PinViolation::NotUtf8)
+            );
+        }
+    }
+    #[test]
+    fn assuan_escaping_round_trips() {
+        for s in ["", "100%", "a\r\nb", "%0A", "€%"] {
+            assert_eq!(assuan_unescape(&assuan_escape(s)), s);
+        }
+        assert_eq!(assuan_escape("50% \n"), "50%25 %0A");
+    }
+
+    #[test]
+    fn assuan_unescape_keeps_malformed_escapes() {
+        assert_eq!(assuan_unescape("%41%4a%4A"), "AJJ");
+        // Truncated at the end of the input
+        assert_eq!(assuan_unescape("%"), "%");
+        assert_eq!(assuan_unescape("a%4"), "a%4");
+        // Not two hex digits
+        assert_eq!(assuan_unescape("%+1%-1%g0% 1"), "%+1%-1%g0% 1");
+        // Escaped bytes that aren't UTF-8
+        assert_eq!(assuan_unescape("%ff1"), "\u{fffd}1");
+        assert_eq!(assuan_unescape("%E2%82%AC"), "€");
+        // Non-ASCII after a percent sign
+        assert_eq!(assuan_unescape("%€"), "%€");
+    }
+}
@@ -0,0 +1,247 @@
+//! Caching of PINs for a session, so repeated operations don't ask the user every time.
+
+use crate::{PinPrompt, PinProvider};
+use std::{
+    ffi::CString,
+    io::{self, Write},
+    process::{Command, Stdio},
+    ptr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How long a [`CachedPin`] keeps a PIN by default.
+///
+/// [`CachedPin`]: struct.CachedPin.html
+pub const DEFAULT_PIN_TTL: Duration = Duration::from_secs(5 * 60);
+
+// Service name of keyring entries
+const SERVICE: &str = "libfido2-rs";
+
+/// Where a [`CachedPin`] keeps the PIN.
+///
+/// [`CachedPin`]: struct.CachedPin.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PinStore {
+    /// In the memory of the `CachedPin`, so the PIN is gone when it is dropped.
+    Memory,
+    /// In the keyring of the OS, so other processes of the user can use the PIN until it expires.
+    ///
+    /// Not every OS has a keyring scoped to the session, and PINs are only written to one that
+    /// is: on Linux the `session` collection of the Secret Service, through `secret-tool`, which
+    /// is not written to disk and is cleared when the user logs out. The keychains of macOS
+    /// persist their items, so there, on other platforms, and with Secret Service
+    /// implementations without a `session` collection, this falls back to `Memory`.
+    Keyring,
+}
+
+/// Provides PINs from another [`PinProvider`], and remembers them for a while.
+///
+/// # Remarks
+/// - A PIN is forgotten when it expires or the device reports it as wrong, and asked from the
+///   provider again.
+/// - The PIN is cached, not a `pinUvAuthToken`, as libfido2 creates a token for every request.
+/// - Keyring entries are looked up by `key`, which should identify the device, e.g. its AAGUID
+///   and serial number. Devices with different PINs must use different keys.
+/// - Expired keyring entries are only removed when they are looked up again, or with [`clear`].
+///
+/// [`PinProvider`]: trait.PinProvider.html
+/// [`clear`]: struct.CachedPin.html#method.clear
+#[derive(Debug)]
+pub struct CachedPin<P> {
+    provider: P,
+    key: String,
+    store: PinStore,
+    ttl: Duration,
+    cached: Option<(CString, Instant)>,
+}
+
+impl<P: PinProvider> CachedPin<P> {
+    /// Caches the PINs of `provider` in memory for [`DEFAULT_PIN_TTL`].
+    ///
+    /// [`DEFAULT_PIN_TTL`]: constant.DEFAULT_PIN_TTL.html
+    pub fn new(provider: P, key: impl Into<String>) -> Self {
+        CachedPin {
+            provider,
+            key: key.into(),
+            store: PinStore::Memory,
+            ttl: DEFAULT_PIN_TTL,
+            cached: None,
+        }
+    }
+
+    /// Sets where the PIN is kept.
+    pub fn with_store(mut self, store: PinStore) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Sets how long the PIN is kept, counted from when the provider returned it.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Forgets the PIN, also removing it from the keyring.
+    pub fn clear(&mut self) {
+        if let Some((pin, _)) = self.cached.take() {
+            zero(pin);
+        }
+        if self.store == PinStore::Keyring {
+            let _ = keyring::delete(&self.key);
+        }
+    }
+
+    fn lookup(&mut self) -> Option<CString> {
+        match &self.cached {
+            Some((pin, expiry)) if Instant::now() < *expiry => return Some(pin.clone()),
+            Some(_) => self.clear(),
+            None => {}
+        }
+        if self.store != PinStore::Keyring {
+            return None;
+        }
+        let entry = keyring::find(&self.key).ok()??;
+        let (expiry, pin) = entry.split_once(':')?;
+        let remaining = expiry
+            .parse()
+            .ok()
+            .map(|expiry| UNIX_EPOCH + Duration::from_secs(expiry))
+            .and_then(|expiry| expiry.duration_since(SystemTime::now()).ok());
+        let pin = CString::new(pin).ok();
+        match (remaining, pin) {
+            (Some(remaining), Some(pin)) => {
+                self.cached = Some((pin.clone(), Instant::now() + remaining));
+                Some(pin)
+            }
+            _ => {
+                self.clear();
+                None
+            }
+        }
+    }
+
+    fn store(&mut self, pin: &CString) {
+        self.cached = Some((pin.clone(), Instant::now() + self.ttl));
+        if self.store != PinStore::Keyring {
+            return;
+        }
+        let expiry = (SystemTime::now() + self.ttl)
+            .duration_since(UNIX_EPOCH)
+            .map(|expiry| expiry.as_secs())
+            .unwrap_or_default();
+        // PINs that aren't UTF-8 are only cached in memory
+        if let Ok(pin) = pin.to_str() {
+            let _ = keyring::store(&self.key, &format!("{}:{}", expiry, pin));
+        }
+    }
+}
+
+impl<P: PinProvider> PinProvider for CachedPin<P> {
+    fn pin(&mut self, prompt: &PinPrompt) -> Option<CString> {
+        if prompt.previous_invalid {
+            self.clear();
+        } else if let Some(pin) = self.lookup() {
+            return Some(pin);
+        }
+        let pin = self.provider.pin(prompt)?;
+        self.store(&pin);
+        Some(pin)
+    }
+
+    fn confirm_last_attempt(&mut self, prompt: &PinPrompt) -> bool {
+        self.provider.confirm_last_attempt(prompt)
+    }
+}
+
+impl<P> Drop for CachedPin<P> {
+    fn drop(&mut self) {
+        if let Some((pin, _)) = self.cached.take() {
+            zero(pin);
+        }
+    }
+}
+
+fn zero(pin: CString) {
+    for byte in pin.into_bytes().iter_mut() {
+        // Volatile, so the compiler can't remove the writes to memory that is freed anyway
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
+/// Runs `command`, writing `input` to its standard input, and returns its standard output if it
+/// succeeded.
+fn run(command: &mut Command, input: &str) -> io::Result<Option<String>> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let written = child
+        .stdin
+        .take()
+        .expect("piped stdin")
+        .write_all(input.as_bytes());
+    let output = child.wait_with_output()?;
+    written?;
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout).ok())
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod keyring {
+    use super::{run, SERVICE};
+    use std::{io, process::Command};
+
+    /// Returns a `secret-tool` command for the entry of `key`.
+    fn secret_tool(action: &str, key: &str, options: &[&str]) -> Command {
+        let mut command = Command::new("secret-tool");
+        command
+            .arg(action)
+            .args(options)
+            .args(["service", SERVICE, "device", key]);
+        command
+    }
+
+    pub(super) fn store(key: &str, secret: &str) -> io::Result<()> {
+        let label = format!("--label=Security key PIN ({})", key);
+        let mut command = secret_tool("store", key, &["--collection=session", &label]);
+        // The secret is read from standard input, so it doesn't show up in the process list
+        run(&mut command, secret)?
+            .map(|_| ())
+            .ok_or_else(|| io::Error::other("secret-tool store failed"))
+    }
+
+    pub(super) fn find(key: &str) -> io::Result<Option<String>> {
+        let output = run(&mut secret_tool("lookup", key, &[]), "")?;
+        Ok(output.map(|secret| secret.trim_end_matches('\n').to_owned()))
+    }
+
+    pub(super) fn delete(key: &str) -> io::Result<()> {
+        run(&mut secret_tool("clear", key, &[]), "").map(|_| ())
+    }
+}
+
+// The keychains of macOS persist their items, so a PIN would outlive the session
+#[cfg(not(target_os = "linux"))]
+mod keyring {
+    use std::io;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "no session keyring on this platform")
+    }
+
+    pub(super) fn store(_key: &str, _secret: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub(super) fn find(_key: &str) -> io::Result<Option<String>> {
+        Err(unsupported())
+    }
+
+    pub(super) fn delete(_key: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
@@ -0,0 +1,63 @@
+//! Helpers for setting up platforms to use FIDO devices.
+
+/// Linux, where access to HID devices is granted through udev rules.
+pub mod linux {
+    use crate::OwnedDeviceInformation;
+    use std::fmt::Write;
+
+    /// Where rules written by administrators are installed.
+    pub const UDEV_RULES_PATH: &str = "/etc/udev/rules.d/70-fido.rules";
+
+    const ACCESS: &str = "TAG+=\"uaccess\", GROUP=\"plugdev\", MODE=\"0660\"";
+
+    // Matches any device with the FIDO HID usage page, with the `fido_id` helper of systemd 244+
+    const GENERIC_RULE: &str = "\
+# FIDO devices, identified by their HID usage page
+ACTION==\"remove\", GOTO=\"fido_end\"
+SUBSYSTEM==\"hidraw\", IMPORT{program}=\"fido_id\"
+SUBSYSTEM==\"hidraw\", ENV{ID_SECURITY_TOKEN}==\"1\", TAG+=\"uaccess\", GROUP=\"plugdev\", \
+MODE=\"0660\"
+LABEL=\"fido_end\"
+";
+
+    /// Generates udev rules that give the logged in user, and the `plugdev` group, access to
+    /// FIDO devices.
+    ///
+    /// The rules contain the generic rule, followed by a rule for each distinct model of
+    /// `devices`, in the format of the rules libfido2 ships.
+    ///
+    /// # Remarks
+    /// - Install the rules at [`UDEV_RULES_PATH`], then run `udevadm control --reload` and
+    ///   `udevadm trigger`, or replug the devices.
+    /// - The generic rule needs systemd 244 or later. The model rules are for older systems, and
+    ///   for devices that don't announce the FIDO usage page.
+    ///
+    /// [`UDEV_RULES_PATH`]: constant.UDEV_RULES_PATH.html
+    pub fn generate_udev_rules(devices: &[OwnedDeviceInformation]) -> String {
+        let mut rules = String::from(GENERIC_RULE);
+        let mut models = Vec::new();
+        for device in devices {
+            // The IDs are unsigned, libfido2 just reports them as `int16_t`
+            let model = (device.vendor_id as u16, device.product_id as u16);
+            if models.contains(&model) {
+                continue;
+            }
+            models.push(model);
+
+            let name = format!("{} {}", device.manufacturer, device.product);
+            let name = match name.trim() {
+                "" => "Unnamed device",
+                name => name,
+            };
+            // Device strings are untrusted, and must not end the comment
+            let _ = writeln!(rules, "\n# {}", name.replace(['\n', '\r'], " "));
+            let _ = writeln!(
+                rules,
+                "KERNEL==\"hidraw*\", SUBSYSTEM==\"hidraw\", ATTRS{{idVendor}}==\"{:04x}\", \
+                 ATTRS{{idProduct}}==\"{:04x}\", {}",
+                model.0, model.1, ACCESS
+            );
+        }
+        rules
+    }
+}
@@ -0,0 +1,12 @@
+//! The commonly used types, for glob importing.
+//!
+//! ```no_run
+//! use libfido2::prelude::*;
+//! ```
+
+pub use crate::{
+    Assertion, AssertionCreationData, AssertionOptions, Authenticator, CreatedCredential,
+    CredProtect, CredentialCreationData, CredentialExtensions, CredentialOptions, CredentialType,
+    Device, DevicePath, DevicePathBuf, ErrorKind, Fido, FidoBuilder, FidoError, Interruption,
+    PinProvider, PublicKey, RpEntity, UserEntity, UvPreference,
+};
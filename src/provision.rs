@@ -0,0 +1,256 @@
+//! Scripted enrollment of devices, as run by IT departments for every key they issue.
+//!
+//! A [`Plan`] lists what to configure, and is run against one device at a time:
+//!
+//! 1. Factory reset, if requested.
+//! 2. Set the PIN, unless the device already has one.
+//! 3. Raise the minimum PIN length.
+//! 4. Enable `alwaysUv` and enterprise attestation.
+//! 5. Create the resident credentials.
+//!
+//! Steps 3 and 4 need the `config` feature.
+//!
+//! The [`ProvisioningReport`] records every step, and stops at the first failure, since later
+//! steps depend on earlier ones.
+//!
+//! [`Plan`]: struct.Plan.html
+//! [`ProvisioningReport`]: struct.ProvisioningReport.html
+
+use crate::{
+    validate_pin, AuthenticatorInfo, CreatedCredential, CredentialOptions, CredentialTemplate,
+    Device, Fido, FidoError, PinPolicy, PinViolation, Result,
+};
+use std::{ffi::CStr, fmt};
+
+/// What to configure on a device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plan<'a> {
+    pin: &'a CStr,
+    reset: bool,
+    #[cfg(feature = "config")]
+    min_pin_length: Option<usize>,
+    #[cfg(feature = "config")]
+    always_uv: bool,
+    #[cfg(feature = "config")]
+    enterprise_attestation: bool,
+    credentials: Vec<CredentialTemplate>,
+}
+
+/// A step of a [`Plan`].
+///
+/// [`Plan`]: struct.Plan.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Step {
+    Reset,
+    /// Requests the `AuthenticatorInfo`, to skip steps that are done already.
+    ReadInfo,
+    SetPin,
+    #[cfg(feature = "config")]
+    SetMinPinLength(usize),
+    #[cfg(feature = "config")]
+    EnableAlwaysUv,
+    #[cfg(feature = "config")]
+    EnableEnterpriseAttestation,
+    /// Creates the credential with this index, in the order they were added to the plan.
+    CreateCredential(usize),
+}
+
+/// The outcome of running a [`Plan`] on a device.
+///
+/// [`Plan`]: struct.Plan.html
+#[derive(Debug)]
+pub struct ProvisioningReport {
+    /// The steps that ran, in order. Only the last one can have failed.
+    pub steps: Vec<(Step, Result<()>)>,
+    /// The created credentials, in the order they were added to the plan.
+    pub credentials: Vec<CreatedCredential>,
+    /// The state of the device after provisioning, or `None` if it could not be requested.
+    pub info: Option<AuthenticatorInfo>,
+}
+
+impl<'a> Plan<'a> {
+    /// Creates a plan which only sets `pin`.
+    pub fn new(pin: &'a CStr) -> Self {
+        Plan {
+            pin,
+            reset: false,
+            #[cfg(feature = "config")]
+            min_pin_length: None,
+            #[cfg(feature = "config")]
+            always_uv: false,
+            #[cfg(feature = "config")]
+            enterprise_attestation: false,
+            credentials: Vec::new(),
+        }
+    }
+
+    /// Factory resets the device first, deleting all its credentials and its PIN.
+    ///
+    /// # Remarks
+    /// - Devices only allow a reset shortly after they were plugged in, and ask the user to
+    ///   touch them. See `Device::reset`.
+    pub fn with_reset(mut self) -> Self {
+        self.reset = true;
+        self
+    }
+
+    /// Raises the minimum PIN length of the device.
+    #[cfg(feature = "config")]
+    pub fn with_min_pin_length(mut self, min_length: usize) -> Self {
+        self.min_pin_length = Some(min_length);
+        self
+    }
+
+    /// Requires user verification for every request.
+    #[cfg(feature = "config")]
+    pub fn with_always_uv(mut self) -> Self {
+        self.always_uv = true;
+        self
+    }
+
+    /// Enables enterprise attestation.
+    #[cfg(feature = "config")]
+    pub fn with_enterprise_attestation(mut self) -> Self {
+        self.enterprise_attestation = true;
+        self
+    }
+
+    /// Adds a credential to create, which is made resident.
+    pub fn with_credential(mut self, credential: CredentialTemplate) -> Self {
+        self.credentials.push(credential);
+        self
+    }
+
+    /// Runs the plan on `device`.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block. The user has to touch the device for the reset, and
+    ///   for every credential.
+    /// - Without a reset, a device that already has a PIN is expected to have `pin`.
+    ///
+    /// # Errors
+    /// - If the PIN doesn't satisfy the minimum length of the plan, before touching the device.
+    pub fn run(
+        &self,
+        fido: &Fido,
+        device: &mut Device,
+    ) -> std::result::Result<ProvisioningReport, PinViolation> {
+        #[cfg(feature = "config")]
+        let policy = PinPolicy::new(self.min_pin_length.unwrap_or_default());
+        #[cfg(not(feature = "config"))]
+        let policy = PinPolicy::default();
+        validate_pin(self.pin, &policy)?;
+
+        let mut report = ProvisioningReport {
+            steps: Vec::new(),
+            credentials: Vec::new(),
+            info: None,
+        };
+        // Stops at the first failure, which is recorded in the report
+        let _ = self.run_steps(fido, device, &mut report);
+        report.info = device.authenticator_info().ok();
+        Ok(report)
+    }
+
+    fn run_steps(
+        &self,
+        fido: &Fido,
+        device: &mut Device,
+        report: &mut ProvisioningReport,
+    ) -> Result<()> {
+        let pin = Some(self.pin);
+        if self.reset {
+            report.record(Step::Reset, device.reset())?;
+        }
+        let info = device.authenticator_info();
+        report.record(Step::ReadInfo, info.as_ref().map(|_| ()).map_err(|error| *error))?;
+        let info = info?;
+        if self.reset || info.options.get("clientPin") != Some(&true) {
+            report.record(Step::SetPin, device.set_pin(self.pin, None))?;
+        }
+        #[cfg(feature = "config")]
+        if let Some(min_length) = self.min_pin_length {
+            let result = device.set_min_pin_length(min_length, pin);
+            report.record(Step::SetMinPinLength(min_length), result)?;
+        }
+        #[cfg(feature = "config")]
+        if self.always_uv {
+            // The command toggles, so it is only sent if the option is off
+            let result = match info.options.get("alwaysUv") {
+                Some(true) => Ok(()),
+                _ => device.toggle_always_uv(pin),
+            };
+            report.record(Step::EnableAlwaysUv, result)?;
+        }
+        #[cfg(feature = "config")]
+        if self.enterprise_attestation {
+            let result = device.enable_enterprise_attestation(pin);
+            report.record(Step::EnableEnterpriseAttestation, result)?;
+        }
+        for (i, credential) in self.credentials.iter().enumerate() {
+            let mut data = credential.data();
+            data.options |= CredentialOptions::RESIDENT_KEY;
+            let result = fido
+                .new_credential_creator(data)
+                .and_then(|creator| device.request_credential_creation(creator, pin))
+                .map(|credential| report.credentials.push(credential));
+            report.record(Step::CreateCredential(i), result)?;
+        }
+        Ok(())
+    }
+}
+
+impl ProvisioningReport {
+    fn record(&mut self, step: Step, result: Result<()>) -> Result<()> {
+        self.steps.push((step, result));
+        result
+    }
+
+    /// Returns whether every step of the plan succeeded.
+    pub fn is_complete(&self) -> bool {
+        self.failure().is_none()
+    }
+
+    /// Returns the step that failed, if any.
+    pub fn failure(&self) -> Option<(Step, FidoError)> {
+        self.steps
+            .iter()
+            .find_map(|(step, result)| result.err().map(|error| (*step, error)))
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Reset => write!(f, "Factory reset"),
+            Step::ReadInfo => write!(f, "Read authenticator info"),
+            Step::SetPin => write!(f, "Set PIN"),
+            #[cfg(feature = "config")]
+            Step::SetMinPinLength(min_length) => {
+                write!(f, "Set minimum PIN length to {}", min_length)
+            }
+            #[cfg(feature = "config")]
+            Step::EnableAlwaysUv => write!(f, "Enable alwaysUv"),
+            #[cfg(feature = "config")]
+            Step::EnableEnterpriseAttestation => write!(f, "Enable enterprise attestation"),
+            Step::CreateCredential(i) => write!(f, "Create credential {}", i),
+        }
+    }
+}
+
+/// One line per step, followed by the AAGUID of the device if it is known.
+impl fmt::Display for ProvisioningReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (step, result) in &self.steps {
+            match result {
+                Ok(()) => writeln!(f, "[ok] {}", step)?,
+                Err(error) => writeln!(f, "[FAILED] {}: {}", step, error)?,
+            }
+        }
+        if let Some(aaguid) = self.info.as_ref().and_then(|info| info.aag_uid.as_ref()) {
+            let hex = aaguid.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+            writeln!(f, "AAGUID: {}", hex)?;
+        }
+        Ok(())
+    }
+}
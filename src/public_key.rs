@@ -1,7 +1,12 @@
-use crate::{ffi::NonNull, CredentialType, FidoError, Result, FIDO_OK};
-use std::convert::TryInto;
+use crate::{
+    ffi::{allocated, to_size_t, NonNull},
+    CoseAlgorithm, CredentialType, FidoError, Result, FIDO_OK,
+};
+use crate::openssl;
+#[cfg(feature = "jose")]
+use crate::jwk::{self, InvalidJwkError, RawKey};
 use libfido2_sys::*;
-use std::os::raw;
+use std::{mem, os::raw};
 
 pub enum PublicKey {
     ES256(#[doc(hidden)] ES256),
@@ -11,10 +16,19 @@ pub enum PublicKey {
 
 // @TODO add way to create this from <something else>. openssl maybe.
 impl PublicKey {
+    /// Parses a public key in COSE format with the given type.
+    pub(crate) fn from_bytes(credential_type: CredentialType, data: &[u8]) -> Result<PublicKey> {
+        match credential_type {
+            CredentialType::ES256 => PublicKey::new_es256(data),
+            CredentialType::RS256 => PublicKey::new_rs256(data),
+            CredentialType::EDDSA => PublicKey::new_eddsa(data),
+        }
+    }
+
     pub(crate) fn new_es256(data: &[u8]) -> Result<PublicKey> {
         unsafe {
-            let mut pk = ES256(NonNull::new(es256_pk_new()).unwrap());
-            match es256_pk_from_ptr(pk.0.as_ptr_mut(), data as *const _ as *const _, data.len().try_into().unwrap()) {
+            let mut pk = ES256(allocated(es256_pk_new())?);
+            match es256_pk_from_ptr(pk.0.as_ptr_mut(), data as *const _ as *const _, to_size_t(data.len())?) {
                 FIDO_OK => Ok(PublicKey::ES256(pk)),
                 err => Err(FidoError(err)),
             }
@@ -23,8 +37,8 @@ impl PublicKey {
 
     pub(crate) fn new_rs256(data: &[u8]) -> Result<PublicKey> {
         unsafe {
-            let mut pk = RS256(NonNull::new(rs256_pk_new()).unwrap());
-            match rs256_pk_from_ptr(pk.0.as_ptr_mut(), data as *const _ as *const _, data.len().try_into().unwrap()) {
+            let mut pk = RS256(allocated(rs256_pk_new())?);
+            match rs256_pk_from_ptr(pk.0.as_ptr_mut(), data as *const _ as *const _, to_size_t(data.len())?) {
                 FIDO_OK => Ok(PublicKey::RS256(pk)),
                 err => Err(FidoError(err)),
             }
@@ -33,14 +47,19 @@ impl PublicKey {
 
     pub(crate) fn new_eddsa(data: &[u8]) -> Result<PublicKey> {
         unsafe {
-            let mut pk = EDDSA(NonNull::new(eddsa_pk_new()).unwrap());
-            match eddsa_pk_from_ptr(pk.0.as_ptr_mut(), data as *const _ as *const _, data.len().try_into().unwrap()) {
+            let mut pk = EDDSA(allocated(eddsa_pk_new())?);
+            match eddsa_pk_from_ptr(pk.0.as_ptr_mut(), data as *const _ as *const _, to_size_t(data.len())?) {
                 FIDO_OK => Ok(PublicKey::EDDSA(pk)),
                 err => Err(FidoError(err)),
             }
         }
     }
 
+    /// Returns the algorithm signatures of this key are verified with.
+    pub fn algorithm(&self) -> CoseAlgorithm {
+        self.credential_type().into()
+    }
+
     pub(crate) fn credential_type(&self) -> CredentialType {
         match self {
             PublicKey::ES256(_) => CredentialType::ES256,
@@ -56,6 +75,112 @@ impl PublicKey {
             PublicKey::EDDSA(inner) => inner.0.as_ptr() as *const _,
         }
     }
+
+    /// Verifies `signature` over `data`, e.g. for schemes that sign with a credential key outside
+    /// of assertions, using the algorithm of the key.
+    ///
+    /// # Remarks
+    /// - ES256 signatures must be DER encoded, as devices return them. RS256 signatures use
+    ///   PKCS #1 v1.5 padding.
+    /// - `data` is the signed message itself. ES256 and RS256 hash it with SHA-256, EdDSA signs
+    ///   it as is.
+    /// - Use `Assertion::verify_one` for assertions, which also checks the authenticator data.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_INVALID_SIG` if the signature is not valid.
+    pub fn verify(&self, data: &[u8], signature: &[u8]) -> Result<()> {
+        let pkey = unsafe { self.to_evp_pkey() };
+        let sha256 = !matches!(self, PublicKey::EDDSA(_));
+        unsafe { openssl::verify_signature(pkey, sha256, data, signature) }
+    }
+
+    /// Converts the key to a new `EVP_PKEY`, which the caller must free, or returns null if
+    /// libfido2 could not convert it.
+    unsafe fn to_evp_pkey(&self) -> *mut EVP_PKEY {
+        match self {
+            PublicKey::ES256(inner) => es256_pk_to_EVP_PKEY(inner.0.as_ptr()),
+            PublicKey::RS256(inner) => rs256_pk_to_EVP_PKEY(inner.0.as_ptr()),
+            PublicKey::EDDSA(inner) => eddsa_pk_to_EVP_PKEY(inner.0.as_ptr()),
+        }
+    }
+
+    /// Encodes the key as a JSON Web Key (RFC 7517), with the `alg` signatures are verified with.
+    ///
+    /// # Remarks
+    /// - ES256 keys are `EC` keys of `P-256`, RS256 keys `RSA` keys, and EdDSA keys `OKP` keys of
+    ///   `Ed25519`.
+    #[cfg(feature = "jose")]
+    pub fn to_jwk(&self) -> Result<String> {
+        let spki = unsafe { openssl::subject_public_key_info(self.to_evp_pkey())? };
+        let jwk = match self {
+            PublicKey::ES256(_) => jwk::es256(&spki),
+            PublicKey::RS256(_) => jwk::rs256(&spki),
+            PublicKey::EDDSA(_) => jwk::eddsa(&spki),
+        };
+        jwk.ok_or(FidoError(FIDO_ERR_INTERNAL))
+    }
+
+    /// Decodes a JSON Web Key (RFC 7517), e.g. one stored by a JWT based backend.
+    ///
+    /// # Remarks
+    /// - Only the key types of [`to_jwk`] are supported, and RSA keys must have a 2048 bit
+    ///   modulus, as libfido2 only verifies those.
+    /// - Members other than the key and `alg`, `use` and `key_ops` are ignored. Keys marked for
+    ///   anything but verifying signatures are rejected, as are JWKs with duplicate members.
+    ///
+    /// [`to_jwk`]: enum.PublicKey.html#method.to_jwk
+    #[cfg(feature = "jose")]
+    pub fn from_jwk(jwk: &str) -> std::result::Result<PublicKey, InvalidJwkError> {
+        let key = match jwk::parse(jwk).ok_or(InvalidJwkError)? {
+            RawKey::Es256(data) => PublicKey::new_es256(&data),
+            RawKey::Rs256(data) => PublicKey::new_rs256(&data),
+            RawKey::Eddsa(data) => PublicKey::new_eddsa(&data),
+        };
+        // libfido2 checks that EC points are on the curve
+        key.map_err(|_| InvalidJwkError)
+    }
+
+    /// Returns the underlying `es256_pk_t`, `rs256_pk_t` or `eddsa_pk_t`, depending on the
+    /// [`algorithm`], to call libfido2 functions this crate does not wrap.
+    ///
+    /// # Remarks
+    /// - The pointer is valid for as long as `self` is, and must not be freed.
+    ///
+    /// [`algorithm`]: enum.PublicKey.html#method.algorithm
+    pub fn as_raw(&self) -> *const raw::c_void {
+        self.as_ptr()
+    }
+
+    /// Gives up ownership of the underlying key, returning its type and pointer.
+    ///
+    /// # Remarks
+    /// - The caller is responsible for freeing it with `es256_pk_free`, `rs256_pk_free` or
+    ///   `eddsa_pk_free`, or with [`from_raw`].
+    ///
+    /// [`from_raw`]: enum.PublicKey.html#method.from_raw
+    pub fn into_raw(self) -> (CredentialType, *mut raw::c_void) {
+        let credential_type = self.credential_type();
+        let mut key = mem::ManuallyDrop::new(self);
+        let raw = match &mut *key {
+            PublicKey::ES256(inner) => inner.0.as_ptr_mut() as *mut _,
+            PublicKey::RS256(inner) => inner.0.as_ptr_mut() as *mut _,
+            PublicKey::EDDSA(inner) => inner.0.as_ptr_mut() as *mut _,
+        };
+        (credential_type, raw)
+    }
+
+    /// Takes ownership of a key of the given type, which is freed on drop.
+    ///
+    /// # Safety
+    /// - `raw` must be a valid `es256_pk_t`, `rs256_pk_t` or `eddsa_pk_t` matching
+    ///   `credential_type`, allocated by libfido2 and not owned by anything else.
+    pub unsafe fn from_raw(credential_type: CredentialType, raw: *mut raw::c_void) -> Option<Self> {
+        Some(match credential_type {
+            CredentialType::ES256 => PublicKey::ES256(ES256(NonNull::new(raw as *mut _)?)),
+            CredentialType::RS256 => PublicKey::RS256(RS256(NonNull::new(raw as *mut _)?)),
+            CredentialType::EDDSA => PublicKey::EDDSA(EDDSA(NonNull::new(raw as *mut _)?)),
+        })
+    }
 }
 
 #[doc(hidden)]
@@ -80,7 +205,7 @@ impl Drop for ES256 {
         unsafe {
             let mut pk = self.0.as_ptr_mut();
             es256_pk_free(&mut pk as *mut _);
-            assert!(pk.is_null());
+            debug_assert!(pk.is_null());
         }
     }
 }
@@ -90,7 +215,7 @@ impl Drop for RS256 {
         unsafe {
             let mut pk = self.0.as_ptr_mut();
             rs256_pk_free(&mut pk as *mut _);
-            assert!(pk.is_null());
+            debug_assert!(pk.is_null());
         }
     }
 }
@@ -100,7 +225,7 @@ impl Drop for EDDSA {
         unsafe {
             let mut pk = self.0.as_ptr_mut();
             eddsa_pk_free(&mut pk as *mut _);
-            assert!(pk.is_null());
+            debug_assert!(pk.is_null());
         }
     }
 }
@@ -0,0 +1,210 @@
+use crate::{
+    cbor::{Reader, Writer},
+    AuthDataFlags, BackupState, BackupTransition, CoseAlgorithm, CreatedCredential, CredentialId,
+    CredentialType, PublicKey, Result, SignCountStatus,
+};
+use std::{convert::TryFrom, error, fmt, os::raw};
+
+const VERSION: u64 = 1;
+
+// Map keys of the encoded record, written in this order
+const KEY_VERSION: u64 = 1;
+const KEY_ID: u64 = 2;
+const KEY_TYPE: u64 = 3;
+const KEY_PUBLIC_KEY: u64 = 4;
+const KEY_RELYING_PARTY_ID: u64 = 5;
+const KEY_USER_ID: u64 = 6;
+const KEY_USER_NAME: u64 = 7;
+const KEY_SIGN_COUNT: u64 = 8;
+const KEY_TRANSPORTS: u64 = 9;
+const KEY_BACKUP_STATE: u64 = 10;
+
+/// The public parts of a created credential, which a relying party stores between
+/// registration and authentication.
+///
+/// # Remarks
+/// - Encodes to a compact CBOR map with integer keys, see [`to_cbor`].
+/// - Update `sign_count` after every authentication, to detect cloned authenticators, and
+///   `backup_state` to follow whether the credential is synced.
+///
+/// [`to_cbor`]: struct.RegistrationRecord.html#method.to_cbor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegistrationRecord {
+    pub id: CredentialId,
+    pub credential_type: CredentialType,
+    pub public_key: Vec<u8>,
+    pub relying_party_id: String,
+    pub user_id: Vec<u8>,
+    pub user_name: Option<String>,
+    pub sign_count: u32,
+    /// Transports of the device, e.g. `usb`, as reported in its `AuthenticatorInfo`.
+    pub transports: Vec<String>,
+    /// Whether the credential is backed up, or `None` for devices that reported an invalid state
+    /// and records written before it was stored.
+    pub backup_state: Option<BackupState>,
+}
+
+impl RegistrationRecord {
+    /// Collects the public parts of a credential that was just created.
+    ///
+    /// # Arguments
+    /// - `transports`: Transports of the device that created the credential
+    pub fn new(credential: &CreatedCredential, transports: &[String]) -> Self {
+        let full = credential.credential();
+        RegistrationRecord {
+            id: credential.id().clone(),
+            credential_type: credential.credential_type(),
+            public_key: credential.public_key_bytes().to_vec(),
+            relying_party_id: full
+                .relying_party_id()
+                .map(|id| id.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            user_id: full.user_id().to_vec(),
+            user_name: full.user_name().map(|name| name.to_string_lossy().into_owned()),
+            sign_count: credential.sign_count(),
+            transports: transports.to_vec(),
+            backup_state: BackupState::from_flags(credential.flags()),
+        }
+    }
+
+    /// Returns the algorithm of the stored public key, which assertions of the credential are
+    /// signed with. Assertions don't report the algorithm themselves.
+    pub fn algorithm(&self) -> CoseAlgorithm {
+        self.credential_type.into()
+    }
+
+    /// Compares the sign count of a new assertion against the stored one.
+    ///
+    /// # Remarks
+    /// - Store the new count afterwards, unless the assertion is rejected.
+    pub fn check_sign_count(&self, sign_count: u32) -> SignCountStatus {
+        SignCountStatus::new(self.sign_count, sign_count)
+    }
+
+    /// Compares the backup flags of a new assertion, see `Statement::flags`, against the stored
+    /// state.
+    ///
+    /// # Remarks
+    /// - Reject the assertion if the transition is a violation, and store
+    ///   `BackupTransition::current` otherwise.
+    /// - Records without a stored state adopt the state of the assertion.
+    pub fn check_backup_state(&self, flags: AuthDataFlags) -> BackupTransition {
+        match self.backup_state.or_else(|| BackupState::from_flags(flags)) {
+            Some(previous) => BackupTransition::new(previous, flags),
+            None => BackupTransition::Invalid,
+        }
+    }
+
+    /// Tries to parse the stored public key as a [`PublicKey`].
+    ///
+    /// [`PublicKey`]: enum.PublicKey.html
+    pub fn public_key(&self) -> Result<PublicKey> {
+        PublicKey::from_bytes(self.credential_type, &self.public_key)
+    }
+
+    /// Encodes the record as CBOR.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        let optional = [self.user_name.is_some(), self.backup_state.is_some()];
+        writer.map(8 + optional.iter().filter(|present| **present).count());
+        writer.uint(KEY_VERSION).uint(VERSION);
+        writer.uint(KEY_ID).bytes(self.id.as_bytes());
+        writer.uint(KEY_TYPE).int(self.credential_type as i64);
+        writer.uint(KEY_PUBLIC_KEY).bytes(&self.public_key);
+        writer.uint(KEY_RELYING_PARTY_ID).text(&self.relying_party_id);
+        writer.uint(KEY_USER_ID).bytes(&self.user_id);
+        if let Some(user_name) = &self.user_name {
+            writer.uint(KEY_USER_NAME).text(user_name);
+        }
+        writer.uint(KEY_SIGN_COUNT).uint(self.sign_count.into());
+        writer.uint(KEY_TRANSPORTS).array(self.transports.len());
+        for transport in &self.transports {
+            writer.text(transport);
+        }
+        if let Some(backup_state) = self.backup_state {
+            writer.uint(KEY_BACKUP_STATE).uint(backup_state.to_u64());
+        }
+        writer.into_bytes()
+    }
+
+    /// Decodes a record encoded with [`to_cbor`].
+    ///
+    /// # Remarks
+    /// - Unknown keys are ignored, so records written by newer versions can still be read.
+    ///
+    /// [`to_cbor`]: struct.RegistrationRecord.html#method.to_cbor
+    pub fn from_cbor(data: &[u8]) -> std::result::Result<Self, InvalidRegistrationRecordError> {
+        Self::parse(data).ok_or(InvalidRegistrationRecordError)
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(data);
+        let mut version = None;
+        let mut id = None;
+        let mut credential_type = None;
+        let mut public_key = None;
+        let mut relying_party_id = None;
+        let mut user_id = None;
+        let mut user_name = None;
+        let mut sign_count = None;
+        let mut transports = None;
+        let mut backup_state = None;
+
+        for _ in 0..reader.map()? {
+            match reader.uint()? {
+                KEY_VERSION => version = Some(reader.uint()?),
+                KEY_ID => id = Some(CredentialId::from(reader.bytes()?)),
+                KEY_TYPE => {
+                    let alg = raw::c_int::try_from(reader.int()?).ok()?;
+                    credential_type = Some(CredentialType::from_ffi(alg)?);
+                }
+                KEY_PUBLIC_KEY => public_key = Some(reader.bytes()?.to_vec()),
+                KEY_RELYING_PARTY_ID => relying_party_id = Some(reader.text()?.to_owned()),
+                KEY_USER_ID => user_id = Some(reader.bytes()?.to_vec()),
+                KEY_USER_NAME => user_name = Some(reader.text()?.to_owned()),
+                KEY_SIGN_COUNT => sign_count = Some(u32::try_from(reader.uint()?).ok()?),
+                KEY_TRANSPORTS => {
+                    let len = reader.array()?;
+                    transports = Some(
+                        (0..len)
+                            .map(|_| reader.text().map(str::to_owned))
+                            .collect::<Option<Vec<_>>>()?,
+                    );
+                }
+                KEY_BACKUP_STATE => backup_state = Some(BackupState::from_u64(reader.uint()?)?),
+                _ => {
+                    reader.skip()?;
+                }
+            }
+        }
+
+        if version? != VERSION || !reader.is_empty() {
+            return None;
+        }
+        Some(RegistrationRecord {
+            id: id?,
+            credential_type: credential_type?,
+            public_key: public_key?,
+            relying_party_id: relying_party_id?,
+            user_id: user_id?,
+            user_name,
+            sign_count: sign_count?,
+            transports: transports.unwrap_or_default(),
+            backup_state,
+        })
+    }
+}
+
+/// Error returned when a [`RegistrationRecord`] could not be decoded.
+///
+/// [`RegistrationRecord`]: struct.RegistrationRecord.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidRegistrationRecordError;
+
+impl error::Error for InvalidRegistrationRecordError {}
+
+impl fmt::Display for InvalidRegistrationRecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The registration record is invalid")
+    }
+}
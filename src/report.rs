@@ -0,0 +1,148 @@
+use crate::{json::string, AuthenticatorInfo, CTAPHIDCapabilities, CTAPHIDInfo, DeviceMode};
+use std::fmt::Write;
+
+/// Snapshot of the state of a device, as returned by [`Device::report`].
+///
+/// Parts the device does not support, or that could not be requested, are `None`.
+///
+/// [`Device::report`]: struct.Device.html#method.report
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceReport {
+    pub mode: DeviceMode,
+    pub ctap_hid: CTAPHIDInfo,
+    /// `None` for U2F only devices.
+    pub info: Option<AuthenticatorInfo>,
+    pub pin_retries: Option<i32>,
+    /// Only requested when a PIN was given.
+    pub resident_keys: Option<ResidentKeyMetadata>,
+    pub bio: Option<BioInfo>,
+}
+
+/// Amount of resident (discoverable) credentials on a device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResidentKeyMetadata {
+    pub existing: u64,
+    /// Estimate of how many more can be created.
+    pub remaining: u64,
+}
+
+/// Information about the fingerprint sensor of a device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BioInfo {
+    /// 1 for touch sensors, 2 for swipe sensors.
+    pub sensor_type: u8,
+    /// Maximum amount of samples needed to enroll a fingerprint.
+    pub max_samples: u8,
+}
+
+impl DeviceReport {
+    /// Encodes the report as a JSON object, e.g. for inventory tools.
+    ///
+    /// # Remarks
+    /// - Byte strings such as the AAGUID are encoded as lowercase hex.
+    /// - Missing parts are encoded as `null`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{");
+        let mode = self.mode.to_string();
+        let _ = write!(json, "\"mode\":{}", string(&mode));
+
+        let ctap_hid = &self.ctap_hid;
+        let capabilities = [
+            (CTAPHIDCapabilities::CBOR, "cbor"),
+            (CTAPHIDCapabilities::NMSG, "nmsg"),
+            (CTAPHIDCapabilities::WINK, "wink"),
+        ]
+        .iter()
+        .filter(|(flag, _)| ctap_hid.capabilities.contains(*flag))
+        .map(|(_, name)| string(name))
+        .collect::<Vec<_>>();
+        let _ = write!(
+            json,
+            ",\"ctap_hid\":{{\"protocol\":{},\"major\":{},\"minor\":{},\"build\":{},\"capabilities\":[{}]}}",
+            ctap_hid.protocol,
+            ctap_hid.major,
+            ctap_hid.minor,
+            ctap_hid.build,
+            capabilities.join(",")
+        );
+
+        json.push_str(",\"info\":");
+        match &self.info {
+            Some(info) => info_json(&mut json, info),
+            None => json.push_str("null"),
+        }
+
+        let _ = write!(json, ",\"pin_retries\":{}", optional(self.pin_retries));
+        json.push_str(",\"resident_keys\":");
+        match self.resident_keys {
+            Some(keys) => {
+                let _ = write!(
+                    json,
+                    "{{\"existing\":{},\"remaining\":{}}}",
+                    keys.existing, keys.remaining
+                );
+            }
+            None => json.push_str("null"),
+        }
+        json.push_str(",\"bio\":");
+        match self.bio {
+            Some(bio) => {
+                let _ = write!(
+                    json,
+                    "{{\"sensor_type\":{},\"max_samples\":{}}}",
+                    bio.sensor_type, bio.max_samples
+                );
+            }
+            None => json.push_str("null"),
+        }
+        json.push('}');
+        json
+    }
+}
+
+fn info_json(json: &mut String, info: &AuthenticatorInfo) {
+    let strings = |values: &[String]| values.iter().map(|s| string(s)).collect::<Vec<_>>().join(",");
+
+    json.push_str("{\"aaguid\":");
+    match &info.aag_uid {
+        Some(aaguid) => {
+            let hex = aaguid.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+            json.push_str(&string(&hex));
+        }
+        None => json.push_str("null"),
+    }
+    let pin_protocols = info
+        .pin_protocols
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>();
+    let _ = write!(json, ",\"pin_protocols\":[{}]", pin_protocols.join(","));
+    let _ = write!(json, ",\"extensions\":[{}]", strings(&info.extensions));
+    let _ = write!(json, ",\"ctap_versions\":[{}]", strings(&info.ctap_versions));
+    let _ = write!(json, ",\"transports\":[{}]", strings(&info.transports));
+
+    // Sorted, so reports of the same device compare equal
+    let mut options = info.options.iter().collect::<Vec<_>>();
+    options.sort();
+    let options = options
+        .iter()
+        .map(|(name, value)| format!("{}:{}", string(name), value))
+        .collect::<Vec<_>>();
+    let _ = write!(json, ",\"options\":{{{}}}", options.join(","));
+    let _ = write!(json, ",\"min_pin_length\":{}", optional(info.min_pin_length));
+    let _ = write!(
+        json,
+        ",\"max_credential_count_in_list\":{}",
+        optional(info.max_credential_count_in_list)
+    );
+    let _ = write!(
+        json,
+        ",\"max_credential_id_length\":{}",
+        optional(info.max_credential_id_length)
+    );
+    let _ = write!(json, ",\"max_msg_size\":{}}}", optional(info.max_msg_size));
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| "null".to_owned(), |value| value.to_string())
+}
@@ -0,0 +1,198 @@
+use std::time::SystemTime;
+
+/// How a sign count relates to the one previously stored for the credential.
+///
+/// # Remarks
+/// - WebAuthn leaves the reaction to relying parties: a suspicious count may mean that the
+///   authenticator was cloned, so it can be rejected or the account flagged for review.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SignCountStatus {
+    /// The count increased, as expected.
+    Increasing,
+    /// Both counts are zero, which means the authenticator does not implement a counter.
+    /// WebAuthn allows this, so it is not suspicious.
+    ConstantZero,
+    /// The count dropped to zero after being non-zero, e.g. because the authenticator was reset
+    /// or the credential was recreated with the same ID.
+    Reset { previous: u32 },
+    /// The count did not increase, which happens when two copies of a credential are used.
+    NonMonotonic { previous: u32, current: u32 },
+}
+
+impl SignCountStatus {
+    /// Compares the count of a new assertion against the stored one.
+    pub fn new(previous: u32, current: u32) -> Self {
+        match (previous, current) {
+            (0, 0) => SignCountStatus::ConstantZero,
+            (previous, 0) => SignCountStatus::Reset { previous },
+            (previous, current) if current > previous => SignCountStatus::Increasing,
+            (previous, current) => SignCountStatus::NonMonotonic { previous, current },
+        }
+    }
+
+    /// Returns `true` for the statuses WebAuthn asks relying parties to treat as a possibly
+    /// cloned authenticator.
+    pub fn is_suspicious(&self) -> bool {
+        match self {
+            SignCountStatus::Increasing | SignCountStatus::ConstantZero => false,
+            SignCountStatus::Reset { .. } | SignCountStatus::NonMonotonic { .. } => true,
+        }
+    }
+}
+
+/// A sign count as stored by a relying party, with the time the assertion was verified.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SignCountSample {
+    pub sign_count: u32,
+    pub time: SystemTime,
+}
+
+/// A suspicious step in a sign count history.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SignCountAnomaly {
+    /// The sample with the suspicious count.
+    pub sample: SignCountSample,
+    pub status: SignCountStatus,
+}
+
+/// Result of [`SignCountReport::new`].
+///
+/// [`SignCountReport::new`]: struct.SignCountReport.html#method.new
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SignCountReport {
+    /// Status of the most recent sample relative to the one before it, `None` for less than two
+    /// samples.
+    pub latest: Option<SignCountStatus>,
+    /// All suspicious steps, oldest first.
+    pub anomalies: Vec<SignCountAnomaly>,
+    /// Whether every sample has a zero count, as expected of authenticators without a counter.
+    pub constant_zero: bool,
+}
+
+impl SignCountReport {
+    /// Classifies every step of a sign count history.
+    ///
+    /// # Remarks
+    /// - The samples are ordered by time first, so they can be passed in storage order. Samples
+    ///   with the same time keep their order.
+    pub fn new(history: &[SignCountSample]) -> Self {
+        let mut history = history.to_vec();
+        history.sort_by_key(|sample| sample.time);
+
+        let mut report = SignCountReport {
+            latest: None,
+            anomalies: Vec::new(),
+            constant_zero: history.iter().all(|sample| sample.sign_count == 0),
+        };
+        for pair in history.windows(2) {
+            let status = SignCountStatus::new(pair[0].sign_count, pair[1].sign_count);
+            if status.is_suspicious() {
+                report.anomalies.push(SignCountAnomaly {
+                    sample: pair[1],
+                    status,
+                });
+            }
+            report.latest = Some(status);
+        }
+        report
+    }
+
+    /// Returns `true` if any step was suspicious.
+    pub fn is_suspicious(&self) -> bool {
+        !self.anomalies.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn sample(sign_count: u32, secs: u64) -> SignCountSample {
+        SignCountSample {
+            sign_count,
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn status_boundaries() {
+        assert_eq!(SignCountStatus::new(0, 0), SignCountStatus::ConstantZero);
+        assert_eq!(SignCountStatus::new(0, 1), SignCountStatus::Increasing);
+        assert_eq!(
+            SignCountStatus::new(u32::MAX - 1, u32::MAX),
+            SignCountStatus::Increasing
+        );
+        assert_eq!(
+            SignCountStatus::new(u32::MAX, 0),
+            SignCountStatus::Reset { previous: u32::MAX }
+        );
+        assert_eq!(
+            SignCountStatus::new(5, 5),
+            SignCountStatus::NonMonotonic {
+                previous: 5,
+                current: 5
+            }
+        );
+        // A counter wrapping around to non-zero
+        assert_eq!(
+            SignCountStatus::new(u32::MAX, 1),
+            SignCountStatus::NonMonotonic {
+                previous: u32::MAX,
+                current: 1
+            }
+        );
+    }
+
+    #[test]
+    fn short_histories() {
+        let report = SignCountReport::new(&[]);
+        assert_eq!(report.latest, None);
+        assert!(report.constant_zero);
+        assert!(!report.is_suspicious());
+
+        let report = SignCountReport::new(&[sample(3, 0)]);
+        assert_eq!(report.latest, None);
+        assert!(!report.constant_zero);
+        assert!(!report.is_suspicious());
+    }
+
+    #[test]
+    fn sorts_by_time() {
+        // Stored out of order, but increasing by time
+        let report = SignCountReport::new(&[sample(3, 30), sample(1, 10), sample(2, 20)]);
+        assert_eq!(report.latest, Some(SignCountStatus::Increasing));
+        assert!(!report.is_suspicious());
+
+        // Samples of the same time keep their order
+        let history = [sample(2, 10), sample(1, 10)];
+        let report = SignCountReport::new(&history);
+        assert_eq!(
+            report.anomalies,
+            vec![SignCountAnomaly {
+                sample: history[1],
+                status: SignCountStatus::NonMonotonic {
+                    previous: 2,
+                    current: 1
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn collects_every_anomaly() {
+        let history = [sample(0, 0), sample(0, 1), sample(0, 2)];
+        let report = SignCountReport::new(&history);
+        assert_eq!(report.latest, Some(SignCountStatus::ConstantZero));
+        assert!(report.constant_zero);
+        assert!(!report.is_suspicious());
+
+        let history = [sample(4, 0), sample(0, 1), sample(2, 2), sample(2, 3), sample(3, 4)];
+        let report = SignCountReport::new(&history);
+        assert_eq!(report.latest, Some(SignCountStatus::Increasing));
+        assert!(!report.constant_zero);
+        let anomalies: Vec<_> = report.anomalies.iter().map(|a| a.sample).collect();
+        assert_eq!(anomalies, vec![history[1], history[3]]);
+        assert_eq!(report.anomalies[0].status, SignCountStatus::Reset { previous: 4 });
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{AuthenticatorInfo, CTAPHIDInfo, Device, DeviceMode, DevicePathBuf, Fido, Result};
+
+/// What a device reports about itself, captured by [`Fido::snapshot_device`] without keeping it
+/// open.
+///
+/// # Remarks
+/// - While a device is open, its HID interface is claimed exclusively on some platforms, so
+///   browsers and other applications can't use it. Prefer keeping snapshots around to keeping
+///   [`Device`]s open, and [`open`] the device again when a request is made.
+///
+/// [`Fido::snapshot_device`]: struct.Fido.html#method.snapshot_device
+/// [`Device`]: struct.Device.html
+/// [`open`]: struct.DeviceInfoSnapshot.html#method.open
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfoSnapshot {
+    pub path: DevicePathBuf,
+    pub mode: DeviceMode,
+    pub ctap_hid: CTAPHIDInfo,
+    /// `None` for U2F only devices.
+    pub info: Option<AuthenticatorInfo>,
+}
+
+impl DeviceInfoSnapshot {
+    /// Opens the device the snapshot was captured from.
+    ///
+    /// # Remarks
+    /// - The path may belong to a different device by now, if the device was replugged.
+    pub fn open(&self, fido: &Fido) -> Result<Device> {
+        fido.new_device(self.path.as_path())
+    }
+
+    /// Returns the value of the option `name`, e.g. `clientPin`, or `None` if the device doesn't
+    /// report it.
+    pub fn option(&self, name: &str) -> Option<bool> {
+        self.info.as_ref()?.options.get(name).copied()
+    }
+
+    /// Returns whether the device reports support for `extension`, e.g. `hmac-secret`.
+    pub fn supports_extension(&self, extension: impl AsRef<str>) -> bool {
+        self.info
+            .as_ref()
+            .is_some_and(|info| info.supports_extension(extension))
+    }
+}
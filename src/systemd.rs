@@ -0,0 +1,225 @@
+//! The `systemd-fido2` token of LUKS2 headers, as written by `systemd-cryptenroll --fido2-device`.
+//!
+//! The token stores what is needed to request the `hmac-secret` output of a credential. The
+//! base64 encoded output is the passphrase of the LUKS2 keyslot, so volumes enrolled here can be
+//! unlocked by systemd, and the other way around.
+//!
+//! Reading and writing the LUKS2 header is left to the caller, e.g. `cryptsetup token import`.
+
+use crate::{
+    hmac::{self, SecretRequest},
+    json::{self, Value},
+    openssl, pem, AssertionOptions, CredentialCreationData, CredentialExtensions, CredentialOptions,
+    Device, Fido, FidoError, Result, RpEntity, UserEntity,
+};
+use libfido2_sys::*;
+use std::{
+    error,
+    ffi::{CStr, CString},
+    fmt,
+};
+
+/// The `type` of the token.
+pub const TOKEN_TYPE: &str = "systemd-fido2";
+
+/// The relying party ID systemd uses, and assumes for tokens without one.
+pub const DEFAULT_RELYING_PARTY: &str = "io.systemd.cryptsetup";
+
+const RELYING_PARTY_NAME: &[u8] = b"Encrypted Volume\0";
+
+/// A `systemd-fido2` token.
+///
+/// # Remarks
+/// - The policy fields are `None` for tokens written by systemd versions that did not record them.
+///   systemd then requires user presence, and no user verification. Without a recorded PIN
+///   policy, it tries without PIN first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fido2Token {
+    /// LUKS2 keyslots unlocked by the passphrase.
+    pub keyslots: Vec<u32>,
+    pub credential_id: Vec<u8>,
+    /// The `hmac-secret` salt.
+    pub salt: Vec<u8>,
+    pub relying_party: String,
+    pub client_pin_required: Option<bool>,
+    pub user_presence_required: Option<bool>,
+    pub user_verification_required: Option<bool>,
+}
+
+/// What [`Fido2Token::enroll`] creates a credential with.
+///
+/// [`Fido2Token::enroll`]: struct.Fido2Token.html#method.enroll
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Enrollment<'a> {
+    pub keyslot: u32,
+    /// Shown by authenticators with a display, systemd uses the volume name.
+    pub user_name: &'a CStr,
+    pub user_presence: bool,
+    pub user_verification: bool,
+}
+
+impl Fido2Token {
+    /// Creates a credential on `device` and requests its output for a new salt, returning the token
+    /// and the passphrase to add to the keyslot.
+    ///
+    /// # Arguments
+    /// - `pin`: PIN of the device, if it has one. It is then required to unlock the volume.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block. The user has to touch the device twice: once for the
+    ///   credential, and once for its output.
+    pub fn enroll(
+        fido: &Fido,
+        device: &mut Device,
+        enrollment: &Enrollment<'_>,
+        pin: Option<&CStr>,
+    ) -> Result<(Self, String)> {
+        let mut user_id = [0; 32];
+        openssl::random_bytes(&mut user_id)?;
+        let mut client_data_hash = [0; 32];
+        openssl::random_bytes(&mut client_data_hash)?;
+        let rp_id = CString::new(DEFAULT_RELYING_PARTY).expect("no nul bytes");
+        let rp_name = CStr::from_bytes_with_nul(RELYING_PARTY_NAME).expect("nul terminated");
+        let relying_party = RpEntity::new(&rp_id).map_err(invalid)?.with_name(rp_name);
+        let user = UserEntity::new(&user_id, enrollment.user_name)
+            .map_err(invalid)?
+            .with_display_name(enrollment.user_name);
+
+        let mut data =
+            CredentialCreationData::with_defaults(&client_data_hash, relying_party, user);
+        data.extensions = CredentialExtensions::HMAC_SECRET;
+        if enrollment.user_verification {
+            data.options = CredentialOptions::USER_VERIFICATION;
+        }
+        let creator = fido.new_credential_creator(data)?;
+        let credential = device.request_credential_creation(creator, pin)?;
+
+        let mut token = Fido2Token {
+            keyslots: vec![enrollment.keyslot],
+            credential_id: credential.id().as_bytes().to_vec(),
+            salt: vec![0; hmac::SALT_LEN],
+            relying_party: DEFAULT_RELYING_PARTY.to_owned(),
+            client_pin_required: Some(pin.is_some()),
+            user_presence_required: Some(enrollment.user_presence),
+            user_verification_required: Some(enrollment.user_verification),
+        };
+        openssl::random_bytes(&mut token.salt)?;
+        let passphrase = token.passphrase(fido, device, pin)?;
+        Ok((token, passphrase))
+    }
+
+    /// Requests the `hmac-secret` output of the credential, and returns it encoded as the keyslot
+    /// passphrase.
+    ///
+    /// # Remarks
+    /// - This is synchronous and will block.
+    /// - `pin` is ignored if the token doesn't require one.
+    pub fn passphrase(
+        &self,
+        fido: &Fido,
+        device: &mut Device,
+        pin: Option<&CStr>,
+    ) -> Result<String> {
+        let rp_id = CString::new(self.relying_party.as_str()).map_err(invalid)?;
+        let relying_party = RpEntity::new(&rp_id).map_err(invalid)?;
+        let mut options = AssertionOptions::empty();
+        if self.user_verification_required == Some(true) {
+            options |= AssertionOptions::USER_VERIFICATION;
+        }
+        let request = SecretRequest {
+            relying_party,
+            credential_id: &self.credential_id,
+            options,
+            user_presence: Some(self.user_presence_required.unwrap_or(true)),
+        };
+        let pin = match self.client_pin_required {
+            Some(false) => None,
+            _ => pin,
+        };
+        let secret = hmac::request_secret(fido, device, &request, &self.salt, pin)?;
+        Ok(pem::base64(&secret))
+    }
+
+    /// Encodes the token as the JSON object stored in the LUKS2 header.
+    pub fn to_json(&self) -> String {
+        let keyslots = self
+            .keyslots
+            .iter()
+            .map(|keyslot| json::string(&keyslot.to_string()))
+            .collect::<Vec<_>>();
+        let mut json = format!(
+            "{{\"type\":{},\"keyslots\":[{}],\"fido2-credential\":{},\"fido2-salt\":{},\
+             \"fido2-rp\":{}",
+            json::string(TOKEN_TYPE),
+            keyslots.join(","),
+            json::string(&pem::base64(&self.credential_id)),
+            json::string(&pem::base64(&self.salt)),
+            json::string(&self.relying_party)
+        );
+        let flags = [
+            ("fido2-clientPin-required", self.client_pin_required),
+            ("fido2-up-required", self.user_presence_required),
+            ("fido2-uv-required", self.user_verification_required),
+        ];
+        for (name, value) in flags.iter() {
+            if let Some(value) = value {
+                json.push_str(&format!(",{}:{}", json::string(name), value));
+            }
+        }
+        json.push('}');
+        json
+    }
+
+    /// Decodes a token from the JSON object stored in the LUKS2 header.
+    ///
+    /// # Remarks
+    /// - Unknown fields are ignored.
+    pub fn from_json(json: &str) -> std::result::Result<Self, InvalidTokenError> {
+        Self::parse(&json::parse(json).ok_or(InvalidTokenError)?).ok_or(InvalidTokenError)
+    }
+
+    fn parse(token: &Value) -> Option<Self> {
+        if token.get("type")?.as_str()? != TOKEN_TYPE {
+            return None;
+        }
+        let keyslots = token
+            .get("keyslots")?
+            .as_array()?
+            .iter()
+            .map(|keyslot| keyslot.as_str()?.parse().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let base64 = |name| pem::base64_decode(token.get(name)?.as_str()?);
+        let flag = |name| match token.get(name) {
+            Some(value) => value.as_bool().map(Some),
+            None => Some(None),
+        };
+        Some(Fido2Token {
+            keyslots,
+            credential_id: base64("fido2-credential").filter(|id| !id.is_empty())?,
+            salt: base64("fido2-salt").filter(|salt| !salt.is_empty())?,
+            relying_party: match token.get("fido2-rp") {
+                Some(rp) => rp.as_str()?.to_owned(),
+                None => DEFAULT_RELYING_PARTY.to_owned(),
+            },
+            client_pin_required: flag("fido2-clientPin-required")?,
+            user_presence_required: flag("fido2-up-required")?,
+            user_verification_required: flag("fido2-uv-required")?,
+        })
+    }
+}
+
+fn invalid<E>(_: E) -> FidoError {
+    FidoError(FIDO_ERR_INVALID_ARGUMENT)
+}
+
+/// The token JSON is invalid, or is not a `systemd-fido2` token.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InvalidTokenError;
+
+impl error::Error for InvalidTokenError {}
+
+impl fmt::Display for InvalidTokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The systemd-fido2 token is invalid")
+    }
+}
@@ -0,0 +1,94 @@
+//! `tracing` spans of device operations, with the `tracing` feature. Without it, these are empty
+//! and compile to nothing.
+
+use crate::FidoError;
+#[cfg(feature = "tracing")]
+use crate::openssl;
+#[cfg(feature = "tracing")]
+use std::{convert::TryFrom, fmt::Write, time::Instant};
+use std::{ffi::CStr, fmt};
+
+/// The parent span of the operations of a device, carrying its path.
+pub(crate) struct DeviceSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl DeviceSpan {
+    pub(crate) fn new(path: &CStr) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = path;
+        DeviceSpan {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!("device", path = %path.to_string_lossy()),
+        }
+    }
+
+    /// For devices of unknown path, e.g. taken from a raw pointer.
+    pub(crate) fn none() -> Self {
+        DeviceSpan {
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::none(),
+        }
+    }
+}
+
+/// A span entered while an operation runs, recording its latency and error when finished.
+pub(crate) struct OperationSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(feature = "tracing")]
+    start: Instant,
+}
+
+impl OperationSpan {
+    /// Starts a span of `operation`, on the credentials of `rp_id` if set.
+    pub(crate) fn start(
+        device: &DeviceSpan,
+        operation: &dyn fmt::Display,
+        rp_id: Option<&CStr>,
+    ) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = (device, operation, rp_id);
+        let span = OperationSpan {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                parent: &device.span,
+                "operation",
+                operation = %operation,
+                rp_id_hash = tracing::field::Empty,
+                latency_us = tracing::field::Empty,
+                error = tracing::field::Empty,
+            )
+            .entered(),
+            #[cfg(feature = "tracing")]
+            start: Instant::now(),
+        };
+        // The SHA-256 of the relying party ID leaves the ID itself out of traces
+        #[cfg(feature = "tracing")]
+        if let Some(rp_id) = rp_id.filter(|_| !span.span.is_disabled()) {
+            if let Ok(rp_id_hash) = openssl::sha256(rp_id.to_bytes()) {
+                let mut hex = String::with_capacity(64);
+                for byte in &rp_id_hash {
+                    let _ = write!(hex, "{:02x}", byte);
+                }
+                span.span.record("rp_id_hash", hex.as_str());
+            }
+        }
+        span
+    }
+
+    pub(crate) fn finish(self, result: Result<(), FidoError>) {
+        #[cfg(feature = "tracing")]
+        {
+            let latency = self.start.elapsed().as_micros();
+            self.span
+                .record("latency_us", u64::try_from(latency).unwrap_or(u64::MAX));
+            if let Err(error) = result {
+                self.span.record("error", tracing::field::display(error));
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = result;
+    }
+}
@@ -0,0 +1,46 @@
+//! Interoperability with credentials registered through U2F.
+
+use crate::{CredentialId, FidoError, PublicKey, Result};
+use libfido2_sys::*;
+
+/// Maximum length of a U2F key handle, in bytes.
+pub const MAX_KEY_HANDLE_LENGTH: usize = 255;
+
+/// Length of an uncompressed P-256 point, as returned in U2F registration responses.
+const U2F_PUBLIC_KEY_LENGTH: usize = 65;
+
+impl CredentialId {
+    /// Converts a U2F key handle to a credential ID, which FIDO2 devices accept in allow and exclude lists.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_INVALID_ARGUMENT` if the key handle is empty or longer than [`MAX_KEY_HANDLE_LENGTH`].
+    ///
+    /// [`MAX_KEY_HANDLE_LENGTH`]: constant.MAX_KEY_HANDLE_LENGTH.html
+    pub fn from_key_handle(key_handle: &[u8]) -> Result<Self> {
+        if key_handle.is_empty() || key_handle.len() > MAX_KEY_HANDLE_LENGTH {
+            return Err(FidoError(FIDO_ERR_INVALID_ARGUMENT));
+        }
+        Ok(CredentialId::from(key_handle))
+    }
+
+    /// Returns the credential ID as a U2F key handle, or `None` if it is too long to be one.
+    ///
+    /// # Remarks
+    /// - Credentials created by FIDO2 devices can only be used by U2F devices if they fit.
+    pub fn as_key_handle(&self) -> Option<&[u8]> {
+        Some(self.as_bytes()).filter(|id| id.len() <= MAX_KEY_HANDLE_LENGTH)
+    }
+}
+
+impl PublicKey {
+    /// Parses the public key of a U2F registration response, an uncompressed P-256 point.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_INVALID_ARGUMENT` if the key is not an uncompressed point.
+    pub fn from_u2f(public_key: &[u8]) -> Result<PublicKey> {
+        if public_key.len() != U2F_PUBLIC_KEY_LENGTH || public_key[0] != 0x04 {
+            return Err(FidoError(FIDO_ERR_INVALID_ARGUMENT));
+        }
+        PublicKey::new_es256(public_key)
+    }
+}
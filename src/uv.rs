@@ -0,0 +1,260 @@
+use crate::{
+    auth_data::AuthData, AssertionOptions, AuthenticatorInfo, CredentialOptions, FidoError, Result,
+};
+use libfido2_sys::*;
+use std::{convert::TryInto, fmt, os::raw};
+
+// An authenticator reports at most 3 factors in the uvm extension
+const MAX_UVM_ENTRIES: usize = 3;
+
+/// Whether a request should verify the user, as the `userVerification` member of WebAuthn.
+///
+/// See [`Device::request_credential_creation_with_uv`] and
+/// [`Device::request_assertion_verification_with_uv`], which map the preference onto the options
+/// and PIN of the request.
+///
+/// [`Device::request_credential_creation_with_uv`]: struct.Device.html#method.request_credential_creation_with_uv
+/// [`Device::request_assertion_verification_with_uv`]: struct.Device.html#method.request_assertion_verification_with_uv
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum UvPreference {
+    /// Only user presence is requested, unless the device insists on verifying the user.
+    Discouraged,
+    /// The user is verified if the device can, otherwise only user presence is requested.
+    Preferred,
+    /// The user must be verified, and the request fails if the device can't.
+    Required,
+}
+
+/// How a request verifies the user, as resolved from a [`UvPreference`] for a device.
+///
+/// [`UvPreference`]: enum.UvPreference.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum UserVerification {
+    /// The user only touches the device.
+    PresenceOnly,
+    /// The device verifies the user itself, e.g. with a fingerprint (the `uv` option).
+    BuiltIn,
+    /// The user enters the PIN of the device, and touches it.
+    Pin,
+}
+
+impl Default for UvPreference {
+    /// `Preferred`, the default of WebAuthn.
+    fn default() -> Self {
+        UvPreference::Preferred
+    }
+}
+
+impl UvPreference {
+    /// Resolves the preference for a credential creation on a device reporting `info`,
+    /// or `None` for U2F only devices.
+    ///
+    /// # Remarks
+    /// - Devices with a PIN require it for every credential, unless they report
+    ///   `makeCredUvNotRqd`, so `Discouraged` doesn't skip it.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_UNSUPPORTED_OPTION` if the user must be verified, but the device has neither
+    ///   built-in user verification nor a PIN.
+    pub fn for_credential(self, info: Option<&AuthenticatorInfo>) -> Result<UserVerification> {
+        let pin_required = info.is_some_and(|info| {
+            option(info, "clientPin") == Some(true)
+                && option(info, "makeCredUvNotRqd") != Some(true)
+        });
+        match self.resolve(info)? {
+            UserVerification::PresenceOnly if pin_required => Ok(UserVerification::Pin),
+            verification => Ok(verification),
+        }
+    }
+
+    /// Resolves the preference for an assertion on a device reporting `info`, or `None` for
+    /// U2F only devices.
+    ///
+    /// # Errors
+    /// - `FIDO_ERR_UNSUPPORTED_OPTION` if the user must be verified, but the device has neither
+    ///   built-in user verification nor a PIN.
+    pub fn for_assertion(self, info: Option<&AuthenticatorInfo>) -> Result<UserVerification> {
+        self.resolve(info)
+    }
+
+    fn resolve(self, info: Option<&AuthenticatorInfo>) -> Result<UserVerification> {
+        const UNSUPPORTED_OPTION: raw::c_int = FIDO_ERR_UNSUPPORTED_OPTION as raw::c_int;
+
+        let info = match info {
+            Some(info) => info,
+            // U2F can't verify the user
+            None if self == UvPreference::Required => return Err(FidoError(UNSUPPORTED_OPTION)),
+            None => return Ok(UserVerification::PresenceOnly),
+        };
+        // `uv` is false for sensors without enrolled fingerprints, which can't verify yet
+        let built_in = option(info, "uv") == Some(true);
+        let pin = option(info, "clientPin") == Some(true);
+        let preference = match self {
+            UvPreference::Discouraged if option(info, "alwaysUv") == Some(true) => {
+                UvPreference::Preferred
+            }
+            preference => preference,
+        };
+        match preference {
+            UvPreference::Discouraged => Ok(UserVerification::PresenceOnly),
+            _ if built_in => Ok(UserVerification::BuiltIn),
+            _ if pin => Ok(UserVerification::Pin),
+            UvPreference::Preferred => Ok(UserVerification::PresenceOnly),
+            UvPreference::Required => Err(FidoError(UNSUPPORTED_OPTION)),
+        }
+    }
+}
+
+impl UserVerification {
+    /// Returns `options` with the `uv` option set as needed.
+    ///
+    /// # Remarks
+    /// - The option is only set for `BuiltIn`, a PIN verifies the user without it.
+    pub fn credential_options(self, options: CredentialOptions) -> CredentialOptions {
+        match self {
+            UserVerification::BuiltIn => options | CredentialOptions::USER_VERIFICATION,
+            _ => options - CredentialOptions::USER_VERIFICATION,
+        }
+    }
+
+    /// Returns `options` with the `uv` option set as needed.
+    ///
+    /// # Remarks
+    /// - The option is only set for `BuiltIn`, a PIN verifies the user without it.
+    pub fn assertion_options(self, options: AssertionOptions) -> AssertionOptions {
+        match self {
+            UserVerification::BuiltIn => options | AssertionOptions::USER_VERIFICATION,
+            _ => options - AssertionOptions::USER_VERIFICATION,
+        }
+    }
+}
+
+/// A user verification method of the FIDO registry, as reported in the `uvm` extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VerificationMethod {
+    /// The user touched the device.
+    PresenceInternal,
+    FingerprintInternal,
+    /// A PIN or password entered on the device, or the `clientPin` of CTAP2.
+    PasscodeInternal,
+    VoiceprintInternal,
+    FaceprintInternal,
+    LocationInternal,
+    EyeprintInternal,
+    PatternInternal,
+    HandprintInternal,
+    /// No user verification.
+    None,
+    /// All the other methods of the entry are required.
+    All,
+    /// A PIN or password entered outside the device.
+    PasscodeExternal,
+    PatternExternal,
+    /// A method this crate has no name for.
+    Other(u32),
+}
+
+impl VerificationMethod {
+    /// Returns the `USER_VERIFY` constant of the method in the FIDO registry.
+    pub fn id(self) -> u32 {
+        match self {
+            VerificationMethod::PresenceInternal => 0x0001,
+            VerificationMethod::FingerprintInternal => 0x0002,
+            VerificationMethod::PasscodeInternal => 0x0004,
+            VerificationMethod::VoiceprintInternal => 0x0008,
+            VerificationMethod::FaceprintInternal => 0x0010,
+            VerificationMethod::LocationInternal => 0x0020,
+            VerificationMethod::EyeprintInternal => 0x0040,
+            VerificationMethod::PatternInternal => 0x0080,
+            VerificationMethod::HandprintInternal => 0x0100,
+            VerificationMethod::None => 0x0200,
+            VerificationMethod::All => 0x0400,
+            VerificationMethod::PasscodeExternal => 0x0800,
+            VerificationMethod::PatternExternal => 0x1000,
+            VerificationMethod::Other(id) => id,
+        }
+    }
+
+    /// Returns the method with the `USER_VERIFY` constant `id`.
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            0x0001 => VerificationMethod::PresenceInternal,
+            0x0002 => VerificationMethod::FingerprintInternal,
+            0x0004 => VerificationMethod::PasscodeInternal,
+            0x0008 => VerificationMethod::VoiceprintInternal,
+            0x0010 => VerificationMethod::FaceprintInternal,
+            0x0020 => VerificationMethod::LocationInternal,
+            0x0040 => VerificationMethod::EyeprintInternal,
+            0x0080 => VerificationMethod::PatternInternal,
+            0x0100 => VerificationMethod::HandprintInternal,
+            0x0200 => VerificationMethod::None,
+            0x0400 => VerificationMethod::All,
+            0x0800 => VerificationMethod::PasscodeExternal,
+            0x1000 => VerificationMethod::PatternExternal,
+            id => VerificationMethod::Other(id),
+        }
+    }
+}
+
+/// The name of the method in the FIDO registry, e.g. `fingerprint_internal`.
+impl fmt::Display for VerificationMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            VerificationMethod::PresenceInternal => "presence_internal",
+            VerificationMethod::FingerprintInternal => "fingerprint_internal",
+            VerificationMethod::PasscodeInternal => "passcode_internal",
+            VerificationMethod::VoiceprintInternal => "voiceprint_internal",
+            VerificationMethod::FaceprintInternal => "faceprint_internal",
+            VerificationMethod::LocationInternal => "location_internal",
+            VerificationMethod::EyeprintInternal => "eyeprint_internal",
+            VerificationMethod::PatternInternal => "pattern_internal",
+            VerificationMethod::HandprintInternal => "handprint_internal",
+            VerificationMethod::None => "none",
+            VerificationMethod::All => "all",
+            VerificationMethod::PasscodeExternal => "passcode_external",
+            VerificationMethod::PatternExternal => "pattern_external",
+            VerificationMethod::Other(id) => return write!(f, "{:#06x}", id),
+        };
+        f.write_str(name)
+    }
+}
+
+/// A factor the device used to verify the user, from the `uvm` extension.
+///
+/// # Remarks
+/// - The protections are the `KEY_PROTECTION` and `MATCHER_PROTECTION` flags of the FIDO
+///   registry, e.g. `KEY_PROTECTION_HARDWARE` (`0x0002`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UvmEntry {
+    pub method: VerificationMethod,
+    /// How the key of the credential is protected.
+    pub key_protection: u16,
+    /// How the matcher of the method is protected.
+    pub matcher_protection: u16,
+}
+
+/// Returns the entries of the `uvm` extension in raw authenticator data, or `None` if it is missing
+/// or malformed.
+pub(crate) fn uvm_entries(auth_data: &[u8]) -> Option<Vec<UvmEntry>> {
+    let mut uvm = AuthData::parse(auth_data)?.extension("uvm")?;
+    let len = uvm.array()?;
+    if len > MAX_UVM_ENTRIES {
+        return None;
+    }
+    (0..len)
+        .map(|_| {
+            if uvm.array()? != 3 {
+                return None;
+            }
+            Some(UvmEntry {
+                method: VerificationMethod::from_id(uvm.uint()?.try_into().ok()?),
+                key_protection: uvm.uint()?.try_into().ok()?,
+                matcher_protection: uvm.uint()?.try_into().ok()?,
+            })
+        })
+        .collect()
+}
+
+fn option(info: &AuthenticatorInfo, name: &str) -> Option<bool> {
+    info.options.get(name).copied()
+}
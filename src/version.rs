@@ -0,0 +1,106 @@
+use std::{fmt, sync::OnceLock};
+
+/// Release of libfido2, as detected by [`libfido2_version`].
+///
+/// [`libfido2_version`]: fn.libfido2_version.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LibraryVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl LibraryVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        LibraryVersion { major, minor }
+    }
+}
+
+impl fmt::Display for LibraryVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Functions first exported by a release, newest first.
+const MARKERS: &[(LibraryVersion, &[u8])] = &[
+    (LibraryVersion::new(1, 13), b"fido_cred_set_attobj\0"),
+    (LibraryVersion::new(1, 12), b"fido_cbor_info_uv_modality\0"),
+    (LibraryVersion::new(1, 9), b"fido_cred_set_pin_minlen\0"),
+    (LibraryVersion::new(1, 8), b"fido_cbor_info_algorithm_count\0"),
+    (LibraryVersion::new(1, 7), b"fido_dev_largeblob_get\0"),
+];
+
+/// Returns the release of the libfido2 the process is running with.
+///
+/// # Remarks
+/// - libfido2 does not report its version, so it is detected from the functions the library
+///   exports. The result is the newest release whose functions are all present, e.g. 1.12 for
+///   libfido2 1.12.0, and a lower bound for releases that only changed existing functions.
+/// - Returns `None` if the version can't be detected: for releases older than 1.7, on platforms
+///   other than Unix, and if libfido2 is linked statically.
+pub fn libfido2_version() -> Option<LibraryVersion> {
+    static VERSION: OnceLock<Option<LibraryVersion>> = OnceLock::new();
+    *VERSION.get_or_init(|| {
+        MARKERS
+            .iter()
+            .find(|(_, symbol)| has_symbol(symbol))
+            .map(|(version, _)| *version)
+    })
+}
+
+/// Parts of libfido2 that are not available in every build of it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LibraryFeature {
+    /// Large blob storage (`fido_dev_largeblob_*`), since 1.7.
+    LargeBlob,
+    /// Authenticator configuration (`fido_dev_enable_entattest` and friends), since 1.7.
+    AuthenticatorConfig,
+    /// The `minPinLength` extension, since 1.9.
+    MinPinLength,
+}
+
+impl LibraryFeature {
+    /// Returns whether the libfido2 the process is running with has this feature, so code can
+    /// skip it instead of failing on older releases.
+    ///
+    /// # Remarks
+    /// - Like [`libfido2_version`], this looks up the functions of the feature, so it returns
+    ///   `false` on platforms other than Unix and if libfido2 is linked statically.
+    /// - This only checks the running library. The wrappers of large blobs and authenticator
+    ///   configuration link their functions, so a binary built with the `largeblob` or `config`
+    ///   features fails to load with releases older than 1.7. Disable those features to support
+    ///   them.
+    ///
+    /// [`libfido2_version`]: fn.libfido2_version.html
+    pub fn is_available(self) -> bool {
+        match self {
+            LibraryFeature::LargeBlob => has_symbol(b"fido_dev_largeblob_get\0"),
+            LibraryFeature::AuthenticatorConfig => has_symbol(b"fido_dev_enable_entattest\0"),
+            LibraryFeature::MinPinLength => has_symbol(b"fido_cred_set_pin_minlen\0"),
+        }
+    }
+}
+
+/// Looks up a nul terminated symbol in the libraries loaded into the process.
+#[cfg(unix)]
+fn has_symbol(symbol: &[u8]) -> bool {
+    use std::os::raw;
+
+    // Searches all loaded libraries in load order
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    const RTLD_DEFAULT: *mut raw::c_void = -2isize as *mut _;
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    const RTLD_DEFAULT: *mut raw::c_void = std::ptr::null_mut();
+
+    extern "C" {
+        fn dlsym(handle: *mut raw::c_void, symbol: *const raw::c_char) -> *mut raw::c_void;
+    }
+
+    debug_assert_eq!(symbol.last(), Some(&0));
+    unsafe { !dlsym(RTLD_DEFAULT, symbol.as_ptr() as *const _).is_null() }
+}
+
+#[cfg(not(unix))]
+fn has_symbol(_symbol: &[u8]) -> bool {
+    false
+}